@@ -165,6 +165,10 @@ impl blockchain::DataSource<Chain> for DataSource {
     fn runtime(&self) -> &[u8] {
         self.mapping.runtime.as_ref()
     }
+
+    fn entities(&self) -> &[String] {
+        &self.mapping.entities
+    }
 }
 
 impl DataSource {
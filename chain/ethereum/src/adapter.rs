@@ -11,7 +11,7 @@ use mockall::predicate::*;
 use prost::Message;
 use prost_types::Any;
 use std::cmp;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::marker::Unpin;
 use thiserror::Error;
@@ -29,7 +29,8 @@ const COMBINED_FILTER_TYPE_URL: &str =
     "type.googleapis.com/sf.ethereum.transform.v1.CombinedFilter";
 
 use crate::capabilities::NodeCapabilities;
-use crate::data_source::{BlockHandlerFilter, DataSource};
+use crate::data_source::{BlockHandlerFilter, DataSource, MappingEventHandler};
+use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger};
 use crate::{Chain, Mapping, ENV_VARS};
 
 pub type EventSignature = H256;
@@ -72,11 +73,69 @@ enum LogFilterNode {
     Event(EventSignature),
 }
 
+/// An event for which a handler only wants logs whose `topic1`, `topic2`
+/// or `topic3` have one of a list of values. If `address` is `None`, the
+/// event is matched on all contracts. A topic that is `None` matches any
+/// value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct EventSignatureWithTopics {
+    pub address: Option<Address>,
+    pub signature: EventSignature,
+    pub topic1: Option<Vec<H256>>,
+    pub topic2: Option<Vec<H256>>,
+    pub topic3: Option<Vec<H256>>,
+}
+
+impl EventSignatureWithTopics {
+    fn new(address: Option<Address>, handler: &MappingEventHandler) -> Self {
+        // An empty list of values is treated like no filter at all since
+        // `eth_getLogs` treats it that way, too. Values are sorted so that
+        // handlers filtering on the same topics can share a filter
+        let topic = |topic: &Option<Vec<H256>>| {
+            topic
+                .clone()
+                .filter(|values| !values.is_empty())
+                .map(|mut values| {
+                    values.sort();
+                    values.dedup();
+                    values
+                })
+        };
+        EventSignatureWithTopics {
+            address,
+            signature: handler.topic0(),
+            topic1: topic(&handler.topic1),
+            topic2: topic(&handler.topic2),
+            topic3: topic(&handler.topic3),
+        }
+    }
+
+    /// Check whether a log from contract `address` with the given `topics`
+    /// matches this event
+    fn matches(&self, address: &Address, topics: &[H256]) -> bool {
+        fn topic_matches(filter: &Option<Vec<H256>>, topic: Option<&H256>) -> bool {
+            match filter {
+                None => true,
+                Some(values) => topic.map_or(false, |topic| values.contains(topic)),
+            }
+        }
+
+        self.address.map_or(true, |a| &a == address)
+            && topics.first() == Some(&self.signature)
+            && topic_matches(&self.topic1, topics.get(1))
+            && topic_matches(&self.topic2, topics.get(2))
+            && topic_matches(&self.topic3, topics.get(3))
+    }
+}
+
 /// Corresponds to an `eth_getLogs` call.
 #[derive(Clone, Debug)]
 pub struct EthGetLogsFilter {
     pub contracts: Vec<Address>,
     pub event_signatures: Vec<EventSignature>,
+    pub topic1: Option<Vec<H256>>,
+    pub topic2: Option<Vec<H256>>,
+    pub topic3: Option<Vec<H256>>,
 }
 
 impl EthGetLogsFilter {
@@ -84,6 +143,9 @@ impl EthGetLogsFilter {
         EthGetLogsFilter {
             contracts: vec![address],
             event_signatures: vec![],
+            topic1: None,
+            topic2: None,
+            topic3: None,
         }
     }
 
@@ -91,8 +153,15 @@ impl EthGetLogsFilter {
         EthGetLogsFilter {
             contracts: vec![],
             event_signatures: vec![event],
+            topic1: None,
+            topic2: None,
+            topic3: None,
         }
     }
+
+    fn has_topic_filters(&self) -> bool {
+        self.topic1.is_some() || self.topic2.is_some() || self.topic3.is_some()
+    }
 }
 
 impl fmt::Display for EthGetLogsFilter {
@@ -103,17 +172,28 @@ impl fmt::Display for EthGetLogsFilter {
                 "contract {:?}, {} events",
                 self.contracts[0],
                 self.event_signatures.len()
-            )
+            )?;
         } else if self.event_signatures.len() == 1 {
             write!(
                 f,
                 "event {:?}, {} contracts",
                 self.event_signatures[0],
                 self.contracts.len()
-            )
+            )?;
+        } else if self.has_topic_filters() {
+            write!(
+                f,
+                "{} contracts, {} events",
+                self.contracts.len(),
+                self.event_signatures.len()
+            )?;
         } else {
-            write!(f, "unreachable")
+            return write!(f, "unreachable");
         }
+        if self.has_topic_filters() {
+            write!(f, ", with topic filters")?;
+        }
+        Ok(())
     }
 }
 
@@ -144,6 +224,7 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
         NodeCapabilities {
             archive: false,
             traces: self.requires_traces(),
+            block_receipts: self.log.requires_transaction_receipts(),
         }
     }
 
@@ -167,9 +248,12 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
         let EthereumBlockFilter {
             contract_addresses: _contract_addresses,
             trigger_every_block,
+            polling_intervals,
         } = self.block.clone();
 
-        if trigger_every_block {
+        // Firehose can not filter blocks by number, so polling and once
+        // block handlers require all blocks, just like unfiltered handlers
+        if trigger_every_block || !polling_intervals.is_empty() {
             return Vec::new();
         }
 
@@ -204,6 +288,12 @@ pub(crate) struct EthereumLogFilter {
     /// Event sigs with no associated address, matching on all addresses.
     /// Maps to a boolean representing if a trigger requires a transaction receipt.
     wildcard_events: HashMap<EventSignature, bool>,
+
+    /// Events whose handlers filter on `topic1`, `topic2` or `topic3`. These
+    /// can only be merged into one `eth_getLogs` call with events that
+    /// filter on the same topics and are therefore kept separately.
+    /// Maps to a boolean representing if a trigger requires a transaction receipt.
+    events_with_topic_filters: HashMap<EventSignatureWithTopics, bool>,
 }
 
 impl Into<Vec<LogFilter>> for EthereumLogFilter {
@@ -213,6 +303,12 @@ impl Into<Vec<LogFilter>> for EthereumLogFilter {
                 |EthGetLogsFilter {
                      contracts,
                      event_signatures,
+                     // Firehose transforms can only filter on addresses
+                     // and event signatures; topic filters are applied
+                     // when triggers are matched against the filter
+                     topic1: _,
+                     topic2: _,
+                     topic3: _,
                  }| LogFilter {
                     addresses: contracts
                         .iter()
@@ -245,18 +341,35 @@ impl EthereumLogFilter {
                     .all_edges()
                     .any(|(s, t, _)| (s == contract && t == event) || (t == contract && s == event))
                     || self.wildcard_events.contains_key(sig)
+                    || self
+                        .events_with_topic_filters
+                        .keys()
+                        .any(|event| event.matches(&log.address, &log.topics))
             }
         }
     }
 
     /// Similar to [`matches`], checks if a transaction receipt is required for this log filter.
+    /// For events with topic filters, this does not look at the topics and
+    /// might therefore ask for receipts that end up not being needed.
     pub fn requires_transaction_receipt(
         &self,
         event_signature: &H256,
         contract_address: Option<&Address>,
     ) -> bool {
+        let topic_filter_requires_receipt =
+            self.events_with_topic_filters
+                .iter()
+                .any(|(event, receipt)| {
+                    *receipt
+                        && &event.signature == event_signature
+                        && (event.address.is_none() || event.address.as_ref() == contract_address)
+                });
+
         if let Some(true) = self.wildcard_events.get(event_signature) {
             true
+        } else if topic_filter_requires_receipt {
+            true
         } else if let Some(address) = contract_address {
             let contract = LogFilterNode::Contract(*address);
             let event = LogFilterNode::Event(*event_signature);
@@ -270,10 +383,30 @@ impl EthereumLogFilter {
         }
     }
 
+    /// Whether any trigger in this filter requires a transaction receipt
+    pub fn requires_transaction_receipts(&self) -> bool {
+        self.contracts_and_events_graph
+            .all_edges()
+            .any(|(_, _, receipt)| *receipt)
+            || self.wildcard_events.values().any(|receipt| *receipt)
+            || self
+                .events_with_topic_filters
+                .values()
+                .any(|receipt| *receipt)
+    }
+
     pub fn from_data_sources<'a>(iter: impl IntoIterator<Item = &'a DataSource>) -> Self {
         let mut this = EthereumLogFilter::default();
         for ds in iter {
             for event_handler in ds.mapping.event_handlers.iter() {
+                if event_handler.has_topic_filters() {
+                    this.events_with_topic_filters.insert(
+                        EventSignatureWithTopics::new(ds.address, event_handler),
+                        event_handler.receipt,
+                    );
+                    continue;
+                }
+
                 let event_sig = event_handler.topic0();
                 match ds.address {
                     Some(contract) => {
@@ -296,6 +429,14 @@ impl EthereumLogFilter {
     pub fn from_mapping(mapping: &Mapping) -> Self {
         let mut this = EthereumLogFilter::default();
         for event_handler in &mapping.event_handlers {
+            if event_handler.has_topic_filters() {
+                this.events_with_topic_filters.insert(
+                    EventSignatureWithTopics::new(None, event_handler),
+                    event_handler.receipt,
+                );
+                continue;
+            }
+
             let signature = event_handler.topic0();
             this.wildcard_events
                 .insert(signature, event_handler.receipt);
@@ -313,11 +454,14 @@ impl EthereumLogFilter {
         let EthereumLogFilter {
             contracts_and_events_graph,
             wildcard_events,
+            events_with_topic_filters,
         } = other;
         for (s, t, e) in contracts_and_events_graph.all_edges() {
             self.contracts_and_events_graph.add_edge(s, t, *e);
         }
         self.wildcard_events.extend(wildcard_events);
+        self.events_with_topic_filters
+            .extend(events_with_topic_filters);
     }
 
     /// An empty filter is one that never matches.
@@ -326,14 +470,36 @@ impl EthereumLogFilter {
         let EthereumLogFilter {
             contracts_and_events_graph,
             wildcard_events,
+            events_with_topic_filters,
         } = self;
-        contracts_and_events_graph.edge_count() == 0 && wildcard_events.is_empty()
+        contracts_and_events_graph.edge_count() == 0
+            && wildcard_events.is_empty()
+            && events_with_topic_filters.is_empty()
     }
 
     /// Filters for `eth_getLogs` calls. The filters will not return false positives. This attempts
     /// to balance between having granular filters but too many calls and having few calls but too
     /// broad filters causing the Ethereum endpoint to timeout.
     pub fn eth_get_logs_filters(self) -> impl Iterator<Item = EthGetLogsFilter> {
+        // Logs for events with topic filters that are also fetched without
+        // topic filters for the same contract must not be fetched twice,
+        // since every log that is returned becomes a trigger
+        let graph = &self.contracts_and_events_graph;
+        let wildcard_events = &self.wildcard_events;
+        let events_with_topic_filters = self
+            .events_with_topic_filters
+            .into_keys()
+            .filter(|event| {
+                let covered_by_graph = event.address.map_or(false, |address| {
+                    graph.contains_edge(
+                        LogFilterNode::Contract(address),
+                        LogFilterNode::Event(event.signature),
+                    )
+                });
+                !covered_by_graph && !wildcard_events.contains_key(&event.signature)
+            })
+            .collect_vec();
+
         // Start with the wildcard event filters.
         let mut filters = self
             .wildcard_events
@@ -341,6 +507,63 @@ impl EthereumLogFilter {
             .map(|(event, _)| EthGetLogsFilter::from_event(event))
             .collect_vec();
 
+        // Events with topic filters can share a call if they filter on the
+        // same topics. First collect the contracts for each event and its
+        // topics, then collect the events that have the same contracts and
+        // topics; neither step adds false positives. Contracts of `None`
+        // stand for an event that is matched on all contracts.
+        type Topics = (Option<Vec<H256>>, Option<Vec<H256>>, Option<Vec<H256>>);
+        let mut contracts_by_event: BTreeMap<(EventSignature, Topics), Option<BTreeSet<Address>>> =
+            BTreeMap::new();
+        for event in events_with_topic_filters {
+            let EventSignatureWithTopics {
+                address,
+                signature,
+                topic1,
+                topic2,
+                topic3,
+            } = event;
+            let contracts = contracts_by_event
+                .entry((signature, (topic1, topic2, topic3)))
+                .or_insert_with(|| Some(BTreeSet::new()));
+            match (contracts.as_mut(), address) {
+                (Some(contracts), Some(address)) => {
+                    contracts.insert(address);
+                }
+                (_, None) => *contracts = None,
+                (None, Some(_)) => {}
+            }
+        }
+
+        let mut events_by_contracts: BTreeMap<(Option<BTreeSet<Address>>, Topics), Vec<_>> =
+            BTreeMap::new();
+        for ((signature, topics), contracts) in contracts_by_event {
+            events_by_contracts
+                .entry((contracts, topics))
+                .or_default()
+                .push(signature);
+        }
+
+        for ((contracts, (topic1, topic2, topic3)), event_signatures) in events_by_contracts {
+            let filter = |contracts: Vec<Address>| EthGetLogsFilter {
+                contracts,
+                event_signatures: event_signatures.clone(),
+                topic1: topic1.clone(),
+                topic2: topic2.clone(),
+                topic3: topic3.clone(),
+            };
+            match contracts {
+                None => filters.push(filter(vec![])),
+                Some(contracts) => filters.extend(
+                    contracts
+                        .into_iter()
+                        .collect_vec()
+                        .chunks(ENV_VARS.get_logs_max_contracts)
+                        .map(|contracts| filter(contracts.to_vec())),
+                ),
+            }
+        }
+
         // The current algorithm is to repeatedly find the maximum cardinality vertex and turn all
         // of its edges into a filter. This is nice because it is neutral between filtering by
         // contract or by events, if there are many events that appear on only one data source
@@ -596,6 +819,10 @@ impl From<&EthereumBlockFilter> for EthereumCallFilter {
 pub(crate) struct EthereumBlockFilter {
     pub contract_addresses: HashSet<(BlockNumber, Address)>,
     pub trigger_every_block: bool,
+    /// Pairs of `(start_block, every)` for `polling` block handlers. An
+    /// interval of `0` stands for a `once` block handler, which only
+    /// triggers on `start_block`
+    pub polling_intervals: HashSet<(BlockNumber, i32)>,
 }
 
 impl Into<Vec<CallToFilter>> for EthereumBlockFilter {
@@ -618,10 +845,17 @@ impl EthereumBlockFilter {
     /// ahead of time. This means the filters applied to the block_stream need to be broad, in this case,
     /// specifically, will match all blocks. The blocks are then further filtered by the subgraph instance manager
     /// which keeps track of deployed contracts and relevant addresses.
+    ///
+    /// `once` block handlers are left out since they only run on the block in which a data source
+    /// is created, and that block gets processed with a filter built by `from_data_sources`.
     pub fn from_mapping(mapping: &Mapping) -> Self {
         Self {
             contract_addresses: HashSet::new(),
-            trigger_every_block: mapping.block_handlers.len() != 0,
+            trigger_every_block: mapping
+                .block_handlers
+                .iter()
+                .any(|handler| handler.filter != Some(BlockHandlerFilter::Once)),
+            polling_intervals: HashSet::new(),
         }
     }
 
@@ -646,8 +880,24 @@ impl EthereumBlockFilter {
                     .into_iter()
                     .any(|block_handler| block_handler.filter.is_none());
 
+                let polling_intervals = data_source
+                    .mapping
+                    .block_handlers
+                    .iter()
+                    .filter_map(|block_handler| match block_handler.filter {
+                        Some(BlockHandlerFilter::Polling { every }) => {
+                            Some((data_source.block_handler_start(), every.get() as i32))
+                        }
+                        Some(BlockHandlerFilter::Once) => {
+                            Some((data_source.block_handler_start(), 0))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+
                 filter_opt.extend(Self {
                     trigger_every_block: has_block_handler_without_filter,
+                    polling_intervals,
                     contract_addresses: if has_block_handler_with_call_filter {
                         vec![(
                             data_source.start_block,
@@ -671,9 +921,11 @@ impl EthereumBlockFilter {
         let EthereumBlockFilter {
             contract_addresses,
             trigger_every_block,
+            polling_intervals,
         } = other;
 
         self.trigger_every_block = self.trigger_every_block || trigger_every_block;
+        self.polling_intervals.extend(polling_intervals);
 
         for other in contract_addresses {
             let (other_start_block, other_address) = other;
@@ -706,7 +958,41 @@ impl EthereumBlockFilter {
             return false;
         }
 
-        self.contract_addresses.is_empty()
+        self.contract_addresses.is_empty() && self.polling_intervals.is_empty()
+    }
+
+    /// Return `true` if a `polling` block handler needs to run at `number`
+    pub fn matches_polling(&self, number: BlockNumber) -> bool {
+        self.polling_intervals.iter().any(|(start, interval)| {
+            *interval > 0 && number >= *start && (number - start) % interval == 0
+        })
+    }
+
+    /// Return `true` if a `once` block handler needs to run at `number`
+    pub fn matches_once(&self, number: BlockNumber) -> bool {
+        self.polling_intervals
+            .iter()
+            .any(|(start, interval)| *interval == 0 && number == *start)
+    }
+
+    /// The block triggers for the block `ptr` that do not depend on the
+    /// calls in that block. A `once` trigger comes before an `every`
+    /// trigger for the same block
+    pub fn block_number_triggers(&self, ptr: &BlockPtr) -> Vec<EthereumTrigger> {
+        let mut triggers = Vec::new();
+        if self.matches_once(ptr.number) {
+            triggers.push(EthereumTrigger::Block(
+                ptr.clone(),
+                EthereumBlockTriggerType::Once,
+            ));
+        }
+        if self.trigger_every_block || self.matches_polling(ptr.number) {
+            triggers.push(EthereumTrigger::Block(
+                ptr.clone(),
+                EthereumBlockTriggerType::Every,
+            ));
+        }
+        triggers
     }
 
     fn find_contract_address(&self, candidate: &Address) -> Option<(i32, Address)> {
@@ -741,6 +1027,7 @@ const STATUS_HELP: &str = "0 = ok, 1 = net_version failed, 2 = get genesis faile
 #[derive(Clone)]
 pub struct ProviderEthRpcMetrics {
     request_duration: Box<HistogramVec>,
+    batch_size: Box<HistogramVec>,
     errors: Box<CounterVec>,
     status: Box<GaugeVec>,
 }
@@ -755,6 +1042,14 @@ impl ProviderEthRpcMetrics {
                 vec![0.05, 0.1, 0.2, 0.4, 0.8, 1.6, 3.2, 6.4, 12.8, 25.6],
             )
             .unwrap();
+        let batch_size = registry
+            .new_histogram_vec(
+                "eth_rpc_batch_size",
+                "Measures the number of requests in eth rpc batches",
+                vec![String::from("method"), String::from("provider")],
+                vec![1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0],
+            )
+            .unwrap();
         let errors = registry
             .new_counter_vec(
                 "eth_rpc_errors",
@@ -772,6 +1067,7 @@ impl ProviderEthRpcMetrics {
             .unwrap();
         Self {
             request_duration,
+            batch_size,
             errors,
             status,
         }
@@ -783,6 +1079,12 @@ impl ProviderEthRpcMetrics {
             .observe(duration);
     }
 
+    pub fn observe_batch_size(&self, size: usize, method: &str, provider: &str) {
+        self.batch_size
+            .with_label_values(&[method, provider])
+            .observe(size as f64);
+    }
+
     pub fn add_error(&self, method: &str, provider: &str) {
         self.errors.with_label_values(&[method, provider]).inc();
     }
@@ -1040,6 +1342,7 @@ mod tests {
             log: EthereumLogFilter {
                 contracts_and_events_graph: GraphMap::new(),
                 wildcard_events: HashMap::new(),
+                events_with_topic_filters: HashMap::new(),
             },
             call: EthereumCallFilter {
                 contract_addresses_function_signatures: HashMap::from_iter(vec![
@@ -1058,6 +1361,7 @@ mod tests {
                     (500, address(1000)),
                 ]),
                 trigger_every_block: false,
+                polling_intervals: HashSet::new(),
             },
         };
 
@@ -1262,11 +1566,13 @@ mod tests {
         let mut base = EthereumBlockFilter {
             contract_addresses: HashSet::new(),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         let extension = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         base.extend(extension);
@@ -1282,11 +1588,13 @@ mod tests {
         let mut base = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         let extension = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(2, address(1))]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         base.extend(extension);
@@ -1302,11 +1610,13 @@ mod tests {
         let mut base = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(2, address(1))]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         let extension = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         base.extend(extension);
@@ -1322,11 +1632,13 @@ mod tests {
         let mut base = EthereumBlockFilter {
             contract_addresses: HashSet::default(),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         let extension = EthereumBlockFilter {
             contract_addresses: HashSet::default(),
             trigger_every_block: true,
+            polling_intervals: HashSet::new(),
         };
 
         base.extend(extension);
@@ -1339,11 +1651,13 @@ mod tests {
         let mut base = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(10, address(2))]),
             trigger_every_block: true,
+            polling_intervals: HashSet::new(),
         };
 
         let extension = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         base.extend(extension);
@@ -1360,11 +1674,13 @@ mod tests {
         let mut base = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(10, address(2))]),
             trigger_every_block: false,
+            polling_intervals: HashSet::new(),
         };
 
         let extension = EthereumBlockFilter {
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: true,
+            polling_intervals: HashSet::new(),
         };
 
         base.extend(extension);
@@ -1376,6 +1692,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extending_ethereum_block_filter_merges_polling_intervals() {
+        let mut base = EthereumBlockFilter {
+            contract_addresses: HashSet::new(),
+            trigger_every_block: false,
+            polling_intervals: HashSet::from_iter(vec![(10, 5)]),
+        };
+
+        let extension = EthereumBlockFilter {
+            contract_addresses: HashSet::new(),
+            trigger_every_block: false,
+            polling_intervals: HashSet::from_iter(vec![(20, 0)]),
+        };
+
+        base.extend(extension);
+
+        assert_eq!(
+            HashSet::from_iter(vec![(10, 5), (20, 0)]),
+            base.polling_intervals,
+        );
+        assert!(!base.is_empty());
+        assert!(base.matches_polling(15));
+        assert!(!base.matches_polling(16));
+        assert!(!base.matches_polling(5));
+        assert!(base.matches_once(20));
+        assert!(!base.matches_once(10));
+    }
+
     #[test]
     fn extending_ethereum_call_filter() {
         let mut base = EthereumCallFilter {
@@ -1461,6 +1805,7 @@ fn complete_log_filter() {
             let logs_filters: Vec<_> = EthereumLogFilter {
                 contracts_and_events_graph,
                 wildcard_events: HashMap::new(),
+                events_with_topic_filters: HashMap::new(),
             }
             .eth_get_logs_filters()
             .collect();
@@ -1555,6 +1900,7 @@ fn log_filter_require_transacion_receipt_method() {
     let filter = EthereumLogFilter {
         contracts_and_events_graph,
         wildcard_events,
+        events_with_topic_filters: HashMap::new(),
     };
 
     // connected contracts and events graph
@@ -1586,3 +1932,210 @@ fn log_filter_require_transacion_receipt_method() {
     assert!(!filter.requires_transaction_receipt(&event_signature_b, Some(&alien_contract_address)));
     assert!(!filter.requires_transaction_receipt(&event_signature_c, Some(&alien_contract_address)));
 }
+
+#[test]
+fn log_filter_with_topics() {
+    let contract = Address::from_low_u64_be(1);
+    let other_contract = Address::from_low_u64_be(2);
+    let signature = H256::from_low_u64_be(3);
+    let wanted = H256::from_low_u64_be(4);
+    let unwanted = H256::from_low_u64_be(5);
+
+    let handler = MappingEventHandler {
+        event: "Transfer(indexed address,indexed address,uint256)".to_string(),
+        topic0: Some(signature),
+        topic1: None,
+        topic2: Some(vec![wanted]),
+        topic3: Some(vec![]),
+        handler: "handleTransfer".to_string(),
+        receipt: false,
+        calls: Default::default(),
+    };
+    assert!(handler.has_topic_filters());
+
+    let filter = EthereumLogFilter {
+        contracts_and_events_graph: GraphMap::new(),
+        wildcard_events: HashMap::new(),
+        events_with_topic_filters: [(
+            EventSignatureWithTopics::new(Some(contract), &handler),
+            false,
+        )]
+        .into_iter()
+        .collect(),
+    };
+
+    let log = |address: Address, topics: Vec<H256>| Log {
+        address,
+        topics,
+        data: web3::types::Bytes::default(),
+        block_hash: None,
+        block_number: None,
+        transaction_hash: None,
+        transaction_index: None,
+        log_index: None,
+        transaction_log_index: None,
+        log_type: None,
+        removed: None,
+    };
+
+    let matching = log(contract, vec![signature, unwanted, wanted]);
+    assert!(filter.matches(&matching));
+    assert!(handler.matches_topics(&matching.topics));
+    assert!(!filter.matches(&log(contract, vec![signature, wanted, unwanted])));
+    assert!(!filter.matches(&log(contract, vec![signature, wanted])));
+    assert!(!filter.matches(&log(other_contract, vec![signature, unwanted, wanted])));
+    assert!(!filter.matches(&log(contract, vec![unwanted, unwanted, wanted])));
+
+    // Topic filters are passed on to `eth_getLogs`, with the empty `topic3`
+    // list treated as no filter
+    let logs_filters: Vec<_> = filter.eth_get_logs_filters().collect();
+    assert_eq!(1, logs_filters.len());
+    assert_eq!(vec![contract], logs_filters[0].contracts);
+    assert_eq!(vec![signature], logs_filters[0].event_signatures);
+    assert_eq!(None, logs_filters[0].topic1);
+    assert_eq!(Some(vec![wanted]), logs_filters[0].topic2);
+    assert_eq!(None, logs_filters[0].topic3);
+}
+
+#[test]
+fn log_filter_groups_topic_filters() {
+    let contract_a = Address::from_low_u64_be(1);
+    let contract_b = Address::from_low_u64_be(2);
+    let transfer = H256::from_low_u64_be(3);
+    let approval = H256::from_low_u64_be(4);
+    let wanted = H256::from_low_u64_be(5);
+    let other = H256::from_low_u64_be(6);
+
+    let handler = |signature: H256, topic2: Vec<H256>| MappingEventHandler {
+        event: "Event(indexed address,indexed address,uint256)".to_string(),
+        topic0: Some(signature),
+        topic1: None,
+        topic2: Some(topic2),
+        topic3: None,
+        handler: "handleEvent".to_string(),
+        receipt: false,
+        calls: Default::default(),
+    };
+
+    // Both events filter on the same topics for both contracts and can
+    // share one call; the order of topic values does not matter. Filtering
+    // on other topics or on all contracts needs separate calls
+    let filter = EthereumLogFilter {
+        contracts_and_events_graph: GraphMap::new(),
+        wildcard_events: HashMap::new(),
+        events_with_topic_filters: [
+            (Some(contract_a), handler(transfer, vec![wanted, other])),
+            (Some(contract_b), handler(transfer, vec![other, wanted])),
+            (Some(contract_a), handler(approval, vec![wanted, other])),
+            (Some(contract_b), handler(approval, vec![wanted, other])),
+            (Some(contract_a), handler(transfer, vec![wanted])),
+            (None, handler(approval, vec![wanted])),
+        ]
+        .iter()
+        .map(|(address, handler)| (EventSignatureWithTopics::new(*address, handler), false))
+        .collect(),
+    };
+
+    let mut logs_filters: Vec<_> = filter
+        .eth_get_logs_filters()
+        .map(|filter| {
+            (
+                filter.contracts,
+                filter.event_signatures,
+                filter.topic1,
+                filter.topic2,
+                filter.topic3,
+            )
+        })
+        .collect();
+    logs_filters.sort();
+    assert_eq!(
+        vec![
+            (vec![], vec![approval], None, Some(vec![wanted]), None),
+            (
+                vec![contract_a],
+                vec![transfer],
+                None,
+                Some(vec![wanted]),
+                None
+            ),
+            (
+                vec![contract_a, contract_b],
+                vec![transfer, approval],
+                None,
+                Some(vec![wanted, other]),
+                None
+            ),
+        ],
+        logs_filters
+    );
+}
+
+#[test]
+fn log_filter_skips_topic_filters_covered_by_plain_filters() {
+    let contract_a = Address::from_low_u64_be(1);
+    let contract_b = Address::from_low_u64_be(2);
+    let transfer = H256::from_low_u64_be(3);
+    let approval = H256::from_low_u64_be(4);
+    let wanted = H256::from_low_u64_be(5);
+
+    let handler = |signature: H256| MappingEventHandler {
+        event: "Event(indexed address,indexed address,uint256)".to_string(),
+        topic0: Some(signature),
+        topic1: Some(vec![wanted]),
+        topic2: None,
+        topic3: None,
+        handler: "handleEvent".to_string(),
+        receipt: false,
+        calls: Default::default(),
+    };
+
+    // `contract_a` has a plain and a topic-filtered handler for `transfer`,
+    // and `approval` has a plain handler on all contracts. Fetching the
+    // topic-filtered logs as well would return these logs twice
+    let mut filter = EthereumLogFilter {
+        contracts_and_events_graph: GraphMap::new(),
+        wildcard_events: [(approval, false)].into_iter().collect(),
+        events_with_topic_filters: [
+            (Some(contract_a), handler(transfer)),
+            (Some(contract_b), handler(transfer)),
+            (Some(contract_a), handler(approval)),
+        ]
+        .iter()
+        .map(|(address, handler)| (EventSignatureWithTopics::new(*address, handler), false))
+        .collect(),
+    };
+    filter.contracts_and_events_graph.add_edge(
+        LogFilterNode::Contract(contract_a),
+        LogFilterNode::Event(transfer),
+        false,
+    );
+
+    let mut logs_filters: Vec<_> = filter
+        .eth_get_logs_filters()
+        .map(|filter| {
+            (
+                filter.contracts,
+                filter.event_signatures,
+                filter.topic1,
+                filter.topic2,
+                filter.topic3,
+            )
+        })
+        .collect();
+    logs_filters.sort();
+    assert_eq!(
+        vec![
+            (vec![], vec![approval], None, None, None),
+            (vec![contract_a], vec![transfer], None, None, None),
+            (
+                vec![contract_b],
+                vec![transfer],
+                Some(vec![wanted]),
+                None,
+                None
+            ),
+        ],
+        logs_filters
+    );
+}
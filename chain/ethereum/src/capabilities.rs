@@ -13,6 +13,11 @@ use crate::DataSource;
 pub struct NodeCapabilities {
     pub archive: bool,
     pub traces: bool,
+    /// Whether all receipts of a block can be fetched with one call to
+    /// `eth_getBlockReceipts` or `parity_getBlockReceipts`. Providers are
+    /// checked for this when they are connected to, and it only affects how
+    /// receipts are fetched
+    pub block_receipts: bool,
 }
 
 // Take all NodeCapabilities fields into account when ordering
@@ -20,16 +25,17 @@ pub struct NodeCapabilities {
 // if all of its fields are equal or greater than the other
 impl Ord for NodeCapabilities {
     fn cmp(&self, other: &Self) -> Ordering {
-        match (
+        let fields = [
             self.archive.cmp(&other.archive),
             self.traces.cmp(&other.traces),
-        ) {
-            (Ordering::Greater, Ordering::Greater) => Ordering::Greater,
-            (Ordering::Greater, Ordering::Equal) => Ordering::Greater,
-            (Ordering::Equal, Ordering::Greater) => Ordering::Greater,
-            (Ordering::Equal, Ordering::Equal) => Ordering::Equal,
-            (Ordering::Less, _) => Ordering::Less,
-            (_, Ordering::Less) => Ordering::Less,
+            self.block_receipts.cmp(&other.block_receipts),
+        ];
+        if fields.contains(&Ordering::Less) {
+            Ordering::Less
+        } else if fields.contains(&Ordering::Greater) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
         }
     }
 }
@@ -48,13 +54,18 @@ impl FromStr for NodeCapabilities {
         Ok(NodeCapabilities {
             archive: capabilities.contains("archive"),
             traces: capabilities.contains("traces"),
+            block_receipts: capabilities.contains("block_receipts"),
         })
     }
 }
 
 impl fmt::Display for NodeCapabilities {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let NodeCapabilities { archive, traces } = self;
+        let NodeCapabilities {
+            archive,
+            traces,
+            block_receipts,
+        } = self;
 
         let mut capabilities = vec![];
         if *archive {
@@ -63,6 +74,9 @@ impl fmt::Display for NodeCapabilities {
         if *traces {
             capabilities.push("traces");
         }
+        if *block_receipts {
+            capabilities.push("block_receipts");
+        }
 
         f.write_str(&capabilities.join(", "))
     }
@@ -81,6 +95,9 @@ impl graph::blockchain::NodeCapabilities<crate::Chain> for NodeCapabilities {
             traces: data_sources.into_iter().any(|ds| {
                 ds.mapping.has_call_handler() || ds.mapping.has_block_handler_with_call_filter()
             }),
+            block_receipts: data_sources
+                .iter()
+                .any(|ds| ds.mapping.has_event_handler_with_receipt()),
         }
     }
 }
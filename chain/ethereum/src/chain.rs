@@ -148,17 +148,16 @@ impl TriggersAdapterSelector<Chain> for EthereumAdapterSelector {
             .subgraph_logger(&loc)
             .new(o!("component" => "BlockStream"));
 
-        let eth_adapter = if capabilities.traces && self.firehose_endpoints.len() > 0 {
+        let mut capabilities = *capabilities;
+        if capabilities.traces && self.firehose_endpoints.len() > 0 {
             debug!(logger, "Removing 'traces' capability requirement for adapter as FirehoseBlockStream will provide the traces");
-            let adjusted_capabilities = crate::capabilities::NodeCapabilities {
-                archive: capabilities.archive,
-                traces: false,
-            };
-
-            self.adapters.cheapest_with(&adjusted_capabilities)?.clone()
-        } else {
-            self.adapters.cheapest_with(capabilities)?.clone()
-        };
+            capabilities.traces = false;
+        }
+        if capabilities.block_receipts && self.adapters.cheapest_with(&capabilities).is_err() {
+            debug!(logger, "No adapter can fetch all receipts of a block at once, receipts will be fetched per transaction");
+            capabilities.block_receipts = false;
+        }
+        let eth_adapter = self.adapters.cheapest_with(&capabilities)?.clone();
 
         let ethrpc_metrics = Arc::new(SubgraphEthRpcMetrics::new(self.registry.clone(), &loc.hash));
 
@@ -8,6 +8,8 @@ use graph::prelude::futures03::future::try_join;
 use graph::prelude::futures03::stream::FuturesOrdered;
 use graph::prelude::{Link, SubgraphManifestValidationError};
 use graph::slog::{o, trace};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
 use std::str::FromStr;
 use std::{convert::TryFrom, sync::Arc};
 use tiny_keccak::{keccak256, Keccak};
@@ -15,17 +17,18 @@ use tiny_keccak::{keccak256, Keccak};
 use graph::{
     blockchain::{self, Blockchain},
     prelude::{
-        async_trait,
-        ethabi::{Address, Contract, Event, Function, LogParam, ParamType, RawLog},
+        async_trait, debug,
+        ethabi::{Address, Contract, Event, Function, LogParam, ParamType, RawLog, Token},
         info, serde_json, warn,
         web3::types::{Log, Transaction, H256},
-        BlockNumber, CheapClone, DataSourceTemplateInfo, Deserialize, EthereumCall,
+        BlockNumber, BlockPtr, CheapClone, DataSourceTemplateInfo, Deserialize, EthereumCall,
         LightEthereumBlock, LightEthereumBlockExt, LinkResolver, Logger, TryStreamExt,
     },
 };
 
 use graph::data::subgraph::{calls_host_fn, DataSourceContext, Source};
 
+use crate::adapter::EthereumContractCall;
 use crate::chain::Chain;
 use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger, MappingTrigger};
 
@@ -128,6 +131,7 @@ impl blockchain::DataSource<Chain> for DataSource {
                 .as_ref()
                 .map(|ctx| serde_json::to_value(&ctx).unwrap()),
             creation_block: self.creation_block,
+            done_at: None,
         }
     }
 
@@ -140,13 +144,24 @@ impl blockchain::DataSource<Chain> for DataSource {
             param,
             context,
             creation_block,
+            done_at: _,
         } = stored;
 
         let context = context.map(serde_json::from_value).transpose()?;
 
         let contract_abi = template.mapping.find_abi(&template.source.abi)?;
 
-        let address = param.map(|x| H160::from_slice(&x));
+        let address = param
+            .map(|x| {
+                ensure!(
+                    x.len() == 20,
+                    "address of dynamic data source `{}` should be 20 bytes long but is {} bytes long",
+                    template.name,
+                    x.len()
+                );
+                Ok(H160::from_slice(&x))
+            })
+            .transpose()?;
         Ok(DataSource {
             kind: template.kind.to_string(),
             network: template.network.as_ref().map(|s| s.to_string()),
@@ -179,25 +194,65 @@ impl blockchain::DataSource<Chain> for DataSource {
         };
 
         // Validate that there are no more than one of each type of block_handler
-        let has_too_many_block_handlers = {
-            let mut non_filtered_block_handler_count = 0;
-            let mut call_filtered_block_handler_count = 0;
-            self.mapping
-                .block_handlers
-                .iter()
-                .for_each(|block_handler| {
-                    if block_handler.filter.is_none() {
-                        non_filtered_block_handler_count += 1
-                    } else {
-                        call_filtered_block_handler_count += 1
-                    }
-                });
-            non_filtered_block_handler_count > 1 || call_filtered_block_handler_count > 1
-        };
+        let mut non_filtered_block_handler_count = 0;
+        let mut call_filtered_block_handler_count = 0;
+        let mut polling_block_handler_count = 0;
+        let mut once_block_handler_count = 0;
+        self.mapping
+            .block_handlers
+            .iter()
+            .for_each(|block_handler| match block_handler.filter {
+                None => non_filtered_block_handler_count += 1,
+                Some(BlockHandlerFilter::Call) => call_filtered_block_handler_count += 1,
+                Some(BlockHandlerFilter::Polling { .. }) => polling_block_handler_count += 1,
+                Some(BlockHandlerFilter::Once) => once_block_handler_count += 1,
+            });
+        let has_too_many_block_handlers = non_filtered_block_handler_count > 1
+            || call_filtered_block_handler_count > 1
+            || polling_block_handler_count > 1
+            || once_block_handler_count > 1;
         if has_too_many_block_handlers {
             errors.push(anyhow!("data source has duplicated block handlers"));
         }
 
+        // A handler without a filter already runs on every block, which
+        // makes a polling handler pointless
+        if non_filtered_block_handler_count > 0 && polling_block_handler_count > 0 {
+            errors.push(anyhow!(
+                "data source has a block handler without a filter and a `polling` block \
+                 handler; only one of them can be used"
+            ));
+        }
+
+        // Validate that declared calls refer to existing functions and can
+        // be made with the parameters of the handler's event
+        for event_handler in &self.mapping.event_handlers {
+            if event_handler.calls.is_empty() {
+                continue;
+            }
+            let event = match self.contract_event_with_signature(&event_handler.event) {
+                Some(event) => event,
+                None => {
+                    errors.push(anyhow!(
+                        "event `{}` with declared calls not found in contract `{}`",
+                        event_handler.event,
+                        self.contract_abi.name
+                    ));
+                    continue;
+                }
+            };
+            for (label, decl) in &event_handler.calls {
+                if let Err(e) = decl.validate(&self.mapping, event) {
+                    errors.push(anyhow!(
+                        "declared call `{}` of the handler for event `{}` is invalid: {}",
+                        label,
+                        event_handler.event,
+                        e
+                    ));
+                }
+            }
+        }
+
         // Validate that event handlers don't require receipts for API versions lower than 0.0.7
         let api_version = self.api_version();
         if api_version < semver::Version::new(0, 0, 7) {
@@ -222,6 +277,10 @@ impl blockchain::DataSource<Chain> for DataSource {
     fn runtime(&self) -> &[u8] {
         self.mapping.runtime.as_ref()
     }
+
+    fn entities(&self) -> &[String] {
+        &self.mapping.entities
+    }
 }
 
 impl DataSource {
@@ -252,6 +311,13 @@ impl DataSource {
         })
     }
 
+    /// The block on which `once` and `polling` block handlers are
+    /// anchored. For data sources created from templates, that is the block
+    /// in which they were created since they have no `start_block`
+    pub(crate) fn block_handler_start(&self) -> BlockNumber {
+        self.creation_block.unwrap_or(self.start_block)
+    }
+
     fn handlers_for_log(&self, log: &Log) -> Result<Vec<MappingEventHandler>, Error> {
         // Get signature from the log
         let topic0 = log.topics.get(0).context("Ethereum event has no topics")?;
@@ -260,13 +326,53 @@ impl DataSource {
             .mapping
             .event_handlers
             .iter()
-            .filter(|handler| *topic0 == handler.topic0())
+            .filter(|handler| *topic0 == handler.topic0() && handler.matches_topics(&log.topics))
             .cloned()
             .collect::<Vec<_>>();
 
         Ok(handlers)
     }
 
+    /// The calls that the event handler for `trigger` declared, resolved
+    /// with the values from the event. Calls that can not be resolved are
+    /// skipped since the handler will run into the same problem when it
+    /// makes the call itself
+    pub(crate) fn declared_calls(
+        &self,
+        logger: &Logger,
+        trigger: &TriggerWithHandler<Chain>,
+    ) -> Vec<EthereumContractCall> {
+        let (block, log, params) = match trigger.trigger() {
+            MappingTrigger::Log {
+                block, log, params, ..
+            } => (block, log, params),
+            MappingTrigger::Call { .. } | MappingTrigger::Block { .. } => return vec![],
+        };
+        let handler = self.mapping.event_handlers.iter().find(|handler| {
+            handler.handler == trigger.handler_name()
+                && log.topics.get(0) == Some(&handler.topic0())
+        });
+        let handler = match handler {
+            Some(handler) => handler,
+            None => return vec![],
+        };
+
+        handler
+            .calls
+            .iter()
+            .filter_map(|(label, decl)| {
+                decl.resolve(&self.mapping, log, params, block.block_ptr())
+                    .map_err(|e| {
+                        debug!(logger, "Skipping declared call";
+                            "call" => label,
+                            "handler" => &handler.handler,
+                            "error" => e.to_string())
+                    })
+                    .ok()
+            })
+            .collect()
+    }
+
     fn handler_for_call(&self, call: &EthereumCall) -> Result<Option<MappingCallHandler>, Error> {
         // First four bytes of the input for the call are the first four
         // bytes of hash of the function signature
@@ -292,13 +398,29 @@ impl DataSource {
     fn handler_for_block(
         &self,
         trigger_type: &EthereumBlockTriggerType,
+        block_number: BlockNumber,
     ) -> Option<MappingBlockHandler> {
         match trigger_type {
             EthereumBlockTriggerType::Every => self
                 .mapping
                 .block_handlers
                 .iter()
-                .find(move |handler| handler.filter == None)
+                .find(move |handler| match handler.filter {
+                    None => true,
+                    Some(BlockHandlerFilter::Polling { every }) => {
+                        is_polling_block(self.block_handler_start(), every, block_number)
+                    }
+                    _ => false,
+                })
+                .cloned(),
+            EthereumBlockTriggerType::Once => self
+                .mapping
+                .block_handlers
+                .iter()
+                .find(move |handler| {
+                    handler.filter == Some(BlockHandlerFilter::Once)
+                        && block_number == self.block_handler_start()
+                })
                 .cloned(),
             EthereumBlockTriggerType::WithCallTo(_address) => self
                 .mapping
@@ -452,6 +574,7 @@ impl DataSource {
 
             // Unfiltered block triggers match any data source address.
             EthereumTrigger::Block(_, EthereumBlockTriggerType::Every) => return true,
+            EthereumTrigger::Block(_, EthereumBlockTriggerType::Once) => return true,
         };
 
         ds_address == *trigger_address
@@ -475,7 +598,7 @@ impl DataSource {
 
         match trigger {
             EthereumTrigger::Block(_, trigger_type) => {
-                let handler = match self.handler_for_block(trigger_type) {
+                let handler = match self.handler_for_block(trigger_type, block.number()) {
                     Some(handler) => handler,
                     None => return Ok(None),
                 };
@@ -881,6 +1004,10 @@ impl Mapping {
         !self.call_handlers.is_empty()
     }
 
+    pub fn has_event_handler_with_receipt(&self) -> bool {
+        self.event_handlers.iter().any(|handler| handler.receipt)
+    }
+
     pub fn has_block_handler_with_call_filter(&self) -> bool {
         self.block_handlers
             .iter()
@@ -997,6 +1124,21 @@ pub enum BlockHandlerFilter {
     // Call filter will trigger on all blocks where the data source contract
     // address has been called
     Call,
+    // Once filter will trigger only on the start block of the data source
+    Once,
+    // Polling filter will trigger on the start block of the data source and
+    // then on every `every` blocks after that
+    Polling { every: NonZeroU32 },
+}
+
+/// Return `true` if a `polling` block handler for a data source starting
+/// at `start_block` needs to run at `block_number`
+fn is_polling_block(
+    start_block: BlockNumber,
+    every: NonZeroU32,
+    block_number: BlockNumber,
+) -> bool {
+    block_number >= start_block && (block_number - start_block) as u32 % every.get() == 0
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
@@ -1009,9 +1151,20 @@ pub struct MappingCallHandler {
 pub struct MappingEventHandler {
     pub event: String,
     pub topic0: Option<H256>,
+    #[serde(default)]
+    pub topic1: Option<Vec<H256>>,
+    #[serde(default)]
+    pub topic2: Option<Vec<H256>>,
+    #[serde(default)]
+    pub topic3: Option<Vec<H256>>,
     pub handler: String,
     #[serde(default)]
     pub receipt: bool,
+    /// Contract calls the handler will make, keyed by a label. They are
+    /// made before the handler runs so that the handler's calls are
+    /// answered from the call cache
+    #[serde(default)]
+    pub calls: BTreeMap<String, CallDecl>,
 }
 
 impl MappingEventHandler {
@@ -1019,6 +1172,235 @@ impl MappingEventHandler {
         self.topic0
             .unwrap_or_else(|| string_to_h256(&self.event.replace("indexed ", "")))
     }
+
+    /// Return `true` if this handler only wants events whose `topic1`,
+    /// `topic2` or `topic3` have specific values
+    pub fn has_topic_filters(&self) -> bool {
+        [&self.topic1, &self.topic2, &self.topic3]
+            .iter()
+            .any(|topic| matches!(topic, Some(values) if !values.is_empty()))
+    }
+
+    /// Check whether the topics of a log, including `topic0`, satisfy the
+    /// `topic1`, `topic2` and `topic3` filters of this handler. A topic
+    /// without a filter, or with an empty list of values, matches anything
+    pub fn matches_topics(&self, topics: &[H256]) -> bool {
+        fn matches(filter: &Option<Vec<H256>>, topic: Option<&H256>) -> bool {
+            match filter {
+                Some(values) if !values.is_empty() => {
+                    topic.map_or(false, |topic| values.contains(topic))
+                }
+                _ => true,
+            }
+        }
+
+        matches(&self.topic1, topics.get(1))
+            && matches(&self.topic2, topics.get(2))
+            && matches(&self.topic3, topics.get(3))
+    }
+}
+
+/// A contract call declared by an event handler, written as
+/// `Contract[address].function(arg, ...)` in the manifest, where `Contract`
+/// is the name of one of the mapping's ABIs and the address and arguments
+/// are either `event.address` or `event.params.<name>`
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CallDecl {
+    pub abi: String,
+    pub address: CallArg,
+    pub function: String,
+    pub args: Vec<CallArg>,
+}
+
+/// A value taken from the event that triggers a handler to use as the
+/// address or an argument of a declared call
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum CallArg {
+    /// `event.address`, the address of the contract that emitted the event
+    Address,
+    /// `event.params.<name>`, the event parameter with that name
+    Param(String),
+}
+
+impl FromStr for CallArg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "event.address" {
+            return Ok(CallArg::Address);
+        }
+        match s.strip_prefix("event.params.") {
+            Some(name) if is_identifier(name) => Ok(CallArg::Param(name.to_string())),
+            _ => Err(anyhow!(
+                "invalid call argument `{}`, it must be `event.address` or `event.params.<name>`",
+                s
+            )),
+        }
+    }
+}
+
+impl CallArg {
+    fn resolve(&self, log: &Log, params: &[LogParam]) -> Result<Token, Error> {
+        match self {
+            CallArg::Address => Ok(Token::Address(log.address)),
+            CallArg::Param(name) => params
+                .iter()
+                .find(|param| &param.name == name)
+                .map(|param| param.value.clone())
+                .ok_or_else(|| anyhow!("the event has no parameter `{}`", name)),
+        }
+    }
+
+    /// The type of the value this argument resolves to for `event`
+    fn kind(&self, event: &Event) -> Result<ParamType, Error> {
+        match self {
+            CallArg::Address => Ok(ParamType::Address),
+            CallArg::Param(name) => event
+                .inputs
+                .iter()
+                .find(|input| &input.name == name)
+                .map(|input| input.kind.clone())
+                .ok_or_else(|| anyhow!("event `{}` has no parameter `{}`", event.name, name)),
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl FromStr for CallDecl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse(s: &str) -> Option<Result<CallDecl, Error>> {
+            let (abi, rest) = s.trim().split_once('[')?;
+            let (address, rest) = rest.split_once("].")?;
+            let (function, rest) = rest.split_once('(')?;
+            let args = rest.trim_end().strip_suffix(')')?;
+            if !is_identifier(abi) || !is_identifier(function) {
+                return None;
+            }
+
+            let decl = || {
+                let args = match args.trim() {
+                    "" => vec![],
+                    args => args
+                        .split(',')
+                        .map(CallArg::from_str)
+                        .collect::<Result<_, _>>()?,
+                };
+                Ok(CallDecl {
+                    abi: abi.to_string(),
+                    address: address.parse()?,
+                    function: function.to_string(),
+                    args,
+                })
+            };
+            Some(decl())
+        }
+
+        parse(s).unwrap_or_else(|| {
+            Err(anyhow!(
+                "invalid declared call `{}`, it must have the form \
+                 `Contract[address].function(arg, ...)`",
+                s
+            ))
+        })
+    }
+}
+
+impl TryFrom<String> for CallDecl {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl CallDecl {
+    /// Find the function this call refers to. Since the function is only
+    /// given by name, it must be the only function with that name that
+    /// takes as many arguments as the call passes
+    fn function(&self, mapping: &Mapping) -> Result<Function, Error> {
+        let abi = mapping.find_abi(&self.abi)?;
+        let mut functions = abi
+            .contract
+            .functions_by_name(&self.function)
+            .with_context(|| {
+                format!(
+                    "contract `{}` has no function `{}`",
+                    self.abi, self.function
+                )
+            })?
+            .iter()
+            .filter(|function| function.inputs.len() == self.args.len());
+        match (functions.next(), functions.next()) {
+            (Some(function), None) => Ok(function.clone()),
+            (None, _) => Err(anyhow!(
+                "contract `{}` has no function `{}` that takes {} arguments",
+                self.abi,
+                self.function,
+                self.args.len()
+            )),
+            (Some(_), Some(_)) => Err(anyhow!(
+                "contract `{}` has several functions `{}` that take {} arguments",
+                self.abi,
+                self.function,
+                self.args.len()
+            )),
+        }
+    }
+
+    /// Check that the call can be made with the parameters of `event`
+    fn validate(&self, mapping: &Mapping, event: &Event) -> Result<(), Error> {
+        let function = self.function(mapping)?;
+        if self.address.kind(event)? != ParamType::Address {
+            return Err(anyhow!("the address of the call is not an address"));
+        }
+        for (arg, input) in self.args.iter().zip(function.inputs.iter()) {
+            let kind = arg.kind(event)?;
+            if kind != input.kind {
+                return Err(anyhow!(
+                    "argument `{}` of function `{}` has type `{}` but is passed a value of type `{}`",
+                    input.name,
+                    function.name,
+                    input.kind,
+                    kind
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn this declaration into a call using the values from the `log`
+    /// that triggered the handler and its decoded `params`
+    fn resolve(
+        &self,
+        mapping: &Mapping,
+        log: &Log,
+        params: &[LogParam],
+        block_ptr: BlockPtr,
+    ) -> Result<EthereumContractCall, Error> {
+        let function = self.function(mapping)?;
+        let address = match self.address.resolve(log, params)? {
+            Token::Address(address) => address,
+            token => return Err(anyhow!("the address of the call is {}", token)),
+        };
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.resolve(log, params))
+            .collect::<Result<_, _>>()?;
+        Ok(EthereumContractCall {
+            address,
+            block_ptr,
+            function,
+            args,
+        })
+    }
 }
 
 /// Hashes a string to a H256 hash.
@@ -1039,3 +1421,76 @@ fn string_to_h256(s: &str) -> H256 {
 pub struct TemplateSource {
     pub abi: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use graph::semver::Version;
+
+    use super::*;
+    use crate::adapter::EthereumBlockFilter;
+
+    #[test]
+    fn template_block_handlers_start_at_creation_block() {
+        let block_handler = |filter| MappingBlockHandler {
+            handler: "handleBlock".to_string(),
+            filter: Some(filter),
+        };
+        let template = DataSourceTemplate {
+            kind: "ethereum/contract".to_string(),
+            network: Some("mainnet".to_string()),
+            name: "Pool".to_string(),
+            source: TemplateSource {
+                abi: "Pool".to_string(),
+            },
+            mapping: Mapping {
+                kind: "ethereum/events".to_string(),
+                api_version: Version::new(0, 0, 6),
+                language: "wasm/assemblyscript".to_string(),
+                entities: vec![],
+                abis: vec![Arc::new(MappingABI {
+                    name: "Pool".to_string(),
+                    contract: Contract::load("[]".as_bytes()).unwrap(),
+                })],
+                block_handlers: vec![
+                    block_handler(BlockHandlerFilter::Once),
+                    block_handler(BlockHandlerFilter::Polling {
+                        every: NonZeroU32::new(5).unwrap(),
+                    }),
+                ],
+                call_handlers: vec![],
+                event_handlers: vec![],
+                runtime: Arc::new(vec![]),
+                link: Link::from("link".to_string()),
+            },
+        };
+
+        let data_source = DataSource::try_from(DataSourceTemplateInfo {
+            template,
+            params: vec!["0x2E645469f354BB4F5c8a05B3b30A929361cf77eC".to_string()],
+            context: None,
+            creation_block: 12,
+        })
+        .unwrap();
+
+        // The `once` handler runs on the block in which the data source was
+        // created, and `polling` counts from that block
+        let once = EthereumBlockTriggerType::Once;
+        assert!(data_source.handler_for_block(&once, 12).is_some());
+        assert!(data_source.handler_for_block(&once, 0).is_none());
+        assert!(data_source.handler_for_block(&once, 13).is_none());
+
+        let every = EthereumBlockTriggerType::Every;
+        assert!(data_source.handler_for_block(&every, 12).is_some());
+        assert!(data_source.handler_for_block(&every, 17).is_some());
+        assert!(data_source.handler_for_block(&every, 15).is_none());
+        assert!(data_source.handler_for_block(&every, 10).is_none());
+
+        let filter = EthereumBlockFilter::from_data_sources([&data_source]);
+        assert_eq!(
+            HashSet::from_iter([(12, 0), (12, 5)]),
+            filter.polling_intervals
+        );
+    }
+}
@@ -39,6 +39,7 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -64,6 +65,53 @@ pub struct EthereumAdapter {
     web3: Arc<Web3<Transport>>,
     metrics: Arc<ProviderEthRpcMetrics>,
     supports_eip_1898: bool,
+    /// The maximum number of requests to send in one JSON-RPC batch
+    batch_size: usize,
+    /// How to fetch all receipts of a block at once, if the provider
+    /// supports that
+    block_receipts: Option<BlockReceiptsMethod>,
+    /// The number of blocks in a row for which the receipts the provider
+    /// returned did not match the receipts root
+    block_receipts_mismatches: Arc<AtomicUsize>,
+}
+
+/// Stop fetching receipts per block from a provider once this many blocks
+/// in a row had receipts that do not match their receipts root
+const MAX_BLOCK_RECEIPTS_MISMATCHES: usize = 3;
+
+/// The JSON-RPC methods with which a provider might return all receipts of
+/// a block at once
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockReceiptsMethod {
+    Eth,
+    Parity,
+}
+
+impl BlockReceiptsMethod {
+    fn name(&self) -> &'static str {
+        match self {
+            BlockReceiptsMethod::Eth => "eth_getBlockReceipts",
+            BlockReceiptsMethod::Parity => "parity_getBlockReceipts",
+        }
+    }
+
+    /// Find the method that the provider behind `web3` supports by asking
+    /// it for the receipts of the latest block. Since each method is tried
+    /// with a timeout of `GRAPH_ETHEREUM_JSON_RPC_TIMEOUT`, this can delay
+    /// startup by up to twice that timeout for an unresponsive provider
+    async fn detect(web3: &Web3<Transport>) -> Option<Self> {
+        use web3::Transport as _;
+
+        for method in [BlockReceiptsMethod::Eth, BlockReceiptsMethod::Parity] {
+            let params = vec![json::Value::String("latest".to_string())];
+            let request = web3.transport().execute(method.name(), params);
+            let response = graph::prelude::tokio::time::timeout(ENV_VARS.json_rpc_timeout, request);
+            if let Ok(Ok(json::Value::Array(_))) = response.await {
+                return Some(method);
+            }
+        }
+        None
+    }
 }
 
 /// Gas limit for `eth_call`. The value of 50_000_000 is a protocol-wide parameter so this
@@ -76,6 +124,21 @@ pub struct EthereumAdapter {
 // See also f0af4ab0-6b7c-4b68-9141-5b79346a5f61.
 const ETH_CALL_GAS: u32 = 50_000_000;
 
+fn call_request(contract_address: Address, call_data: Bytes) -> CallRequest {
+    CallRequest {
+        to: Some(contract_address),
+        gas: Some(web3::types::U256::from(ETH_CALL_GAS)),
+        data: Some(call_data),
+        from: None,
+        gas_price: None,
+        value: None,
+        access_list: None,
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+        transaction_type: None,
+    }
+}
+
 impl CheapClone for EthereumAdapter {
     fn cheap_clone(&self) -> Self {
         Self {
@@ -85,6 +148,9 @@ impl CheapClone for EthereumAdapter {
             web3: self.web3.cheap_clone(),
             metrics: self.metrics.cheap_clone(),
             supports_eip_1898: self.supports_eip_1898,
+            batch_size: self.batch_size,
+            block_receipts: self.block_receipts,
+            block_receipts_mismatches: self.block_receipts_mismatches.cheap_clone(),
         }
     }
 }
@@ -97,6 +163,7 @@ impl EthereumAdapter {
         transport: Transport,
         provider_metrics: Arc<ProviderEthRpcMetrics>,
         supports_eip_1898: bool,
+        batch_size: usize,
     ) -> Self {
         // Unwrap: The transport was constructed with this url, so it is valid and has a host.
         let hostname = graph::url::Url::parse(url)
@@ -116,6 +183,11 @@ impl EthereumAdapter {
             .map(|s| s.contains("TestRPC"))
             .unwrap_or(false);
 
+        let block_receipts = BlockReceiptsMethod::detect(&web3).await;
+        if let Some(method) = block_receipts {
+            debug!(logger, "Fetching receipts per block"; "method" => method.name());
+        }
+
         EthereumAdapter {
             logger,
             provider,
@@ -123,7 +195,175 @@ impl EthereumAdapter {
             web3,
             metrics: provider_metrics,
             supports_eip_1898: supports_eip_1898 && !is_ganache,
+            batch_size: batch_size.max(1),
+            block_receipts,
+            block_receipts_mismatches: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Whether this adapter can fetch all receipts of a block at once
+    pub fn supports_block_receipts(&self) -> bool {
+        self.block_receipts_method().is_some()
+    }
+
+    /// The method for fetching all receipts of a block at once, unless the
+    /// provider does not support that or its receipts did not match the
+    /// receipts root too often
+    fn block_receipts_method(&self) -> Option<BlockReceiptsMethod> {
+        self.block_receipts.filter(|_| {
+            self.block_receipts_mismatches.load(Ordering::SeqCst) < MAX_BLOCK_RECEIPTS_MISMATCHES
+        })
+    }
+
+    /// Fetch all receipts of the block `block_hash` with one call and check
+    /// them against the block's `receipts_root` and `transaction_hashes`.
+    /// Returns `None` if the provider can not do that or the receipts do
+    /// not check out, in which case receipts have to be fetched per
+    /// transaction
+    async fn block_receipts(
+        &self,
+        logger: &Logger,
+        block_hash: H256,
+        receipts_root: H256,
+        transaction_hashes: &[H256],
+    ) -> Option<Vec<Arc<TransactionReceipt>>> {
+        use web3::Transport as _;
+
+        let method = self.block_receipts_method()?.name();
+        let params = vec![json::to_value(block_hash).unwrap()];
+        let request = self.web3.transport().execute(method, params);
+        let start = Instant::now();
+        let response =
+            graph::prelude::tokio::time::timeout(ENV_VARS.json_rpc_timeout, request).await;
+        self.metrics
+            .observe_request(start.elapsed().as_secs_f64(), method, &self.provider);
+
+        let receipts = match response {
+            Ok(Ok(value)) => json::from_value::<Option<Vec<TransactionReceipt>>>(value)
+                .map_err(Error::from)
+                .and_then(|receipts| receipts.ok_or_else(|| anyhow!("block not found"))),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(anyhow!("request timed out")),
+        }
+        .and_then(|receipts| check_block_receipts(receipts, block_hash, transaction_hashes))
+        .and_then(|receipts| {
+            let root = crate::receipts::receipts_root(&receipts);
+            if root == receipts_root {
+                self.block_receipts_mismatches.store(0, Ordering::SeqCst);
+                return Ok(receipts);
+            }
+            let mismatches = self
+                .block_receipts_mismatches
+                .fetch_add(1, Ordering::SeqCst)
+                + 1;
+            if mismatches == MAX_BLOCK_RECEIPTS_MISMATCHES {
+                warn!(logger, "Receipts repeatedly did not match the receipts root, \
+                               fetching receipts per transaction from now on";
+                    "method" => method,
+                    "blocks" => mismatches);
+            }
+            Err(anyhow!(
+                "receipts root {:x} does not match the block's receipts root {:x}",
+                root,
+                receipts_root
+            ))
+        });
+        match receipts {
+            Ok(receipts) => Some(receipts.into_iter().map(Arc::new).collect()),
+            Err(e) => {
+                self.metrics.add_error(method, &self.provider);
+                debug!(logger, "Fetching receipts per transaction instead of per block";
+                    "block_hash" => format!("{:x}", block_hash),
+                    "method" => method,
+                    "error" => e.to_string());
+                None
+            }
+        }
+    }
+
+    /// Fetch the receipts for `transaction_hashes` in the block
+    /// `block_hash`. If the provider supports it and `block` is given, all
+    /// receipts of the block are fetched at once and the ones for
+    /// `transaction_hashes` are picked from them
+    async fn transaction_receipts_in_block(
+        &self,
+        logger: &Logger,
+        block: Option<&LightEthereumBlock>,
+        block_hash: H256,
+        transaction_hashes: &HashSet<H256>,
+    ) -> Result<Vec<Arc<TransactionReceipt>>, IngestorError> {
+        if let Some(block) = block {
+            let transactions: Vec<_> = block.transactions.iter().map(|tx| tx.hash).collect();
+            let receipts = self
+                .block_receipts(logger, block_hash, block.receipts_root, &transactions)
+                .await;
+            if let Some(receipts) = receipts {
+                return Ok(receipts
+                    .into_iter()
+                    .filter(|receipt| transaction_hashes.contains(&receipt.transaction_hash))
+                    .collect());
+            }
         }
+
+        let receipts = transaction_hashes.iter().map(|transaction_hash| {
+            fetch_transaction_receipt_with_retry(
+                self.web3.cheap_clone(),
+                *transaction_hash,
+                block_hash,
+                logger.cheap_clone(),
+            )
+        });
+        futures03::future::try_join_all(receipts).await
+    }
+
+    /// Sends a `method` request for each entry in `params`, grouped into
+    /// JSON-RPC batches of at most `batch_size` requests, and returns one
+    /// result per request in the order of `params`. A batch that takes
+    /// longer than `GRAPH_ETHEREUM_JSON_RPC_TIMEOUT` fails as a whole.
+    /// Failed requests are not retried
+    async fn batch_request(
+        &self,
+        method: &'static str,
+        params: Vec<Vec<json::Value>>,
+    ) -> Vec<Result<json::Value, web3::Error>> {
+        let batches = params
+            .into_iter()
+            .chunks(self.batch_size)
+            .into_iter()
+            .map(|chunk| chunk.collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let batches = batches.into_iter().map(|params| async move {
+            self.metrics
+                .observe_batch_size(params.len(), method, &self.provider);
+            let start = Instant::now();
+            let count = params.len();
+            let request = self.web3.transport().batch_request(method, params);
+            let results = match graph::prelude::tokio::time::timeout(
+                ENV_VARS.json_rpc_timeout,
+                request,
+            )
+            .await
+            {
+                Ok(results) => results,
+                Err(_) => {
+                    let timeout = web3::Error::Transport(web3::error::TransportError::Message(
+                        format!("batch of `{}` requests timed out", method),
+                    ));
+                    vec![Err(timeout); count]
+                }
+            };
+            self.metrics
+                .observe_request(start.elapsed().as_secs_f64(), method, &self.provider);
+            for _ in results.iter().filter(|result| result.is_err()) {
+                self.metrics.add_error(method, &self.provider);
+            }
+            results
+        });
+        futures03::future::join_all(batches)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
     }
 
     async fn traces(
@@ -252,7 +492,12 @@ impl EthereumAdapter {
                         .from_block(from.into())
                         .to_block(to.into())
                         .address(filter.contracts.clone())
-                        .topics(Some(filter.event_signatures.clone()), None, None, None)
+                        .topics(
+                            Some(filter.event_signatures.clone()),
+                            filter.topic1.clone(),
+                            filter.topic2.clone(),
+                            filter.topic3.clone(),
+                        )
                         .build();
 
                     // Request logs from client
@@ -420,14 +665,7 @@ impl EthereumAdapter {
         block_ptr: BlockPtr,
     ) -> impl Future<Item = Bytes, Error = EthereumContractCallError> + Send {
         let web3 = self.web3.clone();
-
-        // Ganache does not support calls by block hash.
-        // See https://github.com/trufflesuite/ganache-cli/issues/973
-        let block_id = if !self.supports_eip_1898 {
-            BlockId::Number(block_ptr.number.into())
-        } else {
-            BlockId::Hash(block_ptr.hash_as_h256())
-        };
+        let block_id = self.call_block_id(&block_ptr);
         let retry_log_message = format!("eth_call RPC call for block {}", block_ptr);
         retry(retry_log_message, &logger)
             .when(|result| match result {
@@ -441,18 +679,7 @@ impl EthereumAdapter {
                 let web3 = web3.cheap_clone();
 
                 async move {
-                    let req = CallRequest {
-                        to: Some(contract_address),
-                        gas: Some(web3::types::U256::from(ETH_CALL_GAS)),
-                        data: Some(call_data.clone()),
-                        from: None,
-                        gas_price: None,
-                        value: None,
-                        access_list: None,
-                        max_fee_per_gas: None,
-                        max_priority_fee_per_gas: None,
-                        transaction_type: None,
-                    };
+                    let req = call_request(contract_address, call_data);
                     let result = web3.eth().call(req, Some(block_id)).boxed().await;
 
                     // Try to check if the call was reverted. The JSON-RPC response for reverts is
@@ -569,35 +796,140 @@ impl EthereumAdapter {
             .compat()
     }
 
-    /// Request blocks by hash through JSON-RPC.
+    /// Fetch the receipts for the transactions `hashes` in `block_hash` in
+    /// JSON-RPC batches. Receipts that could not be fetched that way are
+    /// requested individually
+    async fn fetch_transaction_receipts_batched(
+        &self,
+        logger: &Logger,
+        hashes: Vec<H256>,
+        block_hash: H256,
+    ) -> Result<Vec<Arc<TransactionReceipt>>, IngestorError> {
+        let params = hashes
+            .iter()
+            .map(|hash| vec![json::to_value(hash).unwrap()])
+            .collect();
+        let responses = self
+            .batch_request("eth_getTransactionReceipt", params)
+            .await;
+
+        let receipts = hashes.into_iter().zip(responses).map(|(hash, response)| {
+            let receipt = response
+                .ok()
+                .and_then(|value| json::from_value::<Option<TransactionReceipt>>(value).ok())
+                .flatten()
+                .filter(|receipt| receipt.transaction_hash == hash);
+            let web3 = self.web3.cheap_clone();
+            let logger = logger.cheap_clone();
+            async move {
+                match receipt {
+                    Some(receipt) => {
+                        resolve_transaction_receipt(Some(receipt), hash, block_hash, logger)
+                            .map(Arc::new)
+                    }
+                    None => {
+                        fetch_transaction_receipt_with_retry(web3, hash, block_hash, logger).await
+                    }
+                }
+            }
+        });
+        futures03::future::try_join_all(receipts).await
+    }
+
+    /// The block at which to make an `eth_call` for `block_ptr`
+    fn call_block_id(&self, block_ptr: &BlockPtr) -> BlockId {
+        // Ganache does not support calls by block hash.
+        // See https://github.com/trufflesuite/ganache-cli/issues/973
+        if !self.supports_eip_1898 {
+            BlockId::Number(block_ptr.number.into())
+        } else {
+            BlockId::Hash(block_ptr.hash_as_h256())
+        }
+    }
+
+    /// Request blocks by hash through JSON-RPC. When the provider allows
+    /// it, blocks are requested in batches and blocks that could not be
+    /// loaded that way are requested individually.
     fn load_blocks_rpc(
         &self,
         logger: Logger,
         ids: Vec<H256>,
     ) -> impl Stream<Item = Arc<LightEthereumBlock>, Error = Error> + Send {
-        let web3 = self.web3.clone();
+        let eth = self.cheap_clone();
+        let batches = ids
+            .into_iter()
+            .chunks(self.batch_size)
+            .into_iter()
+            .map(|chunk| chunk.collect::<Vec<_>>())
+            .collect::<Vec<_>>();
 
-        stream::iter_ok::<_, Error>(ids.into_iter().map(move |hash| {
-            let web3 = web3.clone();
-            retry(format!("load block {}", hash), &logger)
-                .limit(ENV_VARS.request_retries)
-                .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
-                .run(move || {
-                    Box::pin(web3.eth().block_with_txs(BlockId::Hash(hash)))
-                        .compat()
-                        .from_err::<Error>()
-                        .and_then(move |block| {
-                            block.map(|block| Arc::new(block)).ok_or_else(|| {
-                                anyhow::anyhow!("Ethereum node did not find block {:?}", hash)
-                            })
-                        })
-                        .compat()
-                })
+        stream::iter_ok::<_, Error>(batches.into_iter().map(move |hashes| {
+            let eth = eth.cheap_clone();
+            let logger = logger.clone();
+            async move { eth.load_block_batch(&logger, hashes).await }
                 .boxed()
                 .compat()
-                .from_err()
         }))
         .buffered(ENV_VARS.block_batch_size)
+        .map(stream::iter_ok::<_, Error>)
+        .flatten()
+    }
+
+    async fn load_block_batch(
+        &self,
+        logger: &Logger,
+        hashes: Vec<H256>,
+    ) -> Result<Vec<Arc<LightEthereumBlock>>, Error> {
+        let responses = if hashes.len() > 1 {
+            let params = hashes
+                .iter()
+                .map(|hash| vec![json::to_value(hash).unwrap(), json::Value::Bool(true)])
+                .collect();
+            self.batch_request("eth_getBlockByHash", params).await
+        } else {
+            Vec::new()
+        };
+
+        let mut responses = responses.into_iter();
+        let blocks = hashes.into_iter().map(|hash| {
+            let block = responses
+                .next()
+                .and_then(|response| response.ok())
+                .and_then(|value| json::from_value::<Option<LightEthereumBlock>>(value).ok())
+                .flatten()
+                .filter(|block| block.hash == Some(hash));
+            async move {
+                match block {
+                    Some(block) => Ok(Arc::new(block)),
+                    None => self.load_block_with_retry(logger, hash).await,
+                }
+            }
+        });
+        futures03::future::try_join_all(blocks).await
+    }
+
+    async fn load_block_with_retry(
+        &self,
+        logger: &Logger,
+        hash: H256,
+    ) -> Result<Arc<LightEthereumBlock>, Error> {
+        let web3 = self.web3.clone();
+        retry(format!("load block {}", hash), logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                Box::pin(web3.eth().block_with_txs(BlockId::Hash(hash)))
+                    .compat()
+                    .from_err::<Error>()
+                    .and_then(move |block| {
+                        block.map(|block| Arc::new(block)).ok_or_else(|| {
+                            anyhow::anyhow!("Ethereum node did not find block {:?}", hash)
+                        })
+                    })
+                    .compat()
+            })
+            .await
+            .map_err(Error::from)
     }
 
     /// Request blocks ptrs for numbers through JSON-RPC.
@@ -684,6 +1016,14 @@ impl EthereumAdapter {
         // Real limits on the number of parallel requests are imposed within the adapter.
         .buffered(ENV_VARS.block_ingestor_max_concurrent_json_rpc_calls)
         .try_concat()
+        // A log can match more than one filter, for example a wildcard
+        // event with topic filters and the same event on a specific
+        // contract, but it must only turn into one trigger
+        .map_ok(|logs| {
+            logs.into_iter()
+                .unique_by(|log| (log.block_hash, log.log_index))
+                .collect::<Vec<_>>()
+        })
         .boxed()
     }
 
@@ -792,6 +1132,16 @@ impl EthereumAdapter {
         )
     }
 
+    /// Like `block_range_to_ptrs`, but only for the blocks in `block_nums`
+    pub(crate) fn block_numbers_to_ptrs(
+        &self,
+        logger: Logger,
+        block_nums: Vec<BlockNumber>,
+    ) -> Box<dyn Future<Item = Vec<BlockPtr>, Error = Error> + Send> {
+        debug!(&logger, "Requesting hashes for {} blocks", block_nums.len());
+        Box::new(self.load_block_ptrs_rpc(logger, block_nums).collect())
+    }
+
     pub async fn chain_id(&self) -> Result<u64, Error> {
         let logger = self.logger.clone();
         let web3 = self.web3.clone();
@@ -807,6 +1157,101 @@ impl EthereumAdapter {
         )
         .map_err(Error::msg)
     }
+
+    /// Make `calls` concurrently and store their results in `cache` so
+    /// that `contract_call` answers them from the cache later on. Calls
+    /// that are already cached are not made again, and calls that fail are
+    /// skipped. When the provider allows it, the calls are sent in JSON-RPC
+    /// batches
+    pub async fn prefetch_calls(
+        &self,
+        logger: &Logger,
+        calls: Vec<EthereumContractCall>,
+        cache: Arc<dyn EthereumCallCache>,
+    ) {
+        let lookup_cache = cache.cheap_clone();
+        let calls = match graph::spawn_blocking_allow_panic(move || {
+            calls
+                .into_iter()
+                .filter_map(|call| {
+                    let call_data = call.function.encode_input(&call.args).ok()?;
+                    match lookup_cache.get_call(call.address, &call_data, call.block_ptr.clone()) {
+                        Ok(Some(_)) => None,
+                        Ok(None) | Err(_) => Some((call, call_data)),
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        {
+            Ok(calls) => calls,
+            Err(e) => {
+                debug!(logger, "Not prefetching declared calls"; "error" => e.to_string());
+                return;
+            }
+        };
+        if calls.is_empty() {
+            return;
+        }
+        let outputs: Vec<Result<Bytes, Error>> = if self.batch_size > 1 {
+            let params = calls
+                .iter()
+                .map(|(call, call_data)| {
+                    let req = call_request(call.address, Bytes(call_data.clone()));
+                    let block_id = self.call_block_id(&call.block_ptr);
+                    vec![
+                        json::to_value(req).unwrap(),
+                        json::to_value(block_id).unwrap(),
+                    ]
+                })
+                .collect();
+            self.batch_request("eth_call", params)
+                .await
+                .into_iter()
+                .map(|result| Ok(json::from_value(result?)?))
+                .collect()
+        } else {
+            let outputs = calls.iter().map(|(call, call_data)| {
+                self.call(
+                    logger.clone(),
+                    call.address,
+                    Bytes(call_data.clone()),
+                    call.block_ptr.clone(),
+                )
+                .compat()
+                .map_err(Error::from)
+            });
+            futures03::future::join_all(outputs).await
+        };
+        let results = calls
+            .into_iter()
+            .zip(outputs)
+            .filter_map(|((call, call_data), result)| match result {
+                Ok(output) => Some((call, call_data, output)),
+                Err(e) => {
+                    debug!(logger, "Prefetching eth_call failed";
+                        "address" => hex::encode(&call.address),
+                        "error" => e.to_string());
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if results.is_empty() {
+            return;
+        }
+
+        // Don't block handler execution on writing to the cache. A handler
+        // that runs before the write is done makes the call itself
+        let logger = logger.clone();
+        let _ = graph::spawn_blocking_allow_panic(move || {
+            for (call, call_data, output) in results {
+                if let Err(e) = cache.set_call(call.address, &call_data, call.block_ptr, &output.0)
+                {
+                    error!(logger, "call cache set error"; "error" => e.to_string());
+                }
+            }
+        });
+    }
 }
 
 #[async_trait]
@@ -1072,9 +1517,20 @@ impl EthereumAdapterTrait for EthereumAdapter {
             })));
         }
         let hashes: Vec<_> = block.transactions.iter().map(|txn| txn.hash).collect();
+        let adapter = self.cheap_clone();
+        let block_logger = logger.clone();
+        let receipts_root = block.receipts_root;
+        let transaction_hashes = hashes.clone();
         let receipts_future = if ENV_VARS.fetch_receipts_in_batches {
             // Deprecated batching retrieval of transaction receipts.
             fetch_transaction_receipts_in_batch_with_retry(web3, hashes, block_hash, logger).boxed()
+        } else if self.batch_size > 1 {
+            let eth = self.cheap_clone();
+            async move {
+                eth.fetch_transaction_receipts_batched(&logger, hashes, block_hash)
+                    .await
+            }
+            .boxed()
         } else {
             let hash_stream = graph::tokio_stream::iter(hashes);
             let receipt_stream = graph::tokio_stream::StreamExt::map(hash_stream, move |tx_hash| {
@@ -1092,6 +1548,23 @@ impl EthereumAdapterTrait for EthereumAdapter {
             .boxed()
         };
 
+        // Fetch all receipts of the block at once if the provider supports
+        // that, and fall back to fetching them per transaction
+        let receipts_future = async move {
+            let receipts = adapter
+                .block_receipts(
+                    &block_logger,
+                    block_hash,
+                    receipts_root,
+                    &transaction_hashes,
+                )
+                .await;
+            match receipts {
+                Some(receipts) => Ok(receipts),
+                None => receipts_future.await,
+            }
+        };
+
         let block_future =
             futures03::TryFutureExt::map_ok(receipts_future, move |transaction_receipts| {
                 EthereumBlock {
@@ -1335,6 +1808,7 @@ pub(crate) async fn blocks_with_triggers(
         let logs_future = get_logs_and_transactions(
             eth.clone(),
             &logger,
+            chain_store.cheap_clone(),
             subgraph_metrics.clone(),
             from,
             to,
@@ -1357,18 +1831,31 @@ pub(crate) async fn blocks_with_triggers(
     }
 
     // Scan for Blocks
-    if filter.block.trigger_every_block {
-        let block_future = adapter
-            .block_range_to_ptrs(logger.clone(), from, to)
+    if filter.block.trigger_every_block || !filter.block.polling_intervals.is_empty() {
+        // Blocks that only `polling` or `once` handlers are interested in
+        // are known from their number alone; all other blocks are skipped
+        let block_filter = filter.block.clone();
+        let ptrs_future = if block_filter.trigger_every_block {
+            adapter.block_range_to_ptrs(logger.clone(), from, to)
+        } else {
+            let block_nums = (from..=to)
+                .filter(|number| {
+                    block_filter.matches_polling(*number) || block_filter.matches_once(*number)
+                })
+                .collect();
+            adapter.block_numbers_to_ptrs(logger.clone(), block_nums)
+        };
+        let block_future = ptrs_future
             .map(move |ptrs| {
-                ptrs.into_iter()
-                    .map(|ptr| EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Every))
+                ptrs.iter()
+                    .flat_map(|ptr| block_filter.block_number_triggers(ptr))
                     .collect()
             })
             .compat()
             .boxed();
         trigger_futs.push(block_future)
-    } else if !filter.block.contract_addresses.is_empty() {
+    }
+    if !filter.block.trigger_every_block && !filter.block.contract_addresses.is_empty() {
         // To determine which blocks include a call to addresses
         // in the block filter, transform the `block_filter` into
         // a `call_filter` and run `blocks_with_calls`
@@ -1573,7 +2060,6 @@ pub(crate) fn parse_block_triggers(
     }
 
     let block_ptr = BlockPtr::from(&block.ethereum_block);
-    let trigger_every_block = block_filter.trigger_every_block;
     let call_filter = EthereumCallFilter::from(block_filter);
     let block_ptr2 = block_ptr.cheap_clone();
     let mut triggers = match &block.calls {
@@ -1589,12 +2075,7 @@ pub(crate) fn parse_block_triggers(
             .collect::<Vec<EthereumTrigger>>(),
         None => vec![],
     };
-    if trigger_every_block {
-        triggers.push(EthereumTrigger::Block(
-            block_ptr,
-            EthereumBlockTriggerType::Every,
-        ));
-    }
+    triggers.extend(block_filter.block_number_triggers(&block_ptr));
     triggers
 }
 
@@ -1824,6 +2305,33 @@ async fn fetch_transaction_receipt_with_retry(
         .map(Arc::new)
 }
 
+/// Check that `receipts` are the receipts for `transaction_hashes`, in
+/// that order, in the block `block_hash`
+fn check_block_receipts(
+    receipts: Vec<TransactionReceipt>,
+    block_hash: H256,
+    transaction_hashes: &[H256],
+) -> Result<Vec<TransactionReceipt>, Error> {
+    ensure!(
+        receipts.len() == transaction_hashes.len(),
+        "expected {} receipts but got {}",
+        transaction_hashes.len(),
+        receipts.len()
+    );
+    for (index, (receipt, transaction_hash)) in receipts.iter().zip(transaction_hashes).enumerate()
+    {
+        ensure!(
+            receipt.transaction_hash == *transaction_hash
+                && receipt.transaction_index.as_usize() == index
+                && receipt.block_hash == Some(block_hash),
+            "receipt {} does not belong to transaction {:x}",
+            index,
+            transaction_hash
+        );
+    }
+    Ok(receipts)
+}
+
 fn resolve_transaction_receipt(
     transaction_receipt: Option<TransactionReceipt>,
     transaction_hash: H256,
@@ -1877,6 +2385,7 @@ fn resolve_transaction_receipt(
 async fn get_logs_and_transactions(
     adapter: Arc<EthereumAdapter>,
     logger: &Logger,
+    chain_store: Arc<dyn ChainStore>,
     subgraph_metrics: Arc<SubgraphEthRpcMetrics>,
     from: BlockNumber,
     to: BlockNumber,
@@ -1920,6 +2429,7 @@ async fn get_logs_and_transactions(
     // Obtain receipts externally
     let transaction_receipts_by_hash = get_transaction_receipts_for_transaction_hashes(
         &adapter,
+        chain_store,
         &transaction_hashes_by_block,
         logger.cheap_clone(),
     )
@@ -1941,6 +2451,7 @@ async fn get_logs_and_transactions(
 /// Tries to retrive all transaction receipts for a set of transaction hashes.
 async fn get_transaction_receipts_for_transaction_hashes(
     adapter: &EthereumAdapter,
+    chain_store: Arc<dyn ChainStore>,
     transaction_hashes_by_block: &HashMap<H256, HashSet<H256>>,
     logger: Logger,
 ) -> Result<HashMap<H256, Arc<TransactionReceipt>>, anyhow::Error> {
@@ -1957,26 +2468,48 @@ async fn get_transaction_receipts_for_transaction_hashes(
     // later use this to check if we have collected the receipts from all required transactions.
     let mut unique_transaction_hashes: HashSet<&H256> = HashSet::new();
 
+    // Fetching all receipts of a block at once needs the block to check the
+    // receipts against. The blocks are loaded through the block cache so
+    // that they are not loaded again when the triggers are turned into
+    // blocks
+    let blocks: HashMap<H256, Arc<LightEthereumBlock>> = match adapter.block_receipts_method() {
+        Some(_) => adapter
+            .load_blocks(
+                logger.cheap_clone(),
+                chain_store,
+                transaction_hashes_by_block.keys().cloned().collect(),
+            )
+            .collect()
+            .compat()
+            .await
+            .unwrap_or_else(|e| {
+                debug!(logger, "Fetching receipts per transaction instead of per block";
+                    "error" => e.to_string());
+                Vec::new()
+            })
+            .into_iter()
+            .filter_map(|block| block.hash.map(|hash| (hash, block)))
+            .collect(),
+        None => HashMap::new(),
+    };
+
     // Request transaction receipts concurrently
     let receipt_futures = FuturesUnordered::new();
 
-    let web3 = Arc::clone(&adapter.web3);
     for (block_hash, transaction_hashes) in transaction_hashes_by_block {
-        for transaction_hash in transaction_hashes {
-            unique_transaction_hashes.insert(transaction_hash);
-            let receipt_future = fetch_transaction_receipt_with_retry(
-                web3.cheap_clone(),
-                *transaction_hash,
-                *block_hash,
-                logger.cheap_clone(),
-            );
-            receipt_futures.push(receipt_future)
-        }
+        unique_transaction_hashes.extend(transaction_hashes);
+        let receipt_future = adapter.transaction_receipts_in_block(
+            &logger,
+            blocks.get(block_hash).map(|block| block.as_ref()),
+            *block_hash,
+            transaction_hashes,
+        );
+        receipt_futures.push(receipt_future)
     }
-    let receipts: Vec<_> = receipt_futures.try_collect().await?;
+    let receipts: Vec<Vec<_>> = receipt_futures.try_collect().await?;
 
     // Build a map between transaction hashes and their receipts
-    for receipt in receipts.into_iter() {
+    for receipt in receipts.into_iter().flatten() {
         if !unique_transaction_hashes.remove(&receipt.transaction_hash) {
             bail!("Received a receipt for a different transaction hash")
         }
@@ -1999,16 +2532,39 @@ async fn get_transaction_receipts_for_transaction_hashes(
 #[cfg(test)]
 mod tests {
 
+    use crate::adapter::{EthereumAdapter as EthereumAdapterTrait, ProviderEthRpcMetrics};
+    use crate::transport::Transport;
     use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger};
+    use crate::EthereumContractCall;
 
-    use super::{parse_block_triggers, EthereumBlock, EthereumBlockFilter, EthereumBlockWithCalls};
+    use super::{
+        parse_block_triggers, BlockReceiptsMethod, EthereumAdapter, EthereumBlock,
+        EthereumBlockFilter, EthereumBlockWithCalls, MAX_BLOCK_RECEIPTS_MISMATCHES,
+    };
+    use crate::receipts::receipts_root;
     use graph::blockchain::BlockPtr;
     use graph::prelude::ethabi::ethereum_types::U64;
-    use graph::prelude::web3::types::{Address, Block, Bytes, H256};
-    use graph::prelude::EthereumCall;
-    use std::collections::HashSet;
+    use graph::prelude::ethabi::{self, Contract, Token};
+    use graph::prelude::serde_json::{self as json, json};
+    use graph::prelude::tokio::{
+        self,
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+    use graph::prelude::web3::api::Web3;
+    use graph::prelude::web3::types::{Address, Block, Bytes, H256, U256};
+    use graph::prelude::{
+        o, slog, CachedEthereumCall, CheapClone, Error, EthereumCall, EthereumCallCache,
+        Future01CompatExt, Logger,
+    };
+    use graph::prometheus::Registry;
+    use graph_core::MetricsRegistry;
+    use http::HeaderMap;
+    use std::collections::{HashMap, HashSet};
     use std::iter::FromIterator;
-    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn parse_block_triggers_every_block() {
@@ -2037,6 +2593,7 @@ mod tests {
                 &EthereumBlockFilter {
                     contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
                     trigger_every_block: true,
+                    polling_intervals: HashSet::new(),
                 },
                 &block
             ),
@@ -2068,6 +2625,7 @@ mod tests {
                 &EthereumBlockFilter {
                     contract_addresses: HashSet::from_iter(vec![(1, address(1))]),
                     trigger_every_block: false,
+                    polling_intervals: HashSet::new(),
                 },
                 &block
             ),
@@ -2102,6 +2660,7 @@ mod tests {
                 &EthereumBlockFilter {
                     contract_addresses: HashSet::from_iter(vec![(1, address(4))]),
                     trigger_every_block: false,
+                    polling_intervals: HashSet::new(),
                 },
                 &block
             ),
@@ -2109,6 +2668,273 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_block_triggers_polling_and_once() {
+        let block = |number: u8| EthereumBlockWithCalls {
+            ethereum_block: EthereumBlock {
+                block: Arc::new(Block {
+                    hash: Some(hash(number)),
+                    number: Some(U64::from(number)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            calls: None,
+        };
+        let filter = EthereumBlockFilter {
+            contract_addresses: HashSet::new(),
+            trigger_every_block: false,
+            polling_intervals: HashSet::from_iter(vec![(4, 3), (10, 0)]),
+        };
+
+        assert_eq!(
+            vec![
+                EthereumTrigger::Block(
+                    BlockPtr::from((hash(10), 10)),
+                    EthereumBlockTriggerType::Once
+                ),
+                EthereumTrigger::Block(
+                    BlockPtr::from((hash(10), 10)),
+                    EthereumBlockTriggerType::Every
+                )
+            ],
+            parse_block_triggers(&filter, &block(10)),
+            "block 10 is the start block of the once handler and a polling block"
+        );
+        assert_eq!(
+            vec![EthereumTrigger::Block(
+                BlockPtr::from((hash(7), 7)),
+                EthereumBlockTriggerType::Every
+            )],
+            parse_block_triggers(&filter, &block(7)),
+            "block 7 is a polling block"
+        );
+        assert_eq!(
+            Vec::<EthereumTrigger>::new(),
+            parse_block_triggers(&filter, &block(1)),
+            "block 1 is before the start of the polling handler"
+        );
+        assert_eq!(
+            Vec::<EthereumTrigger>::new(),
+            parse_block_triggers(&filter, &block(11)),
+            "block 11 is neither a polling block nor the once block"
+        );
+    }
+
+    /// An in-memory call cache
+    #[derive(Default)]
+    struct CallCache(Mutex<HashMap<(Address, Vec<u8>, BlockPtr), Vec<u8>>>);
+
+    impl EthereumCallCache for CallCache {
+        fn get_call(
+            &self,
+            contract_address: Address,
+            encoded_call: &[u8],
+            block: BlockPtr,
+        ) -> Result<Option<Vec<u8>>, Error> {
+            let calls = self.0.lock().unwrap();
+            Ok(calls
+                .get(&(contract_address, encoded_call.to_vec(), block))
+                .cloned())
+        }
+
+        fn get_calls_in_block(&self, _: BlockPtr) -> Result<Vec<CachedEthereumCall>, Error> {
+            unimplemented!()
+        }
+
+        fn set_call(
+            &self,
+            contract_address: Address,
+            encoded_call: &[u8],
+            block: BlockPtr,
+            return_value: &[u8],
+        ) -> Result<(), Error> {
+            let mut calls = self.0.lock().unwrap();
+            calls.insert(
+                (contract_address, encoded_call.to_vec(), block),
+                return_value.to_vec(),
+            );
+            Ok(())
+        }
+    }
+
+    /// An adapter for a provider that answers each JSON-RPC request, on its
+    /// own or in a batch, with the result `respond` returns for it
+    async fn stand_in(
+        respond: impl Fn(&json::Value) -> json::Value + Send + 'static,
+    ) -> EthereumAdapter {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut socket = BufReader::new(socket);
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    socket.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        len = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                socket.read_exact(&mut body).await.unwrap();
+
+                let response = |request: &json::Value| {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "result": respond(request),
+                    })
+                };
+                let body = match json::from_slice(&body).unwrap() {
+                    json::Value::Array(requests) => {
+                        json::Value::Array(requests.iter().map(response).collect())
+                    }
+                    request => response(&request),
+                }
+                .to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let logger = Logger::root(slog::Discard, o!());
+        let registry = Arc::new(MetricsRegistry::new(
+            logger.clone(),
+            Arc::new(Registry::new()),
+        ));
+        EthereumAdapter {
+            logger,
+            url_hostname: Arc::new("127.0.0.1".to_string()),
+            provider: "stand-in".to_string(),
+            web3: Arc::new(Web3::new(Transport::new_rpc(
+                url.parse().unwrap(),
+                HeaderMap::new(),
+            ))),
+            metrics: Arc::new(ProviderEthRpcMetrics::new(registry)),
+            supports_eip_1898: true,
+            batch_size: 10,
+            block_receipts: None,
+            block_receipts_mismatches: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    #[tokio::test]
+    async fn declared_calls_are_answered_from_cache() {
+        const ABI: &str = r#"[{
+            "type": "function",
+            "name": "get",
+            "inputs": [],
+            "outputs": [{ "name": "", "type": "uint256" }],
+            "stateMutability": "view"
+        }]"#;
+        let contract = Contract::load(ABI.as_bytes()).unwrap();
+        let call = |id| EthereumContractCall {
+            address: address(id),
+            block_ptr: BlockPtr::from((hash(1), 1)),
+            function: contract.function("get").unwrap().clone(),
+            args: vec![],
+        };
+        let called = Arc::new(Mutex::new(Vec::new()));
+        let calls = called.cheap_clone();
+        let adapter = stand_in(move |request| {
+            let to = request["params"][0]["to"].as_str().unwrap().to_string();
+            calls.lock().unwrap().push(to);
+            json!(format!("0x{:064x}", 1))
+        })
+        .await;
+        let logger = adapter.logger.clone();
+        let cache = Arc::new(CallCache::default());
+
+        // The call to address 1 is already cached and is not made again
+        let call_data = call(1).function.encode_input(&[]).unwrap();
+        let output = ethabi::encode(&[Token::Uint(U256::from(7))]);
+        cache
+            .set_call(address(1), &call_data, call(1).block_ptr, &output)
+            .unwrap();
+        adapter
+            .prefetch_calls(&logger, vec![call(1), call(2)], cache.cheap_clone())
+            .await;
+        assert_eq!(vec![format!("{:#x}", address(2))], *called.lock().unwrap());
+
+        // The prefetched result is written to the cache in the background
+        for _ in 0..100 {
+            let cached = cache
+                .get_call(address(2), &call_data, call(2).block_ptr)
+                .unwrap();
+            if cached.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The handler's calls are answered from the cache
+        let cache: Arc<dyn EthereumCallCache> = cache;
+        for (id, value) in [(1, 7), (2, 1)] {
+            let tokens = adapter
+                .contract_call(&logger, call(id), cache.cheap_clone())
+                .compat()
+                .await
+                .unwrap();
+            assert_eq!(vec![Token::Uint(U256::from(value))], tokens);
+        }
+        assert_eq!(1, called.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn block_receipts_are_turned_off_after_mismatches() {
+        let mut adapter = stand_in(|request| {
+            assert_eq!("eth_getBlockReceipts", request["method"]);
+            json!([])
+        })
+        .await;
+        adapter.block_receipts = Some(BlockReceiptsMethod::Eth);
+        let logger = adapter.logger.clone();
+
+        // An empty block has the receipts root of the empty trie
+        for _ in 0..MAX_BLOCK_RECEIPTS_MISMATCHES {
+            assert!(adapter.supports_block_receipts());
+            let receipts = adapter.block_receipts(&logger, hash(1), hash(2), &[]).await;
+            assert!(receipts.is_none());
+        }
+        assert!(!adapter.supports_block_receipts());
+        assert!(adapter
+            .block_receipts(&logger, hash(1), receipts_root(&[]), &[])
+            .await
+            .is_none());
+
+        // A match resets the count of mismatches
+        let adapter = EthereumAdapter {
+            block_receipts_mismatches: Arc::new(AtomicUsize::new(0)),
+            ..adapter
+        };
+        for _ in 1..MAX_BLOCK_RECEIPTS_MISMATCHES {
+            assert!(adapter
+                .block_receipts(&logger, hash(1), hash(2), &[])
+                .await
+                .is_none());
+        }
+        let receipts = adapter
+            .block_receipts(&logger, hash(1), receipts_root(&[]), &[])
+            .await;
+        assert_eq!(Some(0), receipts.map(|receipts| receipts.len()));
+        assert!(adapter
+            .block_receipts(&logger, hash(1), hash(2), &[])
+            .await
+            .is_none());
+        assert!(adapter.supports_block_receipts());
+    }
+
     fn address(id: u64) -> Address {
         Address::from_low_u64_be(id)
     }
@@ -5,6 +5,7 @@ mod data_source;
 mod env;
 mod ethereum_adapter;
 mod ingestor;
+mod receipts;
 pub mod runtime;
 mod transport;
 
@@ -15,7 +16,9 @@ pub use self::transport::Transport;
 pub use env::ENV_VARS;
 
 // ETHDEP: These concrete types should probably not be exposed.
-pub use data_source::{DataSource, DataSourceTemplate, Mapping, MappingABI, TemplateSource};
+pub use data_source::{
+    CallArg, CallDecl, DataSource, DataSourceTemplate, Mapping, MappingABI, TemplateSource,
+};
 
 pub mod chain;
 
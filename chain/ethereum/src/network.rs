@@ -155,22 +155,27 @@ mod tests {
         let archive = NodeCapabilities {
             archive: true,
             traces: false,
+            block_receipts: false,
         };
         let traces = NodeCapabilities {
             archive: false,
             traces: true,
+            block_receipts: false,
         };
         let archive_traces = NodeCapabilities {
             archive: true,
             traces: true,
+            block_receipts: false,
         };
         let full = NodeCapabilities {
             archive: false,
             traces: false,
+            block_receipts: false,
         };
         let full_traces = NodeCapabilities {
             archive: false,
             traces: true,
+            block_receipts: false,
         };
 
         // Test all real combinations of capability comparisons
@@ -203,5 +208,22 @@ mod tests {
         assert_eq!(false, &full_traces >= &archive_traces);
         assert_eq!(true, &full_traces >= &full);
         assert_eq!(true, &full_traces >= &full_traces);
+
+        let block_receipts = NodeCapabilities {
+            block_receipts: true,
+            ..full
+        };
+        let archive_block_receipts = NodeCapabilities {
+            block_receipts: true,
+            ..archive
+        };
+
+        assert_eq!(true, &block_receipts >= &full);
+        assert_eq!(false, &full >= &block_receipts);
+        assert_eq!(false, &block_receipts >= &archive);
+        assert_eq!(false, &archive >= &block_receipts);
+        assert_eq!(true, &archive_block_receipts >= &archive);
+        assert_eq!(true, &archive_block_receipts >= &block_receipts);
+        assert_eq!(false, &archive_block_receipts >= &archive_traces);
     }
 }
@@ -0,0 +1,343 @@
+//! Computing the receipts root of a block from its transaction receipts so
+//! that receipts a provider returns for a whole block can be checked
+//! against the block header. This follows the RLP encoding and the Merkle
+//! Patricia trie from the Ethereum yellow paper, and EIP-2718 for the
+//! encoding of typed receipts.
+
+use graph::prelude::web3::types::{Log, TransactionReceipt, H256, U256};
+use tiny_keccak::keccak256;
+
+/// The receipts root for `receipts`, which must be all the receipts of a
+/// block in the order of the block's transactions
+pub(crate) fn receipts_root(receipts: &[TransactionReceipt]) -> H256 {
+    let entries = receipts
+        .iter()
+        .enumerate()
+        .map(|(index, receipt)| {
+            (
+                encode_uint(&(index as u64).to_be_bytes()),
+                encode_receipt(receipt),
+            )
+        })
+        .collect();
+    trie_root(entries)
+}
+
+fn encode_receipt(receipt: &TransactionReceipt) -> Vec<u8> {
+    let outcome = match (receipt.status, receipt.root) {
+        // Receipts from before Byzantium have a state root instead of a
+        // status
+        (None, Some(root)) => encode_bytes(root.as_bytes()),
+        (status, _) => encode_uint(&status.unwrap_or_default().as_u64().to_be_bytes()),
+    };
+    let logs: Vec<u8> = receipt.logs.iter().flat_map(encode_log).collect();
+    let encoded = encode_list(
+        &[
+            outcome,
+            encode_u256(receipt.cumulative_gas_used),
+            encode_bytes(receipt.logs_bloom.as_bytes()),
+            encode_list(&logs),
+        ]
+        .concat(),
+    );
+
+    match receipt.transaction_type.map(|tx_type| tx_type.as_u64()) {
+        None | Some(0) => encoded,
+        Some(tx_type) => [vec![tx_type as u8], encoded].concat(),
+    }
+}
+
+fn encode_log(log: &Log) -> Vec<u8> {
+    let topics: Vec<u8> = log
+        .topics
+        .iter()
+        .flat_map(|topic| encode_bytes(topic.as_bytes()))
+        .collect();
+    encode_list(
+        &[
+            encode_bytes(log.address.as_bytes()),
+            encode_list(&topics),
+            encode_bytes(&log.data.0),
+        ]
+        .concat(),
+    )
+}
+
+fn encode_u256(value: U256) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    encode_uint(&bytes)
+}
+
+/// RLP encoding of the big-endian unsigned integer `bytes`
+fn encode_uint(bytes: &[u8]) -> Vec<u8> {
+    let start = bytes
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(bytes.len());
+    encode_bytes(&bytes[start..])
+}
+
+/// RLP encoding of a byte string
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    match bytes {
+        [byte] if *byte < 0x80 => vec![*byte],
+        _ => [encode_length(bytes.len(), 0x80), bytes.to_vec()].concat(),
+    }
+}
+
+/// RLP encoding of a list whose items are already encoded in `payload`
+fn encode_list(payload: &[u8]) -> Vec<u8> {
+    [encode_length(payload.len(), 0xc0), payload.to_vec()].concat()
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let bytes = len.to_be_bytes();
+    let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(0);
+    [
+        vec![offset + 55 + (bytes.len() - start) as u8],
+        bytes[start..].to_vec(),
+    ]
+    .concat()
+}
+
+/// The root hash of the Merkle Patricia trie that holds `entries`
+fn trie_root(entries: Vec<(Vec<u8>, Vec<u8>)>) -> H256 {
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .map(|(key, value)| (nibbles(&key), value))
+        .collect();
+    entries.sort();
+
+    let root = match entries.is_empty() {
+        true => encode_bytes(&[]),
+        false => encode_node(&entries, 0),
+    };
+    H256(keccak256(&root))
+}
+
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// RLP encoding of the trie node that holds `entries`. The entries must be
+/// sorted by key, and their keys must all start with the same `depth`
+/// nibbles
+fn encode_node(entries: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Vec<u8> {
+    if let [(key, value)] = entries {
+        return encode_list(
+            &[
+                encode_bytes(&hex_prefix(&key[depth..], true)),
+                encode_bytes(value),
+            ]
+            .concat(),
+        );
+    }
+
+    // Since the entries are sorted, a prefix that the first and the last
+    // key share is shared by all keys
+    let first = &entries[0].0;
+    let last = &entries[entries.len() - 1].0;
+    let shared = first[depth..]
+        .iter()
+        .zip(&last[depth..])
+        .take_while(|(a, b)| a == b)
+        .count();
+    if shared > 0 {
+        let child = encode_node(entries, depth + shared);
+        return encode_list(
+            &[
+                encode_bytes(&hex_prefix(&first[depth..depth + shared], false)),
+                node_reference(child),
+            ]
+            .concat(),
+        );
+    }
+
+    // A key that ends at this node sorts first, and its value is stored in
+    // the node itself
+    let (value, entries) = match entries.split_first() {
+        Some(((key, value), rest)) if key.len() == depth => (value.as_slice(), rest),
+        _ => (&[][..], entries),
+    };
+    let mut payload = Vec::new();
+    for nibble in 0..16 {
+        let start = entries.partition_point(|(key, _)| key[depth] < nibble);
+        let end = entries.partition_point(|(key, _)| key[depth] <= nibble);
+        match start == end {
+            true => payload.extend(encode_bytes(&[])),
+            false => payload.extend(node_reference(encode_node(&entries[start..end], depth + 1))),
+        }
+    }
+    payload.extend(encode_bytes(value));
+    encode_list(&payload)
+}
+
+/// Child nodes whose encoding is shorter than a hash are embedded into
+/// their parent, all others are referenced by their hash
+fn node_reference(node: Vec<u8>) -> Vec<u8> {
+    match node.len() < 32 {
+        true => node,
+        false => encode_bytes(&keccak256(&node)),
+    }
+}
+
+/// The hex-prefix encoding of a path of `nibbles`
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let (first, rest) = match nibbles.len() % 2 {
+        1 => (((flag + 1) << 4) | nibbles[0], &nibbles[1..]),
+        _ => (flag << 4, nibbles),
+    };
+    std::iter::once(first)
+        .chain(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::web3::types::{TransactionReceipt, H256};
+    use std::str::FromStr;
+
+    use super::{encode_receipt, receipts_root, trie_root};
+
+    fn root(entries: &[(&str, &str)]) -> H256 {
+        trie_root(
+            entries
+                .iter()
+                .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()))
+                .collect(),
+        )
+    }
+
+    fn hash(s: &str) -> H256 {
+        H256::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn trie_roots() {
+        // Test vectors from the `trieanyorder` tests of ethereum/tests
+        assert_eq!(
+            hash("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"),
+            root(&[])
+        );
+        assert_eq!(
+            hash("17beaa1648bafa633cda809c90c04af50fc8aed3cb40d16efbddee6fdf63c4c3"),
+            root(&[("foo", "bar"), ("food", "bass")])
+        );
+        assert_eq!(
+            hash("3f67c7a47520f79faa29255d2d3c084a7a6df0453116ed7232ff10277a8be68b"),
+            root(&[("be", "e"), ("dog", "puppy"), ("bed", "d")])
+        );
+        assert_eq!(
+            hash("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"),
+            root(&[
+                ("doe", "reindeer"),
+                ("dog", "puppy"),
+                ("dogglesworth", "cat")
+            ])
+        );
+        assert_eq!(
+            hash("5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"),
+            root(&[
+                ("do", "verb"),
+                ("horse", "stallion"),
+                ("doge", "coin"),
+                ("dog", "puppy")
+            ])
+        );
+    }
+
+    fn receipt(gas: u64) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: H256::zero(),
+            transaction_index: 0.into(),
+            block_hash: None,
+            block_number: None,
+            cumulative_gas_used: gas.into(),
+            gas_used: None,
+            contract_address: None,
+            logs: Vec::new(),
+            status: Some(1.into()),
+            root: None,
+            logs_bloom: Default::default(),
+            from: Default::default(),
+            to: None,
+            transaction_type: None,
+            effective_gas_price: None,
+        }
+    }
+
+    #[test]
+    fn receipt_encodings() {
+        // The RLP list header, status or state root, cumulative gas of
+        // 21000, and an empty bloom and logs
+        let encoding = |header: &str, outcome: &str| {
+            hex::decode(format!(
+                "{}{}825208b90100{}c0",
+                header,
+                outcome,
+                "00".repeat(256)
+            ))
+            .unwrap()
+        };
+
+        // Legacy receipts from after Byzantium have a status
+        let legacy = receipt(21000);
+        assert_eq!(encoding("f90108", "01"), encode_receipt(&legacy));
+
+        let failed = TransactionReceipt {
+            status: Some(0.into()),
+            ..receipt(21000)
+        };
+        assert_eq!(encoding("f90108", "80"), encode_receipt(&failed));
+
+        // Typed receipts are prefixed with the transaction type, except for
+        // legacy transactions whose type is 0
+        let typed = TransactionReceipt {
+            transaction_type: Some(2.into()),
+            ..receipt(21000)
+        };
+        assert_eq!(
+            [vec![2], encoding("f90108", "01")].concat(),
+            encode_receipt(&typed)
+        );
+        let untyped = TransactionReceipt {
+            transaction_type: Some(0.into()),
+            ..receipt(21000)
+        };
+        assert_eq!(encoding("f90108", "01"), encode_receipt(&untyped));
+
+        // Receipts from before Byzantium have the state root instead of a
+        // status
+        let root = hash("abababababababababababababababababababababababababababababababab");
+        let pre_byzantium = TransactionReceipt {
+            status: None,
+            root: Some(root),
+            ..receipt(21000)
+        };
+        assert_eq!(
+            encoding("f90128", &format!("a0{:x}", root)),
+            encode_receipt(&pre_byzantium)
+        );
+    }
+
+    #[test]
+    fn receipts_root_depends_on_order() {
+        let receipts = vec![receipt(21000), receipt(42000)];
+        let reversed = vec![receipt(42000), receipt(21000)];
+
+        assert_eq!(receipts_root(&receipts), receipts_root(&receipts));
+        assert_ne!(receipts_root(&receipts), receipts_root(&reversed));
+        assert_eq!(
+            hash("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"),
+            receipts_root(&[])
+        );
+    }
+}
@@ -10,15 +10,16 @@ use blockchain::HostFn;
 use graph::runtime::gas::Gas;
 use graph::runtime::{AscIndexId, IndexForAscTypeId};
 use graph::{
-    blockchain::{self, BlockPtr, HostFnCtx},
+    blockchain::{self, BlockPtr, HostFnCtx, TriggerWithHandler},
     cheap_clone::CheapClone,
     prelude::{
+        async_trait,
         ethabi::{self, Address, Token},
         EthereumCallCache, Future01CompatExt,
     },
     runtime::{asc_get, asc_new, AscPtr, HostExportError},
     semver::Version,
-    slog::{info, trace, Logger},
+    slog::{debug, info, trace, Logger},
 };
 use graph_runtime_wasm::asc_abi::class::{AscEnumArray, EthereumValueKind};
 
@@ -39,6 +40,7 @@ pub struct RuntimeAdapter {
     pub call_cache: Arc<dyn EthereumCallCache>,
 }
 
+#[async_trait]
 impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
     fn host_fns(&self, ds: &DataSource) -> Result<Vec<HostFn>, Error> {
         let abis = ds.mapping.abis.clone();
@@ -48,6 +50,7 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
             .cheapest_with(&NodeCapabilities {
                 archive: ds.mapping.requires_archive()?,
                 traces: false,
+                block_receipts: false,
             })?
             .cheap_clone();
 
@@ -61,6 +64,55 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
 
         Ok(vec![ethereum_call])
     }
+
+    fn prefetches(&self, ds: &DataSource) -> bool {
+        ds.mapping
+            .event_handlers
+            .iter()
+            .any(|handler| !handler.calls.is_empty())
+    }
+
+    async fn prefetch(
+        &self,
+        logger: &Logger,
+        triggers: &[(&DataSource, &TriggerWithHandler<Chain>)],
+    ) {
+        let mut archive = false;
+        let mut calls = Vec::new();
+        for (ds, trigger) in triggers {
+            let declared = ds.declared_calls(logger, trigger);
+            if declared.is_empty() {
+                continue;
+            }
+            match ds.mapping.requires_archive() {
+                Ok(requires_archive) => archive |= requires_archive,
+                Err(e) => {
+                    debug!(logger, "Not prefetching declared calls"; "error" => e.to_string());
+                    continue;
+                }
+            }
+            calls.extend(declared);
+        }
+        if calls.is_empty() {
+            return;
+        }
+
+        let capabilities = NodeCapabilities {
+            archive,
+            traces: false,
+            block_receipts: false,
+        };
+        let eth_adapter = match self.eth_adapters.cheapest_with(&capabilities) {
+            Ok(eth_adapter) => eth_adapter,
+            Err(e) => {
+                debug!(logger, "Not prefetching declared calls"; "error" => e.to_string());
+                return;
+            }
+        };
+        eth_adapter
+            .prefetch_calls(logger, calls, self.call_cache.cheap_clone())
+            .await
+    }
 }
 
 /// function ethereum.call(call: SmartContractCall): Array<Token> | null
@@ -1,17 +1,25 @@
-use jsonrpc_core::types::Call;
+use jsonrpc_core::types::{Call, Id, Output, Request};
 use jsonrpc_core::Value;
 
+use web3::error::TransportError;
 use web3::transports::{http, ipc, ws};
 use web3::RequestId;
 
 use graph::prelude::*;
 use graph::url::Url;
+use std::collections::HashMap;
 use std::future::Future;
 
 /// Abstraction over the different web3 transports.
 #[derive(Clone, Debug)]
 pub enum Transport {
-    RPC(http::Http),
+    RPC {
+        http: http::Http,
+        /// The client and URL of `http`, used to send batches ourselves so
+        /// that we can check the ids of the responses
+        client: reqwest::Client,
+        url: Url,
+    },
     IPC(ipc::Ipc),
     WS(ws::WebSocket),
 }
@@ -44,8 +52,130 @@ impl Transport {
             .default_headers(headers)
             .build()
             .unwrap();
-        Transport::RPC(http::Http::with_client(client, rpc))
+        Transport::RPC {
+            http: http::Http::with_client(client.clone(), rpc.clone()),
+            client,
+            url: rpc,
+        }
+    }
+
+    /// Sends a `method` request for each entry in `params` as one JSON-RPC
+    /// batch and returns one result per request, in the order of `params`.
+    /// Responses are matched to requests by their id, and a response whose
+    /// ids do not match the requests one to one is rejected. If the batch as
+    /// a whole fails, every request gets that error.
+    pub async fn batch_request(
+        &self,
+        method: &str,
+        params: Vec<Vec<Value>>,
+    ) -> Vec<Result<Value, web3::error::Error>> {
+        use web3::{BatchTransport, Transport as _};
+
+        let count = params.len();
+        if count == 0 {
+            // An empty batch is not valid in JSON-RPC
+            return Vec::new();
+        }
+
+        let (ids, calls): (Vec<_>, Vec<_>) = params
+            .into_iter()
+            .map(|params| self.prepare(method, params))
+            .unzip();
+        let results = match self {
+            Transport::RPC { client, url, .. } => post_batch(client, url, calls)
+                .await
+                .and_then(|outputs| match_outputs(method, &ids, outputs)),
+            // The IPC transport routes every response to its request by id
+            Transport::IPC(ipc) => ipc.send_batch(ids.into_iter().zip(calls)).await,
+            // The websocket transport does not expose the ids of the
+            // responses to a batch. Requests on the same websocket share
+            // one connection anyway, so we send them one by one
+            Transport::WS(ws) => {
+                let requests = ids
+                    .into_iter()
+                    .zip(calls)
+                    .map(|(id, call)| ws.send(id, call));
+                return futures03::future::join_all(requests).await;
+            }
+        };
+        match results {
+            Ok(results) => results,
+            Err(e) => vec![Err(e); count],
+        }
+    }
+}
+
+/// Send `calls` as one JSON-RPC batch over HTTP and return the responses as
+/// they were received
+async fn post_batch(
+    client: &reqwest::Client,
+    url: &Url,
+    calls: Vec<Call>,
+) -> Result<Vec<Output>, web3::error::Error> {
+    let transport_error = |msg: String| web3::error::Error::Transport(TransportError::Message(msg));
+
+    let response = client
+        .post(url.clone())
+        .json(&Request::Batch(calls))
+        .send()
+        .await
+        .map_err(|e| transport_error(format!("failed to send request: {}", e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(web3::error::Error::Transport(TransportError::Code(
+            status.as_u16(),
+        )));
     }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| transport_error(format!("failed to read response: {}", e)))?;
+    // Going through `Value` is needed since `Output` is an untagged enum,
+    // which does not deserialize with `arbitrary_precision` otherwise
+    serde_json::from_slice(&body)
+        .and_then(serde_json::from_value)
+        .map_err(|e| transport_error(format!("failed to deserialize response: {}", e)))
+}
+
+/// Match the `outputs` of a batch to the requests with `ids`. Every request
+/// must have exactly one response; responses with missing, duplicate or
+/// unknown ids make the whole batch invalid
+fn match_outputs(
+    method: &str,
+    ids: &[RequestId],
+    outputs: Vec<Output>,
+) -> Result<Vec<Result<Value, web3::error::Error>>, web3::error::Error> {
+    let invalid = |msg: String| {
+        web3::error::Error::InvalidResponse(format!("batch of `{}` requests: {}", method, msg))
+    };
+
+    let mut results = HashMap::with_capacity(outputs.len());
+    for output in outputs {
+        let (id, result) = match output {
+            Output::Success(success) => (success.id, Ok(success.result)),
+            Output::Failure(failure) => (failure.id, Err(web3::error::Error::Rpc(failure.error))),
+        };
+        let id = match id {
+            Id::Num(id) => id as RequestId,
+            id => return Err(invalid(format!("response has unexpected id {:?}", id))),
+        };
+        if results.insert(id, result).is_some() {
+            return Err(invalid(format!("duplicate responses for id {}", id)));
+        }
+    }
+
+    let matched = ids
+        .iter()
+        .map(|id| {
+            results
+                .remove(id)
+                .ok_or_else(|| invalid(format!("no response for id {}", id)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(id) = results.keys().next() {
+        return Err(invalid(format!("response for unknown id {}", id)));
+    }
+    Ok(matched)
 }
 
 impl web3::Transport for Transport {
@@ -53,7 +183,7 @@ impl web3::Transport for Transport {
 
     fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
         match self {
-            Transport::RPC(http) => http.prepare(method, params),
+            Transport::RPC { http, .. } => http.prepare(method, params),
             Transport::IPC(ipc) => ipc.prepare(method, params),
             Transport::WS(ws) => ws.prepare(method, params),
         }
@@ -61,7 +191,7 @@ impl web3::Transport for Transport {
 
     fn send(&self, id: RequestId, request: Call) -> Self::Out {
         match self {
-            Transport::RPC(http) => Box::new(http.send(id, request)),
+            Transport::RPC { http, .. } => Box::new(http.send(id, request)),
             Transport::IPC(ipc) => Box::new(ipc.send(id, request)),
             Transport::WS(ws) => Box::new(ws.send(id, request)),
         }
@@ -80,9 +210,162 @@ impl web3::BatchTransport for Transport {
         T: IntoIterator<Item = (RequestId, Call)>,
     {
         match self {
-            Transport::RPC(http) => Box::new(http.send_batch(requests)),
+            Transport::RPC { http, .. } => Box::new(http.send_batch(requests)),
             Transport::IPC(ipc) => Box::new(ipc.send_batch(requests)),
             Transport::WS(ws) => Box::new(ws.send_batch(requests)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::tokio::{
+        self,
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+    use jsonrpc_core::types::{Failure, Success};
+    use serde_json::json;
+
+    use super::*;
+
+    fn success(id: u64, result: Value) -> Output {
+        Output::Success(Success {
+            jsonrpc: None,
+            result,
+            id: Id::Num(id),
+        })
+    }
+
+    fn failure(id: u64) -> Output {
+        Output::Failure(Failure {
+            jsonrpc: None,
+            error: jsonrpc_core::Error::internal_error(),
+            id: Id::Num(id),
+        })
+    }
+
+    /// Serve one HTTP request with a JSON-RPC batch and answer it with
+    /// `respond`, which gets the requests and returns the status and body
+    async fn stand_in(respond: fn(Vec<Value>) -> (u16, Value)) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                socket.read_line(&mut line).await.unwrap();
+                let line = line.trim_end().to_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    len = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; len];
+            socket.read_exact(&mut body).await.unwrap();
+
+            let (status, body) = respond(serde_json::from_slice(&body).unwrap());
+            let body = body.to_string();
+            let response = format!(
+                "HTTP/1.1 {} X\r\ncontent-type: application/json\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        url.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn batch_matches_responses_by_id() {
+        // Answer in reverse order, and fail the request for `fail`
+        let url = stand_in(|requests| {
+            let responses = requests
+                .into_iter()
+                .rev()
+                .map(|request| match &request["params"][0] {
+                    param if param == "fail" => json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "error": { "code": -32000, "message": "failed" }
+                    }),
+                    param => json!({ "jsonrpc": "2.0", "id": request["id"], "result": param }),
+                })
+                .collect();
+            (200, Value::Array(responses))
+        })
+        .await;
+        let transport = Transport::new_rpc(url, ::http::HeaderMap::new());
+
+        let params = ["a", "fail", "c"].iter().map(|p| vec![json!(p)]).collect();
+        let results = transport.batch_request("echo", params).await;
+
+        assert_eq!(3, results.len());
+        assert_eq!(json!("a"), *results[0].as_ref().unwrap());
+        assert!(matches!(results[1], Err(web3::error::Error::Rpc(_))));
+        assert_eq!(json!("c"), *results[2].as_ref().unwrap());
+    }
+
+    #[tokio::test]
+    async fn batch_failure_fails_every_request() {
+        let url = stand_in(|_| (500, json!({}))).await;
+        let transport = Transport::new_rpc(url, ::http::HeaderMap::new());
+
+        let params = vec![vec![json!(1)], vec![json!(2)]];
+        let results = transport.batch_request("echo", params).await;
+
+        assert_eq!(2, results.len());
+        for result in results {
+            assert_eq!(
+                web3::error::Error::Transport(TransportError::Code(500)),
+                result.unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn batch_ids_must_match() {
+        let ids = [3, 4];
+
+        let results = match_outputs("m", &ids, vec![failure(4), success(3, json!(3))]).unwrap();
+        assert_eq!(json!(3), *results[0].as_ref().unwrap());
+        assert!(results[1].is_err());
+
+        for outputs in [
+            // Missing
+            vec![success(3, json!(3))],
+            // Duplicate
+            vec![
+                success(3, json!(3)),
+                success(3, json!(3)),
+                success(4, json!(4)),
+            ],
+            // Unknown
+            vec![
+                success(3, json!(3)),
+                success(4, json!(4)),
+                success(5, json!(5)),
+            ],
+            // Not numeric
+            vec![
+                success(3, json!(3)),
+                Output::Success(Success {
+                    jsonrpc: None,
+                    result: json!(4),
+                    id: Id::Str("4".to_string()),
+                }),
+            ],
+        ] {
+            assert!(matches!(
+                match_outputs("m", &ids, outputs),
+                Err(web3::error::Error::InvalidResponse(_))
+            ));
+        }
+    }
+}
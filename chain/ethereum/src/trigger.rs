@@ -245,6 +245,9 @@ impl Eq for EthereumTrigger {}
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EthereumBlockTriggerType {
     Every,
+    /// Triggered only on the start block of a data source with a `once`
+    /// block handler
+    Once,
     WithCallTo(Address),
 }
 
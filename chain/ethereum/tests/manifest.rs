@@ -17,7 +17,7 @@ use graph::{
     data::subgraph::SubgraphFeature,
 };
 
-use graph_chain_ethereum::{Chain, NodeCapabilities};
+use graph_chain_ethereum::{CallArg, CallDecl, Chain, NodeCapabilities};
 use test_store::LOGGER;
 
 const GQL_SCHEMA: &str = "type Thing @entity { id: ID! }";
@@ -362,6 +362,120 @@ specVersion: 0.0.2
     assert_eq!(true, required_capabilities.traces);
 }
 
+#[tokio::test]
+async fn parse_declared_calls() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      abi: Factory
+      startBlock: 9562480
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      eventHandlers:
+        - event: Created(address,uint256)
+          handler: handleCreated
+          calls:
+            owner: Factory[event.address].get(event.params.id)
+            other: Factory[event.params.thing].get(event.params.id)
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest(YAML).await;
+    let calls = &manifest.data_sources[0].mapping.event_handlers[0].calls;
+
+    let call = |address, arg: &str| CallDecl {
+        abi: "Factory".to_string(),
+        address,
+        function: "get".to_string(),
+        args: vec![CallArg::Param(arg.to_string())],
+    };
+    assert_eq!(2, calls.len());
+    assert_eq!(&call(CallArg::Address, "id"), calls.get("owner").unwrap());
+    assert_eq!(
+        &call(CallArg::Param("thing".to_string()), "id"),
+        calls.get("other").unwrap()
+    );
+
+    for invalid in [
+        "Factory.get(event.address)",
+        "Factory[event.address].get",
+        "Factory[event.block].get(event.address)",
+        "Factory[event.address].get(event.params.)",
+    ] {
+        assert!(invalid.parse::<CallDecl>().is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn parse_offchain_templates() {
+    const YAML: &str = "
+dataSources: []
+templates:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      abi: Factory
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      callHandlers:
+        - function: get(address)
+          handler: handleget
+  - kind: file/ipfs
+    name: Metadata
+    mapping:
+      apiVersion: 0.0.7
+      language: wasm/assemblyscript
+      entities:
+        - Metadata
+      handler: handleMetadata
+      file:
+        /: /ipfs/Qmmapping
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest(YAML).await;
+
+    assert_eq!(1, manifest.templates.len());
+    assert_eq!("Factory", manifest.templates[0].name);
+
+    assert_eq!(1, manifest.offchain_templates.len());
+    let template = &manifest.offchain_templates[0];
+    assert_eq!("file/ipfs", template.kind);
+    assert_eq!("Metadata", template.name);
+    assert_eq!("handleMetadata", template.mapping.handler);
+    assert_eq!(vec!["Metadata".to_string()], template.mapping.entities);
+}
+
 #[test]
 fn undeclared_grafting_feature_causes_feature_validation_error() {
     const YAML: &str = "
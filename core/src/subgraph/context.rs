@@ -2,11 +2,14 @@ use crate::subgraph::SubgraphInstance;
 use graph::{
     blockchain::Blockchain,
     components::store::DeploymentId,
-    prelude::{CancelGuard, RuntimeHostBuilder},
+    data_source::offchain,
+    prelude::{tokio::sync::mpsc, *},
+    util::backoff::ExponentialBackoff,
 };
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 pub type SharedInstanceKeepAliveMap = Arc<RwLock<HashMap<DeploymentId, CancelGuard>>>;
 
@@ -14,4 +17,240 @@ pub struct IndexingContext<T: RuntimeHostBuilder<C>, C: Blockchain> {
     pub instance: SubgraphInstance<C, T>,
     pub instances: SharedInstanceKeepAliveMap,
     pub filter: C::TriggerFilter,
+    pub offchain_monitor: OffchainMonitor,
+}
+
+/// Fetches the files of offchain data sources in the background. At most
+/// `max_concurrent_fetches` files are fetched at the same time; the other
+/// files wait in a queue until a fetch finishes. Fetching a file is retried
+/// with a backoff, but only `max_attempts` times. The contents of files
+/// that have been fetched are handed to the runner through
+/// `ready_offchain_events`
+pub struct OffchainMonitor {
+    logger: Logger,
+    link_resolver: Arc<dyn LinkResolver>,
+    /// Files that are waiting to be fetched
+    queue: VecDeque<Link>,
+    /// The number of fetches that have not reported back yet
+    fetching: usize,
+    max_concurrent_fetches: usize,
+    max_attempts: u64,
+    backoff_base: Duration,
+    sender: mpsc::Sender<FetchResult>,
+    receiver: mpsc::Receiver<FetchResult>,
+}
+
+/// The file that was fetched, and its content unless all attempts to fetch
+/// it failed
+type FetchResult = (Link, Option<Vec<u8>>);
+
+impl OffchainMonitor {
+    const BACKOFF_BASE: Duration = Duration::from_secs(1);
+    const BACKOFF_CEIL: Duration = Duration::from_secs(5 * 60);
+
+    pub fn new(logger: Logger, link_resolver: Arc<dyn LinkResolver>) -> Self {
+        Self::with_limits(
+            logger,
+            link_resolver,
+            ENV_VARS.mappings.offchain_max_concurrent_fetches,
+            ENV_VARS.mappings.offchain_max_fetch_attempts,
+            Self::BACKOFF_BASE,
+        )
+    }
+
+    fn with_limits(
+        logger: Logger,
+        link_resolver: Arc<dyn LinkResolver>,
+        max_concurrent_fetches: usize,
+        max_attempts: u64,
+        backoff_base: Duration,
+    ) -> Self {
+        // Every fetch sends exactly one message, so the channel never holds
+        // more than `max_concurrent_fetches` messages
+        let (sender, receiver) = mpsc::channel(max_concurrent_fetches);
+        Self {
+            logger,
+            link_resolver,
+            queue: VecDeque::new(),
+            fetching: 0,
+            max_concurrent_fetches,
+            max_attempts,
+            backoff_base,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Queue `source` for fetching
+    pub fn add_source(&mut self, source: Link) {
+        self.queue.push_back(source);
+        self.start_fetches();
+    }
+
+    /// Start fetching queued files while fewer than
+    /// `max_concurrent_fetches` are being fetched
+    fn start_fetches(&mut self) {
+        while self.fetching < self.max_concurrent_fetches {
+            let source = match self.queue.pop_front() {
+                Some(source) => source,
+                None => return,
+            };
+            self.fetching += 1;
+
+            let logger = self.logger.new(o!("file" => source.link.clone()));
+            let link_resolver = self.link_resolver.cheap_clone();
+            let sender = self.sender.clone();
+            let max_attempts = self.max_attempts;
+            let mut backoff = ExponentialBackoff::new(self.backoff_base, Self::BACKOFF_CEIL);
+
+            graph::spawn(async move {
+                let data = loop {
+                    if sender.is_closed() {
+                        return;
+                    }
+                    match link_resolver.cat(&logger, &source).await {
+                        Ok(data) => break Some(data),
+                        Err(e) if backoff.attempt + 1 >= max_attempts => {
+                            warn!(logger, "Failed to fetch file for offchain data source, \
+                                           giving up until the subgraph restarts";
+                                "error" => format!("{:#}", e),
+                                "attempts" => max_attempts);
+                            break None;
+                        }
+                        Err(e) => {
+                            debug!(logger, "Failed to fetch file for offchain data source, retrying";
+                                "error" => format!("{:#}", e),
+                                "attempt" => backoff.attempt);
+                            backoff.sleep_async().await;
+                        }
+                    }
+                };
+                let _ = sender.send((source, data)).await;
+            });
+        }
+    }
+
+    /// The files that have been fetched since the last call
+    pub fn ready_offchain_events(&mut self) -> Vec<offchain::TriggerData> {
+        let mut triggers = vec![];
+        while let Ok((source, data)) = self.receiver.try_recv() {
+            self.fetching -= 1;
+            if let Some(data) = data {
+                triggers.push(offchain::TriggerData {
+                    source,
+                    data: Arc::new(data),
+                });
+            }
+        }
+        self.start_fetches();
+        triggers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::prelude::{async_trait, JsonValueStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves every file after a short delay, or fails every request if
+    /// `fail` is set. Keeps track of how many requests are in flight
+    #[derive(Debug, Default)]
+    struct CountingResolver {
+        fail: bool,
+        requests: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LinkResolver for CountingResolver {
+        fn with_timeout(&self, _timeout: Duration) -> Box<dyn LinkResolver> {
+            unimplemented!()
+        }
+
+        fn with_retries(&self) -> Box<dyn LinkResolver> {
+            unimplemented!()
+        }
+
+        async fn cat(&self, _logger: &Logger, link: &Link) -> Result<Vec<u8>, Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            match self.fail {
+                true => Err(anyhow!("no such file")),
+                false => Ok(link.link.as_bytes().to_vec()),
+            }
+        }
+
+        async fn get_block(&self, _logger: &Logger, _link: &Link) -> Result<Vec<u8>, Error> {
+            unimplemented!()
+        }
+
+        async fn json_stream(
+            &self,
+            _logger: &Logger,
+            _link: &Link,
+        ) -> Result<JsonValueStream, Error> {
+            unimplemented!()
+        }
+    }
+
+    fn monitor(resolver: Arc<CountingResolver>, max_attempts: u64) -> OffchainMonitor {
+        let logger = Logger::root(slog::Discard, o!());
+        OffchainMonitor::with_limits(logger, resolver, 2, max_attempts, Duration::from_millis(1))
+    }
+
+    /// Collect events until no fetches are left
+    async fn drain(monitor: &mut OffchainMonitor) -> Vec<offchain::TriggerData> {
+        let mut triggers = vec![];
+        while monitor.fetching > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            triggers.extend(monitor.ready_offchain_events());
+        }
+        triggers
+    }
+
+    #[tokio::test]
+    async fn fetches_are_bounded() {
+        let resolver = Arc::new(CountingResolver::default());
+        let mut monitor = monitor(resolver.cheap_clone(), 3);
+
+        for i in 0..5 {
+            monitor.add_source(Link::from(format!("file{}", i)));
+        }
+        assert_eq!(2, monitor.fetching);
+        assert_eq!(3, monitor.queue.len());
+
+        let mut files: Vec<_> = drain(&mut monitor)
+            .await
+            .into_iter()
+            .map(|trigger| {
+                assert_eq!(trigger.source.link.as_bytes(), trigger.data.as_slice());
+                trigger.source.link
+            })
+            .collect();
+        files.sort();
+
+        assert_eq!(vec!["file0", "file1", "file2", "file3", "file4"], files);
+        assert_eq!(2, resolver.max_in_flight.load(Ordering::SeqCst));
+        assert_eq!(5, resolver.requests.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn fetches_give_up_after_max_attempts() {
+        let resolver = Arc::new(CountingResolver {
+            fail: true,
+            ..Default::default()
+        });
+        let mut monitor = monitor(resolver.cheap_clone(), 3);
+
+        monitor.add_source(Link::from("missing".to_string()));
+
+        assert!(drain(&mut monitor).await.is_empty());
+        assert_eq!(3, resolver.requests.load(Ordering::SeqCst));
+        assert!(monitor.queue.is_empty());
+    }
 }
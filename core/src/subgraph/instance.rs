@@ -1,16 +1,20 @@
+use atomic_refcell::AtomicRefCell;
 use futures01::sync::mpsc::Sender;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 
 use graph::{blockchain::DataSource, prelude::*};
 use graph::{
-    blockchain::{Block, Blockchain},
+    blockchain::{Block, Blockchain, TriggerWithHandler},
     components::{
-        store::SubgraphFork,
-        subgraph::{MappingError, SharedProofOfIndexing},
+        store::{EntityType, SubgraphFork, WritableStore},
+        subgraph::{MappingError, ProofOfIndexing, SharedProofOfIndexing},
     },
+    data::graphql::DocumentExt as _,
+    data_source::offchain,
     prelude::ENV_VARS,
+    util::lfu_cache::LfuCache,
 };
 
 use super::metrics::SubgraphInstanceMetrics;
@@ -27,8 +31,43 @@ pub struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
     /// stream events are processed by the mappings in this same order.
     hosts: Vec<Arc<T::Host>>,
 
+    /// Runtime hosts for offchain data sources, ordered by creation block.
+    offchain_hosts: Vec<OffchainHost<T::Host>>,
+
+    offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
+
     /// Maps the hash of a module to a channel to the thread in which the module is instantiated.
-    module_cache: HashMap<[u8; 32], Sender<T::Req>>,
+    /// When triggers are processed in parallel, the name of the data source is part of the key
+    /// so that the handlers of different data sources run in different threads.
+    module_cache: HashMap<([u8; 32], Option<String>), Sender<T::Req>>,
+
+    parallel_triggers: bool,
+
+    /// The object types of the schema. Only hosts whose mappings declare
+    /// entity types from this set take part in parallel processing
+    entity_types: HashSet<String>,
+
+    /// The grouping of `hosts` for parallel processing. It is computed
+    /// when it is first needed and reset whenever `hosts` changes
+    entity_groups: Option<Arc<EntityGroups>>,
+}
+
+/// The hosts of a subgraph split into groups that can process triggers
+/// concurrently
+struct EntityGroups {
+    /// The group of each host, `None` for hosts that can't be processed in
+    /// parallel with others
+    host_groups: Vec<Option<usize>>,
+    /// The entity types of each group
+    scopes: Vec<Arc<HashSet<EntityType>>>,
+}
+
+struct OffchainHost<H> {
+    host: Arc<H>,
+    /// The block at which the handler of the data source ran. This is
+    /// persisted with the data source, so that files are not fetched again
+    /// when the subgraph restarts
+    done_at: Option<BlockNumber>,
 }
 
 impl<T, C: Blockchain> SubgraphInstance<C, T>
@@ -40,17 +79,30 @@ where
         manifest: SubgraphManifest<C>,
         host_builder: T,
         host_metrics: Arc<HostMetrics>,
+        parallel_triggers: bool,
     ) -> Result<Self, Error> {
         let subgraph_id = manifest.id.clone();
         let network = manifest.network_name();
         let templates = Arc::new(manifest.templates);
+        let entity_types = manifest
+            .schema
+            .document
+            .get_object_type_definitions()
+            .into_iter()
+            .map(|object_type| object_type.name.clone())
+            .collect();
 
         let mut this = SubgraphInstance {
             host_builder,
             subgraph_id,
             network,
             hosts: Vec::new(),
+            offchain_hosts: Vec::new(),
+            offchain_templates: Arc::new(manifest.offchain_templates),
             module_cache: HashMap::new(),
+            parallel_triggers,
+            entity_types,
+            entity_groups: None,
         };
 
         // Create a new runtime host for each data source in the subgraph manifest;
@@ -76,32 +128,116 @@ where
         templates: Arc<Vec<C::DataSourceTemplate>>,
         host_metrics: Arc<HostMetrics>,
     ) -> Result<T::Host, Error> {
-        let mapping_request_sender = {
-            let module_bytes = data_source.runtime();
-            let module_hash = tiny_keccak::keccak256(module_bytes);
-            if let Some(sender) = self.module_cache.get(&module_hash) {
-                sender.clone()
-            } else {
-                let sender = T::spawn_mapping(
-                    module_bytes.to_owned(),
-                    logger,
-                    self.subgraph_id.clone(),
-                    host_metrics.clone(),
-                )?;
-                self.module_cache.insert(module_hash, sender.clone());
-                sender
-            }
-        };
+        let mapping_request_sender = self.mapping_request_sender(
+            logger,
+            data_source.name(),
+            data_source.runtime(),
+            &host_metrics,
+        )?;
         self.host_builder.build(
             self.network.clone(),
             self.subgraph_id.clone(),
             data_source,
             templates,
+            self.offchain_templates.cheap_clone(),
             mapping_request_sender,
             host_metrics,
         )
     }
 
+    fn new_offchain_host(
+        &mut self,
+        logger: Logger,
+        data_source: offchain::DataSource,
+        templates: Arc<Vec<C::DataSourceTemplate>>,
+        host_metrics: Arc<HostMetrics>,
+    ) -> Result<T::Host, Error> {
+        let runtime = data_source.mapping.runtime.cheap_clone();
+        let mapping_request_sender =
+            self.mapping_request_sender(logger, &data_source.name, &runtime, &host_metrics)?;
+        self.host_builder.build_offchain(
+            self.network.clone(),
+            self.subgraph_id.clone(),
+            data_source,
+            templates,
+            self.offchain_templates.cheap_clone(),
+            mapping_request_sender,
+            host_metrics,
+        )
+    }
+
+    fn mapping_request_sender(
+        &mut self,
+        logger: Logger,
+        data_source_name: &str,
+        module_bytes: &[u8],
+        host_metrics: &Arc<HostMetrics>,
+    ) -> Result<Sender<T::Req>, Error> {
+        let module_hash = tiny_keccak::keccak256(module_bytes);
+        let data_source_name = self.parallel_triggers.then(|| data_source_name.to_owned());
+        let key = (module_hash, data_source_name);
+        if let Some(sender) = self.module_cache.get(&key) {
+            return Ok(sender.clone());
+        }
+        let sender = T::spawn_mapping(
+            module_bytes.to_owned(),
+            logger,
+            self.subgraph_id.clone(),
+            host_metrics.clone(),
+        )?;
+        self.module_cache.insert(key, sender.clone());
+        Ok(sender)
+    }
+
+    /// Load the data that the handlers for `triggers` declared they need
+    /// in one step before any of the handlers run
+    pub(crate) async fn prefetch(
+        &self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        triggers: &[C::TriggerData],
+    ) {
+        self.prefetch_in_runtime_hosts(logger, &self.hosts, block, triggers)
+            .await
+    }
+
+    pub(crate) async fn prefetch_in_runtime_hosts(
+        &self,
+        logger: &Logger,
+        hosts: &[Arc<T::Host>],
+        block: &Arc<C::Block>,
+        triggers: &[C::TriggerData],
+    ) {
+        let hosts: Vec<_> = hosts
+            .iter()
+            .filter_map(|host| host.onchain_data_source().map(|ds| (host, ds)))
+            .filter(|(_, ds)| self.host_builder.prefetches(ds))
+            .collect();
+        if hosts.is_empty() {
+            return;
+        }
+
+        // Errors are ignored here; they are reported when the triggers
+        // are processed
+        let matches: Vec<_> = triggers
+            .iter()
+            .flat_map(|trigger| {
+                hosts.iter().filter_map(move |(host, ds)| {
+                    match host.match_and_decode(trigger, block, logger) {
+                        Ok(Some(mapping_trigger)) => Some((*ds, mapping_trigger)),
+                        Ok(None) | Err(_) => None,
+                    }
+                })
+            })
+            .collect();
+        if matches.is_empty() {
+            return;
+        }
+
+        let triggers: Vec<_> = matches.iter().map(|(ds, trigger)| (*ds, trigger)).collect();
+        self.host_builder.prefetch(logger, &triggers).await
+    }
+
     pub(crate) async fn process_trigger(
         &self,
         logger: &Logger,
@@ -185,6 +321,224 @@ where
         Ok(state)
     }
 
+    /// Process `triggers` like calling `process_trigger` for each of them
+    /// would, but run the handlers of data sources whose mappings declare
+    /// disjoint sets of entity types concurrently. The hosts are split into
+    /// groups that share no entity types, and each group processes its
+    /// triggers in order with its own `BlockState` and with proof of
+    /// indexing events that are kept until all groups are done. The states
+    /// are then merged, and the events are written in trigger order, so the
+    /// result is the same as processing the triggers one after the other.
+    ///
+    /// That only holds if each handler sticks to the entity types of its
+    /// group, and if no handler creates data sources or fails. Otherwise,
+    /// or if a trigger is handled by a data source that is not part of any
+    /// group, `Err` with the entities cached from the store is returned and
+    /// the triggers need to be processed one after the other.
+    pub(crate) async fn process_triggers_in_parallel(
+        &mut self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        triggers: &[C::TriggerData],
+        store: &Arc<dyn WritableStore>,
+        mut lfu_cache: LfuCache<EntityKey, Option<Entity>>,
+        proof_of_indexing: &SharedProofOfIndexing,
+        causality_region: &str,
+        debug_fork: &Option<Arc<dyn SubgraphFork>>,
+        subgraph_metrics: &Arc<SubgraphInstanceMetrics>,
+    ) -> Result<BlockState<C>, LfuCache<EntityKey, Option<Entity>>> {
+        let entity_groups = self.entity_groups();
+        let EntityGroups {
+            host_groups,
+            scopes,
+        } = entity_groups.as_ref();
+
+        // Match all triggers up front. Each match is a handler call; the
+        // calls of a group are processed in the order of the triggers, and
+        // for each trigger in the order of the hosts
+        let mut calls: Vec<Vec<(usize, &Arc<T::Host>, TriggerWithHandler<C>)>> =
+            scopes.iter().map(|_| Vec::new()).collect();
+        let mut call_triggers = Vec::new();
+        for (trigger_index, trigger) in triggers.iter().enumerate() {
+            for (host, group) in self.hosts.iter().zip(host_groups) {
+                match (host.match_and_decode(trigger, block, logger), group) {
+                    (Ok(Some(mapping_trigger)), Some(group)) => {
+                        calls[*group].push((call_triggers.len(), host, mapping_trigger));
+                        call_triggers.push(trigger_index);
+                    }
+                    (Ok(None), _) => {}
+                    (Ok(Some(_)), None) | (Err(_), _) => return Err(lfu_cache),
+                }
+            }
+        }
+
+        // Nothing to gain if all calls end up in the same group
+        if calls.iter().filter(|calls| !calls.is_empty()).count() < 2 {
+            return Err(lfu_cache);
+        }
+
+        let groups = calls
+            .into_iter()
+            .zip(scopes)
+            .filter(|(calls, _)| !calls.is_empty())
+            .map(|(calls, scope)| {
+                let current = lfu_cache.split_off(|key| scope.contains(&key.entity_type));
+                let mut state = BlockState::<C>::new(store.cheap_clone(), current);
+                state.entity_cache.set_scope(scope.cheap_clone());
+
+                async move {
+                    let mut pois = Vec::with_capacity(calls.len());
+                    for (call, host, mapping_trigger) in calls {
+                        let poi: SharedProofOfIndexing = proof_of_indexing.as_ref().map(|_| {
+                            Arc::new(AtomicRefCell::new(ProofOfIndexing::deferred(
+                                block.number(),
+                            )))
+                        });
+
+                        let start = Instant::now();
+                        state = host
+                            .process_mapping_trigger(
+                                logger,
+                                block.ptr(),
+                                mapping_trigger,
+                                state,
+                                poi.cheap_clone(),
+                                debug_fork,
+                            )
+                            .await?;
+                        let elapsed = start.elapsed().as_secs_f64();
+                        subgraph_metrics.observe_trigger_processing_duration(elapsed);
+
+                        pois.push((call, poi));
+                    }
+                    Ok::<_, MappingError>((state, pois))
+                }
+            })
+            .collect::<Vec<_>>();
+        let results = futures03::future::join_all(groups).await;
+
+        // The entities a group cached are lost if the group failed, which
+        // is not an issue for correctness
+        let mut done = Vec::with_capacity(results.len());
+        let mut independent = true;
+        for result in results {
+            match result {
+                Ok((state, pois)) => {
+                    independent &= !state.has_errors()
+                        && !state.has_created_data_sources()
+                        && !state.has_created_offchain_data_sources()
+                        && !state.entity_cache.left_scope();
+                    done.push((state, pois));
+                }
+                Err(_) => independent = false,
+            }
+        }
+        if !independent {
+            debug!(
+                logger,
+                "Handlers depend on each other, processing triggers one after the other"
+            );
+            for (state, _) in done {
+                lfu_cache.merge(state.entity_cache.into_lfu_cache());
+            }
+            return Err(lfu_cache);
+        }
+
+        let mut block_state = BlockState::new(store.cheap_clone(), lfu_cache);
+        let mut pois: Vec<SharedProofOfIndexing> = call_triggers.iter().map(|_| None).collect();
+        for (state, group_pois) in done {
+            block_state.extend(state);
+            for (call, poi) in group_pois {
+                pois[call] = poi;
+            }
+        }
+
+        if let Some(proof_of_indexing) = proof_of_indexing {
+            let mut proof_of_indexing = proof_of_indexing.borrow_mut();
+            let mut pois = call_triggers.into_iter().zip(pois).peekable();
+            for trigger_index in 0..triggers.len() {
+                proof_of_indexing.start_handler(causality_region);
+                while let Some((_, poi)) =
+                    pois.next_if(|(call_trigger, _)| *call_trigger == trigger_index)
+                {
+                    if let Some(poi) = poi {
+                        let poi = std::mem::take(&mut *poi.borrow_mut());
+                        proof_of_indexing.write_deferred(logger, poi);
+                    }
+                }
+            }
+        }
+
+        Ok(block_state)
+    }
+
+    /// Split the hosts into groups such that hosts whose mappings declare a
+    /// common entity type are in the same group. Hosts that declare no
+    /// entity types are put into a group of their own, and hosts that
+    /// declare an entity type that is not in the schema are not put into
+    /// any group since the entity types they touch are unknown.
+    fn entity_groups(&mut self) -> Arc<EntityGroups> {
+        if let Some(entity_groups) = &self.entity_groups {
+            return entity_groups.cheap_clone();
+        }
+
+        let entity_types = &self.entity_types;
+        let in_schema =
+            |host: &Arc<T::Host>| host.entities().iter().all(|e| entity_types.contains(e));
+
+        let mut groups: Vec<HashSet<&str>> = Vec::new();
+        for host in self.hosts.iter().filter(|host| in_schema(host)) {
+            let mut group: HashSet<&str> = host.entities().iter().map(String::as_str).collect();
+            if group.is_empty() {
+                continue;
+            }
+            let (overlapping, disjoint) = groups
+                .into_iter()
+                .partition::<Vec<_>, _>(|other| !other.is_disjoint(&group));
+            group.extend(overlapping.into_iter().flatten());
+            groups = disjoint;
+            groups.push(group);
+        }
+
+        let group_of: HashMap<&str, usize> = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| group.iter().map(move |entity_type| (*entity_type, i)))
+            .collect();
+        let host_groups = self
+            .hosts
+            .iter()
+            .map(|host| {
+                if !in_schema(host) {
+                    return None;
+                }
+                match host.entities().first() {
+                    Some(entity_type) => Some(group_of[entity_type.as_str()]),
+                    None => Some(groups.len()),
+                }
+            })
+            .collect();
+
+        let scopes = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|entity_type| EntityType::new(entity_type.to_string()))
+                    .collect()
+            })
+            .chain(std::iter::once(HashSet::new()))
+            .map(Arc::new)
+            .collect();
+
+        let entity_groups = Arc::new(EntityGroups {
+            host_groups,
+            scopes,
+        });
+        self.entity_groups = Some(entity_groups.cheap_clone());
+        entity_groups
+    }
+
     pub(crate) fn add_dynamic_data_source(
         &mut self,
         logger: &Logger,
@@ -192,15 +546,7 @@ where
         templates: Arc<Vec<C::DataSourceTemplate>>,
         metrics: Arc<HostMetrics>,
     ) -> Result<Option<Arc<T::Host>>, Error> {
-        // Protect against creating more than the allowed maximum number of data sources
-        if let Some(max_data_sources) = ENV_VARS.subgraph_max_data_sources {
-            if self.hosts.len() >= max_data_sources {
-                anyhow::bail!(
-                    "Limit of {} data sources per subgraph exceeded",
-                    max_data_sources,
-                );
-            }
-        }
+        self.check_max_data_sources()?;
 
         // `hosts` will remain ordered by the creation block.
         // See also 8f1bca33-d3b7-4035-affc-fd6161a12448.
@@ -215,11 +561,108 @@ where
             None
         } else {
             self.hosts.push(host.clone());
+            self.entity_groups = None;
             Some(host)
         })
     }
 
-    pub(crate) fn revert_data_sources(&mut self, reverted_block: BlockNumber) {
+    fn check_max_data_sources(&self) -> Result<(), Error> {
+        // Protect against creating more than the allowed maximum number of data sources
+        if let Some(max_data_sources) = ENV_VARS.subgraph_max_data_sources {
+            if self.hosts.len() + self.offchain_hosts.len() >= max_data_sources {
+                anyhow::bail!(
+                    "Limit of {} data sources per subgraph exceeded",
+                    max_data_sources,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a host for an offchain data source whose handler ran at
+    /// `done_at`, or has not run yet if that is `None`
+    pub(crate) fn add_offchain_data_source(
+        &mut self,
+        logger: &Logger,
+        data_source: offchain::DataSource,
+        done_at: Option<BlockNumber>,
+        templates: Arc<Vec<C::DataSourceTemplate>>,
+        metrics: Arc<HostMetrics>,
+    ) -> Result<Option<Arc<T::Host>>, Error> {
+        self.check_max_data_sources()?;
+
+        let host =
+            Arc::new(self.new_offchain_host(logger.clone(), data_source, templates, metrics)?);
+
+        Ok(
+            if self.offchain_hosts.iter().any(|other| other.host == host) {
+                None
+            } else {
+                self.offchain_hosts.push(OffchainHost {
+                    host: host.clone(),
+                    done_at,
+                });
+                Some(host)
+            },
+        )
+    }
+
+    /// Run the handlers of all offchain data sources that are waiting for
+    /// the file in `trigger`. A deterministic error in such a handler only
+    /// affects its data source: the changes of the handler are discarded
+    /// and the data source is marked as done, but the error is not counted
+    /// against the block
+    pub(crate) async fn process_offchain_trigger(
+        &mut self,
+        logger: &Logger,
+        block_ptr: &BlockPtr,
+        trigger: &offchain::TriggerData,
+        mut state: BlockState<C>,
+        debug_fork: &Option<Arc<dyn SubgraphFork>>,
+        subgraph_metrics: &Arc<SubgraphInstanceMetrics>,
+    ) -> Result<BlockState<C>, MappingError> {
+        for offchain_host in self.offchain_hosts.iter_mut() {
+            let data_source = match offchain_host.host.offchain_data_source() {
+                Some(data_source) if data_source.matches(trigger) => data_source,
+                _ => continue,
+            };
+            if offchain_host.done_at.is_some() {
+                continue;
+            }
+
+            let start = Instant::now();
+            let error_count = state.deterministic_errors.len();
+            state = offchain_host
+                .host
+                .process_offchain_trigger(
+                    logger,
+                    block_ptr.cheap_clone(),
+                    trigger.clone(),
+                    state,
+                    debug_fork,
+                )
+                .await?;
+            for e in state.deterministic_errors.split_off(error_count) {
+                error!(logger, "Offchain data source failed, it will not be processed again";
+                    "data_source" => &data_source.name,
+                    "file" => &data_source.source.link,
+                    "error" => &e.message);
+            }
+            state
+                .entity_cache
+                .offchain_data_source_done(data_source, block_ptr.number);
+            offchain_host.done_at = Some(block_ptr.number);
+            let elapsed = start.elapsed().as_secs_f64();
+            subgraph_metrics.observe_trigger_processing_duration(elapsed);
+        }
+
+        Ok(state)
+    }
+
+    /// Remove the data sources created at or after `reverted_block`.
+    /// Returns the files of the offchain data sources whose handler ran in
+    /// the reverted blocks; they need to be fetched and processed again
+    pub(crate) fn revert_data_sources(&mut self, reverted_block: BlockNumber) -> Vec<Link> {
         // `hosts` is ordered by the creation block.
         // See also 8f1bca33-d3b7-4035-affc-fd6161a12448.
         while self
@@ -229,7 +672,20 @@ where
             .is_some()
         {
             self.hosts.pop();
+            self.entity_groups = None;
         }
+
+        self.offchain_hosts
+            .retain(|h| h.host.creation_block_number() < Some(reverted_block));
+
+        self.offchain_hosts
+            .iter_mut()
+            .filter(|h| h.done_at >= Some(reverted_block))
+            .filter_map(|h| {
+                h.done_at = None;
+                h.host.offchain_data_source().map(|ds| ds.source.clone())
+            })
+            .collect()
     }
 
     pub(crate) fn network(&self) -> &str {
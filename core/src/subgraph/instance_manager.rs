@@ -1,4 +1,4 @@
-use crate::subgraph::context::{IndexingContext, SharedInstanceKeepAliveMap};
+use crate::subgraph::context::{IndexingContext, OffchainMonitor, SharedInstanceKeepAliveMap};
 use crate::subgraph::inputs::IndexingInputs;
 use crate::subgraph::loader::load_dynamic_data_sources;
 use crate::subgraph::metrics::{
@@ -23,6 +23,7 @@ pub struct SubgraphInstanceManager<S: SubgraphStore> {
     instances: SharedInstanceKeepAliveMap,
     link_resolver: Arc<dyn LinkResolver>,
     static_filters: bool,
+    parallel_triggers: bool,
 }
 
 #[async_trait]
@@ -107,6 +108,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         metrics_registry: Arc<dyn MetricsRegistry>,
         link_resolver: Arc<dyn LinkResolver>,
         static_filters: bool,
+        parallel_triggers: bool,
     ) -> Self {
         let logger = logger_factory.component_logger("SubgraphInstanceManager", None);
         let logger_factory = logger_factory.with_parent(logger.clone());
@@ -120,6 +122,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             instances: SharedInstanceKeepAliveMap::default(),
             link_resolver,
             static_filters,
+            parallel_triggers,
         }
     }
 
@@ -144,10 +147,10 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         // that is done
         store.start_subgraph_deployment(&logger).await?;
 
-        let manifest: SubgraphManifest<C> = {
+        let (manifest, offchain_data_sources) = {
             info!(logger, "Resolve subgraph files using IPFS");
 
-            let mut manifest = SubgraphManifest::resolve_from_raw(
+            let mut manifest = SubgraphManifest::<C>::resolve_from_raw(
                 deployment.hash.cheap_clone(),
                 manifest,
                 // Allow for infinite retries for subgraph definition files.
@@ -158,9 +161,10 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             .await
             .context("Failed to resolve subgraph from IPFS")?;
 
-            let data_sources = load_dynamic_data_sources(store.clone(), logger.clone(), &manifest)
-                .await
-                .context("Failed to load dynamic data sources")?;
+            let (data_sources, offchain_data_sources) =
+                load_dynamic_data_sources(store.clone(), logger.clone(), &manifest)
+                    .await
+                    .context("Failed to load dynamic data sources")?;
 
             info!(logger, "Successfully resolved subgraph files using IPFS");
 
@@ -173,7 +177,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
                 manifest.data_sources.len()
             );
 
-            manifest
+            (manifest, offchain_data_sources)
         };
 
         let required_capabilities = C::NodeCapabilities::from_data_sources(&manifest.data_sources);
@@ -248,8 +252,31 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
 
         let features = manifest.features.clone();
         let unified_api_version = manifest.unified_mapping_api_version()?;
-        let instance =
-            SubgraphInstance::from_manifest(&logger, manifest, host_builder, host_metrics.clone())?;
+        let mut instance = SubgraphInstance::from_manifest(
+            &logger,
+            manifest,
+            host_builder,
+            host_metrics.clone(),
+            self.parallel_triggers,
+        )?;
+
+        // Only the files of offchain data sources that have not been
+        // processed yet need to be fetched
+        let mut offchain_monitor =
+            OffchainMonitor::new(logger.cheap_clone(), self.link_resolver.cheap_clone());
+        for (data_source, done_at) in offchain_data_sources {
+            let source = data_source.source.clone();
+            let host = instance.add_offchain_data_source(
+                &logger,
+                data_source,
+                done_at,
+                templates.cheap_clone(),
+                host_metrics.clone(),
+            )?;
+            if host.is_some() && done_at.is_none() {
+                offchain_monitor.add_source(source);
+            }
+        }
 
         let inputs = IndexingInputs {
             deployment: deployment.clone(),
@@ -263,6 +290,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             templates,
             unified_api_version,
             static_filters: self.static_filters,
+            parallel_triggers: self.parallel_triggers,
         };
 
         // The subgraph state tracks the state of the subgraph instance over time
@@ -270,6 +298,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             instance,
             instances: self.instances.cheap_clone(),
             filter,
+            offchain_monitor,
         };
 
         let metrics = RunnerMetrics {
@@ -2,18 +2,39 @@ use std::time::Instant;
 
 use graph::blockchain::{Blockchain, DataSource, DataSourceTemplate as _};
 use graph::components::store::WritableStore;
+use graph::data_source::offchain;
 use graph::prelude::*;
 
+/// An offchain data source together with the block at which its handler
+/// ran, if it has run
+pub type OffchainDataSource = (offchain::DataSource, Option<BlockNumber>);
+
 pub async fn load_dynamic_data_sources<C: Blockchain>(
     store: Arc<dyn WritableStore>,
     logger: Logger,
     manifest: &SubgraphManifest<C>,
-) -> Result<Vec<C::DataSource>, Error> {
+) -> Result<(Vec<C::DataSource>, Vec<OffchainDataSource>), Error> {
     let start_time = Instant::now();
 
     let mut data_sources: Vec<C::DataSource> = vec![];
+    let mut offchain_data_sources: Vec<OffchainDataSource> = vec![];
 
     for stored in store.load_dynamic_data_sources().await? {
+        // Offchain data sources are stored alongside the chain's dynamic
+        // data sources and are told apart by their template
+        if let Some(template) = manifest
+            .offchain_templates
+            .iter()
+            .find(|template| template.name == stored.name)
+        {
+            let done_at = stored.done_at;
+            offchain_data_sources.push((
+                offchain::DataSource::from_stored_dynamic_data_source(template, stored)?,
+                done_at,
+            ));
+            continue;
+        }
+
         let template = manifest
             .templates
             .iter()
@@ -38,5 +59,5 @@ pub async fn load_dynamic_data_sources<C: Blockchain>(
         "ms" => start_time.elapsed().as_millis()
     );
 
-    Ok(data_sources)
+    Ok((data_sources, offchain_data_sources))
 }
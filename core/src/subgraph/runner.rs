@@ -17,6 +17,7 @@ use graph::data::subgraph::{
     schema::{SubgraphError, SubgraphHealth, POI_OBJECT},
     SubgraphFeature,
 };
+use graph::data_source::offchain;
 use graph::prelude::*;
 use graph::util::{backoff::ExponentialBackoff, lfu_cache::LfuCache};
 use std::convert::TryFrom;
@@ -243,6 +244,11 @@ where
             // and add runtimes for the data sources to the subgraph instance.
             self.persist_dynamic_data_sources(&mut block_state.entity_cache, data_sources);
 
+            self.ctx
+                .instance
+                .prefetch_in_runtime_hosts(&logger, &runtime_hosts, &block, &triggers)
+                .await;
+
             // Process the triggers in each host in the same order the
             // corresponding data sources have been created.
             for trigger in triggers {
@@ -272,6 +278,36 @@ where
             }
         }
 
+        // Process the offchain data sources whose files have become available. Their handlers do
+        // not contribute to the PoI and can not create onchain data sources, so this does not
+        // affect the deterministic part of indexing.
+        for trigger in self.ctx.offchain_monitor.ready_offchain_events() {
+            block_state = self
+                .ctx
+                .instance
+                .process_offchain_trigger(
+                    &logger,
+                    &block_ptr,
+                    &trigger,
+                    block_state,
+                    &self.inputs.debug_fork,
+                    &self.metrics.subgraph,
+                )
+                .await
+                .map_err(|e| match e {
+                    MappingError::PossibleReorg(e) | MappingError::Unknown(e) => {
+                        BlockProcessingError::Unknown(e)
+                    }
+                })?;
+        }
+
+        // Offchain data sources do not need new filters, so creating them does not restart the
+        // block stream.
+        self.create_offchain_data_sources(
+            &mut block_state.entity_cache,
+            block_state.drain_created_offchain_data_sources(),
+        )?;
+
         let has_errors = block_state.has_errors();
         let is_non_fatal_errors_active = self
             .inputs
@@ -409,10 +445,38 @@ where
         triggers: Vec<C::TriggerData>,
         causality_region: &str,
     ) -> Result<BlockState<C>, MappingError> {
-        let mut block_state = BlockState::new(
-            self.inputs.store.clone(),
-            std::mem::take(&mut self.state.entity_lfu_cache),
-        );
+        let mut entity_lfu_cache = std::mem::take(&mut self.state.entity_lfu_cache);
+
+        self.ctx
+            .instance
+            .prefetch(&self.logger, block, &triggers)
+            .await;
+
+        if self.inputs.parallel_triggers && triggers.len() > 1 {
+            match self
+                .ctx
+                .instance
+                .process_triggers_in_parallel(
+                    &self.logger,
+                    block,
+                    &triggers,
+                    &self.inputs.store,
+                    entity_lfu_cache,
+                    proof_of_indexing,
+                    causality_region,
+                    &self.inputs.debug_fork,
+                    &self.metrics.subgraph,
+                )
+                .await
+            {
+                Ok(block_state) => return Ok(block_state),
+                // Handlers could not be run in parallel; process the
+                // triggers one after the other instead
+                Err(cache) => entity_lfu_cache = cache,
+            }
+        }
+
+        let mut block_state = BlockState::new(self.inputs.store.clone(), entity_lfu_cache);
 
         use graph::blockchain::TriggerData;
 
@@ -511,6 +575,48 @@ where
         // Merge filters from data sources into the block stream builder
         self.ctx.filter.extend(data_sources.iter());
     }
+
+    fn create_offchain_data_sources(
+        &mut self,
+        entity_cache: &mut EntityCache,
+        data_sources: Vec<offchain::DataSource>,
+    ) -> Result<(), Error> {
+        for data_source in data_sources {
+            let host = self.ctx.instance.add_offchain_data_source(
+                &self.logger,
+                data_source.clone(),
+                None,
+                self.inputs.templates.clone(),
+                self.metrics.host.clone(),
+            )?;
+
+            match host {
+                Some(_) => {
+                    debug!(
+                        self.logger,
+                        "Persisting offchain data source";
+                        "name" => &data_source.name,
+                        "file" => &data_source.source.link,
+                    );
+                    entity_cache.add_offchain_data_source(&data_source);
+                    self.ctx
+                        .offchain_monitor
+                        .add_source(data_source.source.clone());
+                }
+                None => {
+                    warn!(
+                        self.logger,
+                        "no runtime host created, there is already a runtime host instantiated for \
+                        this offchain data source";
+                        "name" => &data_source.name,
+                        "file" => &data_source.source.link,
+                    )
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<C, T> SubgraphRunner<C, T>
@@ -815,13 +921,16 @@ where
 
         // Revert the in-memory state:
         // - Remove hosts for reverted dynamic data sources.
+        // - Fetch the files again for offchain data sources processed in reverted blocks.
         // - Clear the entity cache.
         //
         // Note that we do not currently revert the filters, which means the filters
         // will be broader than necessary. This is not ideal for performance, but is not
         // incorrect since we will discard triggers that match the filters but do not
         // match any data sources.
-        self.ctx.instance.revert_data_sources(subgraph_ptr.number);
+        for source in self.ctx.instance.revert_data_sources(subgraph_ptr.number) {
+            self.ctx.offchain_monitor.add_source(source);
+        }
         self.state.entity_lfu_cache = LfuCache::new();
 
         Ok(Action::Continue)
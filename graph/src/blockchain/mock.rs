@@ -88,6 +88,10 @@ impl<C: Blockchain> DataSource<C> for MockDataSource {
         todo!()
     }
 
+    fn entities(&self) -> &[String] {
+        todo!()
+    }
+
     fn match_and_decode(
         &self,
         _trigger: &C::TriggerData,
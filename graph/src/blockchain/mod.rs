@@ -201,6 +201,10 @@ pub trait DataSource<C: Blockchain>:
     fn api_version(&self) -> semver::Version;
     fn runtime(&self) -> &[u8];
 
+    /// The entity types the mapping of this data source declares that it
+    /// writes to.
+    fn entities(&self) -> &[String];
+
     /// Checks if `trigger` matches this data source, and if so decodes it into a `MappingTrigger`.
     /// A return of `Ok(None)` mean the trigger does not match.
     ///
@@ -300,8 +304,28 @@ impl CheapClone for HostFn {
     }
 }
 
+#[async_trait]
 pub trait RuntimeAdapter<C: Blockchain>: Send + Sync {
     fn host_fns(&self, ds: &C::DataSource) -> Result<Vec<HostFn>, Error>;
+
+    /// Whether the handlers of `ds` declare data that `prefetch` can load.
+    /// Triggers for other data sources are not passed to `prefetch`. The
+    /// default is `false`
+    fn prefetches(&self, _ds: &C::DataSource) -> bool {
+        false
+    }
+
+    /// Called with the triggers of a block before any of their handlers
+    /// run so that the data the handlers declared they need can be loaded
+    /// ahead of time in one step. Failures are not reported since the
+    /// handlers will load the data themselves if it wasn't loaded here. The
+    /// default does nothing
+    async fn prefetch(
+        &self,
+        _logger: &Logger,
+        _triggers: &[(&C::DataSource, &TriggerWithHandler<C>)],
+    ) {
+    }
 }
 
 pub trait NodeCapabilities<C: Blockchain> {
@@ -439,6 +463,10 @@ impl<C: Blockchain> TriggerWithHandler<C> {
         &self.handler
     }
 
+    pub fn trigger(&self) -> &C::MappingTrigger {
+        &self.trigger
+    }
+
     pub fn to_asc_ptr<H: AscHeap>(
         self,
         heap: &mut H,
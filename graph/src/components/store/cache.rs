@@ -1,5 +1,5 @@
 use anyhow::anyhow;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Debug};
 use std::sync::Arc;
 
@@ -7,7 +7,9 @@ use crate::blockchain::BlockPtr;
 use crate::components::store::{
     self as s, Entity, EntityKey, EntityOp, EntityOperation, EntityType,
 };
+use crate::data_source::offchain;
 use crate::prelude::ENV_VARS;
+use crate::runtime::DeterministicHostError;
 use crate::util::lfu_cache::LfuCache;
 
 /// A cache for entities from the store that provides the basic functionality
@@ -33,6 +35,13 @@ pub struct EntityCache {
 
     data_sources: Vec<s::StoredDynamicDataSource>,
 
+    /// The entity types this cache is meant to be used for. `None` means
+    /// that all entity types can be used.
+    scope: Option<Arc<HashSet<EntityType>>>,
+
+    /// Marks whether an entity of a type outside of `scope` was accessed.
+    out_of_scope: bool,
+
     /// The store is only used to read entities.
     pub store: Arc<dyn s::WritableStore>,
 }
@@ -60,6 +69,8 @@ impl EntityCache {
             handler_updates: HashMap::new(),
             in_handler: false,
             data_sources: vec![],
+            scope: None,
+            out_of_scope: false,
             store,
         }
     }
@@ -74,10 +85,44 @@ impl EntityCache {
             handler_updates: HashMap::new(),
             in_handler: false,
             data_sources: vec![],
+            scope: None,
+            out_of_scope: false,
             store,
         }
     }
 
+    /// Restrict this cache to entities of the types in `scope`. Entities
+    /// of other types can still be accessed, but doing so is remembered
+    /// and reported by `left_scope`
+    pub fn set_scope(&mut self, scope: Arc<HashSet<EntityType>>) {
+        self.scope = Some(scope);
+    }
+
+    /// The entity types this cache is restricted to, if any
+    pub fn scope(&self) -> Option<&Arc<HashSet<EntityType>>> {
+        self.scope.as_ref()
+    }
+
+    /// Return `true` if an entity of a type outside of the scope of this
+    /// cache was accessed
+    pub fn left_scope(&self) -> bool {
+        self.out_of_scope
+    }
+
+    /// Drop all changes and return the entities that were read from the
+    /// store
+    pub fn into_lfu_cache(self) -> LfuCache<EntityKey, Option<Entity>> {
+        self.current
+    }
+
+    fn check_scope(&mut self, key: &EntityKey) {
+        if let Some(scope) = &self.scope {
+            if !scope.contains(&key.entity_type) {
+                self.out_of_scope = true;
+            }
+        }
+    }
+
     pub(crate) fn enter_handler(&mut self) {
         assert!(!self.in_handler);
         self.in_handler = true;
@@ -101,6 +146,8 @@ impl EntityCache {
     }
 
     pub fn get(&mut self, key: &EntityKey) -> Result<Option<Entity>, s::QueryExecutionError> {
+        self.check_scope(key);
+
         // Get the current entity, apply any updates from `updates`, then
         // from `handler_updates`.
         let mut entity = self.current.get_entity(&*self.store, key)?;
@@ -113,15 +160,42 @@ impl EntityCache {
         Ok(entity)
     }
 
-    pub fn remove(&mut self, key: EntityKey) {
+    /// Whether the entity with the given `key` was set, and not removed
+    /// again, in the current block
+    fn set_in_block(&self, key: &EntityKey) -> bool {
+        match self
+            .handler_updates
+            .get(key)
+            .or_else(|| self.updates.get(key))
+        {
+            Some(EntityOp::Update(_)) | Some(EntityOp::Overwrite(_)) => true,
+            Some(EntityOp::Remove) | None => false,
+        }
+    }
+
+    /// Remove the entity with the given `key`. Entities of immutable types
+    /// can never be removed, and trying to do so results in a
+    /// deterministic error.
+    pub fn remove(&mut self, key: EntityKey) -> Result<(), anyhow::Error> {
+        if self.store.input_schema().is_immutable(&key.entity_type) {
+            return Err(DeterministicHostError::from(anyhow!(
+                "entity type `{}` is immutable; can not remove entity `{}`",
+                key.entity_type,
+                key.entity_id
+            ))
+            .into());
+        }
         self.entity_op(key, EntityOp::Remove);
+        Ok(())
     }
 
     /// Store the `entity` under the given `key`. The `entity` may be only a
     /// partial entity; the cache will ensure partial updates get merged
     /// with existing data. The entity will be validated against the
     /// subgraph schema, and any errors will result in an `Err` being
-    /// returned.
+    /// returned. Entities of immutable types can only be set once; setting
+    /// one that was already set in the same block or that exists in the
+    /// store results in a deterministic error.
     pub fn set(&mut self, key: EntityKey, mut entity: Entity) -> Result<(), anyhow::Error> {
         fn check_id(key: &EntityKey, prev_id: &str) -> Result<(), anyhow::Error> {
             if prev_id != key.entity_id {
@@ -151,6 +225,19 @@ impl EntityCache {
             }
         }
 
+        // An immutable entity can neither be set twice in the current block
+        // nor overwrite a version that was written in an earlier block
+        if self.store.input_schema().is_immutable(&key.entity_type)
+            && (self.set_in_block(&key) || self.current.get_entity(&*self.store, &key)?.is_some())
+        {
+            return Err(DeterministicHostError::from(anyhow!(
+                "entity type `{}` is immutable; can not update entity `{}`",
+                key.entity_type,
+                key.entity_id
+            ))
+            .into());
+        }
+
         let is_valid = entity.validate(&self.store.input_schema(), &key).is_ok();
 
         self.entity_op(key.clone(), EntityOp::Update(entity));
@@ -193,8 +280,28 @@ impl EntityCache {
             .push(data_source.as_stored_dynamic_data_source());
     }
 
+    /// Add an offchain data source
+    pub fn add_offchain_data_source(&mut self, data_source: &offchain::DataSource) {
+        self.data_sources
+            .push(data_source.as_stored_dynamic_data_source());
+    }
+
+    /// Record that the handler of an offchain data source ran at `block`
+    pub fn offchain_data_source_done(
+        &mut self,
+        data_source: &offchain::DataSource,
+        block: s::BlockNumber,
+    ) {
+        self.data_sources.push(s::StoredDynamicDataSource {
+            done_at: Some(block),
+            ..data_source.as_stored_dynamic_data_source()
+        });
+    }
+
     fn entity_op(&mut self, key: EntityKey, op: EntityOp) {
         use std::collections::hash_map::Entry;
+        self.check_scope(&key);
+
         let updates = match self.in_handler {
             true => &mut self.handler_updates,
             false => &mut self.updates,
@@ -211,7 +318,8 @@ impl EntityCache {
     pub(crate) fn extend(&mut self, other: EntityCache) {
         assert!(!other.in_handler);
 
-        self.current.extend(other.current);
+        self.out_of_scope |= other.out_of_scope;
+        self.current.merge(other.current);
         for (key, op) in other.updates {
             self.entity_op(key, op);
         }
@@ -232,9 +340,10 @@ impl EntityCache {
             .keys()
             .filter(|key| !self.current.contains_key(key));
 
-        // For immutable types, we assume that the subgraph is well-behaved,
-        // and all updated immutable entities are in fact new, and skip
-        // looking them up in the store. That ultimately always leads to an
+        // For immutable types, `set` has already made sure that the entity
+        // is new, and we skip looking them up in the store again. For
+        // entities that did not go through `set`, we assume that the
+        // subgraph is well-behaved. That ultimately always leads to an
         // `Insert` modification for immutable entities; if the assumption
         // is wrong and the store already has a version of the entity from a
         // previous block, the attempt to insert will trigger a constraint
@@ -187,6 +187,18 @@ pub struct Child {
     pub derived: bool,
 }
 
+/// The position of an entity in a collection that is sorted by `key` and
+/// then by `id`. It is used to continue a query right after that entity
+/// with a keyset comparison instead of skipping over the entities before it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortPosition {
+    /// The attribute the collection is sorted by and the entity's value
+    /// for it; `None` if the collection is only sorted by `id`
+    pub key: Option<(Attribute, Value)>,
+    pub id: Value,
+    pub descending: bool,
+}
+
 /// Supported types of store filters.
 #[derive(Clone, Debug, PartialEq)]
 pub enum EntityFilter {
@@ -214,6 +226,8 @@ pub enum EntityFilter {
     NotEndsWithNoCase(Attribute, Value),
     ChangeBlockGte(BlockNumber),
     Child(Child),
+    /// Only entities that come after the given position in the sort order
+    After(SortPosition),
 }
 
 // A somewhat concise string representation of a filter
@@ -264,6 +278,13 @@ impl fmt::Display for EntityFilter {
                 child.entity_type,
                 child.filter.to_string()
             ),
+            After(pos) => {
+                let op = if pos.descending { "<" } else { ">" };
+                match &pos.key {
+                    Some((a, v)) => write!(f, "({a}, id) {op} ({v}, {})", pos.id),
+                    None => write!(f, "id {op} {}", pos.id),
+                }
+            }
         }
     }
 }
@@ -310,6 +331,19 @@ impl EntityFilter {
     }
 }
 
+/// Sort entities by an attribute of the entity that they reference with
+/// `join_attribute`, e.g., pools by the symbol of their `token0`
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityOrderByChild {
+    /// The attribute of the entity that holds the id of the child
+    pub join_attribute: Attribute,
+    /// The type of the child
+    pub entity_type: EntityType,
+    /// The attribute of the child to sort by
+    pub sort_by_attribute: Attribute,
+    pub value_type: ValueType,
+}
+
 /// The order in which entities should be restored from a store.
 #[derive(Clone, Debug, PartialEq)]
 pub enum EntityOrder {
@@ -317,6 +351,12 @@ pub enum EntityOrder {
     Ascending(String, ValueType),
     /// Order descending by the given attribute. Use `id` as a tie-breaker
     Descending(String, ValueType),
+    /// Order ascending by an attribute of a referenced entity. Use `id` as
+    /// a tie-breaker
+    ChildAscending(EntityOrderByChild),
+    /// Order descending by an attribute of a referenced entity. Use `id`
+    /// as a tie-breaker
+    ChildDescending(EntityOrderByChild),
     /// Order by the `id` of the entities
     Default,
     /// Do not order at all. This speeds up queries where we know that
@@ -465,6 +505,16 @@ pub type BlockNumber = i32;
 
 pub const BLOCK_NUMBER_MAX: BlockNumber = std::i32::MAX;
 
+/// The values that a fulltext search computes for each entity it finds and
+/// that a query should return together with the entity's attributes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FulltextOutput {
+    /// Return the rank of the entity for the search text
+    pub rank: bool,
+    /// Return an excerpt of the entity's text with the matches highlighted
+    pub snippet: bool,
+}
+
 /// A query for entities in a store.
 ///
 /// Details of how query generation for `EntityQuery` works can be found
@@ -499,6 +549,14 @@ pub struct EntityQuery {
 
     pub query_id: Option<String>,
 
+    /// Whether to collect a trace of the SQL query, its query plan and
+    /// how long it took
+    pub trace: bool,
+
+    /// What to return for the entities found by a fulltext search in
+    /// addition to their attributes
+    pub fulltext: FulltextOutput,
+
     _force_use_of_new: (),
 }
 
@@ -517,6 +575,8 @@ impl EntityQuery {
             range: EntityRange::first(100),
             logger: None,
             query_id: None,
+            trace: false,
+            fulltext: FulltextOutput::default(),
             _force_use_of_new: (),
         }
     }
@@ -577,6 +637,84 @@ impl EntityQuery {
     }
 }
 
+/// An aggregate that is computed over all the entities matching an
+/// `EntityAggregateQuery`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EntityAggregate {
+    /// The number of matching entities
+    Count,
+    /// The sum of the values of a numeric attribute
+    Sum(Attribute),
+    /// The smallest value of a numeric attribute
+    Min(Attribute),
+    /// The largest value of a numeric attribute
+    Max(Attribute),
+    /// The average of the values of a numeric attribute
+    Avg(Attribute),
+}
+
+impl EntityAggregate {
+    /// The attribute this aggregate is computed over, or `None` for
+    /// `Count`
+    pub fn attribute(&self) -> Option<&Attribute> {
+        use EntityAggregate::*;
+        match self {
+            Count => None,
+            Sum(attr) | Min(attr) | Max(attr) | Avg(attr) => Some(attr),
+        }
+    }
+}
+
+/// A query that computes aggregates over the entities of one type that
+/// match a filter. The result of the query contains one value for each of
+/// the `aggregates`, in the same order.
+#[derive(Clone, Debug)]
+pub struct EntityAggregateQuery {
+    /// ID of the subgraph.
+    pub subgraph_id: DeploymentHash,
+
+    /// The block height at which to execute the query.
+    pub block: BlockNumber,
+
+    /// The entity type whose entities are aggregated.
+    pub entity_type: EntityType,
+
+    /// Filter to restrict the entities that are aggregated.
+    pub filter: Option<EntityFilter>,
+
+    /// The aggregates to compute
+    pub aggregates: Vec<EntityAggregate>,
+
+    /// Optional logger for anything related to this query
+    pub logger: Option<Logger>,
+
+    pub query_id: Option<String>,
+}
+
+impl EntityAggregateQuery {
+    pub fn new(subgraph_id: DeploymentHash, block: BlockNumber, entity_type: EntityType) -> Self {
+        EntityAggregateQuery {
+            subgraph_id,
+            block,
+            entity_type,
+            filter: None,
+            aggregates: vec![],
+            logger: None,
+            query_id: None,
+        }
+    }
+
+    pub fn filter(mut self, filter: EntityFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn aggregates(mut self, aggregates: Vec<EntityAggregate>) -> Self {
+        self.aggregates = aggregates;
+        self
+    }
+}
+
 /// Operation types that lead to entity changes.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -864,6 +1002,9 @@ pub struct StoredDynamicDataSource {
     pub param: Option<Bytes>,
     pub context: Option<serde_json::Value>,
     pub creation_block: Option<BlockNumber>,
+    /// The block at which the handler of an offchain data source ran, or
+    /// `None` if it has not run yet. Always `None` for onchain data sources
+    pub done_at: Option<BlockNumber>,
 }
 
 /// An internal identifer for the specific instance of a deployment. The
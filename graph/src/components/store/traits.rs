@@ -5,7 +5,10 @@ use crate::components::server::index_node::VersionInfo;
 use crate::components::transaction_receipt;
 use crate::data::subgraph::status;
 use crate::data::value::Word;
-use crate::data::{query::QueryTarget, subgraph::schema::*};
+use crate::data::{
+    query::{QueryTarget, Trace},
+    subgraph::schema::*,
+};
 
 pub trait SubscriptionManager: Send + Sync + 'static {
     /// Subscribe to changes for specific subgraphs and entities.
@@ -390,10 +393,16 @@ pub trait EthereumCallCache: Send + Sync + 'static {
 /// Store operations used when serving queries for a specific deployment
 #[async_trait]
 pub trait QueryStore: Send + Sync {
+    /// Find the entities matching `query`. The trace is `Trace::None`
+    /// unless `query.trace` is set
     fn find_query_values(
         &self,
         query: EntityQuery,
-    ) -> Result<Vec<BTreeMap<Word, r::Value>>, QueryExecutionError>;
+    ) -> Result<(Vec<BTreeMap<Word, r::Value>>, Trace), QueryExecutionError>;
+
+    /// Compute the aggregates requested by `query`. The result has one
+    /// value for each of `query.aggregates`, in the same order
+    fn aggregate(&self, query: EntityAggregateQuery) -> Result<Vec<Value>, QueryExecutionError>;
 
     async fn is_deployment_synced(&self) -> Result<bool, Error>;
 
@@ -8,6 +8,7 @@ use futures::sync::mpsc;
 
 use crate::blockchain::TriggerWithHandler;
 use crate::components::store::SubgraphFork;
+use crate::data_source::offchain;
 use crate::prelude::*;
 use crate::{blockchain::Blockchain, components::subgraph::SharedProofOfIndexing};
 use crate::{components::metrics::HistogramVec, runtime::DeterministicHostError};
@@ -61,9 +62,29 @@ pub trait RuntimeHost<C: Blockchain>: Send + Sync + 'static {
         debug_fork: &Option<Arc<dyn SubgraphFork>>,
     ) -> Result<BlockState<C>, MappingError>;
 
+    /// The onchain data source of this host, if it is an onchain host.
+    fn onchain_data_source(&self) -> Option<&C::DataSource>;
+
+    /// The offchain data source of this host, if it is an offchain host.
+    fn offchain_data_source(&self) -> Option<&offchain::DataSource>;
+
+    /// Run the handler of an offchain data source for the content of its
+    /// file. Offchain handlers do not contribute to the proof of indexing.
+    async fn process_offchain_trigger(
+        &self,
+        logger: &Logger,
+        block_ptr: BlockPtr,
+        trigger: offchain::TriggerData,
+        state: BlockState<C>,
+        debug_fork: &Option<Arc<dyn SubgraphFork>>,
+    ) -> Result<BlockState<C>, MappingError>;
+
     /// Block number in which this host was created.
     /// Returns `None` for static data sources.
     fn creation_block_number(&self) -> Option<BlockNumber>;
+
+    /// The entity types the mapping of this host declares that it writes to.
+    fn entities(&self) -> &[String];
 }
 
 pub struct HostMetrics {
@@ -142,6 +163,7 @@ impl Drop for HostFnExecutionTimer {
     }
 }
 
+#[async_trait]
 pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
     type Host: RuntimeHost<C> + PartialEq;
     type Req: 'static + Send;
@@ -153,6 +175,19 @@ pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
         subgraph_id: DeploymentHash,
         data_source: C::DataSource,
         top_level_templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
+        mapping_request_sender: mpsc::Sender<Self::Req>,
+        metrics: Arc<HostMetrics>,
+    ) -> Result<Self::Host, Error>;
+
+    /// Build a new runtime host for an offchain data source.
+    fn build_offchain(
+        &self,
+        network_name: String,
+        subgraph_id: DeploymentHash,
+        data_source: offchain::DataSource,
+        top_level_templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
         mapping_request_sender: mpsc::Sender<Self::Req>,
         metrics: Arc<HostMetrics>,
     ) -> Result<Self::Host, Error>;
@@ -165,4 +200,20 @@ pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
         subgraph_id: DeploymentHash,
         metrics: Arc<HostMetrics>,
     ) -> Result<mpsc::Sender<Self::Req>, anyhow::Error>;
+
+    /// Whether the handlers of `data_source` declare data that `prefetch`
+    /// can load. The default is `false`
+    fn prefetches(&self, _data_source: &C::DataSource) -> bool {
+        false
+    }
+
+    /// Load the data that the handlers for `triggers` declared they need
+    /// before any of them run. See `RuntimeAdapter::prefetch`. The default
+    /// does nothing
+    async fn prefetch(
+        &self,
+        _logger: &Logger,
+        _triggers: &[(&C::DataSource, &TriggerWithHandler<C>)],
+    ) {
+    }
 }
@@ -1,4 +1,5 @@
 use crate::blockchain::Blockchain;
+use crate::data_source::offchain;
 use crate::prelude::*;
 use crate::util::lfu_cache::LfuCache;
 use crate::{components::store::WritableStore, data::subgraph::schema::SubgraphError};
@@ -20,6 +21,11 @@ pub struct BlockState<C: Blockchain> {
     // Data sources created in the current handler.
     handler_created_data_sources: Vec<DataSourceTemplateInfo<C>>,
 
+    // Offchain data sources, created in the block and in the current
+    // handler respectively.
+    created_offchain_data_sources: Vec<offchain::DataSource>,
+    handler_created_offchain_data_sources: Vec<offchain::DataSource>,
+
     // Marks whether a handler is currently executing.
     in_handler: bool,
 }
@@ -34,6 +40,8 @@ impl<C: Blockchain> BlockState<C> {
             deterministic_errors: Vec::new(),
             created_data_sources: Vec::new(),
             handler_created_data_sources: Vec::new(),
+            created_offchain_data_sources: Vec::new(),
+            handler_created_offchain_data_sources: Vec::new(),
             in_handler: false,
         }
     }
@@ -46,12 +54,20 @@ impl<C: Blockchain> BlockState<C> {
             deterministic_errors,
             created_data_sources,
             handler_created_data_sources,
+            created_offchain_data_sources,
+            handler_created_offchain_data_sources,
             in_handler,
         } = self;
 
         match in_handler {
-            true => handler_created_data_sources.extend(other.created_data_sources),
-            false => created_data_sources.extend(other.created_data_sources),
+            true => {
+                handler_created_data_sources.extend(other.created_data_sources);
+                handler_created_offchain_data_sources.extend(other.created_offchain_data_sources);
+            }
+            false => {
+                created_data_sources.extend(other.created_data_sources);
+                created_offchain_data_sources.extend(other.created_offchain_data_sources);
+            }
         }
         deterministic_errors.extend(other.deterministic_errors);
         entity_cache.extend(other.entity_cache);
@@ -66,11 +82,21 @@ impl<C: Blockchain> BlockState<C> {
         !self.created_data_sources.is_empty()
     }
 
+    pub fn has_created_offchain_data_sources(&self) -> bool {
+        assert!(!self.in_handler);
+        !self.created_offchain_data_sources.is_empty()
+    }
+
     pub fn drain_created_data_sources(&mut self) -> Vec<DataSourceTemplateInfo<C>> {
         assert!(!self.in_handler);
         std::mem::take(&mut self.created_data_sources)
     }
 
+    pub fn drain_created_offchain_data_sources(&mut self) -> Vec<offchain::DataSource> {
+        assert!(!self.in_handler);
+        std::mem::take(&mut self.created_offchain_data_sources)
+    }
+
     pub fn enter_handler(&mut self) {
         assert!(!self.in_handler);
         self.in_handler = true;
@@ -82,6 +108,8 @@ impl<C: Blockchain> BlockState<C> {
         self.in_handler = false;
         self.created_data_sources
             .append(&mut self.handler_created_data_sources);
+        self.created_offchain_data_sources
+            .append(&mut self.handler_created_offchain_data_sources);
         self.entity_cache.exit_handler()
     }
 
@@ -89,6 +117,7 @@ impl<C: Blockchain> BlockState<C> {
         assert!(self.in_handler);
         self.in_handler = false;
         self.handler_created_data_sources.clear();
+        self.handler_created_offchain_data_sources.clear();
         self.entity_cache.exit_handler_and_discard_changes();
         self.deterministic_errors.push(e);
     }
@@ -97,4 +126,9 @@ impl<C: Blockchain> BlockState<C> {
         assert!(self.in_handler);
         self.handler_created_data_sources.push(ds);
     }
+
+    pub fn push_created_offchain_data_source(&mut self, ds: offchain::DataSource) {
+        assert!(self.in_handler);
+        self.handler_created_offchain_data_sources.push(ds);
+    }
 }
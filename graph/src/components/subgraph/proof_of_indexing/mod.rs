@@ -262,4 +262,64 @@ mod tests {
             }
         }
     }
+
+    /// Writing events through deferred `ProofOfIndexing`s in the order of
+    /// the handlers must give the same digest as writing them directly
+    #[test]
+    fn deferred_events() {
+        let logger = Logger::root(Discard, o!());
+        let data = hashmap! {
+            "val".to_owned() => Value::Int(1)
+        };
+        let events = vec![
+            ProofOfIndexingEvent::SetEntity {
+                entity_type: "t",
+                id: "1",
+                data: &data,
+            },
+            ProofOfIndexingEvent::RemoveEntity {
+                entity_type: "u",
+                id: "2",
+            },
+            ProofOfIndexingEvent::SetEntity {
+                entity_type: "t",
+                id: "3",
+                data: &data,
+            },
+        ];
+
+        fn digest(poi: ProofOfIndexing) -> HashMap<String, Vec<u8>> {
+            poi.take()
+                .into_iter()
+                .map(|(name, region)| (name, region.pause(None)))
+                .collect()
+        }
+
+        let mut direct = ProofOfIndexing::new(7);
+        for event in &events {
+            direct.start_handler("eth");
+            direct.write(&logger, "eth", event);
+        }
+        direct.start_handler("eth");
+        direct.write_deterministic_error(&logger, "eth");
+
+        let mut deferred = ProofOfIndexing::new(7);
+        let mut handlers: Vec<_> = events
+            .iter()
+            .map(|event| {
+                let mut handler = ProofOfIndexing::deferred(7);
+                handler.write(&logger, "eth", event);
+                handler
+            })
+            .collect();
+        let mut failed = ProofOfIndexing::deferred(7);
+        failed.write_deterministic_error(&logger, "eth");
+        handlers.push(failed);
+        for handler in handlers {
+            deferred.start_handler("eth");
+            deferred.write_deferred(&logger, handler);
+        }
+
+        assert_eq!(digest(direct), digest(deferred));
+    }
 }
@@ -5,7 +5,7 @@
 use super::ProofOfIndexingEvent;
 use crate::{
     blockchain::BlockPtr,
-    prelude::{debug, BlockNumber, DeploymentHash, Logger, ENV_VARS},
+    prelude::{debug, BlockNumber, DeploymentHash, Logger, Value, ENV_VARS},
 };
 use stable_hash_legacy::crypto::{Blake3SeqNo, SetHasher};
 use stable_hash_legacy::prelude::*;
@@ -136,6 +136,25 @@ pub struct ProofOfIndexing {
     /// state with other data sources. This may also give us some freedom to change
     /// the order of triggers in the future.
     per_causality_region: HashMap<String, BlockEventStream>,
+    /// Events that are kept in the order in which they were written, for
+    /// a `ProofOfIndexing` created with `deferred`
+    deferred: Option<Vec<(String, DeferredEvent)>>,
+}
+
+/// An owned copy of a `ProofOfIndexingEvent`. The number of redacted events
+/// of a deterministic error depends on the events written before it, and is
+/// therefore only determined when the event is finally written.
+enum DeferredEvent {
+    RemoveEntity {
+        entity_type: String,
+        id: String,
+    },
+    SetEntity {
+        entity_type: String,
+        id: String,
+        data: HashMap<String, Value>,
+    },
+    DeterministicError,
 }
 
 impl fmt::Debug for ProofOfIndexing {
@@ -149,6 +168,19 @@ impl ProofOfIndexing {
         Self {
             block_number,
             per_causality_region: HashMap::new(),
+            deferred: None,
+        }
+    }
+
+    /// Create a `ProofOfIndexing` that keeps the events written to it
+    /// instead of adding them to the digest. Handlers that run concurrently
+    /// write to their own deferred `ProofOfIndexing`, and their events are
+    /// then passed to `write_deferred` in the order in which the handlers
+    /// would have run one after the other.
+    pub fn deferred(block_number: BlockNumber) -> Self {
+        Self {
+            deferred: Some(Vec::new()),
+            ..Self::new(block_number)
         }
     }
 }
@@ -178,6 +210,31 @@ impl ProofOfIndexing {
         causality_region: &str,
         event: &ProofOfIndexingEvent<'_>,
     ) {
+        if let Some(deferred) = &mut self.deferred {
+            let event = match event {
+                ProofOfIndexingEvent::RemoveEntity { entity_type, id } => {
+                    DeferredEvent::RemoveEntity {
+                        entity_type: entity_type.to_string(),
+                        id: id.to_string(),
+                    }
+                }
+                ProofOfIndexingEvent::SetEntity {
+                    entity_type,
+                    id,
+                    data,
+                } => DeferredEvent::SetEntity {
+                    entity_type: entity_type.to_string(),
+                    id: id.to_string(),
+                    data: (*data).clone(),
+                },
+                ProofOfIndexingEvent::DeterministicError { .. } => {
+                    DeferredEvent::DeterministicError
+                }
+            };
+            deferred.push((causality_region.to_owned(), event));
+            return;
+        }
+
         if ENV_VARS.log_poi_events {
             debug!(
                 logger,
@@ -190,6 +247,39 @@ impl ProofOfIndexing {
         self.with_causality_region(causality_region, |entry| entry.write(event))
     }
 
+    /// Write the events that were kept by the deferred `ProofOfIndexing`
+    /// `other` as if they had been written to this one directly
+    pub fn write_deferred(&mut self, logger: &Logger, other: ProofOfIndexing) {
+        for (causality_region, event) in other.deferred.into_iter().flatten() {
+            match event {
+                DeferredEvent::RemoveEntity { entity_type, id } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::RemoveEntity {
+                        entity_type: &entity_type,
+                        id: &id,
+                    },
+                ),
+                DeferredEvent::SetEntity {
+                    entity_type,
+                    id,
+                    data,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::SetEntity {
+                        entity_type: &entity_type,
+                        id: &id,
+                        data: &data,
+                    },
+                ),
+                DeferredEvent::DeterministicError => {
+                    self.write_deterministic_error(logger, &causality_region)
+                }
+            }
+        }
+    }
+
     pub fn start_handler(&mut self, causality_region: &str) {
         self.with_causality_region(causality_region, |entry| entry.start_handler())
     }
@@ -4,15 +4,17 @@ use prometheus::core::GenericCounter;
 use rand::{prelude::Rng, thread_rng};
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
-use crate::components::metrics::{Counter, Gauge, MetricsRegistry};
+use crate::components::metrics::{Counter, CounterVec, Gauge, GaugeVec, MetricsRegistry};
 use crate::components::store::PoolWaitStats;
 use crate::data::graphql::shape_hash::shape_hash;
 use crate::data::query::{CacheStatus, QueryExecutionError};
 use crate::prelude::q;
-use crate::prelude::{async_trait, debug, info, o, warn, Logger, QueryLoadManager, ENV_VARS};
+use crate::prelude::{
+    async_trait, debug, info, o, warn, CheapClone, Logger, QueryLoadManager, ENV_VARS,
+};
 use crate::util::stats::MovingStats;
 
 struct QueryEffort {
@@ -186,6 +188,292 @@ impl Decision {
     }
 }
 
+/// Turn the value of the header that identifies clients into a client id.
+/// Values of the form `Bearer <token>` identify the client by `<token>`
+pub fn client_id(header: &str) -> Option<String> {
+    let header = header.trim();
+    let id = match header.strip_prefix("Bearer") {
+        Some(token) if token.is_empty() || token.starts_with(' ') => token.trim(),
+        _ => header,
+    };
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_string())
+    }
+}
+
+/// A short hash of the client id `client` that identifies the client in
+/// metrics and logs without revealing the id, which is often a secret
+/// like an API key
+fn client_label(client: &str) -> String {
+    let hash = tiny_keccak::keccak256(client.as_bytes());
+    hex::encode(&hash[..8])
+}
+
+/// The decisions that are counted in `query_client_decisions`
+const CLIENT_DECISIONS: [&str; 4] = ["proceed", "concurrency", "rate", "effort"];
+
+/// The key under which clients are tracked together once the maximum
+/// number of clients is tracked. `client_id` never returns an empty id, so
+/// this can not clash with an actual client
+const OTHER_CLIENTS: &str = "";
+
+/// Limits that the `LoadManager` enforces for each client separately
+#[derive(Clone, Debug, Default)]
+struct ClientLimits {
+    max_concurrent: Option<usize>,
+    max_rps: Option<u32>,
+    max_effort: Option<Duration>,
+}
+
+impl ClientLimits {
+    fn from_env() -> Self {
+        Self {
+            max_concurrent: ENV_VARS.load_client_max_concurrent,
+            max_rps: ENV_VARS.load_client_max_rps,
+            max_effort: ENV_VARS.load_client_max_effort,
+        }
+    }
+}
+
+/// The load that one client is currently putting on the system
+struct ClientLoad {
+    /// The label for the client in metrics and logs
+    label: String,
+    /// The number of queries from the client that are currently running
+    in_flight: usize,
+    /// The start of the current one second interval and the number of
+    /// requests the client made in it
+    second_start: Instant,
+    requests: u32,
+    /// The time spent running queries for the client
+    effort: MovingStats,
+    last_seen: Instant,
+}
+
+impl ClientLoad {
+    fn new(label: String, now: Instant) -> Self {
+        Self {
+            label,
+            in_flight: 0,
+            second_start: now,
+            requests: 0,
+            effort: MovingStats::default(),
+            last_seen: now,
+        }
+    }
+
+    /// Check the client's load against `limits`. If a limit is exceeded,
+    /// return its name and a description of it
+    fn exceeded(&mut self, limits: &ClientLimits, now: Instant) -> Option<(&'static str, String)> {
+        if now.saturating_duration_since(self.second_start) >= Duration::from_secs(1) {
+            self.second_start = now;
+            self.requests = 0;
+        }
+        if let Some(max) = limits.max_concurrent {
+            if self.in_flight >= max {
+                return Some(("concurrency", format!("{} concurrent queries", max)));
+            }
+        }
+        if let Some(max) = limits.max_rps {
+            if self.requests >= max {
+                return Some(("rate", format!("{} queries per second", max)));
+            }
+        }
+        if let Some(max) = limits.max_effort {
+            if self.effort.duration_at(now) >= max {
+                return Some((
+                    "effort",
+                    format!(
+                        "{}ms of query time in {}s",
+                        max.as_millis(),
+                        ENV_VARS.load_window_size.as_secs()
+                    ),
+                ));
+            }
+        }
+        None
+    }
+
+    fn start(&mut self, now: Instant) {
+        self.in_flight += 1;
+        self.requests += 1;
+        self.last_seen = now;
+    }
+
+    fn finish(&mut self, now: Instant, duration: Duration) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.effort.add_at(now, duration);
+        self.last_seen = now;
+    }
+}
+
+struct ClientsInner {
+    clients: HashMap<String, ClientLoad>,
+    last_prune: Instant,
+}
+
+/// Track the load that each client puts on the system
+struct Clients {
+    logger: Logger,
+    limits: ClientLimits,
+    /// The maximum number of clients that are tracked separately. Clients
+    /// beyond that are tracked together under the label `other` so that
+    /// rotating client ids can not grow memory and metrics without bound
+    max_clients: usize,
+    inner: Mutex<ClientsInner>,
+    decisions: Box<CounterVec>,
+    effort_gauge: Box<GaugeVec>,
+}
+
+impl Clients {
+    fn new(logger: Logger, registry: &dyn MetricsRegistry) -> Self {
+        let decisions = registry
+            .new_counter_vec(
+                "query_client_decisions",
+                "Count the load manager's decisions for queries from each client",
+                vec![String::from("client"), String::from("decision")],
+            )
+            .expect("failed to create `query_client_decisions` counter");
+        let effort_gauge = registry
+            .new_gauge_vec(
+                "query_client_effort_ms",
+                "Time spent running queries for each client over the load window",
+                vec![String::from("client")],
+            )
+            .expect("failed to create `query_client_effort_ms` gauge");
+        Self::with_metrics(
+            logger,
+            ClientLimits::from_env(),
+            ENV_VARS.load_max_clients,
+            decisions,
+            effort_gauge,
+        )
+    }
+
+    fn with_metrics(
+        logger: Logger,
+        limits: ClientLimits,
+        max_clients: usize,
+        decisions: Box<CounterVec>,
+        effort_gauge: Box<GaugeVec>,
+    ) -> Self {
+        Self {
+            logger,
+            limits,
+            max_clients,
+            inner: Mutex::new(ClientsInner {
+                clients: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+            decisions,
+            effort_gauge,
+        }
+    }
+
+    fn admit(self: &Arc<Self>, mut client: &str) -> Result<ClientPermit, QueryExecutionError> {
+        let now = Instant::now();
+        let (label, exceeded) = {
+            let mut inner = self.inner.lock().unwrap();
+            self.prune(&mut inner, now);
+            if !inner.clients.contains_key(client) && inner.clients.len() >= self.max_clients {
+                client = OTHER_CLIENTS;
+            }
+            let load = inner.clients.entry(client.to_string()).or_insert_with(|| {
+                let label = match client {
+                    OTHER_CLIENTS => "other".to_string(),
+                    client => client_label(client),
+                };
+                ClientLoad::new(label, now)
+            });
+            let exceeded = load.exceeded(&self.limits, now);
+            if exceeded.is_none() || ENV_VARS.load_simulate {
+                load.start(now);
+            }
+            (load.label.clone(), exceeded)
+        };
+
+        let decision = exceeded
+            .as_ref()
+            .map(|(name, _)| *name)
+            .unwrap_or("proceed");
+        self.decisions
+            .with_label_values(&[label.as_str(), decision])
+            .inc();
+
+        match exceeded {
+            None => Ok(self.permit(client, now)),
+            Some((_, limit)) if ENV_VARS.load_simulate => {
+                debug!(self.logger, "Declining query from client";
+                    "client" => &label,
+                    "limit" => &limit);
+                Ok(self.permit(client, now))
+            }
+            Some((_, limit)) => Err(QueryExecutionError::ClientLimitExceeded(limit)),
+        }
+    }
+
+    fn permit(self: &Arc<Self>, client: &str, start: Instant) -> ClientPermit {
+        ClientPermit {
+            clients: self.cheap_clone(),
+            client: client.to_string(),
+            start,
+        }
+    }
+
+    fn finish(&self, client: &str, start: Instant) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(load) = inner.clients.get_mut(client) {
+            load.finish(now, now.saturating_duration_since(start));
+            self.effort_gauge
+                .with_label_values(&[load.label.as_str()])
+                .set(load.effort.duration_at(now).as_millis() as f64);
+        }
+    }
+
+    /// Forget about clients that have been idle for longer than the load
+    /// window so that we do not accumulate state for clients that have
+    /// gone away
+    fn prune(&self, inner: &mut ClientsInner, now: Instant) {
+        let window_size = ENV_VARS.load_window_size;
+        if now.saturating_duration_since(inner.last_prune) < window_size {
+            return;
+        }
+        inner.last_prune = now;
+        let effort_gauge = &self.effort_gauge;
+        let decisions = &self.decisions;
+        inner.clients.retain(|_, load| {
+            let keep =
+                load.in_flight > 0 || now.saturating_duration_since(load.last_seen) < window_size;
+            if !keep {
+                let label = load.label.as_str();
+                effort_gauge.remove_label_values(&[label]).ok();
+                for decision in CLIENT_DECISIONS {
+                    decisions.remove_label_values(&[label, decision]).ok();
+                }
+            }
+            keep
+        });
+    }
+}
+
+/// Permission for a client to run a query. The query counts towards the
+/// client's concurrency limit until the permit is dropped, and the time
+/// between creating and dropping the permit counts towards its effort
+pub struct ClientPermit {
+    clients: Arc<Clients>,
+    client: String,
+    start: Instant,
+}
+
+impl Drop for ClientPermit {
+    fn drop(&mut self) {
+        self.clients.finish(&self.client, self.start);
+    }
+}
+
 pub struct LoadManager {
     logger: Logger,
     effort: QueryEffort,
@@ -201,6 +489,7 @@ pub struct LoadManager {
     effort_gauge: Box<Gauge>,
     query_counters: HashMap<CacheStatus, Counter>,
     kill_rate_gauge: Box<Gauge>,
+    clients: Arc<Clients>,
 }
 
 impl LoadManager {
@@ -252,6 +541,8 @@ impl LoadManager {
             })
             .collect::<HashMap<_, _>>();
 
+        let clients = Arc::new(Clients::new(logger.clone(), registry.as_ref()));
+
         Self {
             logger,
             effort: QueryEffort::default(),
@@ -261,9 +552,17 @@ impl LoadManager {
             effort_gauge,
             query_counters,
             kill_rate_gauge,
+            clients,
         }
     }
 
+    /// Decide whether `client` may run another query given the limits on
+    /// concurrency, request rate and effort that apply to each client.
+    /// The returned permit must be held until the query has finished
+    pub fn admit_client(&self, client: &str) -> Result<ClientPermit, QueryExecutionError> {
+        self.clients.admit(client)
+    }
+
     /// Record that we spent `duration` amount of work for the query
     /// `shape_hash`, where `cache_status` indicates whether the query
     /// was cached or had to actually run
@@ -502,3 +801,109 @@ impl QueryLoadManager for LoadManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::components::metrics::{Collector, CounterVec, GaugeVec, Opts};
+    use crate::prelude::{o, Logger, ENV_VARS};
+
+    use super::{client_id, client_label, ClientLimits, ClientLoad, Clients};
+
+    #[test]
+    fn client_id_from_header() {
+        assert_eq!(Some("key".to_string()), client_id("key"));
+        assert_eq!(Some("token".to_string()), client_id(" Bearer token "));
+        assert_eq!(None, client_id("Bearer "));
+        assert_eq!(None, client_id(""));
+    }
+
+    #[test]
+    fn client_label_hides_id() {
+        let label = client_label("secret-api-key");
+        assert_eq!(16, label.len());
+        assert!(!label.contains("secret"));
+        assert_eq!(label, client_label("secret-api-key"));
+        assert_ne!(label, client_label("other-api-key"));
+    }
+
+    #[test]
+    fn client_limits() {
+        let limits = ClientLimits {
+            max_concurrent: Some(2),
+            max_rps: Some(3),
+            max_effort: Some(Duration::from_millis(100)),
+        };
+        let now = Instant::now();
+        let exceeded = |load: &mut ClientLoad, now| load.exceeded(&limits, now).map(|(n, _)| n);
+
+        // Concurrency
+        let mut load = ClientLoad::new("client".to_string(), now);
+        load.start(now);
+        load.start(now);
+        assert_eq!(Some("concurrency"), exceeded(&mut load, now));
+        load.finish(now, Duration::from_millis(1));
+        assert_eq!(None, exceeded(&mut load, now));
+
+        // Requests per second; the count resets after a second
+        load.start(now);
+        load.finish(now, Duration::from_millis(1));
+        assert_eq!(Some("rate"), exceeded(&mut load, now));
+        let later = now + Duration::from_secs(1);
+        assert_eq!(None, exceeded(&mut load, later));
+
+        // Effort
+        let mut load = ClientLoad::new("client".to_string(), now);
+        load.start(now);
+        load.finish(now, Duration::from_millis(150));
+        assert_eq!(Some("effort"), exceeded(&mut load, now));
+    }
+
+    #[test]
+    fn clients_are_bounded() {
+        fn label_count(metric: &dyn Collector) -> usize {
+            metric
+                .collect()
+                .iter()
+                .map(|family| family.get_metric().len())
+                .sum()
+        }
+
+        let decisions = CounterVec::new(
+            Opts::new("query_client_decisions", "decisions"),
+            &["client", "decision"],
+        )
+        .unwrap();
+        let effort_gauge =
+            GaugeVec::new(Opts::new("query_client_effort_ms", "effort"), &["client"]).unwrap();
+        let clients = Arc::new(Clients::with_metrics(
+            Logger::root(slog::Discard, o!()),
+            ClientLimits::default(),
+            2,
+            Box::new(decisions),
+            Box::new(effort_gauge),
+        ));
+
+        // Rotating client ids only tracks the first two clients separately
+        // and all others together
+        for i in 0..10 {
+            clients.admit(&format!("client-{}", i)).unwrap();
+        }
+        {
+            let inner = clients.inner.lock().unwrap();
+            assert_eq!(3, inner.clients.len());
+            assert!(inner.clients.values().any(|load| load.label == "other"));
+        }
+        assert_eq!(3, label_count(clients.decisions.as_ref()));
+        assert_eq!(3, label_count(clients.effort_gauge.as_ref()));
+
+        // Pruning idle clients removes all their labels
+        let later = Instant::now() + ENV_VARS.load_window_size * 2;
+        clients.prune(&mut clients.inner.lock().unwrap(), later);
+        assert!(clients.inner.lock().unwrap().clients.is_empty());
+        assert_eq!(0, label_count(clients.decisions.as_ref()));
+        assert_eq!(0, label_count(clients.effort_gauge.as_ref()));
+    }
+}
@@ -9,7 +9,7 @@ use std::string::FromUtf8Error;
 use std::sync::Arc;
 
 use crate::data::subgraph::*;
-use crate::prelude::q;
+use crate::prelude::{q, BlockNumber};
 use crate::{components::store::StoreError, prelude::CacheWeight};
 
 #[derive(Debug)]
@@ -71,16 +71,21 @@ pub enum QueryExecutionError {
     CyclicalFragment(String),
     TooExpensive,
     Throttled,
+    ClientLimitExceeded(String), // limit
     UndefinedFragment(String),
     Panic(String),
     EventStreamError,
     FulltextQueryRequiresFilter,
     FulltextQueryInvalidSyntax(String),
+    InvalidCursor(String),
+    PersistedQueryNotFound,
+    PersistedQueryRequired(String),
     DeploymentReverted,
     SubgraphManifestResolveError(Arc<SubgraphManifestResolveError>),
     InvalidSubgraphManifest,
     ResultTooBig(usize, usize),
     DeploymentNotFound(String),
+    BlockBeforeEarliest(String, BlockNumber, BlockNumber), // (deployment, earliest, requested)
 }
 
 impl QueryExecutionError {
@@ -115,6 +120,7 @@ impl QueryExecutionError {
             | CyclicalFragment(_)
             | UndefinedFragment(_)
             | FulltextQueryInvalidSyntax(_)
+            | InvalidCursor(_)
             | FulltextQueryRequiresFilter => true,
             ListValueError(_, _)
             | ResolveEntitiesError(_)
@@ -132,12 +138,16 @@ impl QueryExecutionError {
             | EventStreamError
             | TooExpensive
             | Throttled
+            | ClientLimitExceeded(_)
             | DeploymentReverted
             | SubgraphManifestResolveError(_)
             | InvalidSubgraphManifest
             | ValidationError(_, _)
             | ResultTooBig(_, _)
-            | DeploymentNotFound(_) => false,
+            | DeploymentNotFound(_)
+            | BlockBeforeEarliest(_, _, _)
+            | PersistedQueryNotFound
+            | PersistedQueryRequired(_) => false,
         }
     }
 }
@@ -275,13 +285,22 @@ impl fmt::Display for QueryExecutionError {
             EventStreamError => write!(f, "error in the subscription event stream"),
             FulltextQueryRequiresFilter => write!(f, "fulltext search queries can only use EntityFilter::Equal"),
             FulltextQueryInvalidSyntax(msg) => write!(f, "Invalid fulltext search query syntax. Error: {}. Hint: Search terms with spaces need to be enclosed in single quotes", msg),
+            InvalidCursor(msg) => write!(f, "invalid cursor for `after`: {}", msg),
+            // Clients that use automatic persisted queries look for this
+            // exact message to know that they need to send the query text
+            PersistedQueryNotFound => write!(f, "PersistedQueryNotFound"),
+            PersistedQueryRequired(id) => write!(f, "deployment `{}` only accepts persisted queries", id),
             TooExpensive => write!(f, "query is too expensive"),
             Throttled => write!(f, "service is overloaded and can not run the query right now. Please try again in a few minutes"),
+            ClientLimitExceeded(limit) => write!(f, "client exceeded its limit of {}. Please try again later", limit),
             DeploymentReverted => write!(f, "the chain was reorganized while executing the query"),
             SubgraphManifestResolveError(e) => write!(f, "failed to resolve subgraph manifest: {}", e),
             InvalidSubgraphManifest => write!(f, "invalid subgraph manifest file"),
             ResultTooBig(actual, limit) => write!(f, "the result size of {} is larger than the allowed limit of {}", actual, limit),
-            DeploymentNotFound(id_or_name) => write!(f, "deployment `{}` does not exist", id_or_name)
+            DeploymentNotFound(id_or_name) => write!(f, "deployment `{}` does not exist", id_or_name),
+            BlockBeforeEarliest(id, earliest, block) => write!(f, "subgraph {} only has data starting at block number {} \
+                           and data for block number {} is therefore not available. Blocks before \
+                           the earliest block have either not been indexed or have been pruned", id, earliest, block)
         }
     }
 }
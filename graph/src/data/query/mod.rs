@@ -2,8 +2,10 @@ mod cache_status;
 mod error;
 mod query;
 mod result;
+mod trace;
 
 pub use self::cache_status::CacheStatus;
 pub use self::error::{QueryError, QueryExecutionError};
 pub use self::query::{Query, QueryTarget, QueryVariables};
 pub use self::result::{QueryResult, QueryResults};
+pub use self::trace::Trace;
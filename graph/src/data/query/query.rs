@@ -136,6 +136,13 @@ pub struct Query {
     pub shape_hash: u64,
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
+    /// The id of the query if the client sent it as a persisted query
+    pub persisted_id: Option<Arc<String>>,
+    /// Whether the query is on the allow-list of persisted queries
+    pub allow_listed: bool,
+    /// Whether to collect a trace of how the query was executed and send
+    /// it back with the result
+    pub trace: bool,
     _force_use_of_new: (),
 }
 
@@ -160,7 +167,17 @@ impl Query {
             shape_hash,
             query_text: Arc::new(query_text),
             variables_text: Arc::new(variables_text),
+            persisted_id: None,
+            allow_listed: false,
+            trace: false,
             _force_use_of_new: (),
         }
     }
+
+    /// Mark this query as the persisted query with the given `id`
+    pub fn persisted(mut self, id: impl Into<String>, allow_listed: bool) -> Self {
+        self.persisted_id = Some(Arc::new(id.into()));
+        self.allow_listed = allow_listed;
+        self
+    }
 }
@@ -1,4 +1,5 @@
 use super::error::{QueryError, QueryExecutionError};
+use super::trace::Trace;
 use crate::data::value::Object;
 use crate::prelude::{r, CacheWeight, DeploymentHash};
 use http::header::{
@@ -85,6 +86,10 @@ impl Serialize for QueryResults {
         if has_errors {
             len += 1;
         }
+        let has_trace = self.results.iter().any(|r| !r.trace.is_none());
+        if has_trace {
+            len += 1;
+        }
 
         let mut state = serializer.serialize_struct("QueryResults", len)?;
 
@@ -121,6 +126,17 @@ impl Serialize for QueryResults {
             state.serialize_field("errors", &SerError(self))?;
         }
 
+        // Serialize traces, one for each block at which the query ran
+        if has_trace {
+            let traces: Vec<_> = self
+                .results
+                .iter()
+                .map(|r| &r.trace)
+                .filter(|trace| !trace.is_none())
+                .collect();
+            state.serialize_field("trace", &traces)?;
+        }
+
         state.end()
     }
 }
@@ -199,6 +215,9 @@ pub struct QueryResult {
     errors: Vec<QueryError>,
     #[serde(skip_serializing)]
     pub deployment: Option<DeploymentHash>,
+    /// How the query was executed, if the query asked for a trace
+    #[serde(skip_serializing)]
+    pub trace: Trace,
 }
 
 impl QueryResult {
@@ -207,6 +226,7 @@ impl QueryResult {
             data: Some(data),
             errors: Vec::new(),
             deployment: None,
+            trace: Trace::None,
         }
     }
 
@@ -219,6 +239,7 @@ impl QueryResult {
             data: self.data.clone(),
             errors: self.errors.clone(),
             deployment: self.deployment.clone(),
+            trace: self.trace.clone(),
         }
     }
 
@@ -274,6 +295,7 @@ impl From<QueryExecutionError> for QueryResult {
             data: None,
             errors: vec![e.into()],
             deployment: None,
+            trace: Trace::None,
         }
     }
 }
@@ -284,6 +306,7 @@ impl From<QueryError> for QueryResult {
             data: None,
             errors: vec![e],
             deployment: None,
+            trace: Trace::None,
         }
     }
 }
@@ -294,6 +317,7 @@ impl From<Vec<QueryExecutionError>> for QueryResult {
             data: None,
             errors: e.into_iter().map(QueryError::from).collect(),
             deployment: None,
+            trace: Trace::None,
         }
     }
 }
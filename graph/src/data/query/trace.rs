@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use crate::prelude::BlockNumber;
+
+/// Information about how a query was executed: the SQL queries that were
+/// run for each field of the query, their query plans, and how long the
+/// different steps of execution took. Traces are only collected when a
+/// query asks for it, and are meant for debugging slow queries
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trace {
+    None,
+    /// The execution of the query at one block
+    Root {
+        block: BlockNumber,
+        elapsed: Duration,
+        /// How long each step of execution took, in the order in which
+        /// the steps ran
+        steps: Vec<(&'static str, Duration)>,
+        children: Vec<(String, Trace)>,
+    },
+    /// The SQL query that was run to fetch a field
+    Query {
+        sql: String,
+        /// The output of `EXPLAIN (ANALYZE, BUFFERS)` for `sql`, one entry
+        /// per line
+        explain: Vec<String>,
+        elapsed: Duration,
+        entity_count: usize,
+        children: Vec<(String, Trace)>,
+    },
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Trace::None
+    }
+}
+
+impl Trace {
+    /// Start a trace for executing a query at `block` if `enabled` is
+    /// true, and return `Trace::None` otherwise
+    pub fn root(enabled: bool, block: BlockNumber) -> Trace {
+        if enabled {
+            Trace::Root {
+                block,
+                elapsed: Duration::ZERO,
+                steps: Vec::new(),
+                children: Vec::new(),
+            }
+        } else {
+            Trace::None
+        }
+    }
+
+    pub fn query(
+        sql: String,
+        explain: Vec<String>,
+        elapsed: Duration,
+        entity_count: usize,
+    ) -> Trace {
+        Trace::Query {
+            sql,
+            explain,
+            elapsed,
+            entity_count,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, Trace::None)
+    }
+
+    /// Add the trace for the field with response key `name`. Does nothing
+    /// if `self` or `trace` is `Trace::None`
+    pub fn push(&mut self, name: &str, trace: Trace) {
+        if trace.is_none() {
+            return;
+        }
+        match self {
+            Trace::None => {}
+            Trace::Root { children, .. } | Trace::Query { children, .. } => {
+                children.push((name.to_string(), trace))
+            }
+        }
+    }
+
+    /// Record that the step `name` took `duration`. Only root traces
+    /// record steps
+    pub fn step(&mut self, name: &'static str, duration: Duration) {
+        if let Trace::Root { steps, elapsed, .. } = self {
+            steps.push((name, duration));
+            *elapsed += duration;
+        }
+    }
+}
+
+struct Children<'a>(&'a [(String, Trace)]);
+
+impl Serialize for Children<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, trace) in self.0 {
+            map.serialize_entry(name, trace)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Trace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Trace::None => serializer.serialize_none(),
+            Trace::Root {
+                block,
+                elapsed,
+                steps,
+                children,
+            } => {
+                let mut map = serializer.serialize_map(Some(4))?;
+                map.serialize_entry("block", block)?;
+                map.serialize_entry("elapsed_ms", &(elapsed.as_millis() as u64))?;
+                let steps: Vec<_> = steps
+                    .iter()
+                    .map(|(name, duration)| (*name, duration.as_millis() as u64))
+                    .collect();
+                map.serialize_entry("steps_ms", &steps)?;
+                map.serialize_entry("fields", &Children(children))?;
+                map.end()
+            }
+            Trace::Query {
+                sql,
+                explain,
+                elapsed,
+                entity_count,
+                children,
+            } => {
+                let mut map = serializer.serialize_map(Some(5))?;
+                map.serialize_entry("sql", sql)?;
+                map.serialize_entry("explain", explain)?;
+                map.serialize_entry("elapsed_ms", &(elapsed.as_millis() as u64))?;
+                map.serialize_entry("entity_count", entity_count)?;
+                map.serialize_entry("fields", &Children(children))?;
+                map.end()
+            }
+        }
+    }
+}
+
+#[test]
+fn serialize_trace() {
+    use serde_json::json;
+
+    let mut field = Trace::query(
+        "select 1".to_string(),
+        vec!["Result".to_string()],
+        Duration::from_millis(3),
+        1,
+    );
+    field.push("child", Trace::None);
+    let mut root = Trace::root(true, 7);
+    root.push("things", field);
+    root.step("prefetch", Duration::from_millis(5));
+    root.step("resolve", Duration::from_millis(2));
+
+    let expected = json!({
+        "block": 7,
+        "elapsed_ms": 7,
+        "steps_ms": [["prefetch", 5], ["resolve", 2]],
+        "fields": {
+            "things": {
+                "sql": "select 1",
+                "explain": ["Result"],
+                "elapsed_ms": 3,
+                "entity_count": 1,
+                "fields": {}
+            }
+        }
+    });
+    assert_eq!(expected, serde_json::to_value(&root).unwrap());
+
+    let mut none = Trace::root(false, 7);
+    none.push(
+        "things",
+        Trace::query(String::new(), vec![], Duration::ZERO, 0),
+    );
+    assert!(none.is_none());
+}
@@ -35,6 +35,11 @@ pub const META_FIELD_NAME: &str = "_meta";
 
 pub const BLOCK_FIELD_TYPE: &str = "_Block_";
 
+/// The fields that hold the rank of an entity found by a fulltext search
+/// and an excerpt of its text with the matches highlighted
+pub const FULLTEXT_RANK_FIELD: &str = "_rank";
+pub const FULLTEXT_SNIPPET_FIELD: &str = "_snippet";
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Strings(Vec<String>);
 
@@ -112,6 +117,10 @@ pub enum SchemaValidationError {
     FulltextIncludedFieldMissingRequiredProperty,
     #[error("Fulltext entity field, {0}, not found or not a string")]
     FulltextIncludedFieldInvalid(String),
+    #[error("Fulltext field weight is invalid: {0}")]
+    FulltextIncludedFieldWeightInvalid(String),
+    #[error("Fulltext directive `{0}` includes several entities that do not all implement a common interface")]
+    FulltextIncludedEntitiesWithoutInterface(String),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -211,16 +220,72 @@ pub struct FulltextConfig {
     pub algorithm: FulltextAlgorithm,
 }
 
+/// The weight of a field included in a fulltext search. Matches in fields
+/// with a higher weight lead to a higher rank; `A` is the highest and `D`
+/// the lowest weight
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FulltextWeight {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl TryFrom<&str> for FulltextWeight {
+    type Error = String;
+    fn try_from(weight: &str) -> Result<Self, Self::Error> {
+        match weight {
+            "A" => Ok(FulltextWeight::A),
+            "B" => Ok(FulltextWeight::B),
+            "C" => Ok(FulltextWeight::C),
+            "D" => Ok(FulltextWeight::D),
+            invalid => Err(format!(
+                "The provided fulltext field weight {} is invalid. It must be one of: A, B, C, D",
+                invalid,
+            )),
+        }
+    }
+}
+
+impl FulltextWeight {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FulltextWeight::A => "A",
+            FulltextWeight::B => "B",
+            FulltextWeight::C => "C",
+            FulltextWeight::D => "D",
+        }
+    }
+
+    /// The weight of the included field `field`, which is an object like
+    /// `{ name: "bio", weight: B }`. Fields without a weight get the
+    /// lowest weight, `D`, which is also what Postgres uses for text that
+    /// has not been weighted
+    fn from_field(field: &Value) -> Result<Self, String> {
+        match field.as_object().and_then(|field| field.get("weight")) {
+            None => Ok(FulltextWeight::D),
+            Some(Value::Enum(weight)) | Some(Value::String(weight)) => {
+                FulltextWeight::try_from(weight.as_str())
+            }
+            Some(weight) => Err(weight.to_string()),
+        }
+    }
+}
+
 pub struct FulltextDefinition {
     pub config: FulltextConfig,
-    pub included_fields: HashSet<String>,
+    /// The fields of the entity type that the search includes and their
+    /// weights
+    pub included_fields: BTreeMap<String, FulltextWeight>,
     pub name: String,
 }
 
-impl From<&s::Directive> for FulltextDefinition {
-    // Assumes the input is a Fulltext Directive that has already been validated because it makes
-    // liberal use of unwrap() where specific types are expected
-    fn from(directive: &Directive) -> Self {
+impl FulltextDefinition {
+    /// The definition of the fulltext search `directive` for the entity
+    /// type `entity`. Assumes the input is a Fulltext Directive that has
+    /// already been validated because it makes liberal use of unwrap()
+    /// where specific types are expected
+    pub fn new(directive: &Directive, entity: &str) -> Self {
         let name = directive.argument("name").unwrap().as_str().unwrap();
 
         let algorithm = FulltextAlgorithm::try_from(
@@ -232,21 +297,24 @@ impl From<&s::Directive> for FulltextDefinition {
             FulltextLanguage::try_from(directive.argument("language").unwrap().as_enum().unwrap())
                 .unwrap();
 
+        // A directive can include several entities; each of them gets its
+        // own fulltext column with the fields included for it
         let included_entity_list = directive.argument("include").unwrap().as_list().unwrap();
-        // Currently fulltext query fields are limited to 1 entity, so we just take the first (and only) included Entity
-        let included_entity = included_entity_list.first().unwrap().as_object().unwrap();
-        let included_field_values = included_entity.get("fields").unwrap().as_list().unwrap();
-        let included_fields: HashSet<String> = included_field_values
+        let included_fields = included_entity_list
             .iter()
+            .map(|include| include.as_object().unwrap())
+            .filter(|include| include.get("entity").unwrap().as_str() == Some(entity))
+            .flat_map(|include| include.get("fields").unwrap().as_list().unwrap())
             .map(|field| {
-                field
+                let name = field
                     .as_object()
                     .unwrap()
                     .get("name")
                     .unwrap()
                     .as_str()
                     .unwrap()
-                    .into()
+                    .to_string();
+                (name, FulltextWeight::from_field(field).unwrap())
             })
             .collect();
 
@@ -259,7 +327,49 @@ impl From<&s::Directive> for FulltextDefinition {
             name: name.into(),
         }
     }
+
+    /// The names of the entity types that the fulltext directive
+    /// `directive` includes
+    pub fn included_entities(directive: &Directive) -> Vec<&str> {
+        match directive.argument("include") {
+            Some(Value::List(includes)) => includes
+                .iter()
+                .filter_map(|include| include.as_object())
+                .filter_map(|include| include.get("entity").and_then(|entity| entity.as_str()))
+                .unique()
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The interface that all entity types included in the fulltext
+    /// directive `directive` implement, and that fulltext queries return
+    /// when the directive includes more than one entity type. When there
+    /// are several such interfaces, use the one that the first included
+    /// entity type lists first. Returns `None` if the directive only
+    /// includes one entity type or if there is no such interface
+    pub fn common_interface<'a>(directive: &Directive, document: &'a Document) -> Option<&'a str> {
+        let entities = Self::included_entities(directive);
+        if entities.len() < 2 {
+            return None;
+        }
+        let object_types: Vec<_> = entities
+            .iter()
+            .map(|entity| document.get_object_type_definition(entity))
+            .collect::<Option<_>>()?;
+        object_types
+            .first()?
+            .implements_interfaces
+            .iter()
+            .find(|intf| {
+                object_types
+                    .iter()
+                    .all(|object_type| object_type.implements_interfaces.contains(*intf))
+            })
+            .map(|intf| intf.as_str())
+    }
 }
+
 #[derive(Debug, Error, PartialEq, Eq, Clone)]
 pub enum SchemaImportError {
     #[error("Schema for imported subgraph `{0}` was not found")]
@@ -1172,10 +1282,31 @@ impl Schema {
                                 field_name.clone(),
                             )];
                         };
+
+                        if let Err(weight) = FulltextWeight::from_field(field_value) {
+                            return vec![
+                                SchemaValidationError::FulltextIncludedFieldWeightInvalid(weight),
+                            ];
+                        }
                     }
                 }
             }
         }
+
+        // Queries for a fulltext search over several entity types return
+        // an interface that all of them implement
+        if FulltextDefinition::included_entities(fulltext).len() > 1
+            && FulltextDefinition::common_interface(fulltext, &self.document).is_none()
+        {
+            let name = fulltext
+                .argument("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+            return vec![
+                SchemaValidationError::FulltextIncludedEntitiesWithoutInterface(name.to_string()),
+            ];
+        }
+
         // Fulltext include validations all passed, so we return an empty vector
         return vec![];
     }
@@ -1316,6 +1447,8 @@ impl Schema {
             "String".into(),
             "Bytes".into(),
             "BigInt".into(),
+            "Int8".into(),
+            "Timestamp".into(),
             // Reserved Query and Subscription types
             "Query".into(),
             "Subscription".into(),
@@ -1571,7 +1704,7 @@ impl Schema {
                 }
                 _ => false,
             })
-            .map(FulltextDefinition::from)
+            .map(|directive| FulltextDefinition::new(directive, entity))
             .collect())
     }
 }
@@ -1862,6 +1995,8 @@ fn test_reserved_types_validation() {
         "String",
         "Bytes",
         "BigInt",
+        "Int8",
+        "Timestamp",
         // Reserved keywords
         "Query",
         "Subscription",
@@ -1952,3 +2087,85 @@ type Gravatar @entity {
 
     assert_eq!(schema.validate_fulltext_directives(), vec![]);
 }
+
+#[test]
+fn test_fulltext_directive_weights_and_interfaces() {
+    fn schema(weight: &str, implements: &str) -> Schema {
+        let schema = format!(
+            r#"
+type _Schema_ @fulltext(
+  name: "search"
+  language: en
+  algorithm: rank
+  include: [
+    {{
+      entity: "Gravatar",
+      fields: [
+        {{ name: "displayName", weight: {weight} }},
+        {{ name: "imageUrl"}},
+      ]
+    }},
+    {{
+      entity: "Profile",
+      fields: [{{ name: "bio", weight: B }}]
+    }}
+  ]
+)
+interface Named {{
+  id: ID!
+}}
+type Gravatar implements Named @entity {{
+  id: ID!
+  displayName: String!
+  imageUrl: String!
+}}
+type Profile {implements} @entity {{
+  id: ID!
+  bio: String
+}}"#
+        );
+        let document = graphql_parser::parse_schema(&schema).expect("Failed to parse schema");
+        Schema::new(DeploymentHash::new("id1").unwrap(), document).unwrap()
+    }
+
+    let valid = schema("A", "implements Named");
+    assert_eq!(valid.validate_fulltext_directives(), vec![]);
+
+    let directive = valid
+        .subgraph_schema_object_type()
+        .and_then(|schema_type| schema_type.find_directive("fulltext"))
+        .unwrap();
+    assert_eq!(
+        vec!["Gravatar", "Profile"],
+        FulltextDefinition::included_entities(directive)
+    );
+    assert_eq!(
+        Some("Named"),
+        FulltextDefinition::common_interface(directive, &valid.document)
+    );
+    let gravatar = FulltextDefinition::new(directive, "Gravatar");
+    assert_eq!(
+        vec![
+            ("displayName".to_string(), FulltextWeight::A),
+            ("imageUrl".to_string(), FulltextWeight::D)
+        ],
+        gravatar.included_fields.into_iter().collect::<Vec<_>>()
+    );
+    let profile = FulltextDefinition::new(directive, "Profile");
+    assert_eq!(
+        vec![("bio".to_string(), FulltextWeight::B)],
+        profile.included_fields.into_iter().collect::<Vec<_>>()
+    );
+
+    assert_eq!(
+        schema("E", "implements Named").validate_fulltext_directives(),
+        vec![SchemaValidationError::FulltextIncludedFieldWeightInvalid(
+            "The provided fulltext field weight E is invalid. It must be one of: A, B, C, D"
+                .to_string()
+        )]
+    );
+    assert_eq!(
+        schema("A", "").validate_fulltext_directives(),
+        vec![SchemaValidationError::FulltextIncludedEntitiesWithoutInterface("search".to_string())]
+    );
+}
@@ -139,6 +139,8 @@ pub const ID: &str = "ID";
 pub const BYTES_SCALAR: &str = "Bytes";
 pub const BIG_INT_SCALAR: &str = "BigInt";
 pub const BIG_DECIMAL_SCALAR: &str = "BigDecimal";
+pub const INT8_SCALAR: &str = "Int8";
+pub const TIMESTAMP_SCALAR: &str = "Timestamp";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ValueType {
@@ -147,7 +149,9 @@ pub enum ValueType {
     Bytes,
     BigDecimal,
     Int,
+    Int8,
     String,
+    Timestamp,
 }
 
 impl FromStr for ValueType {
@@ -160,6 +164,8 @@ impl FromStr for ValueType {
             "Bytes" => Ok(ValueType::Bytes),
             "BigDecimal" => Ok(ValueType::BigDecimal),
             "Int" => Ok(ValueType::Int),
+            "Int8" => Ok(ValueType::Int8),
+            "Timestamp" => Ok(ValueType::Timestamp),
             "String" | "ID" => Ok(ValueType::String),
             s => Err(anyhow!("Type not available in this context: {}", s)),
         }
@@ -187,6 +193,8 @@ pub enum Value {
     Null,
     Bytes(scalar::Bytes),
     BigInt(scalar::BigInt),
+    Int8(i64),
+    Timestamp(scalar::Timestamp),
 }
 
 impl stable_hash_legacy::StableHash for Value {
@@ -231,6 +239,12 @@ impl stable_hash_legacy::StableHash for Value {
             BigInt(inner) => {
                 stable_hash_legacy::StableHash::stable_hash(inner, sequence_number, state)
             }
+            Int8(inner) => {
+                stable_hash_legacy::StableHash::stable_hash(inner, sequence_number, state)
+            }
+            Timestamp(inner) => {
+                stable_hash_legacy::StableHash::stable_hash(inner, sequence_number, state)
+            }
         }
     }
 }
@@ -274,6 +288,14 @@ impl StableHash for Value {
                 inner.stable_hash(field_address.child(0), state);
                 7
             }
+            Int8(inner) => {
+                inner.stable_hash(field_address.child(0), state);
+                8
+            }
+            Timestamp(inner) => {
+                inner.stable_hash(field_address.child(0), state);
+                9
+            }
         };
 
         state.write(field_address, &[variant])
@@ -309,9 +331,21 @@ impl Value {
                     BYTES_SCALAR => Value::Bytes(scalar::Bytes::from_str(s)?),
                     BIG_INT_SCALAR => Value::BigInt(scalar::BigInt::from_str(s)?),
                     BIG_DECIMAL_SCALAR => Value::BigDecimal(scalar::BigDecimal::from_str(s)?),
+                    INT8_SCALAR => Value::Int8(s.parse::<i64>().map_err(|e| {
+                        QueryExecutionError::ValueParseError(INT8_SCALAR.to_string(), e.to_string())
+                    })?),
+                    TIMESTAMP_SCALAR => {
+                        Value::Timestamp(scalar::Timestamp::from_str(s).map_err(|e| {
+                            QueryExecutionError::ValueParseError(
+                                TIMESTAMP_SCALAR.to_string(),
+                                e.to_string(),
+                            )
+                        })?)
+                    }
                     _ => Value::String(s.clone()),
                 }
             }
+            (r::Value::Int(i), NamedType(n)) if n == INT8_SCALAR => Value::Int8(*i),
             (r::Value::Int(i), _) => Value::Int(*i as i32),
             (r::Value::Boolean(b), _) => Value::Bool(b.to_owned()),
             (r::Value::Null, _) => Value::Null,
@@ -352,6 +386,22 @@ impl Value {
         }
     }
 
+    pub fn as_int8(&self) -> Option<i64> {
+        if let Value::Int8(i) = self {
+            Some(*i)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<scalar::Timestamp> {
+        if let Value::Timestamp(ts) = self {
+            Some(*ts)
+        } else {
+            None
+        }
+    }
+
     pub fn as_big_decimal(self) -> Option<scalar::BigDecimal> {
         if let Value::BigDecimal(d) = self {
             Some(d)
@@ -400,6 +450,7 @@ impl Value {
             Value::Bool(_) => "Boolean".to_owned(),
             Value::Bytes(_) => "Bytes".to_owned(),
             Value::Int(_) => "Int".to_owned(),
+            Value::Int8(_) => "Int8".to_owned(),
             Value::List(values) => {
                 if let Some(v) = values.first() {
                     format!("[{}]", v.type_name())
@@ -409,6 +460,7 @@ impl Value {
             }
             Value::Null => "Null".to_owned(),
             Value::String(_) => "String".to_owned(),
+            Value::Timestamp(_) => "Timestamp".to_owned(),
         }
     }
 
@@ -420,6 +472,8 @@ impl Value {
             | (Value::Bool(_), ValueType::Boolean)
             | (Value::Bytes(_), ValueType::Bytes)
             | (Value::Int(_), ValueType::Int)
+            | (Value::Int8(_), ValueType::Int8)
+            | (Value::Timestamp(_), ValueType::Timestamp)
             | (Value::Null, _) => true,
             (Value::List(values), _) if is_list => values
                 .iter()
@@ -444,6 +498,8 @@ impl fmt::Display for Value {
                     format!("[{}]", values.iter().map(ToString::to_string).join(", ")),
                 Value::Bytes(ref bytes) => bytes.to_string(),
                 Value::BigInt(ref number) => number.to_string(),
+                Value::Int8(i) => i.to_string(),
+                Value::Timestamp(ts) => ts.to_string(),
             }
         )
     }
@@ -460,6 +516,8 @@ impl fmt::Debug for Value {
             Self::Null => write!(f, "Null"),
             Self::Bytes(bytes) => bytes.fmt(f),
             Self::BigInt(number) => number.fmt(f),
+            Self::Int8(i) => f.debug_tuple("Int8").field(i).finish(),
+            Self::Timestamp(ts) => ts.fmt(f),
         }
     }
 }
@@ -477,6 +535,8 @@ impl From<Value> for q::Value {
             }
             Value::Bytes(bytes) => q::Value::String(bytes.to_string()),
             Value::BigInt(number) => q::Value::String(number.to_string()),
+            Value::Int8(i) => q::Value::String(i.to_string()),
+            Value::Timestamp(ts) => q::Value::String(ts.to_string()),
         }
     }
 }
@@ -494,6 +554,8 @@ impl From<Value> for r::Value {
             }
             Value::Bytes(bytes) => r::Value::String(bytes.to_string()),
             Value::BigInt(number) => r::Value::String(number.to_string()),
+            Value::Int8(i) => r::Value::String(i.to_string()),
+            Value::Timestamp(ts) => r::Value::String(ts.to_string()),
         }
     }
 }
@@ -534,6 +596,18 @@ impl From<i32> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Int8(value)
+    }
+}
+
+impl From<scalar::Timestamp> for Value {
+    fn from(value: scalar::Timestamp) -> Value {
+        Value::Timestamp(value)
+    }
+}
+
 impl From<scalar::BigDecimal> for Value {
     fn from(value: scalar::BigDecimal) -> Value {
         Value::BigDecimal(value)
@@ -1,3 +1,4 @@
+use chrono::{DateTime, TimeZone, Timelike, Utc};
 use diesel::deserialize::FromSql;
 use diesel::serialize::ToSql;
 use diesel_derives::{AsExpression, FromSqlRow};
@@ -525,6 +526,108 @@ impl Shr<u8> for BigInt {
     }
 }
 
+/// A point in time with microsecond precision. It is exposed to GraphQL
+/// and mappings as the number of microseconds since the Unix epoch, and
+/// stored as a `timestamptz` in the database.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(DateTime<Utc>);
+
+#[derive(Error, Debug)]
+#[error("invalid timestamp `{0}`: expected microseconds since the epoch or an RFC 3339 date")]
+pub struct TimestampParseError(String);
+
+impl Timestamp {
+    /// Create a timestamp from the number of microseconds since the Unix
+    /// epoch. Returns `None` if `micros` is out of the range that can be
+    /// represented
+    pub fn from_microseconds_since_epoch(micros: i64) -> Option<Self> {
+        let secs = micros.div_euclid(1_000_000);
+        let nanos = (micros.rem_euclid(1_000_000) * 1_000) as u32;
+        Utc.timestamp_opt(secs, nanos).single().map(Timestamp)
+    }
+
+    pub fn as_microseconds_since_epoch(&self) -> i64 {
+        self.0.timestamp() * 1_000_000 + self.0.timestamp_subsec_micros() as i64
+    }
+
+    pub fn as_datetime(&self) -> &DateTime<Utc> {
+        &self.0
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(dt: DateTime<Utc>) -> Self {
+        // Drop anything beyond microsecond precision so that values
+        // roundtrip through the database unchanged
+        let nanos = dt.nanosecond() / 1_000 * 1_000;
+        Timestamp(dt.with_nanosecond(nanos).unwrap_or(dt))
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_microseconds_since_epoch())
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Timestamp({})", self.0.to_rfc3339())
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampParseError;
+
+    /// Parse either the number of microseconds since the epoch, or an
+    /// RFC 3339 date like `2022-06-01T12:00:00Z`
+    fn from_str(s: &str) -> Result<Timestamp, Self::Err> {
+        match i64::from_str(s) {
+            Ok(micros) => Timestamp::from_microseconds_since_epoch(micros),
+            Err(_) => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|dt| Timestamp::from(dt.with_timezone(&Utc))),
+        }
+        .ok_or_else(|| TimestampParseError(s.to_string()))
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let micros = <String>::deserialize(deserializer)?;
+        Timestamp::from_str(&micros).map_err(D::Error::custom)
+    }
+}
+
+impl stable_hash_legacy::StableHash for Timestamp {
+    fn stable_hash<H: stable_hash_legacy::StableHasher>(
+        &self,
+        sequence_number: H::Seq,
+        state: &mut H,
+    ) {
+        stable_hash_legacy::StableHash::stable_hash(
+            &self.as_microseconds_since_epoch(),
+            sequence_number,
+            state,
+        )
+    }
+}
+
+impl StableHash for Timestamp {
+    fn stable_hash<H: stable_hash::StableHasher>(&self, field_address: H::Addr, state: &mut H) {
+        self.as_microseconds_since_epoch()
+            .stable_hash(field_address, state)
+    }
+}
+
 /// A byte array that's serialized as a hex string prefixed by `0x`.
 #[derive(Clone, PartialEq, Eq)]
 pub struct Bytes(Box<[u8]>);
@@ -631,7 +734,7 @@ impl From<Vec<u8>> for Bytes {
 
 #[cfg(test)]
 mod test {
-    use super::{BigDecimal, BigInt, Bytes};
+    use super::{BigDecimal, BigInt, Bytes, Timestamp};
     use stable_hash_legacy::crypto::SetHasher;
     use stable_hash_legacy::prelude::*;
     use stable_hash_legacy::utils::stable_hash;
@@ -741,4 +844,17 @@ mod test {
         assert_eq!("BigDecimal(-0.17)", format!("{:?}", bd));
         assert_eq!("Bytes(0xdeadbeef)", format!("{:?}", bytes));
     }
+
+    #[test]
+    fn timestamp_roundtrip() {
+        for micros in [0, 1, -1, 1_654_084_800_123_456, -86_400_000_001] {
+            let ts = Timestamp::from_microseconds_since_epoch(micros).unwrap();
+            assert_eq!(micros, ts.as_microseconds_since_epoch());
+            assert_eq!(ts, Timestamp::from_str(&ts.to_string()).unwrap());
+        }
+
+        let ts = Timestamp::from_str("2022-06-01T12:00:00.123456Z").unwrap();
+        assert_eq!(1_654_084_800_123_456, ts.as_microseconds_since_epoch());
+        assert!(Timestamp::from_str("yesterday").is_err());
+    }
 }
@@ -31,6 +31,7 @@ use crate::data::{
     schema::{Schema, SchemaImportError, SchemaValidationError},
     subgraph::features::validate_subgraph_features,
 };
+use crate::data_source::offchain;
 use crate::prelude::{r, CheapClone, ENV_VARS};
 use crate::{blockchain::DataSource, data::graphql::TryFromValue};
 use crate::{blockchain::DataSourceTemplate as _, data::query::QueryExecutionError};
@@ -511,6 +512,11 @@ pub struct BaseSubgraphManifest<C, S, D, T> {
     pub graft: Option<Graft>,
     #[serde(default)]
     pub templates: Vec<T>,
+    /// Templates for offchain data sources. They are listed under
+    /// `templates` in the manifest, but are taken out of that list before
+    /// the chain parses its templates; see `take_offchain_templates`
+    #[serde(skip)]
+    pub offchain_templates: Vec<offchain::DataSourceTemplate>,
     #[serde(skip_serializing, default)]
     pub chain: PhantomData<C>,
 }
@@ -573,6 +579,25 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
             }));
         }
 
+        // Validate that offchain templates can be told apart from all other
+        // templates by their name, since that is how mappings create them
+        for (i, template) in self.0.offchain_templates.iter().enumerate() {
+            let is_duplicate = self
+                .0
+                .templates
+                .iter()
+                .any(|other| other.name() == template.name)
+                || self.0.offchain_templates[..i]
+                    .iter()
+                    .any(|other| other.name == template.name);
+            if is_duplicate {
+                errors.push(SubgraphManifestValidationError::DataSourceValidation(
+                    template.name.clone(),
+                    anyhow!("there is more than one template with this name"),
+                ));
+            }
+        }
+
         // For API versions newer than 0.0.5, validate that all mappings uses the same api_version
         if let Err(different_api_versions) = self.0.unified_mapping_api_version() {
             errors.push(different_api_versions.into());
@@ -649,15 +674,27 @@ impl<C: Blockchain> SubgraphManifest<C> {
             serde_yaml::Value::from(id.to_string()),
         );
 
+        let offchain_templates = take_offchain_templates(&mut raw)?;
+
         // Parse the YAML data into an UnresolvedSubgraphManifest
         let unresolved: UnresolvedSubgraphManifest<C> = serde_yaml::from_value(raw.into())?;
 
         debug!(logger, "Features {:?}", unresolved.features);
 
-        unresolved
+        let mut manifest = unresolved
             .resolve(resolver, logger, max_spec_version)
             .await
-            .map_err(SubgraphManifestResolveError::ResolveError)
+            .map_err(SubgraphManifestResolveError::ResolveError)?;
+
+        manifest.offchain_templates = offchain_templates
+            .into_iter()
+            .map(|template| template.resolve(resolver, logger))
+            .collect::<FuturesOrdered<_>>()
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(SubgraphManifestResolveError::ResolveError)?;
+
+        Ok(manifest)
     }
 
     pub fn network_name(&self) -> String {
@@ -680,6 +717,11 @@ impl<C: Blockchain> SubgraphManifest<C> {
         self.templates
             .iter()
             .map(|template| template.api_version())
+            .chain(
+                self.offchain_templates
+                    .iter()
+                    .map(|template| template.mapping.api_version.clone()),
+            )
             .chain(self.data_sources.iter().map(|source| source.api_version()))
     }
 
@@ -687,6 +729,11 @@ impl<C: Blockchain> SubgraphManifest<C> {
         self.templates
             .iter()
             .map(|template| template.runtime())
+            .chain(
+                self.offchain_templates
+                    .iter()
+                    .map(|template| template.mapping.runtime.as_slice()),
+            )
             .chain(self.data_sources.iter().map(|source| source.runtime()))
     }
 
@@ -714,6 +761,7 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             data_sources,
             graft,
             templates,
+            offchain_templates,
             chain,
         } = self;
 
@@ -763,11 +811,37 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             data_sources,
             graft,
             templates,
+            offchain_templates,
             chain,
         })
     }
 }
 
+/// Remove the templates for offchain data sources from the `templates` in
+/// `raw` and parse them. Chains only know how to parse their own templates
+fn take_offchain_templates(
+    raw: &mut serde_yaml::Mapping,
+) -> Result<Vec<offchain::UnresolvedDataSourceTemplate>, serde_yaml::Error> {
+    let templates = match raw.get_mut(&serde_yaml::Value::from("templates")) {
+        Some(serde_yaml::Value::Sequence(templates)) => templates,
+        _ => return Ok(vec![]),
+    };
+
+    let (offchain_templates, chain_templates): (Vec<_>, Vec<_>) =
+        templates.drain(..).partition(|template| {
+            template
+                .get("kind")
+                .and_then(serde_yaml::Value::as_str)
+                .map_or(false, offchain::is_offchain_kind)
+        });
+    *templates = chain_templates;
+
+    offchain_templates
+        .into_iter()
+        .map(serde_yaml::from_value)
+        .collect()
+}
+
 /// Important details about the current state of a subgraph deployment
 /// used while executing queries against a deployment
 ///
@@ -796,19 +870,26 @@ impl DeploymentState {
         self.latest_block.number > 0
     }
 
-    pub fn block_queryable(&self, block: BlockNumber) -> Result<(), String> {
+    /// Check that the deployment can answer queries at `block`. Data for
+    /// blocks before `earliest_block_number` is not available, either
+    /// because the deployment started indexing later or because historical
+    /// entity versions for these blocks have been pruned
+    pub fn block_queryable(&self, block: BlockNumber) -> Result<(), QueryExecutionError> {
         if block > self.latest_block.number {
-            return Err(format!(
-                "subgraph {} has only indexed up to block number {} \
+            return Err(QueryExecutionError::ValueParseError(
+                "block.number".to_owned(),
+                format!(
+                    "subgraph {} has only indexed up to block number {} \
                         and data for block number {} is therefore not yet available",
-                self.id, self.latest_block.number, block
+                    self.id, self.latest_block.number, block
+                ),
             ));
         }
         if block < self.earliest_block_number {
-            return Err(format!(
-                "subgraph {} only has data starting at block number {} \
-                            and data for block number {} is therefore not available",
-                self.id, self.earliest_block_number, block
+            return Err(QueryExecutionError::BlockBeforeEarliest(
+                self.id.to_string(),
+                self.earliest_block_number,
+                block,
             ));
         }
         Ok(())
@@ -0,0 +1,177 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// One operation of a JSON patch (RFC 6902) that turns a previous
+/// subscription result into the current one.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+/// Compute the operations that turn `old` into `new`. The operations must
+/// be applied in the order in which they are returned. An empty list means
+/// that both values are the same.
+///
+/// Objects and lists are compared recursively. Lists are compared element
+/// by element, so inserting an element at the front of a list leads to a
+/// `replace` for every element after it.
+pub fn diff(old: &Value, new: &Value) -> Vec<PatchOperation> {
+    let mut ops = Vec::new();
+    diff_at(&mut String::new(), old, new, &mut ops);
+    ops
+}
+
+fn diff_at(path: &mut String, old: &Value, new: &Value, ops: &mut Vec<PatchOperation>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let len = push_segment(path, key);
+                match new.get(key) {
+                    Some(new_value) => diff_at(path, old_value, new_value, ops),
+                    None => ops.push(PatchOperation::Remove { path: path.clone() }),
+                }
+                path.truncate(len);
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    let len = push_segment(path, key);
+                    ops.push(PatchOperation::Add {
+                        path: path.clone(),
+                        value: new_value.clone(),
+                    });
+                    path.truncate(len);
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for (i, (old_value, new_value)) in old.iter().zip(new.iter()).enumerate() {
+                let len = push_segment(path, &i.to_string());
+                diff_at(path, old_value, new_value, ops);
+                path.truncate(len);
+            }
+            for (i, new_value) in new.iter().enumerate().skip(old.len()) {
+                let len = push_segment(path, &i.to_string());
+                ops.push(PatchOperation::Add {
+                    path: path.clone(),
+                    value: new_value.clone(),
+                });
+                path.truncate(len);
+            }
+            // Remove from the back so that the indexes of the elements
+            // that still need to be removed do not change
+            for i in (new.len()..old.len()).rev() {
+                let len = push_segment(path, &i.to_string());
+                ops.push(PatchOperation::Remove { path: path.clone() });
+                path.truncate(len);
+            }
+        }
+        (old, new) if old == new => {}
+        (_, new) => ops.push(PatchOperation::Replace {
+            path: path.clone(),
+            value: new.clone(),
+        }),
+    }
+}
+
+/// Append `segment` to the JSON pointer `path`, escaping it as required by
+/// RFC 6901, and return the length of `path` before the segment was added
+fn push_segment(path: &mut String, segment: &str) -> usize {
+    let len = path.len();
+    path.push('/');
+    for c in segment.chars() {
+        match c {
+            '~' => path.push_str("~0"),
+            '/' => path.push_str("~1"),
+            c => path.push(c),
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{diff, PatchOperation::*};
+
+    #[test]
+    fn same_values() {
+        let value = json!({ "data": { "musicians": [{ "id": "m1" }, { "id": "m2" }] } });
+        assert_eq!(Vec::<super::PatchOperation>::new(), diff(&value, &value));
+    }
+
+    #[test]
+    fn changed_fields() {
+        let old = json!({ "data": { "musician": { "id": "m1", "name": "John", "band": "b1" } } });
+        let new = json!({ "data": { "musician": { "id": "m1", "name": "Tom", "bands": [] } } });
+        assert_eq!(
+            vec![
+                Remove {
+                    path: "/data/musician/band".to_string()
+                },
+                Replace {
+                    path: "/data/musician/name".to_string(),
+                    value: json!("Tom")
+                },
+                Add {
+                    path: "/data/musician/bands".to_string(),
+                    value: json!([])
+                },
+            ],
+            diff(&old, &new)
+        );
+    }
+
+    #[test]
+    fn changed_lists() {
+        let old =
+            json!({ "data": { "musicians": [{ "id": "m1" }, { "id": "m2" }, { "id": "m3" }] } });
+        let new = json!({ "data": { "musicians": [{ "id": "m1" }] } });
+        assert_eq!(
+            vec![
+                Remove {
+                    path: "/data/musicians/2".to_string()
+                },
+                Remove {
+                    path: "/data/musicians/1".to_string()
+                },
+            ],
+            diff(&old, &new)
+        );
+        assert_eq!(
+            vec![
+                Add {
+                    path: "/data/musicians/1".to_string(),
+                    value: json!({ "id": "m2" })
+                },
+                Add {
+                    path: "/data/musicians/2".to_string(),
+                    value: json!({ "id": "m3" })
+                },
+            ],
+            diff(&new, &old)
+        );
+    }
+
+    #[test]
+    fn escapes_paths() {
+        let old = json!({ "a/b": 1, "c~d": 2 });
+        let new = json!({ "a/b": 3, "c~d": 4 });
+        assert_eq!(
+            vec![
+                Replace {
+                    path: "/a~1b".to_string(),
+                    value: json!(3)
+                },
+                Replace {
+                    path: "/c~0d".to_string(),
+                    value: json!(4)
+                },
+            ],
+            diff(&old, &new)
+        );
+    }
+}
@@ -1,7 +1,9 @@
+mod delta;
 mod error;
 mod result;
 mod subscription;
 
+pub use self::delta::{diff, PatchOperation};
 pub use self::error::SubscriptionError;
 pub use self::result::{QueryResultStream, SubscriptionResult};
 pub use self::subscription::Subscription;
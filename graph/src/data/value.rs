@@ -249,6 +249,10 @@ impl Value {
             ("Bytes", Value::String(s)) => Ok(Value::String(s)),
             ("BigInt", Value::String(s)) => Ok(Value::String(s)),
             ("BigInt", Value::Int(n)) => Ok(Value::String(n.to_string())),
+            ("Int8", Value::String(s)) => Ok(Value::String(s)),
+            ("Int8", Value::Int(n)) => Ok(Value::String(n.to_string())),
+            ("Timestamp", Value::String(s)) => Ok(Value::String(s)),
+            ("Timestamp", Value::Int(n)) => Ok(Value::String(n.to_string())),
             ("JSONObject", Value::Object(obj)) => Ok(Value::Object(obj)),
             ("Date", Value::String(obj)) => Ok(Value::String(obj)),
             (_, v) => Err(v),
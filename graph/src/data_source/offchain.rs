@@ -0,0 +1,305 @@
+//! Data sources that are not tied to a chain. They are created from
+//! templates in mappings, and their handler runs once the content they
+//! point to becomes available. Since that can happen at a different block
+//! on every indexer, offchain data sources can only access their own
+//! entity types, and their changes are not part of the proof of indexing.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, ensure, Context, Error};
+use serde::Deserialize;
+use slog::{info, Logger};
+
+use crate::components::link_resolver::LinkResolver;
+use crate::components::store::StoredDynamicDataSource;
+use crate::data::store::scalar::Bytes;
+use crate::data::subgraph::{DataSourceContext, Link};
+use crate::prelude::{serde_json, BlockNumber};
+
+/// Data sources for files on IPFS; their handler receives the content of
+/// the file
+pub const IPFS_FILE_KIND: &str = "file/ipfs";
+
+/// All kinds of offchain data sources
+pub const OFFCHAIN_KINDS: &[&str] = &[IPFS_FILE_KIND];
+
+pub fn is_offchain_kind(kind: &str) -> bool {
+    OFFCHAIN_KINDS.contains(&kind)
+}
+
+#[derive(Clone, Debug)]
+pub struct DataSource {
+    pub kind: String,
+    pub name: String,
+    /// The file whose content the handler of this data source processes
+    pub source: Link,
+    pub mapping: Mapping,
+    pub context: Arc<Option<DataSourceContext>>,
+    pub creation_block: Option<BlockNumber>,
+}
+
+impl DataSource {
+    /// Create a data source from `template` when a mapping calls
+    /// `DataSource.create`; the first parameter is the link to the file
+    pub fn from_template(
+        template: &DataSourceTemplate,
+        params: Vec<String>,
+        context: Option<DataSourceContext>,
+        creation_block: BlockNumber,
+    ) -> Result<Self, Error> {
+        let source = params
+            .into_iter()
+            .next()
+            .filter(|source| !source.is_empty())
+            .with_context(|| {
+                format!(
+                    "Failed to create data source from template `{}`: file parameter is missing",
+                    template.name
+                )
+            })?;
+
+        Ok(DataSource {
+            kind: template.kind.clone(),
+            name: template.name.clone(),
+            source: Link::from(source),
+            mapping: template.mapping.clone(),
+            context: Arc::new(context),
+            creation_block: Some(creation_block),
+        })
+    }
+
+    pub fn as_stored_dynamic_data_source(&self) -> StoredDynamicDataSource {
+        StoredDynamicDataSource {
+            name: self.name.clone(),
+            param: Some(Bytes::from(self.source.link.as_bytes())),
+            context: self
+                .context
+                .as_ref()
+                .as_ref()
+                .map(|ctx| serde_json::to_value(&ctx).unwrap()),
+            creation_block: self.creation_block,
+            done_at: None,
+        }
+    }
+
+    pub fn from_stored_dynamic_data_source(
+        template: &DataSourceTemplate,
+        stored: StoredDynamicDataSource,
+    ) -> Result<Self, Error> {
+        let StoredDynamicDataSource {
+            name: _,
+            param,
+            context,
+            creation_block,
+            done_at: _,
+        } = stored;
+
+        let source =
+            param.ok_or_else(|| anyhow!("offchain data source `{}` has no file", template.name))?;
+        let source = String::from_utf8(source.as_slice().to_vec()).with_context(|| {
+            format!(
+                "the file of offchain data source `{}` is not valid UTF-8",
+                template.name
+            )
+        })?;
+        let context = context.map(serde_json::from_value).transpose()?;
+
+        Ok(DataSource {
+            kind: template.kind.clone(),
+            name: template.name.clone(),
+            source: Link::from(source),
+            mapping: template.mapping.clone(),
+            context: Arc::new(context),
+            creation_block,
+        })
+    }
+
+    /// Return `true` if `trigger` carries the content of the file this data
+    /// source is waiting for
+    pub fn matches(&self, trigger: &TriggerData) -> bool {
+        self.source == trigger.source
+    }
+
+    pub fn is_duplicate_of(&self, other: &Self) -> bool {
+        let DataSource {
+            kind,
+            name,
+            source,
+            mapping: _,
+            context,
+            creation_block: _,
+        } = self;
+
+        kind == &other.kind
+            && name == &other.name
+            && source == &other.source
+            && context == &other.context
+    }
+
+    /// The entity types that the handler of this data source can access
+    pub fn entities(&self) -> HashSet<String> {
+        self.mapping.entities.iter().cloned().collect()
+    }
+}
+
+/// The content of a file that offchain data sources are waiting for
+#[derive(Clone)]
+pub struct TriggerData {
+    pub source: Link,
+    pub data: Arc<Vec<u8>>,
+}
+
+impl fmt::Debug for TriggerData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TriggerData")
+            .field("source", &self.source.link)
+            .field("size", &self.data.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnresolvedMapping {
+    pub api_version: String,
+    pub language: String,
+    pub entities: Vec<String>,
+    pub handler: String,
+    pub file: Link,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub api_version: semver::Version,
+    pub language: String,
+    pub entities: Vec<String>,
+    pub handler: String,
+    pub runtime: Arc<Vec<u8>>,
+    pub link: Link,
+}
+
+impl UnresolvedMapping {
+    pub async fn resolve(
+        self,
+        resolver: &Arc<dyn LinkResolver>,
+        logger: &Logger,
+    ) -> Result<Mapping, Error> {
+        let UnresolvedMapping {
+            api_version,
+            language,
+            entities,
+            handler,
+            file: link,
+        } = self;
+
+        info!(logger, "Resolve offchain mapping"; "link" => &link.link);
+
+        let api_version = semver::Version::parse(&api_version)?;
+        let runtime = Arc::new(resolver.cat(logger, &link).await?);
+
+        Ok(Mapping {
+            api_version,
+            language,
+            entities,
+            handler,
+            runtime,
+            link,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BaseDataSourceTemplate<M> {
+    pub kind: String,
+    pub network: Option<String>,
+    pub name: String,
+    pub mapping: M,
+}
+
+pub type UnresolvedDataSourceTemplate = BaseDataSourceTemplate<UnresolvedMapping>;
+pub type DataSourceTemplate = BaseDataSourceTemplate<Mapping>;
+
+impl UnresolvedDataSourceTemplate {
+    pub async fn resolve(
+        self,
+        resolver: &Arc<dyn LinkResolver>,
+        logger: &Logger,
+    ) -> Result<DataSourceTemplate, Error> {
+        let UnresolvedDataSourceTemplate {
+            kind,
+            network,
+            name,
+            mapping,
+        } = self;
+
+        ensure!(
+            is_offchain_kind(&kind),
+            "template `{}` has invalid `kind` {}, expected one of {}",
+            name,
+            kind,
+            OFFCHAIN_KINDS.join(", ")
+        );
+
+        info!(logger, "Resolve offchain data source template"; "name" => &name);
+
+        Ok(DataSourceTemplate {
+            kind,
+            network,
+            name,
+            mapping: mapping.resolve(resolver, logger).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> DataSourceTemplate {
+        DataSourceTemplate {
+            kind: IPFS_FILE_KIND.to_string(),
+            network: None,
+            name: "Metadata".to_string(),
+            mapping: Mapping {
+                api_version: semver::Version::new(0, 0, 7),
+                language: "wasm/assemblyscript".to_string(),
+                entities: vec!["TokenMetadata".to_string()],
+                handler: "handleMetadata".to_string(),
+                runtime: Arc::new(vec![]),
+                link: Link::from("/ipfs/Qmmapping".to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn stored_data_source_roundtrip() {
+        let template = template();
+        let ds = DataSource::from_template(
+            &template,
+            vec!["QmVkvoPGi9jvvuxsHDVJDgzPEzagBaWSZRYoRDzU244HjZ".to_string()],
+            None,
+            17,
+        )
+        .unwrap();
+
+        let stored = ds.as_stored_dynamic_data_source();
+        assert_eq!("Metadata", stored.name);
+        assert_eq!(Some(17), stored.creation_block);
+
+        let loaded = DataSource::from_stored_dynamic_data_source(&template, stored).unwrap();
+        assert!(loaded.is_duplicate_of(&ds));
+        assert_eq!(Some(17), loaded.creation_block);
+        assert!(loaded.matches(&TriggerData {
+            source: ds.source.clone(),
+            data: Arc::new(vec![]),
+        }));
+    }
+
+    #[test]
+    fn create_requires_file() {
+        assert!(DataSource::from_template(&template(), vec![], None, 1).is_err());
+        assert!(DataSource::from_template(&template(), vec!["".to_string()], None, 1).is_err());
+    }
+}
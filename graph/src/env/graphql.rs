@@ -7,6 +7,14 @@ pub struct EnvVarsGraphQl {
     /// Set by the flag `ENABLE_GRAPHQL_VALIDATIONS`. Off by default.
     pub enable_validations: bool,
     pub subscription_throttle_interval: Duration,
+    /// Subscriptions send at most one update per this interval; updates for
+    /// store changes that happen in between are combined into one.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_SUBSCRIPTION_UPDATE_INTERVAL` (expressed in
+    /// milliseconds). The default value is 0, which sends an update for every
+    /// change.
+    pub subscription_update_interval: Duration,
     /// This is the timeout duration for SQL queries.
     ///
     /// If it is not set, no statement timeout will be enforced. The statement
@@ -52,6 +60,20 @@ pub struct EnvVarsGraphQl {
     /// Set by the environment variable `GRAPH_QUERY_CACHE_STALE_PERIOD`. The
     /// default value is 100.
     pub query_cache_stale_period: u64,
+    /// The URL of a key-value store that speaks the Redis protocol, e.g.
+    /// `redis://localhost:6379/0`. When set, query results are also cached
+    /// there so that they can be shared between query nodes and survive
+    /// restarts.
+    ///
+    /// Set by the environment variable `GRAPH_QUERY_CACHE_REDIS_URL`. No
+    /// default value is provided.
+    pub query_cache_redis_url: Option<String>,
+    /// How long query results are kept in the cache set with
+    /// `GRAPH_QUERY_CACHE_REDIS_URL`.
+    ///
+    /// Set by the environment variable `GRAPH_QUERY_CACHE_REDIS_TTL`
+    /// (expressed in seconds). The default value is 3600.
+    pub query_cache_redis_ttl: Duration,
     /// Set by the environment variable `GRAPH_GRAPHQL_QUERY_TIMEOUT` (expressed in
     /// seconds). No default value is provided.
     pub query_timeout: Option<Duration>,
@@ -82,6 +104,41 @@ pub struct EnvVarsGraphQl {
     /// Set by the flag `GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION`. No
     /// default is provided.
     pub max_operations_per_connection: Option<usize>,
+    /// Add `<entities>Aggregate` fields that compute `count`, `sum`, `min`,
+    /// `max` and `avg` over the entities matching a filter to the query
+    /// API of every subgraph.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_ENABLE_AGGREGATES`. Off by default.
+    pub enable_aggregates: bool,
+    /// A JSON file that maps the ids of persisted queries to their text.
+    /// Queries in this file form the allow-list of persisted queries.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_PERSISTED_QUERIES`.
+    /// No default value is provided.
+    pub persisted_queries: Option<String>,
+    /// Deployments that only execute queries from the allow-list of
+    /// persisted queries.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_PERSISTED_QUERIES_ONLY`
+    /// (comma separated). The default is empty.
+    pub persisted_queries_only: Vec<String>,
+    /// The name of the HTTP header that identifies the client sending a
+    /// query, for example an API key. A value of the form `Bearer <token>`
+    /// identifies the client by `<token>`. Per-client limits are only
+    /// enforced for requests that carry this header.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_CLIENT_ID_HEADER`. No
+    /// default value is provided.
+    pub client_id_header: Option<String>,
+    /// A secret token that allows clients to ask for a trace of how their
+    /// query was executed by sending it in the `X-Graph-Explain` header.
+    /// The trace contains the SQL queries that were run and their query
+    /// plans, and should therefore only be available to administrators.
+    /// Traces can not be requested if this is not set.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_EXPLAIN_TOKEN`. No
+    /// default value is provided.
+    pub explain_token: Option<String>,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -98,6 +155,9 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             subscription_throttle_interval: Duration::from_millis(
                 x.subscription_throttle_interval_in_ms,
             ),
+            subscription_update_interval: Duration::from_millis(
+                x.subscription_update_interval_in_ms,
+            ),
             sql_statement_timeout: x.sql_statement_timeout_in_secs.map(Duration::from_secs),
             cached_subgraph_ids: if x.cached_subgraph_ids == "*" {
                 CachedSubgraphIds::All
@@ -116,6 +176,8 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             query_cache_blocks: x.query_cache_blocks,
             query_cache_max_mem: x.query_cache_max_mem_in_mb.0 * 1000 * 1000,
             query_cache_stale_period: x.query_cache_stale_period,
+            query_cache_redis_url: x.query_cache_redis_url,
+            query_cache_redis_ttl: Duration::from_secs(x.query_cache_redis_ttl_in_secs),
             query_timeout: x.query_timeout_in_secs.map(Duration::from_secs),
             max_complexity: x.max_complexity.map(|x| x.0),
             max_depth: x.max_depth.0,
@@ -125,6 +187,17 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             warn_result_size: x.warn_result_size.0 .0,
             error_result_size: x.error_result_size.0 .0,
             max_operations_per_connection: x.max_operations_per_connection,
+            enable_aggregates: x.enable_aggregates.0,
+            persisted_queries: x.persisted_queries,
+            persisted_queries_only: x
+                .persisted_queries_only
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect(),
+            client_id_header: x.client_id_header,
+            explain_token: x.explain_token.filter(|token| !token.is_empty()),
         }
     }
 }
@@ -135,6 +208,8 @@ pub struct InnerGraphQl {
     enable_validations: EnvVarBoolean,
     #[envconfig(from = "SUBSCRIPTION_THROTTLE_INTERVAL", default = "1000")]
     subscription_throttle_interval_in_ms: u64,
+    #[envconfig(from = "GRAPH_GRAPHQL_SUBSCRIPTION_UPDATE_INTERVAL", default = "0")]
+    subscription_update_interval_in_ms: u64,
     #[envconfig(from = "GRAPH_SQL_STATEMENT_TIMEOUT")]
     sql_statement_timeout_in_secs: Option<u64>,
 
@@ -150,6 +225,10 @@ pub struct InnerGraphQl {
     query_cache_max_mem_in_mb: NoUnderscores<usize>,
     #[envconfig(from = "GRAPH_QUERY_CACHE_STALE_PERIOD", default = "100")]
     query_cache_stale_period: u64,
+    #[envconfig(from = "GRAPH_QUERY_CACHE_REDIS_URL")]
+    query_cache_redis_url: Option<String>,
+    #[envconfig(from = "GRAPH_QUERY_CACHE_REDIS_TTL", default = "3600")]
+    query_cache_redis_ttl_in_secs: u64,
     #[envconfig(from = "GRAPH_GRAPHQL_QUERY_TIMEOUT")]
     query_timeout_in_secs: Option<u64>,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_COMPLEXITY")]
@@ -168,4 +247,14 @@ pub struct InnerGraphQl {
     error_result_size: WithDefaultUsize<NoUnderscores<usize>, { usize::MAX }>,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION")]
     max_operations_per_connection: Option<usize>,
+    #[envconfig(from = "GRAPH_GRAPHQL_ENABLE_AGGREGATES", default = "false")]
+    enable_aggregates: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERIES")]
+    persisted_queries: Option<String>,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERIES_ONLY", default = "")]
+    persisted_queries_only: String,
+    #[envconfig(from = "GRAPH_GRAPHQL_CLIENT_ID_HEADER")]
+    client_id_header: Option<String>,
+    #[envconfig(from = "GRAPH_GRAPHQL_EXPLAIN_TOKEN")]
+    explain_token: Option<String>,
 }
@@ -48,6 +48,18 @@ pub struct EnvVarsMapping {
     /// Set by the flag `GRAPH_ALLOW_NON_DETERMINISTIC_IPFS`. Off by
     /// default.
     pub allow_non_deterministic_ipfs: bool,
+    /// The number of files of offchain data sources that are fetched at
+    /// the same time for a subgraph; further files wait in a queue.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_OFFCHAIN_MAX_CONCURRENT_FETCHES`. The default value is 10.
+    pub offchain_max_concurrent_fetches: usize,
+    /// How often fetching the file of an offchain data source is attempted
+    /// before giving up on it until the subgraph restarts.
+    ///
+    /// Set by the environment variable `GRAPH_OFFCHAIN_MAX_FETCH_ATTEMPTS`.
+    /// The default value is 10.
+    pub offchain_max_fetch_attempts: u64,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -72,6 +84,8 @@ impl From<InnerMappingHandlers> for EnvVarsMapping {
             max_ipfs_map_file_size: x.max_ipfs_map_file_size.0,
             max_ipfs_file_bytes: x.max_ipfs_file_bytes,
             allow_non_deterministic_ipfs: x.allow_non_deterministic_ipfs.0,
+            offchain_max_concurrent_fetches: x.offchain_max_concurrent_fetches.max(1),
+            offchain_max_fetch_attempts: x.offchain_max_fetch_attempts.max(1),
         }
     }
 }
@@ -100,4 +114,8 @@ pub struct InnerMappingHandlers {
     max_ipfs_file_bytes: Option<usize>,
     #[envconfig(from = "GRAPH_ALLOW_NON_DETERMINISTIC_IPFS", default = "false")]
     allow_non_deterministic_ipfs: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_OFFCHAIN_MAX_CONCURRENT_FETCHES", default = "10")]
+    offchain_max_concurrent_fetches: usize,
+    #[envconfig(from = "GRAPH_OFFCHAIN_MAX_FETCH_ATTEMPTS", default = "10")]
+    offchain_max_fetch_attempts: u64,
 }
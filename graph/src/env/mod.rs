@@ -120,6 +120,33 @@ pub struct EnvVars {
     /// Set by the environment variable `GRAPH_LOAD_BIN_SIZE` (expressed in
     /// seconds). The default value is 1 second.
     pub load_bin_size: Duration,
+    /// The maximum number of queries that a single client can have running
+    /// at the same time. Clients are identified by the header named in
+    /// `GRAPH_GRAPHQL_CLIENT_ID_HEADER`.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_CLIENT_MAX_CONCURRENT`.
+    /// No default value is provided (unlimited).
+    pub load_client_max_concurrent: Option<usize>,
+    /// The maximum number of queries per second that a single client can
+    /// send.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_CLIENT_MAX_RPS`. No
+    /// default value is provided (unlimited).
+    pub load_client_max_rps: Option<u32>,
+    /// The maximum amount of time that queries from a single client can
+    /// take within the window set by `GRAPH_LOAD_WINDOW_SIZE`.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_CLIENT_MAX_EFFORT`
+    /// (expressed in milliseconds). No default value is provided
+    /// (unlimited).
+    pub load_client_max_effort: Option<Duration>,
+    /// The maximum number of clients whose load is tracked separately.
+    /// Queries from clients beyond that are tracked together, as one
+    /// client.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_MAX_CLIENTS`. The
+    /// default value is 1000.
+    pub load_max_clients: usize,
     /// Set by the environment variable
     /// `GRAPH_ELASTIC_SEARCH_FLUSH_INTERVAL_SECS` (expressed in seconds). The
     /// default value is 5 seconds.
@@ -173,6 +200,12 @@ pub struct EnvVars {
     ///
     /// Set by the flag `GRAPH_DISABLE_FAIL_FAST`. Off by default.
     pub disable_fail_fast: bool,
+    /// Process the triggers of data sources whose mappings declare disjoint
+    /// sets of entity types concurrently. The entity types that mappings
+    /// declare are checked against the schema when a subgraph is deployed.
+    ///
+    /// Set by the flag `GRAPH_SUBGRAPH_PARALLEL_TRIGGERS`. Off by default.
+    pub subgraph_parallel_triggers: bool,
     /// Ceiling for the backoff retry of non-deterministic errors.
     ///
     /// Set by the environment variable `GRAPH_SUBGRAPH_ERROR_RETRY_CEIL_SECS`
@@ -228,6 +261,12 @@ impl EnvVars {
             disable_grafts: inner.disable_grafts.0,
             load_window_size: Duration::from_secs(inner.load_window_size_in_secs),
             load_bin_size: Duration::from_secs(inner.load_bin_size_in_secs),
+            load_client_max_concurrent: inner.load_client_max_concurrent,
+            load_client_max_rps: inner.load_client_max_rps,
+            load_client_max_effort: inner
+                .load_client_max_effort_in_ms
+                .map(Duration::from_millis),
+            load_max_clients: inner.load_max_clients,
             elastic_search_flush_interval: Duration::from_secs(
                 inner.elastic_search_flush_interval_in_secs,
             ),
@@ -250,6 +289,7 @@ impl EnvVars {
             poi_access_token: inner.poi_access_token,
             subgraph_max_data_sources: inner.subgraph_max_data_sources,
             disable_fail_fast: inner.disable_fail_fast.0,
+            subgraph_parallel_triggers: inner.subgraph_parallel_triggers.0,
             subgraph_error_retry_ceil: Duration::from_secs(inner.subgraph_error_retry_ceil_in_secs),
             enable_select_by_specific_attributes: inner.enable_select_by_specific_attributes.0,
             log_trigger_data: inner.log_trigger_data.0,
@@ -318,6 +358,14 @@ struct Inner {
     load_window_size_in_secs: u64,
     #[envconfig(from = "GRAPH_LOAD_BIN_SIZE", default = "1")]
     load_bin_size_in_secs: u64,
+    #[envconfig(from = "GRAPH_LOAD_CLIENT_MAX_CONCURRENT")]
+    load_client_max_concurrent: Option<usize>,
+    #[envconfig(from = "GRAPH_LOAD_CLIENT_MAX_RPS")]
+    load_client_max_rps: Option<u32>,
+    #[envconfig(from = "GRAPH_LOAD_CLIENT_MAX_EFFORT")]
+    load_client_max_effort_in_ms: Option<u64>,
+    #[envconfig(from = "GRAPH_LOAD_MAX_CLIENTS", default = "1000")]
+    load_max_clients: usize,
     #[envconfig(from = "GRAPH_ELASTIC_SEARCH_FLUSH_INTERVAL_SECS", default = "5")]
     elastic_search_flush_interval_in_secs: u64,
     #[envconfig(from = "GRAPH_ELASTIC_SEARCH_MAX_RETRIES", default = "5")]
@@ -350,6 +398,8 @@ struct Inner {
     subgraph_max_data_sources: Option<usize>,
     #[envconfig(from = "GRAPH_DISABLE_FAIL_FAST", default = "false")]
     disable_fail_fast: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_SUBGRAPH_PARALLEL_TRIGGERS", default = "false")]
+    subgraph_parallel_triggers: EnvVarBoolean,
     #[envconfig(from = "GRAPH_SUBGRAPH_ERROR_RETRY_CEIL_SECS", default = "1800")]
     subgraph_error_retry_ceil_in_secs: u64,
     #[envconfig(from = "GRAPH_ENABLE_SELECT_BY_SPECIFIC_ATTRIBUTES", default = "false")]
@@ -86,6 +86,17 @@ pub struct EnvVarsStore {
     /// Setting this to `0` disables pipelined writes, and writes will be
     /// done synchronously.
     pub write_queue_size: usize,
+    /// The maximum number of entity modifications that consecutive block
+    /// writes in the write queue are combined into before they are
+    /// committed in one transaction. Set by the environment variable
+    /// `GRAPH_STORE_WRITE_BATCH_SIZE`. The default is 10000; setting this
+    /// to `0` writes each block in its own transaction.
+    pub write_batch_size: usize,
+    /// How long a batch of block writes in the write queue accepts more
+    /// blocks before it gets closed. Set by the environment variable
+    /// `GRAPH_STORE_WRITE_BATCH_DURATION` (expressed in seconds). The
+    /// default value is 300s.
+    pub write_batch_duration: Duration,
 
     /// This is just in case new behavior causes issues. This can be removed
     /// once the new behavior has run in the hosted service for a few days
@@ -127,6 +138,8 @@ impl From<InnerStore> for EnvVarsStore {
             connection_min_idle: x.connection_min_idle,
             connection_idle_timeout: Duration::from_secs(x.connection_idle_timeout_in_secs),
             write_queue_size: x.write_queue_size,
+            write_batch_size: x.write_batch_size,
+            write_batch_duration: Duration::from_secs(x.write_batch_duration_in_secs),
             disable_error_for_toplevel_parents: x.disable_error_for_toplevel_parents.0,
         }
     }
@@ -171,6 +184,10 @@ pub struct InnerStore {
     connection_idle_timeout_in_secs: u64,
     #[envconfig(from = "GRAPH_STORE_WRITE_QUEUE", default = "5")]
     write_queue_size: usize,
+    #[envconfig(from = "GRAPH_STORE_WRITE_BATCH_SIZE", default = "10000")]
+    write_batch_size: usize,
+    #[envconfig(from = "GRAPH_STORE_WRITE_BATCH_DURATION", default = "300")]
+    write_batch_duration_in_secs: u64,
     #[envconfig(from = "GRAPH_DISABLE_ERROR_FOR_TOPLEVEL_PARENTS", default = "false")]
     disable_error_for_toplevel_parents: EnvVarBoolean,
 }
@@ -20,6 +20,9 @@ pub mod ipfs_client;
 
 pub mod blockchain;
 
+/// Data sources that are independent of any chain.
+pub mod data_source;
+
 pub mod runtime;
 
 pub mod firehose;
@@ -115,11 +118,12 @@ pub mod prelude {
     pub use crate::components::server::subscription::SubscriptionServer;
     pub use crate::components::store::{
         AttributeNames, BlockNumber, CachedEthereumCall, ChainStore, Child, ChildMultiplicity,
-        EntityCache, EntityChange, EntityChangeOperation, EntityCollection, EntityFilter,
-        EntityKey, EntityLink, EntityModification, EntityOperation, EntityOrder, EntityQuery,
-        EntityRange, EntityWindow, EthereumCallCache, ParentLink, PartialBlockPtr, PoolWaitStats,
-        QueryStore, QueryStoreManager, StoreError, StoreEvent, StoreEventStream,
-        StoreEventStreamBox, SubgraphStore, UnfailOutcome, WindowAttribute, BLOCK_NUMBER_MAX,
+        EntityAggregate, EntityAggregateQuery, EntityCache, EntityChange, EntityChangeOperation,
+        EntityCollection, EntityFilter, EntityKey, EntityLink, EntityModification, EntityOperation,
+        EntityOrder, EntityOrderByChild, EntityQuery, EntityRange, EntityWindow, EthereumCallCache,
+        FulltextOutput, ParentLink, PartialBlockPtr, PoolWaitStats, QueryStore, QueryStoreManager,
+        SortPosition, StoreError, StoreEvent, StoreEventStream, StoreEventStreamBox, SubgraphStore,
+        UnfailOutcome, WindowAttribute, BLOCK_NUMBER_MAX,
     };
     pub use crate::components::subgraph::{
         BlockState, DataSourceTemplateInfo, HostMetrics, RuntimeHost, RuntimeHostBuilder,
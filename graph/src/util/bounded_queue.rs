@@ -86,6 +86,12 @@ impl<T: Clone> BoundedQueue<T> {
         item.clone()
     }
 
+    /// Return a copy of the newest entry in the queue, i.e., the entry that
+    /// was pushed last, or `None` if the queue is empty
+    pub fn back(&self) -> Option<T> {
+        self.queue.lock().unwrap().back().cloned()
+    }
+
     /// Push an item into the queue. If the queue is currently full this method
     /// blocks until an item is available
     pub async fn push(&self, item: T) {
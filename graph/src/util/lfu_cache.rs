@@ -183,6 +183,37 @@ impl<K: Clone + Ord + Eq + Hash + Debug + CacheWeight, V: CacheWeight + Default>
         self.queue.len()
     }
 
+    /// Move the entries whose key satisfies `f` into a new cache, keeping
+    /// their frequency
+    pub fn split_off(&mut self, f: impl Fn(&K) -> bool) -> Self {
+        let mut other = LfuCache::new();
+        other.stale_counter = self.stale_counter;
+
+        let queue = std::mem::replace(&mut self.queue, PriorityQueue::new());
+        self.total_weight = 0;
+        for (entry, priority) in queue {
+            let cache = if f(&entry.key) {
+                &mut other
+            } else {
+                &mut *self
+            };
+            cache.total_weight += entry.weight;
+            cache.queue.push(entry, priority);
+        }
+        other
+    }
+
+    /// Add the entries of `other` to this cache. Entries that are in both
+    /// caches keep the value and frequency they have in this cache
+    pub fn merge(&mut self, other: Self) {
+        for (entry, priority) in other.queue {
+            if self.queue.get(&entry).is_none() {
+                self.total_weight += entry.weight;
+                self.queue.push(entry, priority);
+            }
+        }
+    }
+
     /// Same as `evict_with_period(max_weight, STALE_PERIOD)`
     pub fn evict(&mut self, max_weight: usize) -> Option<EvictStats> {
         self.evict_with_period(max_weight, STALE_PERIOD)
@@ -335,3 +366,30 @@ fn entity_lru_cache() {
     assert!(cache.get(&"alligator").is_none());
     assert_eq!(cache.get(&"lion"), Some(&Weight(lion_inner_weight)));
 }
+
+#[test]
+fn split_off_and_merge() {
+    let mut cache: LfuCache<&'static str, String> = LfuCache::new();
+    cache.insert("cow", "moo".to_owned());
+    cache.insert("calf", "mooo".to_owned());
+    cache.insert("panda", "squeak".to_owned());
+    let cow_weight = cache.weight("cow");
+    let calf_weight = cache.weight("calf");
+    let total_weight = cache.total_weight;
+
+    let mut cows = cache.split_off(|key| key.starts_with('c'));
+    assert_eq!(cows.len(), 2);
+    assert_eq!(cows.total_weight, cow_weight + calf_weight);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.total_weight, total_weight - cows.total_weight);
+    assert_eq!(cows.get(&"calf").map(String::as_str), Some("mooo"));
+    assert!(cache.get(&"calf").is_none());
+
+    // Entries that are in both caches keep their value in `cache`
+    cows.insert("panda", "growl".to_owned());
+    cache.merge(cows);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.total_weight, total_weight);
+    assert_eq!(cache.get(&"panda").map(String::as_str), Some("squeak"));
+    assert_eq!(cache.get(&"cow").map(String::as_str), Some("moo"));
+}
@@ -155,6 +155,13 @@ impl MovingStats {
         self.total.duration
     }
 
+    /// Return the total duration of the measurements that are still
+    /// within `window_size` of `now`
+    pub fn duration_at(&mut self, now: Instant) -> Duration {
+        self.expire_bins(now);
+        self.total.duration
+    }
+
     /// Adds `duration` to the stats, and register the average ms to `avg_gauge`.
     pub fn add_and_register(&mut self, duration: Duration, avg_gauge: &Gauge) {
         let wait_avg = {
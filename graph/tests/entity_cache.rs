@@ -2,6 +2,7 @@ use async_trait::async_trait;
 use graph::blockchain::BlockPtr;
 use graph::data::subgraph::schema::{SubgraphError, SubgraphHealth};
 use graph::prelude::{Schema, StopwatchMetrics, StoreError, UnfailOutcome};
+use graph::runtime::DeterministicHostError;
 use lazy_static::lazy_static;
 use slog::Logger;
 use std::collections::BTreeMap;
@@ -26,6 +27,11 @@ lazy_static! {
                 founded: Int
                 label: String
             }
+
+            type Album @entity(immutable: true) {
+                id: ID!
+                title: String!
+            }
             ",
             SUBGRAPH_ID.clone(),
         )
@@ -340,3 +346,70 @@ fn consecutive_modifications() {
         },])
     );
 }
+
+fn make_album(id: &'static str, title: &'static str) -> (EntityKey, Entity) {
+    (
+        EntityKey::data(SUBGRAPH_ID.clone(), "Album".to_string(), id.into()),
+        Entity::from(vec![("id", id.into()), ("title", title.into())]),
+    )
+}
+
+#[test]
+fn immutable_modifications() {
+    let store = {
+        let entities = vec![make_album("tnt", "TNT").1];
+        MockStore::new(entity_version_map("Album", entities))
+    };
+
+    let store = Arc::new(store);
+    let mut cache = EntityCache::new(store.clone());
+
+    let is_deterministic = |e: anyhow::Error| e.is::<DeterministicHostError>();
+
+    // Inserting a new immutable entity is fine, but setting it a second
+    // time is not
+    let (key, data) = make_album("mmi", "Millions Now Living Will Never Die");
+    cache.set(key.clone(), data.clone()).unwrap();
+    let err = cache.set(key.clone(), data.clone()).unwrap_err();
+    assert!(is_deterministic(err));
+
+    // Entities that are already in the store can not be updated or removed
+    let (tnt_key, tnt_data) = make_album("tnt", "TNT (Remastered)");
+    let err = cache.set(tnt_key.clone(), tnt_data).unwrap_err();
+    assert!(is_deterministic(err));
+    let err = cache.remove(tnt_key).unwrap_err();
+    assert!(is_deterministic(err));
+
+    let result = cache.as_modifications();
+    assert_eq!(
+        result.unwrap().modifications,
+        vec![EntityModification::Insert { key, data }]
+    );
+}
+
+#[test]
+fn immutable_update_across_blocks() {
+    let is_deterministic = |e: anyhow::Error| e.is::<DeterministicHostError>();
+
+    // Insert an immutable entity in one block
+    let store = Arc::new(MockStore::new(entity_version_map("Album", vec![])));
+    let mut cache = EntityCache::new(store);
+    let (key, data) = make_album("tnt", "TNT");
+    cache.set(key.clone(), data.clone()).unwrap();
+    let modifications = cache.as_modifications().unwrap().modifications;
+    assert_eq!(
+        modifications,
+        vec![EntityModification::Insert {
+            key: key.clone(),
+            data: data.clone()
+        }]
+    );
+
+    // Updating it in a later block, once it is in the store, fails
+    let store = Arc::new(MockStore::new(entity_version_map("Album", vec![data])));
+    let mut cache = EntityCache::new(store);
+    let (key, data) = make_album("tnt", "TNT (Remastered)");
+    let err = cache.set(key, data).unwrap_err();
+    assert!(is_deterministic(err));
+    assert!(cache.as_modifications().unwrap().modifications.is_empty());
+}
@@ -1,21 +1,38 @@
 use futures03::future::FutureExt;
 use futures03::future::Shared;
+use futures03::StreamExt;
 use graph::{
-    prelude::{debug, futures03, BlockPtr, CheapClone, Logger, QueryResult},
-    util::timed_rw_lock::TimedMutex,
+    blockchain::ChainHeadUpdateStream,
+    data::value::Object,
+    prelude::{
+        anyhow, async_trait, debug, futures03, hex, info, q, serde_json, warn, BlockPtr,
+        CacheWeight, ChainStore, CheapClone, DeploymentHash, Logger, QueryError,
+        QueryExecutionError, QueryResult, ENV_VARS,
+    },
+    util::{
+        lfu_cache::{EvictStats, LfuCache},
+        timed_rw_lock::TimedMutex,
+    },
 };
+use lazy_static::lazy_static;
+use parking_lot::{MutexGuard, RwLock};
+use sha2::{Digest, Sha256};
 use stable_hash_legacy::crypto::SetHasher;
 use stable_hash_legacy::prelude::*;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::{collections::HashMap, time::Duration};
 use std::{
     collections::{hash_map::Entry, VecDeque},
     time::Instant,
 };
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
+use super::redis::RedisResultCache;
 use super::QueryHash;
 
 type Hash = <SetHasher as StableHasher>::Out;
@@ -248,4 +265,482 @@ impl QueryBlockCache {
         }
         None
     }
+
+    /// Remove the results for blocks of `network` that are not on the chain
+    /// whose head is `head`
+    pub fn revert(&mut self, network: &str, head: &BlockPtr) {
+        if let Some(cache) = self
+            .cache_by_network
+            .iter_mut()
+            .find(|(n, _)| n == network)
+            .map(|(_, c)| c)
+        {
+            cache.retain(|c| c.block.number < head.number || &c.block == head);
+        }
+    }
+}
+
+lazy_static! {
+    /// The cache for query results, chosen by the configuration in the
+    /// environment
+    pub(crate) static ref QUERY_RESULT_CACHE: Arc<dyn QueryResultCache> = {
+        let in_memory = Arc::new(InMemoryResultCache::from_env());
+        match &ENV_VARS.graphql.query_cache_redis_url {
+            None => in_memory,
+            Some(url) => {
+                let redis = RedisResultCache::new(url, ENV_VARS.graphql.query_cache_redis_ttl)
+                    .expect("GRAPH_QUERY_CACHE_REDIS_URL must be a valid redis:// URL");
+                Arc::new(LayeredResultCache::new(in_memory, Arc::new(redis)))
+            }
+        }
+    };
+}
+
+/// A cache for the results of queries, keyed by the hash of the query and
+/// the block pointer the query was run against. Implementations must be
+/// safe to use from many queries at once. Failing to reach the cache must
+/// never fail a query; implementations should treat such failures as a
+/// cache miss
+#[async_trait]
+pub trait QueryResultCache: Send + Sync + 'static {
+    /// Look up the result of the query with hash `key` at `block_ptr`
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>>;
+
+    /// Cache `result`, which must not contain errors. Return `true` if the
+    /// result was stored
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> bool;
+
+    /// The chain `network` was reorganized and now ends in `head`. Forget
+    /// results for blocks that are no longer on the chain
+    async fn revert(&self, logger: &Logger, network: &str, head: &BlockPtr);
+}
+
+struct WeightedResult {
+    result: Arc<QueryResult>,
+    weight: usize,
+}
+
+impl CacheWeight for WeightedResult {
+    fn indirect_weight(&self) -> usize {
+        self.weight
+    }
+}
+
+impl Default for WeightedResult {
+    fn default() -> Self {
+        WeightedResult {
+            result: Arc::new(QueryResult::new(Object::default())),
+            weight: 0,
+        }
+    }
+}
+
+/// The cache that is kept in the memory of this process. Results for
+/// recent blocks go into a sharded `QueryBlockCache`, and results for
+/// older blocks into a sharded `LfuCache`
+pub struct InMemoryResultCache {
+    // The `VecDeque` inside each `QueryBlockCache` works as a ring buffer
+    // with a capacity of `GRAPH_QUERY_CACHE_BLOCKS`
+    block_caches: Vec<TimedMutex<QueryBlockCache>>,
+    lfu_caches: Vec<TimedMutex<LfuCache<QueryHash, WeightedResult>>>,
+}
+
+impl InMemoryResultCache {
+    pub fn from_env() -> Self {
+        let shards = ENV_VARS.graphql.query_block_cache_shards;
+        let blocks = ENV_VARS.graphql.query_cache_blocks;
+
+        // The memory budget is evenly divided among blocks and their shards.
+        let max_weight = ENV_VARS.graphql.query_cache_max_mem / (blocks * shards as usize);
+        let block_caches = (0..shards)
+            .map(|i| {
+                let id = format!("query_block_cache_{}", i);
+                TimedMutex::new(QueryBlockCache::new(blocks, i, max_weight), id)
+            })
+            .collect();
+        let lfu_caches =
+            std::iter::repeat_with(|| TimedMutex::new(LfuCache::new(), "query_lfu_cache"))
+                .take(ENV_VARS.graphql.query_lfu_cache_shards as usize)
+                .collect();
+        Self {
+            block_caches,
+            lfu_caches,
+        }
+    }
+
+    fn block_cache(&self, key: &QueryHash) -> &TimedMutex<QueryBlockCache> {
+        &self.block_caches[(key[0] as usize) % self.block_caches.len()]
+    }
+
+    fn lfu_cache(
+        &self,
+        logger: &Logger,
+        key: &QueryHash,
+    ) -> Option<MutexGuard<'_, LfuCache<QueryHash, WeightedResult>>> {
+        match self.lfu_caches.len() {
+            0 => None,
+            n => {
+                let shard = (key[0] as usize) % n;
+                Some(self.lfu_caches[shard].lock(logger))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl QueryResultCache for InMemoryResultCache {
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>> {
+        // Check if the response is cached, first in the recent blocks cache,
+        // and then in the LfuCache for historical queries
+        // The blocks are used to delimit how long locks need to be held
+        {
+            let cache = self.block_cache(key).lock(logger);
+            if let Some(result) = cache.get(network, block_ptr, key) {
+                return Some(result);
+            }
+        }
+        if let Some(mut cache) = self.lfu_cache(logger, key) {
+            if let Some(weighted) = cache.get(key) {
+                return Some(weighted.result.cheap_clone());
+            }
+        }
+        None
+    }
+
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> bool {
+        let inserted = self.block_cache(&key).lock(logger).insert(
+            network,
+            block_ptr,
+            key,
+            result.cheap_clone(),
+            weight,
+            logger.cheap_clone(),
+        );
+
+        if inserted {
+            return true;
+        }
+        if let Some(mut cache) = self.lfu_cache(logger, &key) {
+            // Results that are too old for the block cache go into the LfuCache
+            let max_mem = ENV_VARS.graphql.query_cache_max_mem
+                / ENV_VARS.graphql.query_lfu_cache_shards as usize;
+
+            let evict_stats =
+                cache.evict_with_period(max_mem, ENV_VARS.graphql.query_cache_stale_period);
+
+            log_lfu_evict_stats(logger, network, &key, evict_stats);
+
+            cache.insert(key, WeightedResult { result, weight });
+            return true;
+        }
+        false
+    }
+
+    async fn revert(&self, logger: &Logger, network: &str, head: &BlockPtr) {
+        // Results in the LfuCache are keyed by the hash of their block and
+        // will never be used again; they get evicted eventually
+        for cache in &self.block_caches {
+            cache.lock(logger).revert(network, head);
+        }
+    }
+}
+
+fn log_lfu_evict_stats(
+    logger: &Logger,
+    network: &str,
+    cache_key: &[u8; 32],
+    evict_stats: Option<EvictStats>,
+) {
+    let total_shards = ENV_VARS.graphql.query_lfu_cache_shards as usize;
+
+    if total_shards > 0 {
+        if let Some(EvictStats {
+            new_weight,
+            evicted_weight,
+            new_count,
+            evicted_count,
+            stale_update,
+            evict_time,
+        }) = evict_stats
+        {
+            {
+                let shard = (cache_key[0] as usize) % total_shards;
+                let network = network.to_string();
+                let logger = logger.clone();
+
+                graph::spawn(async move {
+                    debug!(logger, "Evicted LFU cache";
+                        "shard" => shard,
+                        "network" => network,
+                        "entries" => new_count,
+                        "entries_evicted" => evicted_count,
+                        "weight" => new_weight,
+                        "weight_evicted" => evicted_weight,
+                        "stale_update" => stale_update,
+                        "evict_time_ms" => evict_time.as_millis()
+                    )
+                });
+            }
+        }
+    }
+}
+
+/// A cache that looks for results in a `local` cache first, and then in a
+/// `shared` cache, e.g., one that all query nodes use
+pub struct LayeredResultCache {
+    local: Arc<dyn QueryResultCache>,
+    shared: Arc<dyn QueryResultCache>,
+}
+
+impl LayeredResultCache {
+    pub fn new(local: Arc<dyn QueryResultCache>, shared: Arc<dyn QueryResultCache>) -> Self {
+        Self { local, shared }
+    }
+}
+
+#[async_trait]
+impl QueryResultCache for LayeredResultCache {
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>> {
+        if let Some(result) = self.local.get(logger, network, block_ptr, key).await {
+            return Some(result);
+        }
+        let result = self.shared.get(logger, network, block_ptr, key).await?;
+        let weight = result.weight();
+        self.local
+            .insert(
+                logger,
+                network,
+                block_ptr.clone(),
+                *key,
+                result.cheap_clone(),
+                weight,
+            )
+            .await;
+        Some(result)
+    }
+
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> bool {
+        let local = self
+            .local
+            .insert(
+                logger,
+                network,
+                block_ptr.clone(),
+                key,
+                result.cheap_clone(),
+                weight,
+            )
+            .await;
+        let shared = self
+            .shared
+            .insert(logger, network, block_ptr, key, result, weight)
+            .await;
+        local || shared
+    }
+
+    async fn revert(&self, logger: &Logger, network: &str, head: &BlockPtr) {
+        self.local.revert(logger, network, head).await;
+        self.shared.revert(logger, network, head).await;
+    }
+}
+
+/// Forget cached query results for blocks that were removed from the chain
+/// `network` by a reorg. Reorgs are detected by watching `chain_head_updates`
+/// for a chain head that does not extend the previous one and by looking up
+/// the head in `chain_store`.
+///
+/// Results are keyed by the hash of their block and are therefore never
+/// served for the wrong block, even when this task lags behind; removing
+/// them only frees up space in the cache
+pub fn revert_query_cache_on_reorg(
+    logger: Logger,
+    network: String,
+    mut chain_head_updates: ChainHeadUpdateStream,
+    chain_store: Arc<dyn ChainStore>,
+) {
+    graph::spawn(async move {
+        let mut last_head: Option<BlockPtr> = None;
+        while chain_head_updates.next().await.is_some() {
+            let head = match chain_store.cheap_clone().chain_head_ptr().await {
+                Ok(Some(head)) => head,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(logger, "Failed to get chain head for query cache";
+                                  "network" => &network,
+                                  "error" => e.to_string());
+                    continue;
+                }
+            };
+            if let Some(last_head) = &last_head {
+                if head.number <= last_head.number && &head != last_head {
+                    debug!(logger, "Reverting query cache";
+                                   "network" => &network,
+                                   "from" => last_head.number,
+                                   "to" => head.number);
+                    QUERY_RESULT_CACHE.revert(&logger, &network, &head).await;
+                }
+            }
+            last_head = Some(head);
+        }
+    });
+}
+
+/// The most queries that clients can register with automatic persisted
+/// queries. Once the limit is reached, clients have to keep sending the
+/// text of new queries
+const MAX_REGISTERED_QUERIES: usize = 10_000;
+
+/// Persisted queries that clients can run by sending just their id. The
+/// documents are kept in parsed form so that they do not need to be parsed
+/// again for every request.
+///
+/// Queries come from two sources: the allow-list, which is read from a
+/// file when the node starts, and queries that clients register with the
+/// automatic persisted queries (APQ) protocol by sending the text of a
+/// query together with its SHA-256 hash. Only queries from the allow-list
+/// can be run against deployments that only accept persisted queries
+#[derive(Default)]
+pub struct PersistedQueries {
+    allowed: HashMap<String, Arc<q::Document>>,
+    registered: RwLock<HashMap<String, Arc<q::Document>>>,
+}
+
+impl PersistedQueries {
+    /// Create persisted queries with the given allow-list, which maps ids
+    /// to query texts. Ids can be chosen freely, but usually are the
+    /// SHA-256 hash of the query text
+    pub fn new(allowed: HashMap<String, String>) -> Result<Self, anyhow::Error> {
+        let allowed = allowed
+            .into_iter()
+            .map(|(id, text)| {
+                let document = graphql_parser::parse_query(&text)
+                    .map_err(|e| anyhow!("persisted query `{}` is invalid: {}", id, e))?
+                    .into_static();
+                Ok((id, Arc::new(document)))
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(PersistedQueries {
+            allowed,
+            ..Default::default()
+        })
+    }
+
+    /// Read the allow-list from the file in `GRAPH_GRAPHQL_PERSISTED_QUERIES`
+    /// if it is set. The file must contain a JSON object that maps query
+    /// ids to query texts
+    pub fn from_env(logger: &Logger) -> Result<Self, anyhow::Error> {
+        let path = match &ENV_VARS.graphql.persisted_queries {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow!("can not read persisted queries from {}: {}", path, e))?;
+        let allowed: HashMap<String, String> = serde_json::from_reader(file)
+            .map_err(|e| anyhow!("persisted queries in {} are malformed: {}", path, e))?;
+        info!(logger, "Loaded persisted queries";
+                      "path" => path,
+                      "count" => allowed.len());
+        Self::new(allowed)
+    }
+
+    /// Look up the query with `id`. Return the query and whether it is on
+    /// the allow-list
+    pub fn get(&self, id: &str) -> Option<(Arc<q::Document>, bool)> {
+        if let Some(document) = self.allowed.get(id) {
+            return Some((document.cheap_clone(), true));
+        }
+        self.registered
+            .read()
+            .get(id)
+            .map(|document| (document.cheap_clone(), false))
+    }
+
+    /// Register `text` as the persisted query with the SHA-256 hash `hash`
+    /// and return it in the same form as `get`. It is an error if `hash`
+    /// is not the hash of `text`
+    pub fn register(&self, hash: &str, text: &str) -> Result<(Arc<q::Document>, bool), QueryError> {
+        if hex::encode(Sha256::digest(text.as_bytes())) != hash.to_lowercase() {
+            return Err(QueryExecutionError::ValidationError(
+                None,
+                "the hash of the persisted query does not match its text".to_string(),
+            )
+            .into());
+        }
+        if let Some(found) = self.get(hash) {
+            return Ok(found);
+        }
+
+        let document = graphql_parser::parse_query(text)
+            .map_err(|e| QueryError::ParseError(Arc::new(e.into())))?
+            .into_static();
+        let document = Arc::new(document);
+        let mut registered = self.registered.write();
+        if registered.len() < MAX_REGISTERED_QUERIES {
+            registered.insert(hash.to_string(), document.cheap_clone());
+        }
+        Ok((document, false))
+    }
+}
+
+/// Remembers which persisted queries passed validation against the schema
+/// of a deployment so that they are not validated again for every request
+#[derive(Default)]
+pub(crate) struct ValidatedQueries {
+    validated: RwLock<HashSet<(DeploymentHash, Arc<String>)>>,
+}
+
+impl ValidatedQueries {
+    pub fn contains(&self, deployment: &DeploymentHash, id: &Arc<String>) -> bool {
+        self.validated
+            .read()
+            .contains(&(deployment.clone(), id.cheap_clone()))
+    }
+
+    pub fn insert(&self, deployment: &DeploymentHash, id: &Arc<String>) {
+        self.validated
+            .write()
+            .insert((deployment.clone(), id.cheap_clone()));
+    }
 }
@@ -1,22 +1,19 @@
-use super::cache::{QueryBlockCache, QueryCache};
+use super::cache::{QueryCache, QUERY_RESULT_CACHE};
 use crossbeam::atomic::AtomicCell;
 use graph::{
     data::{schema::META_FIELD_NAME, value::Object},
     prelude::{s, CheapClone},
-    util::{lfu_cache::EvictStats, timed_rw_lock::TimedMutex},
 };
 use lazy_static::lazy_static;
-use parking_lot::MutexGuard;
 use stable_hash::{FieldAddress, StableHash, StableHasher};
 use stable_hash_legacy::SequenceNumber;
 use std::time::Instant;
 use std::{borrow::ToOwned, collections::HashSet};
 
 use graph::data::graphql::*;
-use graph::data::query::CacheStatus;
+use graph::data::query::{CacheStatus, Trace};
 use graph::env::CachedSubgraphIds;
 use graph::prelude::*;
-use graph::util::lfu_cache::LfuCache;
 
 use super::QueryHash;
 use crate::execution::ast as a;
@@ -25,44 +22,9 @@ use crate::prelude::*;
 use crate::schema::ast as sast;
 
 lazy_static! {
-    // Sharded query results cache for recent blocks by network.
-    // The `VecDeque` works as a ring buffer with a capacity of `QUERY_CACHE_BLOCKS`.
-    static ref QUERY_BLOCK_CACHE: Vec<TimedMutex<QueryBlockCache>> = {
-            let shards = ENV_VARS.graphql.query_block_cache_shards;
-            let blocks = ENV_VARS.graphql.query_cache_blocks;
-
-            // The memory budget is evenly divided among blocks and their shards.
-            let max_weight = ENV_VARS.graphql.query_cache_max_mem / (blocks * shards as usize);
-            let mut caches = Vec::new();
-            for i in 0..shards {
-                let id = format!("query_block_cache_{}", i);
-                caches.push(TimedMutex::new(QueryBlockCache::new(blocks, i, max_weight), id))
-            }
-            caches
-    };
     static ref QUERY_HERD_CACHE: QueryCache<Arc<QueryResult>> = QueryCache::new("query_herd_cache");
 }
 
-struct WeightedResult {
-    result: Arc<QueryResult>,
-    weight: usize,
-}
-
-impl CacheWeight for WeightedResult {
-    fn indirect_weight(&self) -> usize {
-        self.weight
-    }
-}
-
-impl Default for WeightedResult {
-    fn default() -> Self {
-        WeightedResult {
-            result: Arc::new(QueryResult::new(Object::default())),
-            weight: 0,
-        }
-    }
-}
-
 struct HashableQuery<'a> {
     query_schema_id: &'a DeploymentHash,
     selection_set: &'a a::SelectionSet,
@@ -73,7 +35,9 @@ struct HashableQuery<'a> {
 /// we are converting items to a string inside here as a quick-and-dirty
 /// implementation. This precludes the ability to add new fields (unlikely
 /// anyway). So, this hash isn't really Stable in the way that the StableHash
-/// crate defines it. Since hashes are only persisted for this process, we don't
+/// crate defines it. Hashes are only shared with other processes through an
+/// external query cache, and a different version of graph-node computing a
+/// different hash for the same query only leads to a cache miss, so we don't
 /// need that property. The reason we are using StableHash is to get collision
 /// resistance and use it's foolproof API to prevent easy mistakes instead.
 ///
@@ -143,66 +107,6 @@ fn cache_key(
     stable_hash_legacy::utils::stable_hash::<stable_hash_legacy::crypto::SetHasher, _>(&query)
 }
 
-fn lfu_cache(
-    logger: &Logger,
-    cache_key: &[u8; 32],
-) -> Option<MutexGuard<'static, LfuCache<[u8; 32], WeightedResult>>> {
-    lazy_static! {
-        static ref QUERY_LFU_CACHE: Vec<TimedMutex<LfuCache<QueryHash, WeightedResult>>> = {
-            std::iter::repeat_with(|| TimedMutex::new(LfuCache::new(), "query_lfu_cache"))
-                .take(ENV_VARS.graphql.query_lfu_cache_shards as usize)
-                .collect()
-        };
-    }
-
-    match QUERY_LFU_CACHE.len() {
-        0 => None,
-        n => {
-            let shard = (cache_key[0] as usize) % n;
-            Some(QUERY_LFU_CACHE[shard].lock(logger))
-        }
-    }
-}
-
-fn log_lfu_evict_stats(
-    logger: &Logger,
-    network: &str,
-    cache_key: &[u8; 32],
-    evict_stats: Option<EvictStats>,
-) {
-    let total_shards = ENV_VARS.graphql.query_lfu_cache_shards as usize;
-
-    if total_shards > 0 {
-        if let Some(EvictStats {
-            new_weight,
-            evicted_weight,
-            new_count,
-            evicted_count,
-            stale_update,
-            evict_time,
-        }) = evict_stats
-        {
-            {
-                let shard = (cache_key[0] as usize) % total_shards;
-                let network = network.to_string();
-                let logger = logger.clone();
-
-                graph::spawn(async move {
-                    debug!(logger, "Evicted LFU cache";
-                        "shard" => shard,
-                        "network" => network,
-                        "entries" => new_count,
-                        "entries_evicted" => evicted_count,
-                        "weight" => new_weight,
-                        "weight_evicted" => evicted_weight,
-                        "stale_update" => stale_update,
-                        "evict_time_ms" => evict_time.as_millis()
-                    )
-                });
-            }
-        }
-    }
-}
 /// Contextual information passed around during query execution.
 pub struct ExecutionContext<R>
 where
@@ -268,7 +172,7 @@ pub(crate) fn execute_root_selection_set_uncached(
     ctx: &ExecutionContext<impl Resolver>,
     selection_set: &a::SelectionSet,
     root_type: &sast::ObjectType,
-) -> Result<Object, Vec<QueryExecutionError>> {
+) -> Result<(Object, Trace), Vec<QueryExecutionError>> {
     // Split the top-level fields into introspection fields and
     // regular data fields
     let mut data_set = a::SelectionSet::empty_from(selection_set);
@@ -289,12 +193,18 @@ pub(crate) fn execute_root_selection_set_uncached(
     }
 
     // If we are getting regular data, prefetch it from the database
-    let mut values = if data_set.is_empty() && meta_items.is_empty() {
-        Object::default()
+    let (mut values, trace) = if data_set.is_empty() && meta_items.is_empty() {
+        (Object::default(), Trace::None)
     } else {
-        let initial_data = ctx.resolver.prefetch(ctx, &data_set)?;
+        let start = Instant::now();
+        let (initial_data, mut trace) = ctx.resolver.prefetch(ctx, &data_set)?;
+        trace.step("prefetch", start.elapsed());
+
+        let start = Instant::now();
         data_set.push_fields(meta_items)?;
-        execute_selection_set_to_map(ctx, &data_set, root_type, initial_data)?
+        let values = execute_selection_set_to_map(ctx, &data_set, root_type, initial_data)?;
+        trace.step("resolve", start.elapsed());
+        (values, trace)
     };
 
     // Resolve introspection fields, if there are any
@@ -309,7 +219,7 @@ pub(crate) fn execute_root_selection_set_uncached(
         )?);
     }
 
-    Ok(values)
+    Ok((values, trace))
 }
 
 /// Executes the root selection set of a query.
@@ -323,7 +233,10 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     // and once for insert.
     let mut key: Option<QueryHash> = None;
 
+    // Traced queries are never served from or stored in the cache since
+    // the trace is only meaningful for a query that actually ran
     let should_check_cache = R::CACHEABLE
+        && !ctx.query.trace
         && match ENV_VARS.graphql.cached_subgraph_ids {
             CachedSubgraphIds::All => true,
             CachedSubgraphIds::Only(ref subgraph_ids) => {
@@ -337,25 +250,15 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
             // - Metadata queries are not cacheable.
             // - Caching `BLOCK_NUMBER_MAX` would make this cache think all other blocks are old.
             if block_ptr.number != BLOCK_NUMBER_MAX {
-                // Calculate the hash outside of the lock
+                // Calculate the hash outside of any cache locks
                 let cache_key = cache_key(&ctx, &selection_set, block_ptr);
-                let shard = (cache_key[0] as usize) % QUERY_BLOCK_CACHE.len();
 
-                // Check if the response is cached, first in the recent blocks cache,
-                // and then in the LfuCache for historical queries
-                // The blocks are used to delimit how long locks need to be held
+                if let Some(result) = QUERY_RESULT_CACHE
+                    .get(&ctx.logger, network, block_ptr, &cache_key)
+                    .await
                 {
-                    let cache = QUERY_BLOCK_CACHE[shard].lock(&ctx.logger);
-                    if let Some(result) = cache.get(network, block_ptr, &cache_key) {
-                        ctx.cache_status.store(CacheStatus::Hit);
-                        return result;
-                    }
-                }
-                if let Some(mut cache) = lfu_cache(&ctx.logger, &cache_key) {
-                    if let Some(weighted) = cache.get(&cache_key) {
-                        ctx.cache_status.store(CacheStatus::Hit);
-                        return weighted.result.cheap_clone();
-                    }
+                    ctx.cache_status.store(CacheStatus::Hit);
+                    return result;
                 }
                 key = Some(cache_key);
             }
@@ -372,11 +275,16 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
         let query_text = execute_ctx.query.query_text.cheap_clone();
         let variables_text = execute_ctx.query.variables_text.cheap_clone();
         match graph::spawn_blocking_allow_panic(move || {
-            let mut query_res = QueryResult::from(execute_root_selection_set_uncached(
+            let (values, trace) = match execute_root_selection_set_uncached(
                 &execute_ctx,
                 &execute_selection_set,
                 &execute_root_type,
-            ));
+            ) {
+                Ok((values, trace)) => (Ok(values), trace),
+                Err(e) => (Err(e), Trace::None),
+            };
+            let mut query_res = QueryResult::from(values);
+            query_res.trace = trace;
 
             // Unwrap: In practice should never fail, but if it does we will catch the panic.
             execute_ctx.resolver.post_process(&mut query_res).unwrap();
@@ -428,38 +336,21 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     if let (false, Some(key), Some(block_ptr), Some(network)) =
         (no_cache, key, block_ptr, &ctx.query.network)
     {
-        // Calculate the weight outside the lock.
+        // Calculate the weight outside of any cache locks.
         let weight = result.weight();
-        let shard = (key[0] as usize) % QUERY_BLOCK_CACHE.len();
-        let inserted = QUERY_BLOCK_CACHE[shard].lock(&ctx.logger).insert(
-            network,
-            block_ptr,
-            key,
-            result.cheap_clone(),
-            weight,
-            ctx.logger.cheap_clone(),
-        );
+        let inserted = QUERY_RESULT_CACHE
+            .insert(
+                &ctx.logger,
+                network,
+                block_ptr,
+                key,
+                result.cheap_clone(),
+                weight,
+            )
+            .await;
 
         if inserted {
             ctx.cache_status.store(CacheStatus::Insert);
-        } else if let Some(mut cache) = lfu_cache(&ctx.logger, &key) {
-            // Results that are too old for the QUERY_BLOCK_CACHE go into the QUERY_LFU_CACHE
-            let max_mem = ENV_VARS.graphql.query_cache_max_mem
-                / ENV_VARS.graphql.query_lfu_cache_shards as usize;
-
-            let evict_stats =
-                cache.evict_with_period(max_mem, ENV_VARS.graphql.query_cache_stale_period);
-
-            log_lfu_evict_stats(&ctx.logger, network, &key, evict_stats);
-
-            cache.insert(
-                key,
-                WeightedResult {
-                    result: result.cheap_clone(),
-                    weight,
-                },
-            );
-            ctx.cache_status.store(CacheStatus::Insert);
         }
     }
 
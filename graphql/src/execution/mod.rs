@@ -2,6 +2,8 @@ mod cache;
 /// Implementation of the GraphQL execution algorithm.
 mod execution;
 mod query;
+/// A query result cache in an external key-value store
+mod redis;
 /// Common trait for field resolvers used in the execution.
 mod resolver;
 
@@ -10,6 +12,7 @@ pub mod ast;
 
 use stable_hash_legacy::{crypto::SetHasher, StableHasher};
 
+pub use self::cache::{revert_query_cache_on_reorg, PersistedQueries};
 pub use self::execution::*;
 pub use self::query::Query;
 pub use self::resolver::Resolver;
@@ -11,19 +11,27 @@ use std::sync::Arc;
 use std::time::Instant;
 use std::{collections::hash_map::DefaultHasher, convert::TryFrom};
 
-use graph::data::graphql::{ext::TypeExt, ObjectOrInterface};
+use graph::data::graphql::{
+    ext::{DirectiveFinder, TypeExt},
+    ObjectOrInterface,
+};
 use graph::data::query::QueryExecutionError;
 use graph::data::query::{Query as GraphDataQuery, QueryVariables};
 use graph::data::schema::ApiSchema;
 use graph::prelude::{info, o, q, r, s, BlockNumber, CheapClone, Logger, TryFromValue, ENV_VARS};
 
 use crate::execution::ast as a;
+use crate::execution::cache::ValidatedQueries;
 use crate::query::{ast as qast, ext::BlockConstraint};
 use crate::schema::ast::{self as sast};
 use crate::values::coercion;
-use crate::{execution::get_field, schema::api::ErrorPolicy};
+use crate::{
+    execution::get_field,
+    schema::api::{ErrorPolicy, AGGREGATE_DIRECTIVE},
+};
 
 lazy_static! {
+    static ref VALIDATED_QUERIES: ValidatedQueries = ValidatedQueries::default();
     static ref GRAPHQL_VALIDATION_PLAN: ValidationPlan =
         ValidationPlan::from(if !ENV_VARS.graphql.enable_validations {
             vec![]
@@ -133,6 +141,9 @@ pub struct Query {
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
     pub query_id: String,
+
+    /// Whether to collect a trace of how the query was executed
+    pub trace: bool,
 }
 
 impl Query {
@@ -148,16 +159,30 @@ impl Query {
         max_complexity: Option<u64>,
         max_depth: u8,
     ) -> Result<Arc<Self>, Vec<QueryExecutionError>> {
-        let validation_errors =
-            validate(schema.document(), &query.document, &GRAPHQL_VALIDATION_PLAN);
-
-        if !validation_errors.is_empty() {
-            return Err(validation_errors
-                .into_iter()
-                .map(|e| {
-                    QueryExecutionError::ValidationError(e.locations.first().cloned(), e.message)
-                })
-                .collect());
+        // Persisted queries only need to be validated once per deployment
+        let validated = query
+            .persisted_id
+            .as_ref()
+            .map(|id| VALIDATED_QUERIES.contains(schema.id(), id))
+            .unwrap_or(false);
+        if !validated {
+            let validation_errors =
+                validate(schema.document(), &query.document, &GRAPHQL_VALIDATION_PLAN);
+
+            if !validation_errors.is_empty() {
+                return Err(validation_errors
+                    .into_iter()
+                    .map(|e| {
+                        QueryExecutionError::ValidationError(
+                            e.locations.first().cloned(),
+                            e.message,
+                        )
+                    })
+                    .collect());
+            }
+            if let Some(id) = &query.persisted_id {
+                VALIDATED_QUERIES.insert(schema.id(), id);
+            }
         }
 
         let mut operation = None;
@@ -238,6 +263,7 @@ impl Query {
             query_text: query.query_text.cheap_clone(),
             variables_text: query.variables_text.cheap_clone(),
             query_id,
+            trace: query.trace,
         };
 
         Ok(Arc::new(query))
@@ -511,6 +537,18 @@ impl<'s> RawQuery<'s> {
                             visited_fragments,
                         )?;
 
+                        // Aggregates look at all entities that match their
+                        // filter; count them like a collection query for as
+                        // many entities as a query can ask for
+                        if s_field.find_directive(AGGREGATE_DIRECTIVE).is_some() {
+                            let max_entities = ENV_VARS.graphql.max_first as u64;
+                            return max_entities
+                                .checked_add(
+                                    max_entities.checked_mul(field_complexity).ok_or(Overflow)?,
+                                )
+                                .ok_or(Overflow);
+                        }
+
                         // Non-collection queries pass through.
                         if !sast::is_list_or_non_null_list_field(&s_field) {
                             return Ok(total_complexity + field_complexity);
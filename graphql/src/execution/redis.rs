@@ -0,0 +1,819 @@
+//! A query result cache in a key-value store that speaks the Redis
+//! protocol. Query nodes that point at the same store share cached results,
+//! and cached results survive restarts of the query nodes.
+//!
+//! Each result is stored under a key made from the network, the hash of the
+//! block at which the query ran and the hash of the query. So that results
+//! for blocks that were reverted can be removed, we also keep a sorted set
+//! of the blocks with cached results for each network, and the set of keys
+//! of cached results for each block.
+//!
+//! The store is strictly a cache: any error talking to it is treated like a
+//! cache miss, and the connection is reestablished on the next request.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use graph::{
+    data::value::Object,
+    prelude::{
+        anyhow::{self, anyhow},
+        async_trait, hex, info, r, serde_json, warn, BlockNumber, BlockPtr, DeploymentHash, Logger,
+        QueryResult,
+    },
+    url::Url,
+};
+use serde_json::{json, Value as JsonValue};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use super::cache::QueryResultCache;
+use super::QueryHash;
+
+/// Prefix for all our keys. The version needs to change whenever the way
+/// we encode results changes
+const PREFIX: &str = "graph:query_cache:v1";
+
+/// How long to wait for the store before treating a request as a miss
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The number of connections we keep open to the store
+const CONNECTIONS: usize = 8;
+
+/// How many blocks behind the latest block with cached results we keep
+/// track of for reverts. Results for older blocks are not removed on a
+/// revert, but they still expire after the configured TTL
+const TRACKED_BLOCKS: BlockNumber = 250;
+
+/// A reply from the store. We never need the values of status and
+/// integer replies, and therefore do not keep them
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Status,
+    Error(String),
+    Int,
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+impl Reply {
+    /// The bulk strings in an array reply; anything else is ignored
+    fn into_bulks(self) -> Vec<Vec<u8>> {
+        match self {
+            Reply::Array(Some(items)) => items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Reply::Bulk(Some(bytes)) => Some(bytes),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// A command, encoded as an array of bulk strings
+struct Command(Vec<Vec<u8>>);
+
+impl Command {
+    fn new(name: &str) -> Self {
+        Command(vec![name.as_bytes().to_vec()])
+    }
+
+    fn arg(mut self, arg: impl AsRef<[u8]>) -> Self {
+        self.0.push(arg.as_ref().to_vec());
+        self
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(format!("*{}\r\n", self.0.len()).as_bytes());
+        for arg in &self.0 {
+            buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            buf.extend_from_slice(arg);
+            buf.extend_from_slice(b"\r\n");
+        }
+    }
+}
+
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<String, anyhow::Error> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line).await?;
+    if !line.ends_with(b"\r\n") {
+        return Err(anyhow!("connection closed by the query cache"));
+    }
+    line.truncate(line.len() - 2);
+    Ok(String::from_utf8(line)?)
+}
+
+fn read_reply<'a, R>(
+    reader: &'a mut R,
+) -> Pin<Box<dyn Future<Output = Result<Reply, anyhow::Error>> + Send + 'a>>
+where
+    R: AsyncBufRead + Unpin + Send,
+{
+    Box::pin(async move {
+        let line = read_line(reader).await?;
+        if line.is_empty() {
+            return Err(anyhow!("empty reply from the query cache"));
+        }
+        let (kind, rest) = line.split_at(1);
+        let reply = match kind {
+            "+" => Reply::Status,
+            "-" => Reply::Error(rest.to_string()),
+            ":" => {
+                rest.parse::<i64>()?;
+                Reply::Int
+            }
+            "$" => {
+                let len: i64 = rest.parse()?;
+                if len < 0 {
+                    Reply::Bulk(None)
+                } else {
+                    // Read the trailing CRLF along with the data
+                    let mut bytes = vec![0; len as usize + 2];
+                    reader.read_exact(&mut bytes).await?;
+                    bytes.truncate(len as usize);
+                    Reply::Bulk(Some(bytes))
+                }
+            }
+            "*" => {
+                let len: i64 = rest.parse()?;
+                if len < 0 {
+                    Reply::Array(None)
+                } else {
+                    let mut items = Vec::with_capacity(len as usize);
+                    for _ in 0..len {
+                        items.push(read_reply(reader).await?);
+                    }
+                    Reply::Array(Some(items))
+                }
+            }
+            _ => return Err(anyhow!("invalid reply from the query cache: {}", line)),
+        };
+        Ok(reply)
+    })
+}
+
+struct Connection {
+    stream: BufStream<TcpStream>,
+}
+
+impl Connection {
+    async fn connect(url: &Url) -> Result<Self, anyhow::Error> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("the query cache URL has no host"))?;
+        let port = url.port().unwrap_or(6379);
+        let stream = TcpStream::connect((host, port)).await?;
+        let mut conn = Connection {
+            stream: BufStream::new(stream),
+        };
+
+        let mut setup = Vec::new();
+        if let Some(password) = url.password() {
+            let mut auth = Command::new("AUTH");
+            if !url.username().is_empty() {
+                auth = auth.arg(url.username());
+            }
+            setup.push(auth.arg(password));
+        }
+        let db = url.path().trim_start_matches('/');
+        if !db.is_empty() {
+            setup.push(Command::new("SELECT").arg(db));
+        }
+        if !setup.is_empty() {
+            conn.pipeline(&setup).await?;
+        }
+        Ok(conn)
+    }
+
+    /// Send all `commands` and then read their replies. Fail if any of the
+    /// commands failed, but only after all replies have been read so that
+    /// the connection can still be used
+    async fn pipeline(&mut self, commands: &[Command]) -> Result<Vec<Reply>, anyhow::Error> {
+        let mut buf = Vec::new();
+        for command in commands {
+            command.encode(&mut buf);
+        }
+        self.stream.write_all(&buf).await?;
+        self.stream.flush().await?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for _ in commands {
+            replies.push(read_reply(&mut self.stream).await?);
+        }
+        if let Some(Reply::Error(e)) = replies.iter().find(|r| matches!(r, Reply::Error(_))) {
+            return Err(anyhow!("query cache error: {}", e));
+        }
+        Ok(replies)
+    }
+}
+
+/// A `QueryResultCache` that keeps results in a store that speaks the
+/// Redis protocol
+pub struct RedisResultCache {
+    url: Url,
+    ttl: Duration,
+    connections: Vec<Mutex<Option<Connection>>>,
+    next: AtomicUsize,
+    /// Whether the last request to the store succeeded; used to log only
+    /// when the store becomes unavailable or available again
+    healthy: AtomicBool,
+}
+
+impl RedisResultCache {
+    pub fn new(url: &str, ttl: Duration) -> Result<Self, anyhow::Error> {
+        let url = Url::parse(url)?;
+        if url.scheme() != "redis" {
+            return Err(anyhow!(
+                "the query cache URL must use the `redis` scheme but uses `{}`",
+                url.scheme()
+            ));
+        }
+        Ok(RedisResultCache {
+            url,
+            ttl,
+            connections: (0..CONNECTIONS).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+            healthy: AtomicBool::new(true),
+        })
+    }
+
+    /// Run `commands` on one of our connections, connecting first if
+    /// needed. The connection is taken out of its slot for the duration of
+    /// the request and only put back once all replies have been read. If
+    /// the request fails, times out, or the future is dropped halfway
+    /// through, the connection is dropped so that the next request that
+    /// uses the slot reconnects instead of reading stale replies
+    async fn pipeline(
+        &self,
+        logger: &Logger,
+        commands: &[Command],
+    ) -> Result<Vec<Reply>, anyhow::Error> {
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+        let mut slot = self.connections[idx].lock().await;
+        let conn = slot.take();
+        let res = tokio::time::timeout(TIMEOUT, async {
+            let mut conn = match conn {
+                Some(conn) => conn,
+                None => Connection::connect(&self.url).await?,
+            };
+            let replies = conn.pipeline(commands).await?;
+            Ok((conn, replies))
+        })
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("request to the query cache timed out")));
+
+        match res {
+            Ok((conn, replies)) => {
+                *slot = Some(conn);
+                if !self.healthy.swap(true, Ordering::Relaxed) {
+                    info!(logger, "Query cache is available again");
+                }
+                Ok(replies)
+            }
+            Err(e) => {
+                if self.healthy.swap(false, Ordering::Relaxed) {
+                    warn!(logger, "Query cache is unavailable, treating lookups as misses";
+                        "error" => e.to_string());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn ttl_millis(&self) -> String {
+        self.ttl.as_millis().to_string()
+    }
+}
+
+fn result_key(network: &str, block_ptr: &BlockPtr, key: &QueryHash) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        PREFIX,
+        network,
+        block_ptr.hash_hex(),
+        hex::encode(key)
+    )
+}
+
+/// The key of the set of result keys for the block with hash `hash_hex`
+fn block_key(network: &str, hash_hex: &str) -> String {
+    format!("{}:{}:{}", PREFIX, network, hash_hex)
+}
+
+/// The key of the sorted set of blocks with cached results for `network`
+fn blocks_key(network: &str) -> String {
+    format!("{}:{}:blocks", PREFIX, network)
+}
+
+fn block_member(block_ptr: &BlockPtr) -> String {
+    format!("{}:{}", block_ptr.number, block_ptr.hash_hex())
+}
+
+/// Encode a value as JSON. JSON objects would not preserve the order of
+/// keys and JSON numbers do not distinguish between `Int` and `Float`, so
+/// everything but strings, booleans and null is encoded as a tagged array
+fn encode_value(value: &r::Value) -> JsonValue {
+    match value {
+        r::Value::Null => JsonValue::Null,
+        r::Value::Boolean(b) => JsonValue::Bool(*b),
+        r::Value::String(s) => JsonValue::String(s.clone()),
+        r::Value::Int(i) => json!(["i", i]),
+        r::Value::Float(f) => json!(["f", f]),
+        r::Value::Enum(e) => json!(["e", e]),
+        r::Value::List(values) => json!(["l", values.iter().map(encode_value).collect::<Vec<_>>()]),
+        r::Value::Object(obj) => json!(["o", encode_object(obj)]),
+    }
+}
+
+fn encode_object(obj: &Object) -> JsonValue {
+    JsonValue::Array(
+        obj.iter()
+            .map(|(key, value)| json!([key, encode_value(value)]))
+            .collect(),
+    )
+}
+
+fn decode_value(value: &JsonValue) -> Result<r::Value, anyhow::Error> {
+    let value = match value {
+        JsonValue::Null => r::Value::Null,
+        JsonValue::Bool(b) => r::Value::Boolean(*b),
+        JsonValue::String(s) => r::Value::String(s.clone()),
+        JsonValue::Array(tagged) if tagged.len() == 2 => {
+            let inner = &tagged[1];
+            match (tagged[0].as_str(), inner) {
+                (Some("i"), _) => r::Value::Int(
+                    inner
+                        .as_i64()
+                        .ok_or_else(|| anyhow!("invalid Int `{}`", inner))?,
+                ),
+                (Some("f"), _) => r::Value::Float(
+                    inner
+                        .as_f64()
+                        .ok_or_else(|| anyhow!("invalid Float `{}`", inner))?,
+                ),
+                (Some("e"), JsonValue::String(e)) => r::Value::Enum(e.clone()),
+                (Some("l"), JsonValue::Array(values)) => {
+                    r::Value::List(values.iter().map(decode_value).collect::<Result<_, _>>()?)
+                }
+                (Some("o"), _) => r::Value::Object(decode_object(inner)?),
+                _ => return Err(anyhow!("invalid cached value `{}`", value)),
+            }
+        }
+        _ => return Err(anyhow!("invalid cached value `{}`", value)),
+    };
+    Ok(value)
+}
+
+fn decode_object(value: &JsonValue) -> Result<Object, anyhow::Error> {
+    let entries = value
+        .as_array()
+        .ok_or_else(|| anyhow!("invalid cached object `{}`", value))?;
+    entries
+        .iter()
+        .map(|entry| match entry.as_array().map(|kv| kv.as_slice()) {
+            Some([JsonValue::String(key), value]) => Ok((key.clone(), decode_value(value)?)),
+            _ => Err(anyhow!("invalid cached object entry `{}`", entry)),
+        })
+        .collect()
+}
+
+fn encode_result(result: &QueryResult) -> Vec<u8> {
+    let payload = json!({
+        "deployment": result.deployment.as_ref().map(|id| id.as_str()),
+        "data": result.data().map(encode_object),
+    });
+    payload.to_string().into_bytes()
+}
+
+fn decode_result(bytes: &[u8]) -> Result<QueryResult, anyhow::Error> {
+    let payload: JsonValue = serde_json::from_slice(bytes)?;
+    let data = decode_object(&payload["data"])?;
+    let mut result = QueryResult::new(data);
+    if let Some(id) = payload["deployment"].as_str() {
+        result.deployment = Some(
+            DeploymentHash::new(id)
+                .map_err(|id| anyhow!("invalid deployment `{}` in cached result", id))?,
+        );
+    }
+    Ok(result)
+}
+
+#[async_trait]
+impl QueryResultCache for RedisResultCache {
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>> {
+        let get = Command::new("GET").arg(result_key(network, block_ptr, key));
+        let reply = self.pipeline(logger, &[get]).await.ok()?.pop()?;
+        match reply {
+            Reply::Bulk(Some(bytes)) => match decode_result(&bytes) {
+                Ok(result) => Some(Arc::new(result)),
+                Err(e) => {
+                    warn!(logger, "Ignoring invalid cached query result";
+                        "error" => e.to_string());
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        _weight: usize,
+    ) -> bool {
+        let ttl = self.ttl_millis();
+        let result_key = result_key(network, &block_ptr, &key);
+        let block_key = block_key(network, &block_ptr.hash_hex());
+        let blocks_key = blocks_key(network);
+        let oldest = block_ptr.number.saturating_sub(TRACKED_BLOCKS);
+
+        let commands = [
+            Command::new("SET")
+                .arg(&result_key)
+                .arg(encode_result(&result))
+                .arg("PX")
+                .arg(&ttl),
+            Command::new("SADD").arg(&block_key).arg(&result_key),
+            Command::new("PEXPIRE").arg(&block_key).arg(&ttl),
+            Command::new("ZADD")
+                .arg(&blocks_key)
+                .arg(block_ptr.number.to_string())
+                .arg(block_member(&block_ptr)),
+            Command::new("PEXPIRE").arg(&blocks_key).arg(&ttl),
+            Command::new("ZREMRANGEBYSCORE")
+                .arg(&blocks_key)
+                .arg("-inf")
+                .arg(format!("({}", oldest)),
+        ];
+        self.pipeline(logger, &commands).await.is_ok()
+    }
+
+    async fn revert(&self, logger: &Logger, network: &str, head: &BlockPtr) {
+        let blocks_key = blocks_key(network);
+        let range = Command::new("ZRANGEBYSCORE")
+            .arg(&blocks_key)
+            .arg(head.number.to_string())
+            .arg("+inf");
+        let members = match self.pipeline(logger, &[range]).await {
+            Ok(mut replies) => replies.pop().map(Reply::into_bulks).unwrap_or_default(),
+            Err(_) => return,
+        };
+
+        // Every block in `members` is at or above the head; the only one
+        // that is still on the chain is the head itself
+        let head_member = block_member(head);
+        let stale: Vec<_> = members
+            .into_iter()
+            .filter_map(|member| String::from_utf8(member).ok())
+            .filter(|member| member != &head_member)
+            .collect();
+        if stale.is_empty() {
+            return;
+        }
+
+        let block_keys: Vec<_> = stale
+            .iter()
+            .filter_map(|member| member.split_once(':'))
+            .map(|(_, hash)| block_key(network, hash))
+            .collect();
+        let smembers: Vec<_> = block_keys
+            .iter()
+            .map(|block_key| Command::new("SMEMBERS").arg(block_key))
+            .collect();
+        let result_keys = match self.pipeline(logger, &smembers).await {
+            Ok(replies) => replies.into_iter().flat_map(Reply::into_bulks),
+            Err(_) => return,
+        };
+
+        let mut del = Command::new("DEL");
+        for key in result_keys.chain(block_keys.iter().map(|key| key.as_bytes().to_vec())) {
+            del = del.arg(key);
+        }
+        let mut zrem = Command::new("ZREM").arg(&blocks_key);
+        for member in &stale {
+            zrem = zrem.arg(member);
+        }
+        if self.pipeline(logger, &[del, zrem]).await.is_ok() {
+            info!(logger, "Removed cached query results for reverted blocks";
+                "network" => network, "blocks" => stale.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    use graph::prelude::{futures03::FutureExt, o, slog, web3::types::H256};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// The subset of the store's state that `RedisResultCache` uses.
+    /// Expiration is not simulated
+    #[derive(Default)]
+    struct Store {
+        strings: HashMap<Vec<u8>, Vec<u8>>,
+        sets: HashMap<Vec<u8>, HashSet<Vec<u8>>>,
+        zsets: HashMap<Vec<u8>, BTreeMap<Vec<u8>, f64>>,
+    }
+
+    fn score(arg: &[u8]) -> (f64, bool) {
+        let arg = std::str::from_utf8(arg).unwrap();
+        match arg {
+            "-inf" => (f64::NEG_INFINITY, false),
+            "+inf" => (f64::INFINITY, false),
+            _ => match arg.strip_prefix('(') {
+                Some(num) => (num.parse().unwrap(), true),
+                None => (arg.parse().unwrap(), false),
+            },
+        }
+    }
+
+    fn in_range(value: f64, min: &[u8], max: &[u8]) -> bool {
+        let (min, min_excl) = score(min);
+        let (max, max_excl) = score(max);
+        (value > min || (!min_excl && value == min)) && (value < max || (!max_excl && value == max))
+    }
+
+    fn bulks(items: impl IntoIterator<Item = Vec<u8>>) -> Reply {
+        Reply::Array(Some(
+            items
+                .into_iter()
+                .map(|item| Reply::Bulk(Some(item)))
+                .collect(),
+        ))
+    }
+
+    impl Store {
+        fn run(&mut self, args: Vec<Vec<u8>>) -> Reply {
+            let name = String::from_utf8(args[0].clone()).unwrap();
+            match name.as_str() {
+                "GET" => Reply::Bulk(self.strings.get(&args[1]).cloned()),
+                "SET" => {
+                    self.strings.insert(args[1].clone(), args[2].clone());
+                    Reply::Status
+                }
+                "PEXPIRE" => Reply::Int,
+                "SADD" => {
+                    let set = self.sets.entry(args[1].clone()).or_default();
+                    set.insert(args[2].clone());
+                    Reply::Int
+                }
+                "SMEMBERS" => bulks(self.sets.get(&args[1]).cloned().unwrap_or_default()),
+                "DEL" => {
+                    for key in &args[1..] {
+                        self.strings.remove(key);
+                        self.sets.remove(key);
+                        self.zsets.remove(key);
+                    }
+                    Reply::Int
+                }
+                "ZADD" => {
+                    let zset = self.zsets.entry(args[1].clone()).or_default();
+                    zset.insert(args[3].clone(), score(&args[2]).0);
+                    Reply::Int
+                }
+                "ZRANGEBYSCORE" => {
+                    let zset = self.zsets.get(&args[1]).cloned().unwrap_or_default();
+                    bulks(
+                        zset.into_iter()
+                            .filter(|(_, score)| in_range(*score, &args[2], &args[3]))
+                            .map(|(member, _)| member),
+                    )
+                }
+                "ZREMRANGEBYSCORE" => {
+                    let zset = self.zsets.entry(args[1].clone()).or_default();
+                    zset.retain(|_, score| !in_range(*score, &args[2], &args[3]));
+                    Reply::Int
+                }
+                "ZREM" => {
+                    let zset = self.zsets.entry(args[1].clone()).or_default();
+                    for member in &args[2..] {
+                        zset.remove(member);
+                    }
+                    Reply::Int
+                }
+                _ => Reply::Error(format!("ERR unknown command `{}`", name)),
+            }
+        }
+    }
+
+    fn encode_reply(reply: &Reply, buf: &mut Vec<u8>) {
+        match reply {
+            Reply::Status => buf.extend_from_slice(b"+OK\r\n"),
+            Reply::Error(e) => buf.extend_from_slice(format!("-{}\r\n", e).as_bytes()),
+            Reply::Int => buf.extend_from_slice(b":0\r\n"),
+            Reply::Bulk(None) => buf.extend_from_slice(b"$-1\r\n"),
+            Reply::Bulk(Some(bytes)) => {
+                buf.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                buf.extend_from_slice(bytes);
+                buf.extend_from_slice(b"\r\n");
+            }
+            Reply::Array(None) => buf.extend_from_slice(b"*-1\r\n"),
+            Reply::Array(Some(items)) => {
+                buf.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    encode_reply(item, buf);
+                }
+            }
+        }
+    }
+
+    /// Start a stand-in for the store on a local port and return its URL
+    /// together with its state
+    async fn stand_in() -> (String, Arc<std::sync::Mutex<Store>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let store = Arc::new(std::sync::Mutex::new(Store::default()));
+        let state = store.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufStream::new(socket);
+                    while let Ok(Reply::Array(Some(args))) = read_reply(&mut stream).await {
+                        let args = Reply::Array(Some(args)).into_bulks();
+                        let reply = store.lock().unwrap().run(args);
+                        let mut buf = Vec::new();
+                        encode_reply(&reply, &mut buf);
+                        stream.write_all(&buf).await.unwrap();
+                        stream.flush().await.unwrap();
+                    }
+                });
+            }
+        });
+        (url, state)
+    }
+
+    fn ptr(number: i32, hash: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(hash), number))
+    }
+
+    fn result(value: i64) -> Arc<QueryResult> {
+        let data = Object::from_iter(vec![
+            ("count".to_string(), r::Value::Int(value)),
+            ("name".to_string(), r::Value::String("thing".to_string())),
+        ]);
+        let mut result = QueryResult::new(data);
+        result.deployment = Some(DeploymentHash::new("QmCache").unwrap());
+        Arc::new(result)
+    }
+
+    fn count(result: &QueryResult) -> Option<&r::Value> {
+        result.data().and_then(|data| data.get("count"))
+    }
+
+    #[tokio::test]
+    async fn get_and_insert() {
+        let logger = Logger::root(slog::Discard, o!());
+        let (url, _) = stand_in().await;
+        let cache = RedisResultCache::new(&url, Duration::from_secs(60)).unwrap();
+        let block = ptr(10, 10);
+        let key = [7u8; 32];
+
+        assert!(cache.get(&logger, "mainnet", &block, &key).await.is_none());
+        assert!(
+            cache
+                .insert(&logger, "mainnet", block.clone(), key, result(42), 0)
+                .await
+        );
+
+        let cached = cache.get(&logger, "mainnet", &block, &key).await.unwrap();
+        assert_eq!(Some(&r::Value::Int(42)), count(&cached));
+        assert_eq!("QmCache", cached.deployment.as_ref().unwrap().as_str());
+
+        // Results are per network and per block
+        assert!(cache.get(&logger, "goerli", &block, &key).await.is_none());
+        assert!(cache
+            .get(&logger, "mainnet", &ptr(10, 11), &key)
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn revert_removes_stale_blocks() {
+        let logger = Logger::root(slog::Discard, o!());
+        let (url, store) = stand_in().await;
+        let cache = RedisResultCache::new(&url, Duration::from_secs(60)).unwrap();
+        let key = [1u8; 32];
+
+        let blocks = vec![ptr(9, 9), ptr(10, 10), ptr(11, 11), ptr(10, 100)];
+        for block in &blocks {
+            cache
+                .insert(&logger, "mainnet", block.clone(), key, result(1), 0)
+                .await;
+        }
+
+        // The chain now ends in block #10 with hash 100
+        cache.revert(&logger, "mainnet", &ptr(10, 100)).await;
+
+        let mut present = Vec::new();
+        for block in &blocks {
+            present.push(cache.get(&logger, "mainnet", block, &key).await.is_some());
+        }
+        assert_eq!(vec![true, false, false, true], present);
+
+        let store = store.lock().unwrap();
+        let tracked = store.zsets.get(blocks_key("mainnet").as_bytes()).unwrap();
+        assert_eq!(2, tracked.len());
+        assert!(!store
+            .sets
+            .contains_key(block_key("mainnet", &ptr(11, 11).hash_hex()).as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn unavailable_store_is_a_miss() {
+        let logger = Logger::root(slog::Discard, o!());
+        // Nothing listens on this port since we drop the listener right away
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let cache = RedisResultCache::new(&url, Duration::from_secs(60)).unwrap();
+        let block = ptr(1, 1);
+        assert!(
+            !cache
+                .insert(&logger, "mainnet", block.clone(), [0; 32], result(1), 0)
+                .await
+        );
+        assert!(cache
+            .get(&logger, "mainnet", &block, &[0; 32])
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_drops_connection() {
+        let logger = Logger::root(slog::Discard, o!());
+        let (url, _) = stand_in().await;
+        let cache = RedisResultCache::new(&url, Duration::from_secs(60)).unwrap();
+        let block = ptr(10, 10);
+
+        for value in 0..CONNECTIONS as i64 {
+            let key = [value as u8; 32];
+            cache
+                .insert(&logger, "mainnet", block.clone(), key, result(value), 0)
+                .await;
+        }
+
+        // Drop every lookup after polling it once, i.e., after its commands
+        // have been sent but before the replies have been read
+        for value in 0..CONNECTIONS as i64 {
+            let key = [value as u8; 32];
+            let get = cache.get(&logger, "mainnet", &block, &key);
+            assert!(get.now_or_never().is_none());
+        }
+
+        // Later requests must not see the replies for the dropped ones
+        for value in (0..CONNECTIONS as i64).rev() {
+            let key = [value as u8; 32];
+            let cached = cache.get(&logger, "mainnet", &block, &key).await.unwrap();
+            assert_eq!(Some(&r::Value::Int(value)), count(&cached));
+        }
+    }
+
+    #[test]
+    fn encoding_preserves_values() {
+        let data = Object::from_iter(vec![
+            ("zebra".to_string(), r::Value::Int(1)),
+            ("apple".to_string(), r::Value::Float(1.5)),
+            ("kind".to_string(), r::Value::Enum("Big".to_string())),
+            (
+                "list".to_string(),
+                r::Value::List(vec![r::Value::Null, r::Value::Boolean(true)]),
+            ),
+            (
+                "nested".to_string(),
+                r::Value::Object(Object::from_iter(vec![
+                    ("b".to_string(), r::Value::String("x".to_string())),
+                    ("a".to_string(), r::Value::Int(-3)),
+                ])),
+            ),
+        ]);
+        let result = QueryResult::new(data.clone());
+
+        let decoded = decode_result(&encode_result(&result)).unwrap();
+        assert_eq!(Some(&data), decoded.data());
+        let keys: Vec<_> = decoded.data().unwrap().iter().map(|(k, _)| k).collect();
+        assert_eq!(vec!["zebra", "apple", "kind", "list", "nested"], keys);
+        assert!(RedisResultCache::new("http://localhost", Duration::from_secs(1)).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use graph::components::store::UnitStream;
 use graph::prelude::{async_trait, s, tokio, ApiSchema, Error, QueryExecutionError};
 use graph::{
-    data::graphql::ObjectOrInterface,
+    data::{graphql::ObjectOrInterface, query::Trace},
     prelude::{r, QueryResult},
 };
 
@@ -14,12 +14,14 @@ pub trait Resolver: Sized + Send + Sync + 'static {
 
     async fn query_permit(&self) -> Result<tokio::sync::OwnedSemaphorePermit, QueryExecutionError>;
 
-    /// Prepare for executing a query by prefetching as much data as possible
+    /// Prepare for executing a query by prefetching as much data as possible.
+    /// If the query asked for a trace, also return a trace of the work that
+    /// prefetching did, and `Trace::None` otherwise
     fn prefetch(
         &self,
         ctx: &ExecutionContext<Self>,
         selection_set: &a::SelectionSet,
-    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>>;
+    ) -> Result<(Option<r::Value>, Trace), Vec<QueryExecutionError>>;
 
     /// Resolves list of objects, `prefetched_objects` is `Some` if the parent already calculated the value.
     fn resolve_objects(
@@ -3,6 +3,7 @@ use graphql_parser::Pos;
 use std::collections::BTreeMap;
 
 use graph::data::graphql::{object, DocumentExt, ObjectOrInterface};
+use graph::data::query::Trace;
 use graph::prelude::*;
 
 use crate::execution::ast as a;
@@ -366,8 +367,8 @@ impl Resolver for IntrospectionResolver {
         &self,
         _: &ExecutionContext<Self>,
         _: &a::SelectionSet,
-    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
-        Ok(None)
+    ) -> Result<(Option<r::Value>, Trace), Vec<QueryExecutionError>> {
+        Ok((None, Trace::None))
     }
 
     fn resolve_objects(
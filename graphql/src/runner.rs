@@ -10,8 +10,9 @@ use graph::prometheus::{Gauge, Histogram};
 use graph::{
     components::store::SubscriptionManager,
     prelude::{
-        async_trait, o, CheapClone, DeploymentState, GraphQlRunner as GraphQlRunnerTrait, Logger,
-        Query, QueryExecutionError, Subscription, SubscriptionError, SubscriptionResult, ENV_VARS,
+        async_trait, o, ApiSchema, CheapClone, DeploymentState,
+        GraphQlRunner as GraphQlRunnerTrait, Logger, Query, QueryExecutionError, Subscription,
+        SubscriptionError, SubscriptionResult, ENV_VARS,
     },
 };
 use graph::{data::graphql::effort::LoadManager, prelude::QueryStoreManager};
@@ -66,6 +67,22 @@ impl ResultSizeMetrics {
     }
 }
 
+/// Deployments listed in `GRAPH_GRAPHQL_PERSISTED_QUERIES_ONLY` only run
+/// queries that are on the allow-list of persisted queries
+fn check_allow_listed(query: &Query, schema: &ApiSchema) -> Result<(), QueryExecutionError> {
+    let only_persisted = ENV_VARS
+        .graphql
+        .persisted_queries_only
+        .iter()
+        .any(|id| id == schema.id().as_str());
+    if only_persisted && !query.allow_listed {
+        return Err(QueryExecutionError::PersistedQueryRequired(
+            schema.id().to_string(),
+        ));
+    }
+    Ok(())
+}
+
 /// GraphQL runner implementation for The Graph.
 pub struct GraphQlRunner<S, SM> {
     logger: Logger,
@@ -158,6 +175,7 @@ where
         let state = store.deployment_state().await?;
         let network = Some(store.network_name().to_string());
         let schema = store.api_schema()?;
+        check_allow_listed(&query, &schema)?;
 
         // Test only, see c435c25decbc4ad7bbbadf8e0ced0ff2
         #[cfg(debug_assertions)]
@@ -273,6 +291,7 @@ where
         let store = self.store.query_store(target, true).await?;
         let schema = store.api_schema()?;
         let network = store.network_name().to_string();
+        check_allow_listed(&subscription.query, &schema)?;
 
         let query = crate::execution::Query::new(
             &self.logger,
@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::str::FromStr;
 
 use graphql_parser::Pos;
@@ -7,8 +8,11 @@ use lazy_static::lazy_static;
 use crate::schema::ast;
 
 use graph::data::{
-    graphql::ext::{DirectiveExt, DocumentExt, ValueExt},
-    schema::{META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME},
+    graphql::ext::{DirectiveExt, DocumentExt, TypeExt, ValueExt},
+    schema::{
+        FulltextDefinition, FULLTEXT_RANK_FIELD, FULLTEXT_SNIPPET_FIELD, META_FIELD_NAME,
+        META_FIELD_TYPE, SCHEMA_TYPE_NAME,
+    },
 };
 use graph::prelude::s::{Value, *};
 use graph::prelude::*;
@@ -29,6 +33,25 @@ const BLOCK_HEIGHT: &str = "Block_height";
 const CHANGE_BLOCK_FILTER_NAME: &str = "BlockChangedFilter";
 const ERROR_POLICY_TYPE: &str = "_SubgraphErrorPolicy_";
 
+/// The names of the boolean combinators in `<type_name>_filter` types
+pub(crate) const AND_FILTER_NAME: &str = "and";
+pub(crate) const OR_FILTER_NAME: &str = "or";
+
+/// The field of entity types that holds an opaque cursor for the entity,
+/// and the argument of collection fields that continues after a cursor
+pub(crate) const CURSOR_FIELD: &str = "_cursor";
+pub(crate) const AFTER_ARGUMENT: &str = "after";
+
+/// The directive that marks the `<entities>Aggregate` fields of the `Query`
+/// type; its `entity` argument names the entity type being aggregated
+pub(crate) const AGGREGATE_DIRECTIVE: &str = "aggregate";
+/// The fields of the `<type_name>_aggregate` types
+pub(crate) const AGGREGATE_COUNT: &str = "count";
+pub(crate) const AGGREGATE_SUM: &str = "sum";
+pub(crate) const AGGREGATE_MIN: &str = "min";
+pub(crate) const AGGREGATE_MAX: &str = "max";
+pub(crate) const AGGREGATE_AVG: &str = "avg";
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum ErrorPolicy {
     Allow,
@@ -87,7 +110,12 @@ pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
     add_field_arguments(&mut schema, input_schema)?;
+    add_cursor_fields(&mut schema, &object_types, &interface_types);
+    add_fulltext_fields(&mut schema)?;
     add_query_type(&mut schema, &object_types, &interface_types)?;
+    if ENV_VARS.graphql.enable_aggregates {
+        add_aggregate_fields(&mut schema, &object_types)?;
+    }
     add_subscription_type(&mut schema, &object_types, &interface_types)?;
 
     // Remove the `_Schema_` type from the generated schema.
@@ -123,30 +151,189 @@ fn add_types_for_object_types(
 ) -> Result<(), APISchemaError> {
     for object_type in object_types {
         if !object_type.name.eq(SCHEMA_TYPE_NAME) {
-            add_order_by_type(schema, &object_type.name, &object_type.fields)?;
+            let child_values = child_order_by_values(schema, &object_type.fields);
+            add_order_by_type(schema, &object_type.name, &object_type.fields, child_values)?;
             add_filter_type(schema, &object_type.name, &object_type.fields)?;
         }
     }
     Ok(())
 }
 
+/// The `<field>__<childField>` values of an `*_orderBy` enum that sort by
+/// a field of the entity that `field` references. We only allow that for
+/// fields that reference a single entity of a concrete type
+fn child_order_by_values(schema: &Document, fields: &[Field]) -> Vec<String> {
+    let is_entity = |type_name: &str| {
+        matches!(
+            schema.get_named_type(type_name),
+            Some(TypeDefinition::Object(_)) | Some(TypeDefinition::Interface(_))
+        )
+    };
+
+    fields
+        .iter()
+        .filter(|field| {
+            !ast::is_list(&field.field_type) && ast::get_derived_from_directive(field).is_none()
+        })
+        .filter_map(
+            |field| match schema.get_named_type(field.field_type.get_base_type()) {
+                Some(TypeDefinition::Object(child)) => Some((field, child)),
+                _ => None,
+            },
+        )
+        .flat_map(|(field, child)| {
+            child
+                .fields
+                .iter()
+                .filter(|child_field| {
+                    !ast::is_list(&child_field.field_type)
+                        && !is_entity(child_field.field_type.get_base_type())
+                })
+                .map(move |child_field| format!("{}__{}", field.name, child_field.name))
+        })
+        .collect()
+}
+
+/// Adds a `_cursor` field to all entity types and interfaces. Its value can
+/// be passed as the `after` argument of a collection field to continue
+/// with the entities that follow it
+fn add_cursor_fields(
+    schema: &mut Document,
+    object_types: &[&ObjectType],
+    interface_types: &[&InterfaceType],
+) {
+    let cursor_field = || Field {
+        position: Pos::default(),
+        description: Some(
+            "An opaque cursor for this entity. Pass it as the `after` argument \
+             of a collection field that uses the same `orderBy` and \
+             `orderDirection` to get the entities that follow this one"
+                .to_owned(),
+        ),
+        name: CURSOR_FIELD.to_owned(),
+        arguments: vec![],
+        field_type: Type::NonNullType(Box::new(Type::NamedType("String".to_owned()))),
+        directives: vec![],
+    };
+    let has_cursor = |fields: &[Field]| fields.iter().any(|field| field.name == CURSOR_FIELD);
+
+    for object_type in object_types {
+        if object_type.name.eq(SCHEMA_TYPE_NAME) {
+            continue;
+        }
+        if let Some(object_type) = ast::get_object_type_mut(schema, &object_type.name) {
+            if !has_cursor(&object_type.fields) {
+                object_type.fields.push(cursor_field());
+            }
+        }
+    }
+    for interface_type in interface_types {
+        if let Some(interface_type) = ast::get_interface_type_mut(schema, &interface_type.name) {
+            if !has_cursor(&interface_type.fields) {
+                interface_type.fields.push(cursor_field());
+            }
+        }
+    }
+}
+
+/// Adds `_rank` and `_snippet` fields to the entity types that fulltext
+/// searches include. A search over several entity types returns an
+/// interface that all of them implement; that interface and all its
+/// implementations get the fields, too. The fields are only set for
+/// entities that were found by a fulltext search
+fn add_fulltext_fields(schema: &mut Document) -> Result<(), APISchemaError> {
+    let mut object_types = BTreeSet::new();
+    let mut interface_types = BTreeSet::new();
+    for fulltext in schema
+        .get_fulltext_directives()
+        .map_err(|_| APISchemaError::FulltextSearchNonDeterministic)?
+    {
+        object_types.extend(
+            FulltextDefinition::included_entities(fulltext)
+                .into_iter()
+                .map(str::to_owned),
+        );
+        if let Some(interface) = FulltextDefinition::common_interface(fulltext, schema) {
+            interface_types.insert(interface.to_owned());
+            object_types.extend(
+                schema
+                    .get_object_type_definitions()
+                    .into_iter()
+                    .filter(|object_type| {
+                        object_type
+                            .implements_interfaces
+                            .iter()
+                            .any(|name| name == interface)
+                    })
+                    .map(|object_type| object_type.name.clone()),
+            );
+        }
+    }
+
+    let add_fields = |fields: &mut Vec<Field>| {
+        let rank_field = Field {
+            position: Pos::default(),
+            description: Some(
+                "How well this entity matches the text of a fulltext search; \
+                 higher values indicate better matches"
+                    .to_owned(),
+            ),
+            name: FULLTEXT_RANK_FIELD.to_owned(),
+            arguments: vec![],
+            field_type: Type::NamedType("Float".to_owned()),
+            directives: vec![],
+        };
+        let snippet_field = Field {
+            position: Pos::default(),
+            description: Some(
+                "An excerpt of the text of this entity that a fulltext search \
+                 matched, with the matching words enclosed in `<b>` and `</b>`"
+                    .to_owned(),
+            ),
+            name: FULLTEXT_SNIPPET_FIELD.to_owned(),
+            arguments: vec![],
+            field_type: Type::NamedType("String".to_owned()),
+            directives: vec![],
+        };
+        for field in [rank_field, snippet_field] {
+            if !fields.iter().any(|existing| existing.name == field.name) {
+                fields.push(field);
+            }
+        }
+    };
+
+    for name in object_types {
+        if let Some(object_type) = ast::get_object_type_mut(schema, &name) {
+            add_fields(&mut object_type.fields);
+        }
+    }
+    for name in interface_types {
+        if let Some(interface_type) = ast::get_interface_type_mut(schema, &name) {
+            add_fields(&mut interface_type.fields);
+        }
+    }
+    Ok(())
+}
+
 /// Adds `*_orderBy` and `*_filter` enum types for the given interfaces to the schema.
 fn add_types_for_interface_types(
     schema: &mut Document,
     interface_types: &[&InterfaceType],
 ) -> Result<(), APISchemaError> {
     for interface_type in interface_types {
-        add_order_by_type(schema, &interface_type.name, &interface_type.fields)?;
+        add_order_by_type(schema, &interface_type.name, &interface_type.fields, vec![])?;
         add_filter_type(schema, &interface_type.name, &interface_type.fields)?;
     }
     Ok(())
 }
 
-/// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
+/// Adds a `<type_name>_orderBy` enum type for the given fields and
+/// `child_values` to the schema.
 fn add_order_by_type(
     schema: &mut Document,
     type_name: &str,
     fields: &[Field],
+    child_values: Vec<String>,
 ) -> Result<(), APISchemaError> {
     let type_name = format!("{}_orderBy", type_name);
 
@@ -159,11 +346,12 @@ fn add_order_by_type(
                 directives: vec![],
                 values: fields
                     .iter()
-                    .map(|field| &field.name)
+                    .map(|field| field.name.clone())
+                    .chain(child_values)
                     .map(|name| EnumValue {
                         position: Pos::default(),
                         description: None,
-                        name: name.to_owned(),
+                        name,
                         directives: vec![],
                     })
                     .collect(),
@@ -187,6 +375,7 @@ fn add_filter_type(
         None => {
             let mut generated_filter_fields = field_input_values(schema, fields)?;
             generated_filter_fields.push(block_changed_filter_argument());
+            generated_filter_fields.extend(boolean_filter_arguments(&filter_type_name, fields));
 
             let typedef = TypeDefinition::InputObject(InputObjectType {
                 position: Pos::default(),
@@ -249,7 +438,7 @@ fn field_filter_input_values(
                 TypeDefinition::Interface(_) => {
                     // Only add `where` filter fields for object and interface fields
                     // if they are not @derivedFrom
-                    if ast::get_derived_from_directive(field).is_some() {
+                    let mut input_values = if ast::get_derived_from_directive(field).is_some() {
                         vec![]
                     } else {
                         // We allow filtering with `where: { other: "some-id" }` and
@@ -261,7 +450,9 @@ fn field_filter_input_values(
                             field,
                             &ScalarType::new(String::from("String")),
                         )
-                    }
+                    };
+                    extend_with_child_filter_input_value(field, name, &mut input_values);
+                    input_values
                 }
                 TypeDefinition::Scalar(ref t) => field_scalar_filter_input_values(schema, field, t),
                 TypeDefinition::Enum(ref t) => field_enum_filter_input_values(schema, field, t),
@@ -288,6 +479,8 @@ fn field_scalar_filter_input_values(
         "BigDecimal" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "ID" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "Int" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Int8" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Timestamp" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "String" => vec![
             "",
             "not",
@@ -369,7 +562,7 @@ fn field_list_filter_input_values(
         // Decide what type of values can be passed to the filter. In the case
         // one-to-many or many-to-many object or interface fields that are not
         // derived, we allow ID strings to be passed on.
-        // Adds child filter only to object types.
+        // Adds child filter only to object and interface types.
         let (input_field_type, parent_type_name) = match typedef {
             TypeDefinition::Object(parent) => {
                 if ast::get_derived_from_directive(field).is_some() {
@@ -381,11 +574,14 @@ fn field_list_filter_input_values(
                     )
                 }
             }
-            TypeDefinition::Interface(_) => {
+            TypeDefinition::Interface(parent) => {
                 if ast::get_derived_from_directive(field).is_some() {
-                    (None, None)
+                    (None, Some(parent.name.clone()))
                 } else {
-                    (Some(Type::NamedType("String".into())), None)
+                    (
+                        Some(Type::NamedType("String".into())),
+                        Some(parent.name.clone()),
+                    )
                 }
             }
             TypeDefinition::Scalar(ref t) => (Some(Type::NamedType(t.name.to_owned())), None),
@@ -465,7 +661,7 @@ fn add_query_type(
         .get_fulltext_directives()
         .map_err(|_| APISchemaError::FulltextSearchNonDeterministic)?
         .iter()
-        .filter_map(|fulltext| query_field_for_fulltext(fulltext))
+        .filter_map(|fulltext| query_field_for_fulltext(fulltext, schema))
         .collect();
     fields.append(&mut fulltext_fields);
     fields.push(meta_field());
@@ -483,14 +679,154 @@ fn add_query_type(
     Ok(())
 }
 
-fn query_field_for_fulltext(fulltext: &Directive) -> Option<Field> {
+/// Adds an `<entities>Aggregate` field to the `Query` type for each of the
+/// `object_types`, together with the `<type_name>_aggregate` type it
+/// returns and the types that type needs to hold the aggregates of numeric
+/// fields.
+fn add_aggregate_fields(
+    schema: &mut Document,
+    object_types: &[&ObjectType],
+) -> Result<(), APISchemaError> {
+    let mut fields = vec![];
+    for object_type in object_types {
+        if object_type.name.eq(SCHEMA_TYPE_NAME) {
+            continue;
+        }
+        add_aggregate_types(schema, &object_type.name, &object_type.fields)?;
+        fields.push(aggregate_query_field(&object_type.name));
+    }
+
+    let query_type = ast::get_object_type_mut(schema, "Query")
+        .ok_or_else(|| APISchemaError::TypeNotFound("Query".to_string()))?;
+    query_type.fields.extend(fields);
+    Ok(())
+}
+
+/// Adds the `<type_name>_aggregate` type for the given fields to the
+/// schema. If there are numeric fields, also adds
+/// `<type_name>_aggregateValues` for their sums, minima and maxima, where
+/// `Int` fields are widened to `BigInt`, and `<type_name>_aggregateAverages`
+/// for their averages, which are always `BigDecimal`
+fn add_aggregate_types(
+    schema: &mut Document,
+    type_name: &str,
+    fields: &[Field],
+) -> Result<(), APISchemaError> {
+    fn object_type(name: String, fields: Vec<Field>) -> Definition {
+        Definition::TypeDefinition(TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name,
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields,
+        }))
+    }
+
+    fn field(name: &str, field_type: Type) -> Field {
+        Field {
+            position: Pos::default(),
+            description: None,
+            name: name.to_owned(),
+            arguments: vec![],
+            field_type,
+            directives: vec![],
+        }
+    }
+
+    let aggregate_type_name = format!("{}_aggregate", type_name);
+    let values_type_name = format!("{}_aggregateValues", type_name);
+    let averages_type_name = format!("{}_aggregateAverages", type_name);
+    for name in [&aggregate_type_name, &values_type_name, &averages_type_name] {
+        if schema.get_named_type(name).is_some() {
+            return Err(APISchemaError::TypeExists(name.to_owned()));
+        }
+    }
+
+    let numeric_fields: Vec<_> = fields
+        .iter()
+        .filter(|field| !field.field_type.is_list())
+        .filter_map(|field| match field.field_type.get_base_type() {
+            "Int" | "Int8" | "BigInt" => Some((field.name.as_str(), "BigInt")),
+            "BigDecimal" => Some((field.name.as_str(), "BigDecimal")),
+            _ => None,
+        })
+        .collect();
+
+    let mut aggregate_fields = vec![field(
+        AGGREGATE_COUNT,
+        Type::NonNullType(Box::new(Type::NamedType("Int".to_owned()))),
+    )];
+    if !numeric_fields.is_empty() {
+        for name in [AGGREGATE_SUM, AGGREGATE_MIN, AGGREGATE_MAX] {
+            aggregate_fields.push(field(name, Type::NamedType(values_type_name.clone())));
+        }
+        aggregate_fields.push(field(
+            AGGREGATE_AVG,
+            Type::NamedType(averages_type_name.clone()),
+        ));
+
+        let values_fields = numeric_fields
+            .iter()
+            .map(|(name, value_type)| field(name, Type::NamedType(value_type.to_string())))
+            .collect();
+        let averages_fields = numeric_fields
+            .iter()
+            .map(|(name, _)| field(name, Type::NamedType("BigDecimal".to_owned())))
+            .collect();
+        schema
+            .definitions
+            .push(object_type(values_type_name, values_fields));
+        schema
+            .definitions
+            .push(object_type(averages_type_name, averages_fields));
+    }
+    schema
+        .definitions
+        .push(object_type(aggregate_type_name, aggregate_fields));
+    Ok(())
+}
+
+/// Generates the `<entities>Aggregate` field of the `Query` type for the
+/// given type name
+fn aggregate_query_field(type_name: &str) -> Field {
+    let mut arguments = vec![input_value(
+        &"where".to_string(),
+        "",
+        Type::NamedType(format!("{}_filter", type_name)),
+    )];
+    arguments.push(block_argument());
+    arguments.push(subgraph_error_argument());
+
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Aggregate", type_name.to_plural().to_camel_case()),
+        arguments,
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}_aggregate",
+            type_name
+        )))),
+        directives: vec![Directive {
+            position: Pos::default(),
+            name: AGGREGATE_DIRECTIVE.to_owned(),
+            arguments: vec![("entity".to_owned(), Value::String(type_name.to_owned()))],
+        }],
+    }
+}
+
+fn query_field_for_fulltext(fulltext: &Directive, schema: &Document) -> Option<Field> {
     let name = fulltext.argument("name").unwrap().as_str().unwrap().into();
 
-    let includes = fulltext.argument("include").unwrap().as_list().unwrap();
-    // Only one include is allowed per fulltext directive
-    let include = includes.iter().next().unwrap();
-    let included_entity = include.as_object().unwrap();
-    let entity_name = included_entity.get("entity").unwrap().as_str().unwrap();
+    // A search over several entity types returns an interface that all of
+    // them implement
+    let entity_name = FulltextDefinition::common_interface(fulltext, schema)
+        .or_else(|| {
+            FulltextDefinition::included_entities(fulltext)
+                .first()
+                .copied()
+        })
+        .unwrap();
 
     let mut arguments = vec![
         // text: String
@@ -533,7 +869,7 @@ fn query_field_for_fulltext(fulltext: &Directive) -> Option<Field> {
         arguments,
         field_type: Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
             Box::new(Type::NamedType(entity_name.into())),
-        ))))), // included entity type name
+        ))))), // included entity type or interface name
         directives: vec![fulltext.clone()],
     })
 }
@@ -603,6 +939,27 @@ fn block_changed_filter_argument() -> InputValue {
     }
 }
 
+/// Generates the `and` and `or` input values of a `<type_name>_filter`
+/// type, each of which accepts a list of filters of that same type. A
+/// combinator is omitted if the type has a field with the same name
+/// since the field filter would otherwise be shadowed.
+fn boolean_filter_arguments(filter_type_name: &str, fields: &[Field]) -> Vec<InputValue> {
+    [AND_FILTER_NAME, OR_FILTER_NAME]
+        .iter()
+        .filter(|name| !fields.iter().any(|field| &field.name == *name))
+        .map(|name| InputValue {
+            position: Pos::default(),
+            description: None,
+            name: name.to_string(),
+            value_type: Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+                filter_type_name.to_owned(),
+            ))))),
+            default_value: None,
+            directives: vec![],
+        })
+        .collect()
+}
+
 fn subgraph_error_argument() -> InputValue {
     InputValue {
         position: Pos::default(),
@@ -712,6 +1069,7 @@ fn collection_arguments_for_named_type(type_name: &str) -> Vec<InputValue> {
             "",
             Type::NamedType(format!("{}_filter", type_name)),
         ),
+        input_value(AFTER_ARGUMENT, "", Type::NamedType("String".to_string())),
     ];
 
     args
@@ -795,7 +1153,7 @@ mod tests {
     use graph::data::graphql::DocumentExt;
     use graphql_parser::schema::*;
 
-    use super::api_schema;
+    use super::{add_aggregate_fields, api_schema};
     use crate::schema::ast;
 
     #[test]
@@ -878,6 +1236,37 @@ mod tests {
         assert_eq!(values, ["id", "name"]);
     }
 
+    #[test]
+    fn api_schema_contains_child_order_by_values() {
+        let input_schema = parse_schema(
+            "type Token { id: ID!, symbol: String!, pools: [Pool!]! @derivedFrom(field: \"token0\") }
+             type Pool { id: ID!, token0: Token!, tokens: [Token!]!, fee: Int! }",
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let values = |type_name: &str| -> Vec<String> {
+            match schema.get_named_type(type_name) {
+                Some(TypeDefinition::Enum(t)) => {
+                    t.values.iter().map(|value| value.name.clone()).collect()
+                }
+                _ => panic!("{} type is missing in derived API schema", type_name),
+            }
+        };
+        assert_eq!(
+            values("Pool_orderBy"),
+            [
+                "id",
+                "token0",
+                "tokens",
+                "fee",
+                "token0__id",
+                "token0__symbol"
+            ]
+        );
+        assert_eq!(values("Token_orderBy"), ["id", "symbol", "pools"]);
+    }
+
     #[test]
     fn api_schema_contains_object_type_filter_enum() {
         let input_schema = parse_schema(
@@ -995,7 +1384,9 @@ mod tests {
                 "favoritePet_",
                 "leastFavoritePet_",
                 "mostFavoritePets_",
-                "_change_block"
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -1072,7 +1463,9 @@ mod tests {
                 "mostLovedBy_not_contains",
                 "mostLovedBy_not_contains_nocase",
                 "mostLovedBy_",
-                "_change_block"
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -1096,6 +1489,13 @@ mod tests {
         schema
             .get_named_type("BlockChangedFilter")
             .expect("BlockChangedFilter type is missing in derived API schema");
+
+        let or_filter = user_filter_type
+            .fields
+            .iter()
+            .find(|field| field.name == "or")
+            .expect("or field is missing in User_filter");
+        assert_eq!(or_filter.value_type.to_string(), "[User_filter!]");
     }
 
     #[test]
@@ -1174,6 +1574,7 @@ mod tests {
                 "name_ends_with_nocase",
                 "name_not_ends_with",
                 "name_not_ends_with_nocase",
+                "pets_",
                 "favoritePet",
                 "favoritePet_not",
                 "favoritePet_gt",
@@ -1194,7 +1595,10 @@ mod tests {
                 "favoritePet_ends_with_nocase",
                 "favoritePet_not_ends_with",
                 "favoritePet_not_ends_with_nocase",
-                "_change_block"
+                "favoritePet_",
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -1281,6 +1685,7 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
                 "block",
                 "subgraphError",
             ]
@@ -1378,6 +1783,7 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
                 "block",
                 "subgraphError"
             ]
@@ -1387,6 +1793,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn api_schema_contains_cursor_fields() {
+        let input_schema = parse_schema(
+            "
+            interface Node { id: ID!, name: String! }
+            type User implements Node @entity { id: ID!, name: String! }
+            ",
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let cursor = match schema.get_named_type("User") {
+            Some(TypeDefinition::Object(t)) => ast::get_field(t, "_cursor"),
+            _ => None,
+        }
+        .expect("User has no `_cursor` field");
+        assert_eq!(
+            cursor.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("String".to_string())))
+        );
+
+        match schema.get_named_type("Node") {
+            Some(TypeDefinition::Interface(t)) => {
+                assert!(t.fields.iter().any(|field| field.name == "_cursor"))
+            }
+            _ => panic!("Node type is missing"),
+        }
+
+        // The cursor is not a field that can be sorted or filtered by
+        match schema.get_named_type("User_orderBy") {
+            Some(TypeDefinition::Enum(t)) => {
+                assert!(!t.values.iter().any(|value| value.name == "_cursor"))
+            }
+            _ => panic!("User_orderBy type is missing"),
+        }
+    }
+
     #[test]
     fn api_schema_contains_fulltext_query_field_on_query_type() {
         const SCHEMA: &str = r#"
@@ -1424,4 +1867,144 @@ type Gravatar @entity {
         }
         .expect("\"metadata\" field is missing on Query type");
     }
+
+    #[test]
+    fn api_schema_contains_fulltext_fields_for_interfaces() {
+        const SCHEMA: &str = r#"
+type _Schema_ @fulltext(
+  name: "search"
+  language: en
+  algorithm: rank
+  include: [
+    { entity: "Gravatar", fields: [{ name: "displayName", weight: A }] },
+    { entity: "Profile", fields: [{ name: "bio" }] }
+  ]
+)
+interface Named {
+  id: ID!
+}
+type Gravatar implements Named @entity {
+  id: ID!
+  displayName: String!
+}
+type Profile implements Named @entity {
+  id: ID!
+  bio: String
+}
+type Company implements Named @entity {
+  id: ID!
+}
+type Other @entity {
+  id: ID!
+}
+"#;
+        let input_schema = parse_schema(SCHEMA).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing in derived API schema"),
+        };
+        let search = ast::get_field(query_type, "search")
+            .expect("\"search\" field is missing on Query type");
+        assert_eq!("[Named!]!", search.field_type.to_string());
+
+        let field_type = |type_name: &str, field_name: &str| {
+            let field = match schema.get_named_type(type_name) {
+                Some(TypeDefinition::Object(t)) => ast::get_field(t, field_name),
+                Some(TypeDefinition::Interface(t)) => ast::get_field(t, field_name),
+                _ => panic!("type {} is missing in derived API schema", type_name),
+            };
+            field.map(|field| field.field_type.to_string())
+        };
+        for type_name in ["Named", "Gravatar", "Profile", "Company"] {
+            assert_eq!(
+                Some("Float".to_string()),
+                field_type(type_name, "_rank"),
+                "{}",
+                type_name
+            );
+            assert_eq!(
+                Some("String".to_string()),
+                field_type(type_name, "_snippet"),
+                "{}",
+                type_name
+            );
+        }
+        assert_eq!(None, field_type("Other", "_rank"));
+        assert_eq!(None, field_type("Other", "_snippet"));
+    }
+
+    #[test]
+    fn api_schema_contains_aggregate_fields() {
+        let input_schema = parse_schema(
+            "type Token @entity { id: ID!, name: String!, decimals: Int!, volume: BigDecimal!, \
+                                  balances: [BigInt!]! } \
+             type Account @entity { id: ID!, name: String! }",
+        )
+        .expect("Failed to parse input schema");
+        let mut schema = api_schema(&input_schema).expect("Failed to derive API schema");
+        add_aggregate_fields(&mut schema, &input_schema.get_object_type_definitions())
+            .expect("Failed to add aggregate fields");
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing in derived API schema"),
+        };
+        let tokens_aggregate = ast::get_field(query_type, "tokensAggregate")
+            .expect("\"tokensAggregate\" field is missing on Query type");
+        assert_eq!(
+            tokens_aggregate.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("Token_aggregate".to_string())))
+        );
+        assert_eq!(
+            tokens_aggregate
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["where", "block", "subgraphError"]
+        );
+
+        let field_names = |type_name: &str| match schema.get_named_type(type_name) {
+            Some(TypeDefinition::Object(t)) => t
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), field.field_type.to_string()))
+                .collect::<Vec<_>>(),
+            _ => panic!("type {} is missing in derived API schema", type_name),
+        };
+        let fields = |fields: &[(&str, &str)]| {
+            fields
+                .iter()
+                .map(|(name, typ)| (name.to_string(), typ.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            field_names("Token_aggregate"),
+            fields(&[
+                ("count", "Int!"),
+                ("sum", "Token_aggregateValues"),
+                ("min", "Token_aggregateValues"),
+                ("max", "Token_aggregateValues"),
+                ("avg", "Token_aggregateAverages")
+            ])
+        );
+        assert_eq!(
+            field_names("Token_aggregateValues"),
+            fields(&[("decimals", "BigInt"), ("volume", "BigDecimal")])
+        );
+        assert_eq!(
+            field_names("Token_aggregateAverages"),
+            fields(&[("decimals", "BigDecimal"), ("volume", "BigDecimal")])
+        );
+
+        // Types without numeric fields can only be counted
+        assert_eq!(
+            field_names("Account_aggregate"),
+            fields(&[("count", "Int!")])
+        );
+        assert!(schema.get_named_type("Account_aggregateValues").is_none());
+    }
 }
@@ -3,6 +3,7 @@
 
 use anyhow::{anyhow, Error};
 use graph::constraint_violation;
+use graph::data::query::Trace;
 use graph::data::value::{Object, Word};
 use graph::prelude::{r, CacheWeight};
 use graph::slog::warn;
@@ -15,18 +16,23 @@ use std::time::Instant;
 use graph::{components::store::EntityType, data::graphql::*};
 use graph::{
     data::graphql::ext::DirectiveFinder,
+    data::schema::{FULLTEXT_RANK_FIELD, FULLTEXT_SNIPPET_FIELD},
     prelude::{
-        s, ApiSchema, AttributeNames, BlockNumber, ChildMultiplicity, EntityCollection,
-        EntityFilter, EntityLink, EntityOrder, EntityWindow, Logger, ParentLink,
-        QueryExecutionError, QueryStore, StoreError, Value as StoreValue, WindowAttribute,
-        ENV_VARS,
+        s, ApiSchema, AttributeNames, BlockNumber, ChildMultiplicity, EntityAggregate,
+        EntityCollection, EntityFilter, EntityLink, EntityOrder, EntityWindow, FulltextOutput,
+        Logger, ParentLink, QueryExecutionError, QueryStore, StoreError, Value as StoreValue,
+        WindowAttribute, ENV_VARS,
     },
 };
 
 use crate::execution::{ast as a, ExecutionContext, Resolver};
 use crate::runner::ResultSizeMetrics;
+use crate::schema::api::{
+    AGGREGATE_AVG, AGGREGATE_COUNT, AGGREGATE_DIRECTIVE, AGGREGATE_MAX, AGGREGATE_MIN,
+    AGGREGATE_SUM, CURSOR_FIELD,
+};
 use crate::schema::ast as sast;
-use crate::store::query::build_query;
+use crate::store::query::{build_aggregate_query, build_query, encode_cursor};
 use crate::store::StoreResolver;
 
 lazy_static! {
@@ -481,8 +487,9 @@ pub fn run(
     ctx: &ExecutionContext<impl Resolver>,
     selection_set: &a::SelectionSet,
     result_size: &ResultSizeMetrics,
-) -> Result<r::Value, Vec<QueryExecutionError>> {
-    execute_root_selection_set(resolver, ctx, selection_set).map(|nodes| {
+) -> Result<(r::Value, Trace), Vec<QueryExecutionError>> {
+    let mut trace = Trace::root(ctx.query.trace, resolver.block_number());
+    execute_root_selection_set(resolver, ctx, selection_set, &mut trace).map(|nodes| {
         result_size.observe(nodes.weight());
         let obj = Object::from_iter(
             nodes
@@ -494,7 +501,7 @@ pub fn run(
                 })
                 .flatten(),
         );
-        r::Value::Object(obj)
+        (r::Value::Object(obj), trace)
     })
 }
 
@@ -503,9 +510,10 @@ fn execute_root_selection_set(
     resolver: &StoreResolver,
     ctx: &ExecutionContext<impl Resolver>,
     selection_set: &a::SelectionSet,
+    trace: &mut Trace,
 ) -> Result<Vec<Node>, Vec<QueryExecutionError>> {
     // Execute the root selection set against the root query type
-    execute_selection_set(resolver, ctx, make_root_node(), selection_set)
+    execute_selection_set(resolver, ctx, make_root_node(), selection_set, trace)
 }
 
 fn check_result_size<'a>(
@@ -529,6 +537,7 @@ fn execute_selection_set<'a>(
     ctx: &'a ExecutionContext<impl Resolver>,
     mut parents: Vec<Node>,
     selection_set: &a::SelectionSet,
+    trace: &mut Trace,
 ) -> Result<Vec<Node>, Vec<QueryExecutionError>> {
     let schema = &ctx.query.schema;
     let mut errors: Vec<QueryExecutionError> = Vec::new();
@@ -560,6 +569,15 @@ fn execute_selection_set<'a>(
             let field_type = object_type
                 .field(&field.name)
                 .expect("field names are valid");
+
+            if let Some(entity_type) = aggregate_entity_type(field_type) {
+                match execute_aggregate(resolver, ctx, entity_type, field, field_type) {
+                    Ok(node) => Join::perform(&mut parents, vec![node], field.response_key()),
+                    Err(e) => errors.push(e),
+                }
+                continue;
+            }
+
             let child_type = schema
                 .object_or_interface(field_type.field_type.get_base_type())
                 .expect("we only collect fields that are objects or interfaces");
@@ -590,8 +608,14 @@ fn execute_selection_set<'a>(
                 field_type,
                 collected_columns,
             ) {
-                Ok(children) => {
-                    match execute_selection_set(resolver, ctx, children, &field.selection_set) {
+                Ok((children, mut field_trace)) => {
+                    match execute_selection_set(
+                        resolver,
+                        ctx,
+                        children,
+                        &field.selection_set,
+                        &mut field_trace,
+                    ) {
                         Ok(children) => {
                             Join::perform(&mut parents, children, field.response_key());
                             let weight =
@@ -600,6 +624,7 @@ fn execute_selection_set<'a>(
                         }
                         Err(mut e) => errors.append(&mut e),
                     }
+                    trace.push(field.response_key(), field_trace);
                 }
                 Err(mut e) => {
                     errors.append(&mut e);
@@ -624,7 +649,7 @@ fn execute_field(
     field: &a::Field,
     field_definition: &s::Field,
     selected_attrs: SelectedAttributes,
-) -> Result<Vec<Node>, Vec<QueryExecutionError>> {
+) -> Result<(Vec<Node>, Trace), Vec<QueryExecutionError>> {
     let multiplicity = if sast::is_list_or_non_null_list_field(field_definition) {
         ChildMultiplicity::Many
     } else {
@@ -645,10 +670,97 @@ fn execute_field(
         ctx.max_skip,
         ctx.query.query_id.clone(),
         selected_attrs,
+        ctx.query.trace,
     )
     .map_err(|e| vec![e])
 }
 
+/// If `field` is an `<entities>Aggregate` field, return the name of the
+/// entity type it aggregates
+fn aggregate_entity_type(field: &s::Field) -> Option<&str> {
+    field
+        .find_directive(AGGREGATE_DIRECTIVE)
+        .and_then(|directive| directive.argument("entity"))
+        .and_then(|entity| match entity {
+            s::Value::String(entity) => Some(entity.as_str()),
+            _ => None,
+        })
+}
+
+/// Compute the aggregates selected by the `<entities>Aggregate` `field`.
+/// The resulting node has the `count` as an attribute, and a child for each
+/// of the selected `sum`, `min`, `max`, and `avg` fields that holds the
+/// aggregates of the selected attributes
+fn execute_aggregate(
+    resolver: &StoreResolver,
+    ctx: &ExecutionContext<impl Resolver>,
+    entity_type: &str,
+    field: &a::Field,
+    field_definition: &s::Field,
+) -> Result<Node, QueryExecutionError> {
+    let schema = ctx.query.schema.as_ref();
+    let entity = match schema.get_named_type(entity_type) {
+        Some(s::TypeDefinition::Object(entity)) => entity,
+        _ => return Err(QueryExecutionError::NamedTypeError(entity_type.to_string())),
+    };
+
+    let mut query = build_aggregate_query(entity, resolver.block_number(), field, schema)?;
+    query.query_id = Some(ctx.query.query_id.clone());
+    query.logger = Some(ctx.logger.clone());
+    let aggregates = query.aggregates.clone();
+    let values = resolver.store.aggregate(query)?;
+    let value = |aggregate: &EntityAggregate| -> r::Value {
+        aggregates
+            .iter()
+            .position(|agg| agg == aggregate)
+            .map(|pos| values[pos].clone().into())
+            .unwrap_or(r::Value::Null)
+    };
+
+    let typename = |field_type: &s::Type| r::Value::String(field_type.get_base_type().to_string());
+    let mut data = BTreeMap::new();
+    data.insert(
+        Word::from("__typename"),
+        typename(&field_definition.field_type),
+    );
+    let mut children = BTreeMap::new();
+    for (object_type, fields) in field.selection_set.fields() {
+        for field in fields {
+            let make: fn(String) -> EntityAggregate = match field.name.as_str() {
+                AGGREGATE_COUNT => {
+                    data.insert(Word::from(AGGREGATE_COUNT), value(&EntityAggregate::Count));
+                    continue;
+                }
+                AGGREGATE_SUM => EntityAggregate::Sum,
+                AGGREGATE_MIN => EntityAggregate::Min,
+                AGGREGATE_MAX => EntityAggregate::Max,
+                AGGREGATE_AVG => EntityAggregate::Avg,
+                _ => continue,
+            };
+            let field_type = &sast::get_field(object_type, &field.name)
+                .expect("aggregate fields are valid")
+                .field_type;
+            let mut child = BTreeMap::new();
+            child.insert(Word::from("__typename"), typename(field_type));
+            for (_, attrs) in field.selection_set.fields() {
+                for attr in attrs.filter(|attr| !attr.name.starts_with("__")) {
+                    child.insert(
+                        Word::from(attr.name.as_str()),
+                        value(&make(attr.name.clone())),
+                    );
+                }
+            }
+            children.insert(field.response_key().to_owned(), Node::from(child));
+        }
+    }
+
+    let mut node = Node::from(data);
+    for (response_key, child) in children {
+        node.set_children(response_key, vec![Rc::new(child)]);
+    }
+    Ok(node)
+}
+
 /// Query child entities for `parents` from the store. The `join` indicates
 /// in which child field to look for the parent's id/join field. When
 /// `is_single` is `true`, there is at most one child per parent.
@@ -666,7 +778,8 @@ fn fetch(
     max_skip: u32,
     query_id: String,
     selected_attrs: SelectedAttributes,
-) -> Result<Vec<Node>, QueryExecutionError> {
+    trace: bool,
+) -> Result<(Vec<Node>, Trace), QueryExecutionError> {
     let mut query = build_query(
         join.child_type,
         block,
@@ -678,6 +791,7 @@ fn fetch(
         schema,
     )?;
     query.query_id = Some(query_id);
+    query.trace = trace;
 
     if multiplicity == ChildMultiplicity::Single {
         // Suppress 'order by' in lookups of scalar values since
@@ -698,13 +812,40 @@ fn fetch(
         // by the parent list
         let windows = join.windows(parents, multiplicity, &query.collection);
         if windows.is_empty() {
-            return Ok(vec![]);
+            return Ok((vec![], Trace::None));
         }
         query.collection = EntityCollection::Window(windows);
     }
+    // The rank and snippet of a fulltext search are computed by the store
+    query.fulltext = FulltextOutput {
+        rank: selects_field(field, FULLTEXT_RANK_FIELD),
+        snippet: selects_field(field, FULLTEXT_SNIPPET_FIELD),
+    };
+    // Cursors are computed from the sort key and id of each entity
+    let cursor_order = selects_field(field, CURSOR_FIELD).then(|| query.order.clone());
     store
         .find_query_values(query)
-        .map(|entities| entities.into_iter().map(|entity| entity.into()).collect())
+        .and_then(|(entities, trace)| {
+            let nodes = entities
+                .into_iter()
+                .map(|mut entity| {
+                    if let Some(order) = &cursor_order {
+                        let cursor = encode_cursor(order, &entity)?;
+                        entity.insert(Word::from(CURSOR_FIELD), cursor);
+                    }
+                    Ok(entity.into())
+                })
+                .collect::<Result<_, QueryExecutionError>>()?;
+            Ok((nodes, trace))
+        })
+}
+
+/// Whether the field `name` is selected for any of the types of `field`
+fn selects_field(field: &a::Field, name: &str) -> bool {
+    field
+        .selection_set
+        .fields()
+        .any(|(_, mut fields)| fields.any(|field| field.name == name))
 }
 
 #[derive(Debug, Default, Clone)]
@@ -712,8 +853,24 @@ pub(crate) struct SelectedAttributes(BTreeMap<String, AttributeNames>);
 
 impl SelectedAttributes {
     /// Extract the attributes we should select from `selection_set`. In
-    /// particular, disregard derived fields since they are not stored
+    /// particular, disregard derived fields, `_cursor`, and the `_rank` and
+    /// `_snippet` of fulltext searches since they are not stored
     fn for_field(field: &a::Field) -> Result<SelectedAttributes, Vec<QueryExecutionError>> {
+        // We need to also select the `orderBy` field if there is one.
+        // Because of how the API Schema is set up, `orderBy` can only have
+        // an enum value
+        let order_by = match field.argument_value("orderBy") {
+            None => None,
+            Some(r::Value::Enum(e)) => Some(e),
+            Some(v) => {
+                return Err(vec![constraint_violation!(
+                    "'orderBy' attribute must be an enum but is {:?}",
+                    v
+                )
+                .into()]);
+            }
+        };
+
         let mut map = BTreeMap::new();
         for (object_type, fields) in field.selection_set.fields() {
             let column_names = fields
@@ -725,35 +882,30 @@ impl SelectedAttributes {
                         .unwrap_or(false)
                 })
                 .filter_map(|field| {
-                    if field.name.starts_with("__") {
+                    if field.name.starts_with("__")
+                        || field.name == CURSOR_FIELD
+                        || field.name == FULLTEXT_RANK_FIELD
+                        || field.name == FULLTEXT_SNIPPET_FIELD
+                    {
                         None
                     } else {
                         Some(field.name.clone())
                     }
                 })
                 .collect();
-            map.insert(
-                object_type.name().to_string(),
-                AttributeNames::Select(column_names),
-            );
-        }
-        // We need to also select the `orderBy` field if there is one.
-        // Because of how the API Schema is set up, `orderBy` can only have
-        // an enum value
-        match field.argument_value("orderBy") {
-            None => { /* nothing to do */ }
-            Some(r::Value::Enum(e)) => {
-                for columns in map.values_mut() {
-                    columns.add_str(e);
+            let mut column_names = AttributeNames::Select(column_names);
+            if let Some(order_by) = order_by {
+                // When sorting by `<field>__<childField>`, we need `<field>`
+                // to find the child
+                match (
+                    sast::get_field(object_type, order_by),
+                    order_by.split_once("__"),
+                ) {
+                    (None, Some((parent, _))) => column_names.add_str(parent),
+                    _ => column_names.add_str(order_by),
                 }
             }
-            Some(v) => {
-                return Err(vec![constraint_violation!(
-                    "'orderBy' attribute must be an enum but is {:?}",
-                    v
-                )
-                .into()]);
-            }
+            map.insert(object_type.name().to_string(), column_names);
         }
         Ok(SelectedAttributes(map))
     }
@@ -3,12 +3,17 @@ use std::mem::discriminant;
 
 use graph::data::graphql::ext::DirectiveFinder;
 use graph::data::graphql::TypeExt as _;
-use graph::data::value::Object;
+use graph::data::schema::FulltextDefinition;
 use graph::data::value::Value as DataValue;
+use graph::data::value::{Object, Word};
 use graph::prelude::*;
 use graph::{components::store::EntityType, data::graphql::ObjectOrInterface};
 
 use crate::execution::ast as a;
+use crate::schema::api::{
+    AFTER_ARGUMENT, AGGREGATE_AVG, AGGREGATE_COUNT, AGGREGATE_MAX, AGGREGATE_MIN, AGGREGATE_SUM,
+    AND_FILTER_NAME, OR_FILTER_NAME,
+};
 use crate::schema::ast as sast;
 
 use super::prefetch::SelectedAttributes;
@@ -38,14 +43,23 @@ pub(crate) fn build_query<'a>(
             let selected_columns = column_names.get(object);
             vec![((*object).into(), selected_columns)]
         }
-        ObjectOrInterface::Interface(interface) => types_for_interface
-            [&EntityType::from(*interface)]
-            .iter()
-            .map(|o| {
-                let selected_columns = column_names.get(o);
-                (o.into(), selected_columns)
-            })
-            .collect(),
+        ObjectOrInterface::Interface(interface) => {
+            // A fulltext search only covers the entity types it includes;
+            // other implementations of the interface can not be searched
+            let fulltext_entities = fulltext_entities(field, schema);
+            types_for_interface[&EntityType::from(*interface)]
+                .iter()
+                .filter(|o| {
+                    fulltext_entities
+                        .as_ref()
+                        .map_or(true, |entities| entities.contains(&o.name.as_str()))
+                })
+                .map(|o| {
+                    let selected_columns = column_names.get(o);
+                    (o.into(), selected_columns)
+                })
+                .collect()
+        }
     });
     let mut query = EntityQuery::new(parse_subgraph_id(entity)?, block, entity_types)
         .range(build_range(field, max_first, max_skip)?);
@@ -53,21 +67,86 @@ pub(crate) fn build_query<'a>(
         query = query.filter(filter);
     }
     let order = match (
-        build_order_by(entity, field)?,
+        build_order_by(entity, field, schema)?,
         build_order_direction(field)?,
     ) {
-        (Some((attr, value_type)), OrderDirection::Ascending) => {
+        (Some(OrderByValue::Direct(attr, value_type)), OrderDirection::Ascending) => {
             EntityOrder::Ascending(attr, value_type)
         }
-        (Some((attr, value_type)), OrderDirection::Descending) => {
+        (Some(OrderByValue::Direct(attr, value_type)), OrderDirection::Descending) => {
             EntityOrder::Descending(attr, value_type)
         }
+        (Some(OrderByValue::Child(child)), OrderDirection::Ascending) => {
+            EntityOrder::ChildAscending(child)
+        }
+        (Some(OrderByValue::Child(child)), OrderDirection::Descending) => {
+            EntityOrder::ChildDescending(child)
+        }
         (None, _) => EntityOrder::Default,
     };
+    if let Some(after) = build_after(entity, field, &order)? {
+        query.filter = Some(after.and_maybe(query.filter));
+    }
     query = query.order(order);
     Ok(query)
 }
 
+/// The entity types that `field` searches if it is a fulltext search
+/// field of the `Query` type, and `None` for any other field
+fn fulltext_entities<'a>(field: &a::Field, schema: &'a ApiSchema) -> Option<Vec<&'a str>> {
+    field.argument_value("text")?;
+    schema
+        .query_type
+        .fields
+        .iter()
+        .find(|query_field| query_field.name == field.name)
+        .and_then(|query_field| query_field.find_directive("fulltext"))
+        .map(FulltextDefinition::included_entities)
+}
+
+/// Builds an EntityAggregateQuery for an `<entities>Aggregate` field from
+/// its `where` argument and the aggregates selected in its selection set.
+pub(crate) fn build_aggregate_query(
+    entity: &s::ObjectType,
+    block: BlockNumber,
+    field: &a::Field,
+    schema: &ApiSchema,
+) -> Result<EntityAggregateQuery, QueryExecutionError> {
+    let mut query = EntityAggregateQuery::new(parse_subgraph_id(entity)?, block, entity.into());
+    if let Some(filter) = build_filter(entity.into(), field, schema)? {
+        query = query.filter(filter);
+    }
+
+    let mut aggregates = vec![];
+    let mut add = |aggregate: EntityAggregate| {
+        if !aggregates.contains(&aggregate) {
+            aggregates.push(aggregate);
+        }
+    };
+    for (_, fields) in field.selection_set.fields() {
+        for field in fields {
+            let make: fn(Attribute) -> EntityAggregate = match field.name.as_str() {
+                AGGREGATE_COUNT => {
+                    add(EntityAggregate::Count);
+                    continue;
+                }
+                AGGREGATE_SUM => EntityAggregate::Sum,
+                AGGREGATE_MIN => EntityAggregate::Min,
+                AGGREGATE_MAX => EntityAggregate::Max,
+                AGGREGATE_AVG => EntityAggregate::Avg,
+                // `__typename`
+                _ => continue,
+            };
+            for (_, attrs) in field.selection_set.fields() {
+                for attr in attrs.filter(|attr| !attr.name.starts_with("__")) {
+                    add(make(attr.name.clone()));
+                }
+            }
+        }
+    }
+    Ok(query.aggregates(aggregates))
+}
+
 /// Parses GraphQL arguments into a EntityRange, if present.
 fn build_range(
     field: &a::Field,
@@ -180,6 +259,19 @@ fn build_filter_from_object(
                     };
                 }
 
+                // `and` and `or` combine a list of filters of the same
+                // entity type, unless the entity has a field with that name
+                if (key == AND_FILTER_NAME || key == OR_FILTER_NAME)
+                    && sast::get_field(entity, key).is_none()
+                {
+                    let filters = build_list_filter_from_value(entity, key, value, schema)?;
+                    return Ok(if key == AND_FILTER_NAME {
+                        EntityFilter::And(filters)
+                    } else {
+                        EntityFilter::Or(filters)
+                    });
+                }
+
                 use self::sast::FilterOp::*;
                 let (field_name, op) = sast::parse_field_as_filter(key);
 
@@ -248,6 +340,28 @@ fn build_filter_from_object(
     }))
 }
 
+/// Parses the list of filters passed to an `and` or `or` combinator
+fn build_list_filter_from_value(
+    entity: ObjectOrInterface,
+    key: &str,
+    value: &r::Value,
+    schema: &ApiSchema,
+) -> Result<Vec<EntityFilter>, QueryExecutionError> {
+    match value {
+        r::Value::List(values) => values
+            .iter()
+            .map(|value| match value {
+                r::Value::Object(object) => build_filter_from_object(entity, object, schema),
+                _ => Err(QueryExecutionError::AttributeTypeError(
+                    value.to_string(),
+                    format!("{}_filter", entity.name()),
+                )),
+            })
+            .collect(),
+        _ => Err(QueryExecutionError::ListFilterError(key.to_string())),
+    }
+}
+
 fn build_child_filter_from_object(
     entity: ObjectOrInterface,
     field_name: String,
@@ -261,21 +375,39 @@ fn build_child_filter_from_object(
     let child_entity = schema
         .object_or_interface(type_name)
         .ok_or(QueryExecutionError::InvalidFilterError)?;
-    let filter = build_filter_from_object(child_entity, object, schema)?;
+    let filter = Box::new(build_filter_from_object(child_entity, object, schema)?);
     let derived = field.is_derived();
 
-    Ok(EntityFilter::Child(Child {
-        attr: match derived {
-            true => sast::get_derived_from_field(child_entity, field)
+    let child = |child_type: &s::ObjectType| -> Result<EntityFilter, QueryExecutionError> {
+        Ok(EntityFilter::Child(Child {
+            attr: match derived {
+                true => sast::get_derived_from_field(child_type, field)
+                    .ok_or(QueryExecutionError::InvalidFilterError)?
+                    .name
+                    .to_string(),
+                false => field_name.clone(),
+            },
+            entity_type: EntityType::from(child_type),
+            filter: filter.clone(),
+            derived,
+        }))
+    };
+
+    match child_entity {
+        ObjectOrInterface::Object(object_type) => child(object_type),
+        // Interfaces do not have a table of their own; an entity matches if
+        // it has a matching child of any of the types that implement the
+        // interface
+        ObjectOrInterface::Interface(interface) => Ok(EntityFilter::Or(
+            schema
+                .types_for_interface()
+                .get(&EntityType::from(interface))
                 .ok_or(QueryExecutionError::InvalidFilterError)?
-                .name
-                .to_string(),
-            false => field_name,
-        },
-        entity_type: EntityType::new(type_name.to_string()),
-        filter: Box::new(filter),
-        derived,
-    }))
+                .iter()
+                .map(child)
+                .collect::<Result<_, _>>()?,
+        )),
+    }
 }
 
 /// Parses a list of GraphQL values into a vector of entity field values.
@@ -306,25 +438,32 @@ fn list_values(value: Value, filter_type: &str) -> Result<Vec<Value>, QueryExecu
     }
 }
 
+/// What an `orderBy` argument sorts by
+enum OrderByValue {
+    /// An attribute of the entity itself
+    Direct(String, ValueType),
+    /// An attribute of an entity that the entity references
+    Child(EntityOrderByChild),
+}
+
 /// Parses GraphQL arguments into an field name to order by, if present.
 fn build_order_by(
     entity: ObjectOrInterface,
     field: &a::Field,
-) -> Result<Option<(String, ValueType)>, QueryExecutionError> {
+    schema: &ApiSchema,
+) -> Result<Option<OrderByValue>, QueryExecutionError> {
     match field.argument_value("orderBy") {
-        Some(r::Value::Enum(name)) => {
-            let field = sast::get_field(entity, name).ok_or_else(|| {
-                QueryExecutionError::EntityFieldError(entity.name().to_owned(), name.clone())
-            })?;
-            sast::get_field_value_type(&field.field_type)
-                .map(|value_type| Some((name.to_owned(), value_type)))
+        Some(r::Value::Enum(name)) => match sast::get_field(entity, name) {
+            Some(field) => sast::get_field_value_type(&field.field_type)
+                .map(|value_type| Some(OrderByValue::Direct(name.to_owned(), value_type)))
                 .map_err(|_| {
                     QueryExecutionError::OrderByNotSupportedError(
                         entity.name().to_owned(),
                         name.clone(),
                     )
-                })
-        }
+                }),
+            None => build_child_order_by(entity, name, schema).map(Some),
+        },
         _ => match field.argument_value("text") {
             Some(r::Value::Object(filter)) => build_fulltext_order_by_from_object(filter),
             None => Ok(None),
@@ -333,14 +472,54 @@ fn build_order_by(
     }
 }
 
+/// Parses an `orderBy` value of the form `<field>__<childField>`
+fn build_child_order_by(
+    entity: ObjectOrInterface,
+    name: &str,
+    schema: &ApiSchema,
+) -> Result<OrderByValue, QueryExecutionError> {
+    let not_supported =
+        || QueryExecutionError::OrderByNotSupportedError(entity.name().to_owned(), name.to_owned());
+
+    let (parent_name, child_name) = name.split_once("__").ok_or_else(|| {
+        QueryExecutionError::EntityFieldError(entity.name().to_owned(), name.to_owned())
+    })?;
+    // The API schema only has these values for object types; querying an
+    // interface would need to sort across several tables
+    if let ObjectOrInterface::Interface(_) = entity {
+        return Err(not_supported());
+    }
+    let parent_field = sast::get_field(entity, parent_name).ok_or_else(not_supported)?;
+    if sast::is_list(&parent_field.field_type) || parent_field.is_derived() {
+        return Err(not_supported());
+    }
+    let child_type = match schema.object_or_interface(parent_field.field_type.get_base_type()) {
+        Some(ObjectOrInterface::Object(child_type)) => child_type,
+        _ => return Err(not_supported()),
+    };
+    let child_field = sast::get_field(child_type, child_name).ok_or_else(not_supported)?;
+    let value_type =
+        sast::get_field_value_type(&child_field.field_type).map_err(|_| not_supported())?;
+
+    Ok(OrderByValue::Child(EntityOrderByChild {
+        join_attribute: parent_name.to_owned(),
+        entity_type: EntityType::from(child_type),
+        sort_by_attribute: child_name.to_owned(),
+        value_type,
+    }))
+}
+
 fn build_fulltext_order_by_from_object(
     object: &Object,
-) -> Result<Option<(String, ValueType)>, QueryExecutionError> {
+) -> Result<Option<OrderByValue>, QueryExecutionError> {
     object.iter().next().map_or(
         Err(QueryExecutionError::FulltextQueryRequiresFilter),
         |(key, value)| {
             if let r::Value::String(_) = value {
-                Ok(Some((key.to_string(), ValueType::String)))
+                Ok(Some(OrderByValue::Direct(
+                    key.to_string(),
+                    ValueType::String,
+                )))
             } else {
                 Err(QueryExecutionError::FulltextQueryRequiresFilter)
             }
@@ -360,6 +539,97 @@ fn build_order_direction(field: &a::Field) -> Result<OrderDirection, QueryExecut
         .unwrap_or(OrderDirection::Ascending))
 }
 
+/// The sort key and direction that a cursor for `order` refers to. Cursors
+/// can not be used when sorting by an attribute of a referenced entity
+fn cursor_order(order: &EntityOrder) -> Result<(Option<&str>, &'static str), QueryExecutionError> {
+    match order {
+        EntityOrder::Ascending(attr, _) => Ok((Some(attr.as_str()), "asc")),
+        EntityOrder::Descending(attr, _) => Ok((Some(attr.as_str()), "desc")),
+        EntityOrder::Default | EntityOrder::Unordered => Ok((None, "asc")),
+        EntityOrder::ChildAscending(_) | EntityOrder::ChildDescending(_) => {
+            Err(QueryExecutionError::InvalidCursor(
+                "cursors can not be used when sorting by a field of a referenced entity"
+                    .to_string(),
+            ))
+        }
+    }
+}
+
+/// Encode the position of `entity` in a collection sorted by `order` as an
+/// opaque cursor. The cursor is the hex encoding of the JSON array
+/// `[orderBy, orderDirection, sortValue, id]`; `entity` must contain the
+/// attribute it is sorted by
+pub(crate) fn encode_cursor(
+    order: &EntityOrder,
+    entity: &BTreeMap<Word, r::Value>,
+) -> Result<r::Value, QueryExecutionError> {
+    let (attr, direction) = cursor_order(order)?;
+    let value = attr
+        .and_then(|attr| entity.get(attr))
+        .cloned()
+        .unwrap_or(r::Value::Null);
+    let id = entity.get("id").cloned().unwrap_or(r::Value::Null);
+    let cursor = serde_json::json!([attr, direction, value, id]);
+    Ok(r::Value::String(hex::encode(cursor.to_string())))
+}
+
+/// Parses the `after` argument into a filter that selects the entities
+/// following the cursor in the order `order`
+fn build_after(
+    entity: ObjectOrInterface,
+    field: &a::Field,
+    order: &EntityOrder,
+) -> Result<Option<EntityFilter>, QueryExecutionError> {
+    let invalid = |msg: &str| QueryExecutionError::InvalidCursor(msg.to_string());
+
+    let cursor = match field.argument_value(AFTER_ARGUMENT) {
+        Some(r::Value::String(cursor)) => cursor,
+        Some(r::Value::Null) | None => return Ok(None),
+        Some(_) => return Err(invalid("the cursor must be a string")),
+    };
+    if field.argument_value("text").is_some() {
+        return Err(invalid("cursors can not be used with fulltext search"));
+    }
+
+    let parts = hex::decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Vec<serde_json::Value>>(&bytes).ok())
+        .ok_or_else(|| invalid("the cursor is malformed"))?;
+    let (cursor_attr, cursor_direction, value, id) = match parts.as_slice() {
+        [attr, direction, value, id] => (attr, direction, value, id),
+        _ => return Err(invalid("the cursor is malformed")),
+    };
+
+    let (attr, direction) = cursor_order(order)?;
+    if cursor_attr.as_str() != attr || cursor_direction.as_str() != Some(direction) {
+        return Err(invalid(
+            "the cursor was created for a different `orderBy` or `orderDirection`",
+        ));
+    }
+
+    let field_type = |name: &str| {
+        sast::get_field(entity, name)
+            .map(|field_def| &field_def.field_type)
+            .ok_or_else(|| {
+                QueryExecutionError::EntityFieldError(entity.name().to_owned(), name.to_owned())
+            })
+    };
+    let id = Value::from_query_value(&r::Value::from(id.clone()), field_type("id")?)?;
+    let key = match attr {
+        Some(attr) => {
+            let value = Value::from_query_value(&r::Value::from(value.clone()), field_type(attr)?)?;
+            Some((attr.to_string(), value))
+        }
+        None => None,
+    };
+
+    Ok(Some(EntityFilter::After(SortPosition {
+        key,
+        id,
+        descending: direction == "desc",
+    })))
+}
+
 /// Parses the subgraph ID from the ObjectType directives.
 pub fn parse_subgraph_id<'a>(
     entity: impl Into<ObjectOrInterface<'a>>,
@@ -436,7 +706,8 @@ mod tests {
         data::value::Object,
         prelude::{
             r, ApiSchema, AttributeNames, DeploymentHash, EntityCollection, EntityFilter,
-            EntityRange, Schema, Value, ValueType, BLOCK_NUMBER_MAX,
+            EntityRange, QueryExecutionError, Schema, SortPosition, Value, ValueType,
+            BLOCK_NUMBER_MAX,
         },
         prelude::{
             s::{self, Directive, Field, InputValue, ObjectType, Type, Value as SchemaValue},
@@ -446,7 +717,7 @@ mod tests {
     use graphql_parser::Pos;
     use std::{collections::BTreeMap, iter::FromIterator, sync::Arc};
 
-    use super::{a, build_query};
+    use super::{a, build_query, encode_cursor};
 
     fn default_object() -> ObjectType {
         let subgraph_id_argument = (
@@ -895,4 +1166,78 @@ mod tests {
             Some(EntityFilter::And(vec![EntityFilter::ChangeBlockGte(10)]))
         )
     }
+
+    #[test]
+    fn build_query_yields_after_filter_from_cursor() {
+        let schema = build_default_schema();
+        let object = ObjectType {
+            fields: vec![
+                field("id", Type::NamedType("ID".to_owned())),
+                field("name", Type::NamedType("String".to_owned())),
+            ],
+            ..default_object()
+        };
+        let entity = BTreeMap::from_iter(vec![
+            ("id".into(), r::Value::String("u1".to_owned())),
+            ("name".into(), r::Value::String("Bob".to_owned())),
+        ]);
+        let order = EntityOrder::Descending("name".to_owned(), ValueType::String);
+        let cursor = encode_cursor(&order, &entity).unwrap();
+
+        let query_field = default_field_with_vec(vec![
+            ("orderBy", r::Value::Enum("name".to_string())),
+            ("orderDirection", r::Value::Enum("desc".to_string())),
+            ("after", cursor.clone()),
+        ]);
+        let query = build_query(
+            &object,
+            BLOCK_NUMBER_MAX,
+            &query_field,
+            &BTreeMap::new(),
+            std::u32::MAX,
+            std::u32::MAX,
+            Default::default(),
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(
+            query.filter,
+            Some(EntityFilter::After(SortPosition {
+                key: Some(("name".to_owned(), Value::String("Bob".to_owned()))),
+                id: Value::String("u1".to_owned()),
+                descending: true,
+            }))
+        );
+        assert_eq!(query.order, order);
+
+        // The cursor can't be used with a different sort order
+        let query_field = default_field_with_vec(vec![
+            ("orderBy", r::Value::Enum("name".to_string())),
+            ("after", cursor),
+        ]);
+        let res = build_query(
+            &object,
+            BLOCK_NUMBER_MAX,
+            &query_field,
+            &BTreeMap::new(),
+            std::u32::MAX,
+            std::u32::MAX,
+            Default::default(),
+            &schema,
+        );
+        assert!(matches!(res, Err(QueryExecutionError::InvalidCursor(_))));
+
+        let query_field = default_field_with("after", r::Value::String("not hex".to_owned()));
+        let res = build_query(
+            &object,
+            BLOCK_NUMBER_MAX,
+            &query_field,
+            &BTreeMap::new(),
+            std::u32::MAX,
+            std::u32::MAX,
+            Default::default(),
+            &schema,
+        );
+        assert!(matches!(res, Err(QueryExecutionError::InvalidCursor(_))));
+    }
 }
@@ -5,6 +5,7 @@ use std::sync::Arc;
 use graph::data::value::Object;
 use graph::data::{
     graphql::{object, ObjectOrInterface},
+    query::Trace,
     schema::META_FIELD_TYPE,
 };
 use graph::prelude::*;
@@ -111,9 +112,7 @@ impl StoreResolver {
             state: &DeploymentState,
             block: BlockNumber,
         ) -> Result<(), QueryExecutionError> {
-            state
-                .block_queryable(block)
-                .map_err(|msg| QueryExecutionError::ValueParseError("block.number".to_owned(), msg))
+            state.block_queryable(block)
         }
 
         match bc {
@@ -220,8 +219,9 @@ impl Resolver for StoreResolver {
         &self,
         ctx: &ExecutionContext<Self>,
         selection_set: &a::SelectionSet,
-    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
-        super::prefetch::run(self, ctx, selection_set, &self.result_size).map(Some)
+    ) -> Result<(Option<r::Value>, Trace), Vec<QueryExecutionError>> {
+        super::prefetch::run(self, ctx, selection_set, &self.result_size)
+            .map(|(value, trace)| (Some(value), trace))
     }
 
     fn resolve_objects(
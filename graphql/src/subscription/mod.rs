@@ -156,7 +156,10 @@ fn map_source_to_response_stream(
     } = options;
 
     trigger_stream
-        .chain(source_stream)
+        .chain(throttle(
+            source_stream,
+            ENV_VARS.graphql.subscription_update_interval,
+        ))
         .then(move |()| {
             execute_subscription_event(
                 logger.clone(),
@@ -173,6 +176,35 @@ fn map_source_to_response_stream(
         .boxed()
 }
 
+/// Let at most one event per `interval` through. Events that arrive while
+/// waiting for the interval to pass are combined into one, so that a burst
+/// of store changes leads to a single update that reflects all of them.
+fn throttle(source_stream: UnitStream, interval: Duration) -> UnitStream {
+    if interval.is_zero() {
+        return source_stream;
+    }
+
+    // The initial execution of the subscription counts as the first update
+    futures03::stream::unfold(
+        (source_stream, Instant::now()),
+        move |(mut source_stream, last_update)| async move {
+            source_stream.next().await?;
+
+            let elapsed = last_update.elapsed();
+            if elapsed < interval {
+                tokio::time::sleep(interval - elapsed).await;
+            }
+
+            // Swallow the events that are already waiting; the update we
+            // are about to send covers them
+            while let Some(Some(())) = source_stream.next().now_or_never() {}
+
+            Some(((), (source_stream, Instant::now())))
+        },
+    )
+    .boxed()
+}
+
 async fn execute_subscription_event(
     logger: Logger,
     store: Arc<dyn QueryStore>,
@@ -236,3 +268,47 @@ async fn execute_subscription_event(
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use futures03::channel::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn throttle_combines_events() {
+        let interval = Duration::from_millis(100);
+        let (sender, receiver) = mpsc::unbounded();
+        let start = Instant::now();
+        let mut updates = throttle(Box::new(receiver), interval);
+
+        // Events that arrive within the interval lead to one update once
+        // the interval has passed
+        for _ in 0..3 {
+            sender.unbounded_send(()).unwrap();
+        }
+        assert_eq!(Some(()), updates.next().await);
+        assert!(start.elapsed() >= interval);
+        assert_eq!(None, updates.next().now_or_never());
+
+        // The next event waits for the interval since the last update
+        sender.unbounded_send(()).unwrap();
+        assert_eq!(Some(()), updates.next().await);
+        assert!(start.elapsed() >= interval * 2);
+
+        drop(sender);
+        assert_eq!(None, updates.next().await);
+    }
+
+    #[tokio::test]
+    async fn throttle_without_interval_passes_events() {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut updates = throttle(Box::new(receiver), Duration::ZERO);
+
+        for _ in 0..3 {
+            sender.unbounded_send(()).unwrap();
+        }
+        drop(sender);
+        assert_eq!(3, updates.collect::<Vec<_>>().await.len());
+    }
+}
@@ -52,6 +52,14 @@ impl MaybeCoercible<ScalarType> for q::Value {
             ("BigInt", q::Value::Int(n)) => Ok(r::Value::String(
                 n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
             )),
+            ("Int8", q::Value::String(s)) => Ok(r::Value::String(s)),
+            ("Int8", q::Value::Int(n)) => Ok(r::Value::String(
+                n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
+            )),
+            ("Timestamp", q::Value::String(s)) => Ok(r::Value::String(s)),
+            ("Timestamp", q::Value::Int(n)) => Ok(r::Value::String(
+                n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
+            )),
             (_, v) => Err(v),
         }
     }
@@ -395,6 +403,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn coerce_int8_and_timestamp_scalars() {
+        for name in ["Int8", "Timestamp"] {
+            let scalar_type = TypeDefinition::Scalar(ScalarType::new(name.to_string()));
+            let resolver = |_: &str| Some(&scalar_type);
+
+            // We can coerce from Value::String and Value::Int, including
+            // values that do not fit into an i32
+            assert_eq!(
+                coerce_to_definition(Value::String("1654084800".to_string()), "", &resolver),
+                Ok(Value::String("1654084800".to_string()))
+            );
+            assert_eq!(
+                coerce_to_definition(Value::Int(1654084800123456i64.into()), "", &resolver),
+                Ok(Value::String("1654084800123456".to_string()))
+            );
+            assert!(coerce_to_definition(Value::Boolean(true), "", &resolver).is_err());
+        }
+    }
+
     #[test]
     fn coerce_bytes_scalar() {
         let bytes_type = TypeDefinition::Scalar(ScalarType::new("Bytes".to_string()));
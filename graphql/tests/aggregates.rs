@@ -0,0 +1,202 @@
+//! Tests for `<entities>Aggregate` queries. Aggregates are only part of the
+//! API schema when `GRAPH_GRAPHQL_ENABLE_AGGREGATES` is set, and that has
+//! to happen before `ENV_VARS` is first used, which is why these tests are
+//! in their own test binary.
+
+#[macro_use]
+extern crate pretty_assertions;
+
+use std::sync::Arc;
+
+use graph::{
+    components::store::DeploymentLocator,
+    data::{graphql::object, query::QueryTarget},
+    entity,
+    prelude::{
+        r, BlockPtr, DeploymentHash, Entity, EntityKey, EntityOperation, Query, QueryError,
+        QueryExecutionError, QueryResult, ENV_VARS,
+    },
+};
+use graph_graphql::prelude::*;
+use test_store::{
+    block_store::{self, BLOCK_ONE, GENESIS_BLOCK},
+    create_test_subgraph, run_test_sequentially, Store, GENESIS_PTR, LOAD_MANAGER, LOGGER,
+    METRICS_REGISTRY, NETWORK_NAME, STORE, SUBSCRIPTION_MANAGER,
+};
+
+const SCHEMA: &str = "
+type Song @entity {
+    id: ID!
+    title: String!
+    plays: Int!
+}";
+
+fn run_test<F, R>(test: F)
+where
+    F: FnOnce(Arc<Store>, DeploymentLocator) -> R + Send + 'static,
+    R: std::future::Future<Output = ()> + Send + 'static,
+{
+    std::env::set_var("GRAPH_GRAPHQL_ENABLE_AGGREGATES", "true");
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup(&store).await;
+        test(store, deployment).await
+    })
+}
+
+/// Create a deployment with three songs, two of them at the genesis block
+/// and one at block one
+async fn setup(store: &Arc<Store>) -> DeploymentLocator {
+    block_store::set_chain(vec![&*GENESIS_BLOCK, &*BLOCK_ONE], NETWORK_NAME);
+
+    let id = DeploymentHash::new("graphqlTestsAggregates").unwrap();
+    let deployment = create_test_subgraph(&id, SCHEMA).await;
+
+    async fn insert_at(
+        store: &Arc<Store>,
+        deployment: &DeploymentLocator,
+        block_ptr: BlockPtr,
+        entities: Vec<Entity>,
+    ) {
+        let ops = entities
+            .into_iter()
+            .map(|data| EntityOperation::Set {
+                key: EntityKey::data(
+                    deployment.hash.clone(),
+                    "Song".to_owned(),
+                    data.get("id").unwrap().clone().as_string().unwrap(),
+                ),
+                data,
+            })
+            .collect();
+        test_store::transact_and_wait(&store.subgraph_store(), deployment, block_ptr, ops)
+            .await
+            .unwrap();
+    }
+
+    insert_at(
+        store,
+        &deployment,
+        GENESIS_PTR.clone(),
+        vec![
+            entity! { id: "s1", title: "Cheesy Tune", plays: 10 },
+            entity! { id: "s2", title: "Rock Tune", plays: 15 },
+        ],
+    )
+    .await;
+    insert_at(
+        store,
+        &deployment,
+        BLOCK_ONE.block_ptr(),
+        vec![entity! { id: "s3", title: "Pop Tune", plays: 5 }],
+    )
+    .await;
+    deployment
+}
+
+async fn execute_query(
+    deployment: &DeploymentLocator,
+    query: &str,
+    max_complexity: Option<u64>,
+) -> QueryResult {
+    let runner = Arc::new(GraphQlRunner::new(
+        &*LOGGER,
+        STORE.clone(),
+        SUBSCRIPTION_MANAGER.clone(),
+        LOAD_MANAGER.clone(),
+        METRICS_REGISTRY.clone(),
+    ));
+    let query = Query::new(
+        graphql_parser::parse_query(query)
+            .expect("invalid test query")
+            .into_static(),
+        None,
+    );
+    let target = QueryTarget::Deployment(deployment.hash.clone());
+
+    runner
+        .run_query_with_complexity(query, target, max_complexity, None, None, None)
+        .await
+        .first()
+        .unwrap()
+        .duplicate()
+}
+
+fn extract_data(result: QueryResult) -> r::Value {
+    match result.to_result() {
+        Err(errors) => panic!("Unexpected errors return for query: {:#?}", errors),
+        Ok(data) => data.unwrap(),
+    }
+}
+
+#[test]
+fn aggregates() {
+    run_test(|_, deployment| async move {
+        const QUERY: &str = "query {
+            songsAggregate {
+                count
+                sum { plays }
+                min { plays }
+                max { plays }
+            }
+            filtered: songsAggregate(where: { plays_gt: 8 }) {
+                count
+                sum { plays }
+            }
+            genesis: songsAggregate(block: { number: 0 }) {
+                count
+            }
+            none: songsAggregate(where: { plays_gt: 100 }) {
+                count
+                max { plays }
+            }
+        }";
+
+        let data = extract_data(execute_query(&deployment, QUERY, None).await);
+        let exp = object! {
+            songsAggregate: object! {
+                count: 3,
+                sum: object! { plays: "30" },
+                min: object! { plays: "5" },
+                max: object! { plays: "15" },
+            },
+            filtered: object! {
+                count: 2,
+                sum: object! { plays: "25" },
+            },
+            genesis: object! { count: 2 },
+            none: object! {
+                count: 0,
+                max: object! { plays: r::Value::Null },
+            },
+        };
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn aggregates_complexity() {
+    run_test(|_, deployment| async move {
+        const QUERY: &str = "query {
+            songsAggregate {
+                count
+                sum { plays }
+            }
+        }";
+
+        // An aggregate costs as much as a query for the most entities a
+        // query can ask for
+        let complexity = ENV_VARS.graphql.max_first as u64;
+
+        let result = execute_query(&deployment, QUERY, Some(complexity)).await;
+        assert!(!result.has_errors());
+
+        let result = execute_query(&deployment, QUERY, Some(complexity - 1)).await;
+        match &result.to_result().unwrap_err()[0] {
+            QueryError::ExecutionError(QueryExecutionError::TooComplex(actual, _)) => {
+                assert_eq!(complexity, *actual)
+            }
+            e => panic!("did not catch complexity: {:?}", e),
+        }
+    })
+}
@@ -148,6 +148,7 @@ fn test_schema(id: DeploymentHash, id_type: IdType) -> Schema {
         mainBand: Band
         bands: [Band!]!
         writtenSongs: [Song]! @derivedFrom(field: \"writtenBy\")
+        reviews: [Review!]! @derivedFrom(field: \"author\")
     }
 
     type Band @entity {
@@ -174,6 +175,26 @@ fn test_schema(id: DeploymentHash, id_type: IdType) -> Schema {
     type Publisher {
         id: Bytes!
     }
+
+    interface Review {
+        id: ID!
+        body: String!
+        author: Musician!
+    }
+
+    type SongReview implements Review @entity {
+        id: ID!
+        body: String!
+        song: Song!
+        author: Musician!
+    }
+
+    type BandReview implements Review @entity {
+        id: ID!
+        body: String!
+        band: Band!
+        author: Musician!
+    }
     ";
 
     Schema::parse(&SCHEMA.replace("@ID@", id_type.as_str()), id).expect("Test schema invalid")
@@ -211,6 +232,10 @@ async fn insert_test_entities(
         entity! { __typename: "Song", id: s[4], title: "Folk Tune",    publisher: "0xb1", writtenBy: "m3" },
         entity! { __typename: "SongStat", id: s[1], played: 10 },
         entity! { __typename: "SongStat", id: s[2], played: 15 },
+        entity! { __typename: "BandReview", id: "r1", body: "Bad musicians",   band: "b1", author: "m1" },
+        entity! { __typename: "BandReview", id: "r2", body: "Good amateurs",   band: "b2", author: "m2" },
+        entity! { __typename: "SongReview", id: "r3", body: "Bad",             song: s[2], author: "m1" },
+        entity! { __typename: "SongReview", id: "r4", body: "Good",            song: s[3], author: "m2" },
     ];
 
     let entities1 = vec![
@@ -653,6 +678,175 @@ fn can_query_with_child_filter_on_derived_named_type_field() {
     })
 }
 
+#[test]
+fn can_query_with_child_filter_on_derived_interface_list_field() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: id, where: { reviews_: { body_starts_with: \"Good\" } }) {
+            name
+            reviews(orderBy: id) {
+                body
+            }
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! {
+                    name: "Lisa",
+                    reviews: vec![ object! { body: "Good amateurs" }, object! { body: "Good" } ]
+                },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_nested_child_filter() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: id, where: { bands_: { originalSongs_: { title: \"Folk Tune\" } } }) {
+            name
+        }
+        bands(first: 100, orderBy: id, where: { members_: { reviews_: { body: \"Good\" } } }) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "John" },
+                object! { name: "Tom" },
+            ],
+            bands: vec![
+                object! { name: "The Musicians" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_nested_child_filter_at_block() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: id, block: { number: 0 }, where: { bands_: { originalSongs_: { title: \"Folk Tune\" } } }) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "John" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_sorted_by_child_field() {
+    const QUERY: &str = "
+    query {
+        asc: musicians(orderBy: mainBand__name, orderDirection: asc) {
+            name
+        }
+        desc: musicians(orderBy: mainBand__name, orderDirection: desc) {
+            name
+        }
+        bands(orderBy: id) {
+            members(orderBy: mainBand__name) {
+                name
+            }
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            asc: vec![
+                object! { name: "Tom" },
+                object! { name: "John" },
+                object! { name: "Lisa" },
+                object! { name: "Valerie" },
+            ],
+            desc: vec![
+                object! { name: "Valerie" },
+                object! { name: "Lisa" },
+                object! { name: "John" },
+                object! { name: "Tom" },
+            ],
+            bands: vec![
+                object! { members: vec![
+                    object! { name: "Tom" },
+                    object! { name: "John" },
+                    object! { name: "Lisa" },
+                ]},
+                object! { members: vec![
+                    object! { name: "Tom" },
+                    object! { name: "John" },
+                ]},
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_or_filter() {
+    const QUERY: &str = "
+    query {
+        musicians(orderBy: id, where: { or: [{ name: \"John\" }, { mainBand_: { name: \"The Amateurs\" } }] }) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "John" },
+                object! { name: "Tom" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_nested_and_or_filter() {
+    const QUERY: &str = "
+    query {
+        musicians(orderBy: id, where: { and: [{ name_not: \"John\" }, { or: [{ mainBand: \"b1\" }, { mainBand: null }] }] }) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "Lisa" },
+                object! { name: "Valerie" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 #[test]
 fn root_fragments_are_expanded() {
     const QUERY: &str = r#"
@@ -1589,6 +1783,59 @@ fn query_detects_reorg() {
     })
 }
 
+#[test]
+fn query_below_earliest_block() {
+    use test_store::block_store::BLOCK_TWO;
+
+    async fn query_at(deployment: &DeploymentLocator, block: i32) -> QueryResult {
+        let query =
+            format!("query {{ musician(id: \"m1\", block: {{ number: {block} }}) {{ id }} }}");
+        execute_query(&deployment, &query).await
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup(
+            store.as_ref(),
+            "graphqlQueryBelowEarliestBlock",
+            BTreeSet::new(),
+            IdType::String,
+        )
+        .await;
+
+        // Move the subgraph head to block 2 and prune all history except
+        // for the last block so that the earliest block becomes block 1
+        test_store::transact_and_wait(
+            &STORE.subgraph_store(),
+            &deployment,
+            BLOCK_TWO.block_ptr(),
+            vec![],
+        )
+        .await
+        .unwrap();
+        let (earliest_block, _) = STORE.subgraph_store().prune(&deployment, Some(1)).unwrap();
+        assert_eq!(1, earliest_block);
+
+        let result = query_at(&deployment, 1).await;
+        assert_eq!(
+            extract_data!(result),
+            Some(object!(musician: object!(id: "m1")))
+        );
+
+        let result = query_at(&deployment, 0).await;
+        match &result.to_result().unwrap_err()[0] {
+            QueryError::ExecutionError(QueryExecutionError::BlockBeforeEarliest(
+                id,
+                earliest,
+                block,
+            )) => {
+                assert_eq!(deployment.hash.as_str(), id.as_str());
+                assert_eq!((1, 0), (*earliest, *block));
+            }
+            e => panic!("unexpected error for query below earliest block: {:?}", e),
+        }
+    })
+}
+
 #[test]
 fn can_query_meta() {
     // metadata for the latest block (block 1)
@@ -1754,3 +2001,53 @@ fn can_query_root_typename() {
         assert_eq!(extract_data!(result), Some(exp));
     })
 }
+
+#[test]
+fn query_trace() {
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+        let runner = Arc::new(GraphQlRunner::new(
+            &*LOGGER,
+            STORE.clone(),
+            SUBSCRIPTION_MANAGER.clone(),
+            LOAD_MANAGER.clone(),
+            METRICS_REGISTRY.clone(),
+        ));
+        let run = |trace: bool| {
+            let runner = runner.clone();
+            let target = QueryTarget::Deployment(deployment.hash.clone());
+            async move {
+                let document = graphql_parser::parse_query(
+                    "query { musicians(first: 2) { id bands { id } } }",
+                )
+                .unwrap()
+                .into_static();
+                let mut query = Query::new(document, None);
+                query.trace = trace;
+                let results = runner
+                    .run_query_with_complexity(query, target, None, None, None, None)
+                    .await;
+                serde_json::to_value(&results).unwrap()
+            }
+        };
+
+        let result = run(true).await;
+        let root = &result["trace"][0];
+        let steps: Vec<_> = root["steps_ms"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|step| step[0].as_str().unwrap())
+            .collect();
+        assert_eq!(vec!["prefetch", "resolve"], steps);
+        let musicians = &root["fields"]["musicians"];
+        assert!(musicians["sql"].as_str().unwrap().contains("musician"));
+        assert!(!musicians["explain"].as_array().unwrap().is_empty());
+        assert!(musicians["fields"]["bands"]["sql"].is_string());
+
+        // Queries only get a trace when they ask for one
+        let result = run(false).await;
+        assert!(result.get("trace").is_none());
+        assert!(result.get("data").is_some());
+    })
+}
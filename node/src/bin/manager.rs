@@ -26,7 +26,9 @@ use graph_store_postgres::{
     SubscriptionManager, PRIMARY_SHARD,
 };
 use lazy_static::lazy_static;
-use std::{collections::HashMap, env, num::ParseIntError, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap, env, num::ParseIntError, path::PathBuf, sync::Arc, time::Duration,
+};
 use structopt::StructOpt;
 
 const VERSION_LABEL_KEY: &str = "version";
@@ -186,6 +188,11 @@ pub enum Command {
     Copy(CopyCommand),
     /// Run a GraphQL query
     Query {
+        /// Include a trace of the execution in the result: the SQL queries
+        /// that were run, their `EXPLAIN (ANALYZE, BUFFERS)` output, and how
+        /// long each step took
+        #[structopt(long)]
+        explain: bool,
         /// The subgraph to query
         ///
         /// Either a deployment id `Qm..` or a subgraph name
@@ -202,6 +209,66 @@ pub enum Command {
 
     /// Manage database indexes
     Index(IndexCommand),
+
+    /// Prune historical entity versions of a deployment
+    ///
+    /// Remove all entity versions that are only needed to answer queries
+    /// for blocks that lie more than `history` blocks before the
+    /// deployment's head. Queries for such blocks fail after pruning. The
+    /// value for `history` is remembered for the deployment; if it is not
+    /// passed, the remembered value is used.
+    Prune {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// How many blocks of history to keep
+        #[structopt(long)]
+        history: Option<i32>,
+        #[structopt(
+            long,
+            short,
+            default_value = "50",
+            env = "ETHEREUM_REORG_THRESHOLD",
+            help = "the reorg threshold; at least this many blocks of history are kept\n"
+        )]
+        reorg_threshold: i32,
+    },
+    /// Write the entities of a deployment into files, one per entity type
+    ///
+    /// The GraphQL schema of the deployment is written to `schema.graphql`
+    /// and the block and manifest details to `metadata.json` in the output
+    /// directory. A dump in CSV or JSONL format can be loaded with `restore`
+    Dump {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// The block at which to read the entities; defaults to the latest block
+        #[structopt(long, short)]
+        block: Option<i32>,
+        /// The format of the files
+        #[structopt(
+            long,
+            short,
+            default_value = "csv",
+            possible_values = &["csv", "jsonl", "parquet"]
+        )]
+        format: commands::dump::Format,
+        /// The directory into which to write the files
+        #[structopt(long, short, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Create a deployment from a dump made with `dump`
+    ///
+    /// The entities are loaded as of the block of the dump, and indexing
+    /// continues from there once the deployment is assigned to `node`. The
+    /// deployment must not exist yet
+    Restore {
+        /// The directory that contains the dump
+        directory: PathBuf,
+        /// The name of the subgraph to which the deployment should belong
+        #[structopt(long, short)]
+        name: String,
+        /// The name of the node that should index the deployment
+        node: String,
+    },
 }
 
 impl Command {
@@ -904,10 +971,11 @@ async fn main() -> anyhow::Result<()> {
             }
         }
         Query {
+            explain,
             target,
             query,
             vars,
-        } => commands::query::run(ctx.graphql_runner(), target, query, vars).await,
+        } => commands::query::run(ctx.graphql_runner(), target, query, vars, explain).await,
         Chain(cmd) => {
             use ChainCommand::*;
             match cmd {
@@ -1003,6 +1071,34 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
+        Prune {
+            deployment,
+            history,
+            reorg_threshold,
+        } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            commands::prune::run(
+                store.subgraph_store(),
+                primary_pool,
+                deployment,
+                history,
+                reorg_threshold,
+            )
+        }
+        Dump {
+            deployment,
+            block,
+            format,
+            output,
+        } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            commands::dump::run(store, primary_pool, deployment, block, format, output)
+        }
+        Restore {
+            directory,
+            name,
+            node,
+        } => commands::restore::run(ctx.subgraph_store(), directory, name, node),
     }
 }
 
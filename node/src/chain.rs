@@ -117,7 +117,7 @@ pub async fn create_ethereum_networks(
 
         for provider in &chain.providers {
             if let ProviderDetails::Web3(web3) = &provider.details {
-                let capabilities = web3.node_capabilities();
+                let mut capabilities = web3.node_capabilities();
 
                 let logger = logger.new(o!("provider" => provider.label.clone()));
                 info!(
@@ -137,20 +137,24 @@ pub async fn create_ethereum_networks(
 
                 let supports_eip_1898 = !web3.features.contains("no_eip1898");
 
+                let adapter = Arc::new(
+                    graph_chain_ethereum::EthereumAdapter::new(
+                        logger,
+                        provider.label.clone(),
+                        &web3.url,
+                        transport,
+                        eth_rpc_metrics.clone(),
+                        supports_eip_1898,
+                        web3.batch_size,
+                    )
+                    .await,
+                );
+                capabilities.block_receipts = adapter.supports_block_receipts();
+
                 parsed_networks.insert(
                     name.to_string(),
                     capabilities,
-                    Arc::new(
-                        graph_chain_ethereum::EthereumAdapter::new(
-                            logger,
-                            provider.label.clone(),
-                            &web3.url,
-                            transport,
-                            eth_rpc_metrics.clone(),
-                            supports_eip_1898,
-                        )
-                        .await,
-                    ),
+                    adapter,
                     web3.limit_for(&config.node),
                 );
             }
@@ -431,10 +435,12 @@ mod test {
         let traces = NodeCapabilities {
             archive: false,
             traces: true,
+            block_receipts: false,
         };
         let archive = NodeCapabilities {
             archive: true,
             traces: false,
+            block_receipts: false,
         };
         let has_mainnet_with_traces = ethereum_networks
             .adapter_with_capabilities("mainnet".to_string(), &traces)
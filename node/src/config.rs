@@ -474,6 +474,7 @@ impl ChainSection {
                         features,
                         headers: Default::default(),
                         rules: Vec::new(),
+                        batch_size: default_batch_size(),
                     }),
                 };
                 let entry = chains.entry(name.to_string()).or_insert_with(|| Chain {
@@ -601,6 +602,15 @@ pub struct Web3Provider {
 
     #[serde(default, rename = "match")]
     rules: Vec<Web3Rule>,
+
+    /// The maximum number of requests to send to this provider in one
+    /// JSON-RPC batch. A value of 1 disables batching
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+}
+
+fn default_batch_size() -> usize {
+    1
 }
 
 impl Web3Provider {
@@ -608,6 +618,9 @@ impl Web3Provider {
         NodeCapabilities {
             archive: self.features.contains("archive"),
             traces: self.features.contains("traces"),
+            // Whether the provider supports this is detected when the
+            // adapter for it is created
+            block_receipts: false,
         }
     }
 
@@ -671,6 +684,13 @@ impl Provider {
                     }
                 }
 
+                if web3.batch_size == 0 {
+                    return Err(anyhow!(
+                        "the batch_size for provider {} must be at least 1",
+                        self.label
+                    ));
+                }
+
                 web3.url = shellexpand::env(&web3.url)?.into_owned();
 
                 let label = &self.label;
@@ -714,6 +734,7 @@ impl<'de> Deserialize<'de> for Provider {
                 let mut transport = None;
                 let mut features = None;
                 let mut headers = None;
+                let mut batch_size = None;
                 let mut nodes = Vec::new();
 
                 while let Some(key) = map.next_key()? {
@@ -756,6 +777,12 @@ impl<'de> Deserialize<'de> for Provider {
                             let raw_headers: BTreeMap<String, String> = map.next_value()?;
                             headers = Some(btree_map_to_http_headers(raw_headers));
                         }
+                        ProviderField::BatchSize => {
+                            if batch_size.is_some() {
+                                return Err(serde::de::Error::duplicate_field("batch_size"));
+                            }
+                            batch_size = Some(map.next_value()?);
+                        }
                         ProviderField::Match => {
                             nodes = map.next_value()?;
                         }
@@ -772,6 +799,9 @@ impl<'de> Deserialize<'de> for Provider {
                         {
                             return Err(serde::de::Error::custom("when `details` field is provided, deprecated `url`, `transport`, `features` and `headers` cannot be specified"));
                         }
+                        if batch_size.is_some() {
+                            return Err(serde::de::Error::custom("when `details` field is provided, `batch_size` must be specified inside `details`"));
+                        }
 
                         v
                     }
@@ -782,6 +812,7 @@ impl<'de> Deserialize<'de> for Provider {
                             .ok_or_else(|| serde::de::Error::missing_field("features"))?,
                         headers: headers.unwrap_or_else(|| HeaderMap::new()),
                         rules: nodes,
+                        batch_size: batch_size.unwrap_or_else(default_batch_size),
                     }),
                 };
 
@@ -796,6 +827,7 @@ impl<'de> Deserialize<'de> for Provider {
             "url",
             "features",
             "headers",
+            "batch_size",
         ];
         deserializer.deserialize_struct("Provider", FIELDS, ProviderVisitor)
     }
@@ -813,6 +845,9 @@ enum ProviderField {
     Transport,
     Features,
     Headers,
+
+    #[serde(rename = "batch_size")]
+    BatchSize,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -1170,6 +1205,7 @@ mod tests {
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
                     rules: Vec::new(),
+                    batch_size: 1,
                 }),
             },
             actual
@@ -1196,12 +1232,63 @@ mod tests {
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
                     rules: Vec::new(),
+                    batch_size: 1,
                 }),
             },
             actual
         );
     }
 
+    #[test]
+    fn it_parses_web3_provider_batch_size() {
+        let deprecated = toml::from_str::<Provider>(
+            r#"
+            label = "peering"
+            url = "http://localhost:8545"
+            features = []
+            batch_size = 50
+        "#,
+        )
+        .unwrap();
+        let details = toml::from_str::<Provider>(
+            r#"
+            label = "peering"
+            details = { type = "web3", url = "http://localhost:8545", features = [], batch_size = 50 }
+        "#,
+        )
+        .unwrap();
+
+        for provider in [deprecated, details] {
+            match provider.details {
+                ProviderDetails::Web3(web3) => assert_eq!(50, web3.batch_size),
+                ProviderDetails::Firehose(_) => panic!("expected a web3 provider"),
+            }
+        }
+
+        let actual = toml::from_str::<Provider>(
+            r#"
+            label = "peering"
+            batch_size = 50
+            details = { type = "web3", url = "http://localhost:8545", features = [] }
+        "#,
+        );
+        assert_eq!(
+            actual.unwrap_err().to_string(),
+            "when `details` field is provided, `batch_size` must be specified inside `details` at line 1 column 1"
+        );
+
+        let mut provider = toml::from_str::<Provider>(
+            r#"
+            label = "peering"
+            url = "http://localhost:8545"
+            features = []
+            batch_size = 0
+        "#,
+        )
+        .unwrap();
+        assert!(provider.validate().is_err());
+    }
+
     #[test]
     fn it_errors_on_deprecated_provider_missing_url_from_toml() {
         let actual = toml::from_str::<Provider>(
@@ -1261,6 +1348,7 @@ mod tests {
                     features,
                     headers,
                     rules: Vec::new(),
+                    batch_size: 1,
                 }),
             },
             actual
@@ -1286,6 +1374,7 @@ mod tests {
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
                     rules: Vec::new(),
+                    batch_size: 1,
                 }),
             },
             actual
@@ -4,7 +4,10 @@ use ethereum::{
 };
 use git_testament::{git_testament, render_testament};
 use graph::blockchain::firehose_block_ingestor::FirehoseBlockIngestor;
-use graph::blockchain::{Block as BlockchainBlock, Blockchain, BlockchainKind, BlockchainMap};
+use graph::blockchain::{
+    Block as BlockchainBlock, Blockchain, BlockchainKind, BlockchainMap,
+    ChainHeadUpdateListener as _,
+};
 use graph::components::store::BlockStore;
 use graph::data::graphql::effort::LoadManager;
 use graph::env::EnvVars;
@@ -21,7 +24,7 @@ use graph_core::{
     LinkResolver, MetricsRegistry, SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider,
     SubgraphInstanceManager, SubgraphRegistrar as IpfsSubgraphRegistrar,
 };
-use graph_graphql::prelude::GraphQlRunner;
+use graph_graphql::prelude::{revert_query_cache_on_reorg, GraphQlRunner, PersistedQueries};
 use graph_node::chain::{
     connect_ethereum_networks, connect_firehose_networks, create_ethereum_networks,
     create_firehose_networks, create_ipfs_clients,
@@ -269,15 +272,33 @@ async fn main() {
         )
         .await;
 
-        let network_identifiers = ethereum_idents
+        let network_identifiers: Vec<_> = ethereum_idents
             .into_iter()
             .chain(arweave_idents)
             .chain(near_idents)
             .chain(cosmos_idents)
             .collect();
+        let network_names: Vec<_> = network_identifiers
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
 
         let network_store = store_builder.network_store(network_identifiers);
 
+        // Forget cached query results for blocks that were removed by a reorg
+        for network in network_names {
+            if let Some(chain_store) = network_store.block_store().chain_store(&network) {
+                let chain_head_updates =
+                    chain_head_update_listener.subscribe(network.clone(), logger.clone());
+                revert_query_cache_on_reorg(
+                    logger.clone(),
+                    network,
+                    chain_head_updates,
+                    chain_store,
+                );
+            }
+        }
+
         let arweave_chains = arweave_networks_as_chains(
             &mut blockchain_map,
             &logger,
@@ -295,7 +316,7 @@ async fn main() {
             firehose_networks_by_kind.get(&BlockchainKind::Ethereum),
             &eth_networks,
             network_store.as_ref(),
-            chain_head_update_listener,
+            chain_head_update_listener.clone(),
             &logger_factory,
         );
 
@@ -331,14 +352,22 @@ async fn main() {
             load_manager,
             metrics_registry.clone(),
         ));
+        let persisted_queries = Arc::new(
+            PersistedQueries::from_env(&logger).expect("failed to load persisted queries"),
+        );
         let mut graphql_server = GraphQLQueryServer::new(
             &logger_factory,
             graphql_metrics_registry,
             graphql_runner.clone(),
+            persisted_queries.clone(),
             node_id.clone(),
         );
-        let subscription_server =
-            GraphQLSubscriptionServer::new(&logger, graphql_runner.clone(), network_store.clone());
+        let subscription_server = GraphQLSubscriptionServer::new(
+            &logger,
+            graphql_runner.clone(),
+            network_store.clone(),
+            persisted_queries,
+        );
 
         let mut index_node_server = IndexNodeServer::new(
             &logger_factory,
@@ -388,6 +417,7 @@ async fn main() {
             graph::spawn_blocking(job_runner.start());
         }
         let static_filters = ENV_VARS.experimental_static_filters;
+        let parallel_triggers = ENV_VARS.subgraph_parallel_triggers;
 
         let subgraph_instance_manager = SubgraphInstanceManager::new(
             &logger_factory,
@@ -396,6 +426,7 @@ async fn main() {
             metrics_registry.clone(),
             link_resolver.clone(),
             static_filters,
+            parallel_triggers,
         );
 
         // Create IPFS-based subgraph provider
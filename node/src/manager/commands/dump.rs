@@ -0,0 +1,491 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use arrow::array::{ArrayRef, BooleanArray, Int32Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
+use arrow::record_batch::RecordBatch;
+use graph::components::store::{BlockStore as _, SubgraphStore as _};
+use graph::data::value::Object;
+use graph::prelude::{
+    anyhow::{self, anyhow, bail},
+    r, serde_json, BlockHash, BlockNumber, ChainStore as _, Entity, Value,
+};
+use graph_store_postgres::command_support::{catalog, ColumnType, Table};
+use graph_store_postgres::{connection_pool::ConnectionPool, Store};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+
+use crate::manager::deployment::DeploymentSearch;
+
+/// How many entities to read from the database at once
+const BATCH_SIZE: u32 = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(anyhow!(
+                "unknown format `{}`, use one of csv, jsonl or parquet",
+                s
+            )),
+        }
+    }
+}
+
+/// Information about a dump that is needed to restore it; it is written
+/// to `metadata.json` in the output directory
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub deployment: String,
+    pub network: String,
+    pub block_number: BlockNumber,
+    pub block_hash: String,
+    pub spec_version: String,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub features: Vec<String>,
+    /// The number of dynamic data sources the deployment had created by
+    /// `block_number`. These are not part of the dump
+    pub dynamic_data_sources: usize,
+}
+
+impl Metadata {
+    pub const FILE: &'static str = "metadata.json";
+    pub const SCHEMA_FILE: &'static str = "schema.graphql";
+}
+
+/// The fields of an entity type that get dumped, in the order in which
+/// they appear in the schema
+struct Columns {
+    fields: Vec<(String, ColumnType, bool)>,
+}
+
+impl Columns {
+    fn new(table: &Table) -> Self {
+        let fields = table
+            .columns
+            .iter()
+            .filter(|column| !matches!(column.column_type, ColumnType::TSVector(_)))
+            .map(|column| {
+                (
+                    column.field.clone(),
+                    column.column_type.clone(),
+                    column.is_list(),
+                )
+            })
+            .collect();
+        Columns { fields }
+    }
+}
+
+/// Writes the entities of one entity type into a file
+enum EntityWriter {
+    Csv(BufWriter<File>),
+    Jsonl(BufWriter<File>),
+    Parquet(ArrowWriter<File>, Arc<ArrowSchema>),
+}
+
+impl EntityWriter {
+    fn new(format: Format, path: &Path, columns: &Columns) -> Result<Self, anyhow::Error> {
+        let file = File::create(path)?;
+        match format {
+            Format::Csv => {
+                let mut out = BufWriter::new(file);
+                let header: Vec<_> = columns
+                    .fields
+                    .iter()
+                    .map(|(field, _, _)| csv_escape(field))
+                    .collect();
+                writeln!(out, "{}", header.join(","))?;
+                Ok(EntityWriter::Csv(out))
+            }
+            Format::Jsonl => Ok(EntityWriter::Jsonl(BufWriter::new(file))),
+            Format::Parquet => {
+                let fields = columns
+                    .fields
+                    .iter()
+                    .map(|(field, column_type, is_list)| {
+                        Field::new(field, arrow_type(column_type, *is_list), true)
+                    })
+                    .collect();
+                let schema = Arc::new(ArrowSchema::new(fields));
+                let writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+                Ok(EntityWriter::Parquet(writer, schema))
+            }
+        }
+    }
+
+    fn write(&mut self, columns: &Columns, entities: Vec<Entity>) -> Result<(), anyhow::Error> {
+        match self {
+            EntityWriter::Csv(out) => {
+                for entity in entities {
+                    let row: Vec<_> = columns
+                        .fields
+                        .iter()
+                        .map(|(field, _, _)| match entity.get(field) {
+                            None | Some(Value::Null) => String::new(),
+                            Some(value) => csv_escape(&value_to_string(value)),
+                        })
+                        .collect();
+                    writeln!(out, "{}", row.join(","))?;
+                }
+            }
+            EntityWriter::Jsonl(out) => {
+                for entity in entities {
+                    let object: Object = columns
+                        .fields
+                        .iter()
+                        .map(|(field, _, _)| {
+                            let value = entity.get(field).cloned().unwrap_or(Value::Null);
+                            (field.clone(), r::Value::from(value))
+                        })
+                        .collect();
+                    serde_json::to_writer(&mut *out, &r::Value::Object(object))?;
+                    writeln!(out)?;
+                }
+            }
+            EntityWriter::Parquet(writer, schema) => {
+                if entities.is_empty() {
+                    return Ok(());
+                }
+                let arrays = columns
+                    .fields
+                    .iter()
+                    .map(|(field, column_type, is_list)| {
+                        let values = entities.iter().map(|entity| match entity.get(field) {
+                            None | Some(Value::Null) => None,
+                            Some(value) => Some(value),
+                        });
+                        let array: ArrayRef = match arrow_type(column_type, *is_list) {
+                            DataType::Boolean => Arc::new(BooleanArray::from(
+                                values
+                                    .map(|value| match value {
+                                        Some(Value::Bool(b)) => Some(*b),
+                                        _ => None,
+                                    })
+                                    .collect::<Vec<_>>(),
+                            )),
+                            DataType::Int32 => Arc::new(Int32Array::from(
+                                values
+                                    .map(|value| match value {
+                                        Some(Value::Int(i)) => Some(*i),
+                                        _ => None,
+                                    })
+                                    .collect::<Vec<_>>(),
+                            )),
+                            DataType::Int64 => Arc::new(Int64Array::from(
+                                values
+                                    .map(|value| match value {
+                                        Some(Value::Int8(i)) => Some(*i),
+                                        _ => None,
+                                    })
+                                    .collect::<Vec<_>>(),
+                            )),
+                            _ => Arc::new(StringArray::from(
+                                values
+                                    .map(|value| value.map(value_to_string))
+                                    .collect::<Vec<_>>(),
+                            )),
+                        };
+                        array
+                    })
+                    .collect();
+                writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), anyhow::Error> {
+        match self {
+            EntityWriter::Csv(mut out) | EntityWriter::Jsonl(mut out) => out.flush()?,
+            EntityWriter::Parquet(writer, _) => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn arrow_type(column_type: &ColumnType, is_list: bool) -> DataType {
+    match (column_type, is_list) {
+        (ColumnType::Boolean, false) => DataType::Boolean,
+        (ColumnType::Int, false) => DataType::Int32,
+        (ColumnType::Int8, false) => DataType::Int64,
+        _ => DataType::Utf8,
+    }
+}
+
+/// Format `value` the way the GraphQL API does; lists are written as JSON
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::List(_) => serde_json::to_string(&r::Value::from(value.clone())).unwrap(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Quote `s` if necessary. Empty strings are always quoted so that they
+/// can be told apart from nulls, which are written as an empty field
+pub(crate) fn csv_escape(s: &str) -> String {
+    if s.is_empty() || s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Look up the hash of block `number` in the block cache of `network`
+fn block_hash(
+    store: &Store,
+    network: &str,
+    number: BlockNumber,
+) -> Result<BlockHash, anyhow::Error> {
+    let chain_store = store
+        .block_store()
+        .chain_store(network)
+        .ok_or_else(|| anyhow!("could not find chain store for network {}", network))?;
+    let mut hashes = chain_store.block_hashes_by_block_number(number)?;
+    match hashes.len() {
+        0 => bail!("could not find a block with number {} in our cache", number),
+        1 => Ok(hashes.pop().unwrap()),
+        n => bail!(
+            "the cache contains {} hashes for block number {}",
+            n,
+            number
+        ),
+    }
+}
+
+pub fn run(
+    store: Arc<Store>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    block: Option<BlockNumber>,
+    format: Format,
+    output: PathBuf,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary_pool)?;
+    let site = catalog::Connection::new(primary_pool.get()?)
+        .locate_site(locator.clone())?
+        .ok_or_else(|| anyhow!("failed to locate site for {locator}"))?;
+    let subgraph_store = store.subgraph_store();
+    fs::create_dir_all(&output)?;
+
+    let schema = subgraph_store.input_schema(&locator.hash)?;
+    fs::write(
+        output.join(Metadata::SCHEMA_FILE),
+        schema.document.to_string(),
+    )?;
+
+    println!("Dumping deployment {} to {}", locator, output.display());
+    let start = Instant::now();
+
+    // The entity type that is currently being written, with its writer
+    let mut current: Option<(String, Columns, EntityWriter, usize)> = None;
+    let finish = |current: Option<(String, Columns, EntityWriter, usize)>| {
+        if let Some((name, _, writer, count)) = current {
+            writer.finish()?;
+            println!("  {:<40} {:>10} entities", name, count);
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let block = subgraph_store.dump(&locator, block, BATCH_SIZE, &mut |table, entities| {
+        let name = table.object.as_str();
+        if current.as_ref().map(|(current, ..)| current.as_str()) != Some(name) {
+            finish(current.take())?;
+            let columns = Columns::new(table);
+            let path = output.join(format!("{}.{}", name, format.extension()));
+            let writer = EntityWriter::new(format, &path, &columns)?;
+            current = Some((name.to_string(), columns, writer, 0));
+        }
+        // Unwrap: we just made sure that `current` is set
+        let (_, columns, writer, count) = current.as_mut().unwrap();
+        *count += entities.len();
+        writer.write(columns, entities)
+    })?;
+    finish(current.take())?;
+
+    let deployment = subgraph_store.load_deployment(&locator)?;
+    let block_hash = match deployment.latest_block {
+        Some(ptr) if ptr.number == block => ptr.hash,
+        _ => block_hash(&store, &site.network, block)?,
+    };
+    let metadata = Metadata {
+        deployment: locator.hash.to_string(),
+        network: site.network,
+        block_number: block,
+        block_hash: block_hash.to_string(),
+        spec_version: deployment.manifest.spec_version,
+        description: deployment.manifest.description,
+        repository: deployment.manifest.repository,
+        features: deployment.manifest.features,
+        dynamic_data_sources: subgraph_store.dynamic_data_source_count(&locator, block)?,
+    };
+    let file = File::create(output.join(Metadata::FILE))?;
+    serde_json::to_writer_pretty(file, &metadata)?;
+
+    println!(
+        "Dumped entities as of block {} in {}s",
+        block,
+        start.elapsed().as_secs()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use graph::entity;
+    use graph::prelude::{Entity, Value};
+    use graph_store_postgres::command_support::ColumnType;
+    use parquet::basic::Type as PhysicalType;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use super::{csv_escape, Columns, EntityWriter, Format};
+    use crate::manager::commands::restore::{csv_entities, jsonl_entities, Fields};
+
+    fn columns() -> Columns {
+        Columns {
+            fields: vec![
+                ("id".to_string(), ColumnType::String, false),
+                ("name".to_string(), ColumnType::String, false),
+                ("count".to_string(), ColumnType::Int, false),
+                ("tags".to_string(), ColumnType::String, true),
+            ],
+        }
+    }
+
+    fn entities() -> Vec<Entity> {
+        vec![
+            entity! { id: "1", name: "a \"quoted\",\r\nmultiline name", count: 1, tags: vec!["a,b", ""] },
+            entity! { id: "2", name: "", count: -7, tags: Vec::<String>::new() },
+            entity! { id: "3" },
+        ]
+    }
+
+    /// Write `entities` in `format` to a temporary file and return its path
+    fn dump(format: Format, test: &str, entities: Vec<Entity>) -> PathBuf {
+        let columns = columns();
+        let path = std::env::temp_dir().join(format!(
+            "graphman-{}-{}.{}",
+            test,
+            std::process::id(),
+            format.extension()
+        ));
+        let mut writer = EntityWriter::new(format, &path, &columns).unwrap();
+        writer.write(&columns, entities).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn format_from_str() {
+        assert_eq!(Format::Csv, Format::from_str("csv").unwrap());
+        assert_eq!(Format::Jsonl, Format::from_str("jsonl").unwrap());
+        assert_eq!(Format::Parquet, Format::from_str("parquet").unwrap());
+        assert!(Format::from_str("xml").is_err());
+    }
+
+    #[test]
+    fn csv_escape_quotes_when_needed() {
+        assert_eq!("plain", csv_escape("plain"));
+        assert_eq!("\"\"", csv_escape(""));
+        assert_eq!("\"a,b\"", csv_escape("a,b"));
+        assert_eq!("\"say \"\"hi\"\"\"", csv_escape("say \"hi\""));
+        assert_eq!("\"a\r\nb\"", csv_escape("a\r\nb"));
+    }
+
+    #[test]
+    fn dump_and_restore_round_trip() {
+        let columns = columns();
+        let fields: Fields = columns
+            .fields
+            .iter()
+            .map(|(field, column_type, is_list)| (field.clone(), (column_type.clone(), *is_list)))
+            .collect();
+        let entities = entities();
+
+        for format in [Format::Csv, Format::Jsonl] {
+            let path = dump(format, "round-trip", entities.clone());
+            let restored = match format {
+                Format::Csv => csv_entities(&path, fields.clone()),
+                _ => jsonl_entities(&path, fields.clone()),
+            }
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(entities.len(), restored.len(), "{:?}", format);
+            for (entity, restored) in entities.iter().zip(restored.iter()) {
+                for (field, _, _) in &columns.fields {
+                    assert_eq!(
+                        entity.get(field).unwrap_or(&Value::Null),
+                        restored.get(field).unwrap_or(&Value::Null),
+                        "{:?}: field `{}` of entity {:?}",
+                        format,
+                        field,
+                        entity.get("id")
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dump_parquet() {
+        let path = dump(Format::Parquet, "parquet", entities());
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        let columns: Vec<_> = metadata
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|column| (column.name().to_string(), column.physical_type()))
+            .collect();
+        let num_rows = metadata.num_rows();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(3, num_rows);
+        assert_eq!(
+            vec![
+                ("id".to_string(), PhysicalType::BYTE_ARRAY),
+                ("name".to_string(), PhysicalType::BYTE_ARRAY),
+                ("count".to_string(), PhysicalType::INT32),
+                ("tags".to_string(), PhysicalType::BYTE_ARRAY),
+            ],
+            columns
+        );
+    }
+}
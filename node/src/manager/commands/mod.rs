@@ -4,11 +4,14 @@ pub mod check_blocks;
 pub mod config;
 pub mod copy;
 pub mod create;
+pub mod dump;
 pub mod index;
 pub mod info;
 pub mod listen;
+pub mod prune;
 pub mod query;
 pub mod remove;
+pub mod restore;
 pub mod rewind;
 pub mod run;
 pub mod stats;
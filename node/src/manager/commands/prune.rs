@@ -0,0 +1,49 @@
+use std::{sync::Arc, time::Instant};
+
+use graph::prelude::{anyhow, BlockNumber};
+use graph_store_postgres::{connection_pool::ConnectionPool, SubgraphStore};
+
+use crate::manager::deployment::DeploymentSearch;
+
+pub fn run(
+    store: Arc<SubgraphStore>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    history: Option<BlockNumber>,
+    reorg_threshold: BlockNumber,
+) -> Result<(), anyhow::Error> {
+    if let Some(history) = history {
+        // Reverting a block needs all entity versions that were visible
+        // at its parent; we must therefore keep at least as many blocks
+        // as can possibly be reverted
+        if history < reorg_threshold {
+            anyhow::bail!(
+                "the deployment must keep at least {} blocks of history, \
+                 the reorg threshold, but only {} were requested",
+                reorg_threshold,
+                history
+            );
+        }
+    }
+
+    let locator = search.locate_unique(&primary_pool)?;
+    println!("Pruning deployment {}", locator);
+
+    let start = Instant::now();
+    let (earliest_block, removed) = store.prune(&locator, history)?;
+    if removed == 0 {
+        println!(
+            "Nothing to prune; the deployment can be queried starting at block {}",
+            earliest_block
+        );
+    } else {
+        println!(
+            "Removed {} entity versions in {}s; the deployment can now be queried \
+             starting at block {}",
+            removed,
+            start.elapsed().as_secs(),
+            earliest_block
+        );
+    }
+    Ok(())
+}
@@ -19,6 +19,7 @@ pub async fn run(
     target: String,
     query: String,
     vars: Vec<String>,
+    explain: bool,
 ) -> Result<(), anyhow::Error> {
     let target = if target.starts_with("Qm") {
         let id =
@@ -49,10 +50,11 @@ pub async fn run(
             }
         })
         .collect::<Result<_, _>>()?;
-    let query = Query::new(
+    let mut query = Query::new(
         document,
         Some(QueryVariables::new(HashMap::from_iter(vars))),
     );
+    query.trace = explain;
 
     let res = runner.run_query(query, target).await;
     let json = serde_json::to_string(&res)?;
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use csv_core::{ReadFieldResult, Terminator};
+use graph::data::store::scalar;
+use graph::data::subgraph::schema::{DeploymentCreate, SubgraphManifestEntity};
+use graph::prelude::{
+    anyhow::{self, anyhow, bail},
+    serde_json, BlockHash, BlockPtr, DeploymentHash, Entity, NodeId, Schema, SubgraphName, Value,
+};
+use graph_store_postgres::command_support::{ColumnType, EntitySource, Table};
+use graph_store_postgres::SubgraphStore;
+
+use crate::manager::commands::dump::Metadata;
+
+/// The type and list-ness of the fields of an entity type that can be
+/// restored, i.e., everything but fulltext fields
+pub(crate) type Fields = HashMap<String, (ColumnType, bool)>;
+
+fn fields(table: &Table) -> Fields {
+    table
+        .columns
+        .iter()
+        .filter(|column| !column.is_fulltext())
+        .map(|column| {
+            (
+                column.field.clone(),
+                (column.column_type.clone(), column.is_list()),
+            )
+        })
+        .collect()
+}
+
+/// Convert a value as it was written by `graphman dump` back into a
+/// `Value` of type `column_type`
+fn parse_scalar(column_type: &ColumnType, s: &str) -> Result<Value, anyhow::Error> {
+    let value = match column_type {
+        ColumnType::Boolean => Value::Bool(bool::from_str(s)?),
+        ColumnType::BigDecimal => Value::BigDecimal(scalar::BigDecimal::from_str(s)?),
+        ColumnType::BigInt => Value::BigInt(scalar::BigInt::from_str(s)?),
+        ColumnType::Bytes => Value::Bytes(scalar::Bytes::from_str(s)?),
+        ColumnType::Int => Value::Int(i32::from_str(s)?),
+        ColumnType::Int8 => Value::Int8(i64::from_str(s)?),
+        ColumnType::Timestamp => Value::Timestamp(scalar::Timestamp::from_str(s)?),
+        ColumnType::String | ColumnType::Enum(_) => Value::String(s.to_string()),
+        ColumnType::TSVector(_) => bail!("fulltext fields can not be restored"),
+    };
+    Ok(value)
+}
+
+fn parse_json(
+    column_type: &ColumnType,
+    is_list: bool,
+    json: serde_json::Value,
+) -> Result<Value, anyhow::Error> {
+    use serde_json::Value as J;
+
+    match json {
+        J::Null => Ok(Value::Null),
+        J::String(s) => parse_scalar(column_type, &s),
+        J::Array(values) if is_list => values
+            .into_iter()
+            .map(|value| parse_json(column_type, false, value))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        J::Array(_) | J::Object(_) => bail!("unexpected value {} for a scalar field", json),
+        json => parse_scalar(column_type, &json.to_string()),
+    }
+}
+
+/// A reader for the CSV files that `graphman dump` writes. Fields are
+/// returned as `None` if they are empty and unquoted, which is how nulls
+/// are written. Since the CSV parser does not report whether a field was
+/// quoted, we look at the first byte of each field ourselves
+struct CsvReader {
+    input: BufReader<File>,
+    parser: csv_core::Reader,
+}
+
+impl CsvReader {
+    fn new(path: &Path) -> Result<Self, anyhow::Error> {
+        let input = BufReader::new(File::open(path)?);
+        // `graphman dump` ends records with `\n`; a `\r` can only appear
+        // inside a quoted field
+        let parser = csv_core::ReaderBuilder::new()
+            .terminator(Terminator::Any(b'\n'))
+            .build();
+        Ok(CsvReader { input, parser })
+    }
+
+    fn record(&mut self) -> Result<Option<Vec<Option<String>>>, anyhow::Error> {
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        let mut quoted = None;
+        let mut output = [0u8; 4096];
+        loop {
+            let input = self.input.fill_buf()?;
+            if quoted.is_none() {
+                quoted = input.first().map(|b| *b == b'"');
+            }
+            let (result, nin, nout) = self.parser.read_field(input, &mut output);
+            self.input.consume(nin);
+            field.extend_from_slice(&output[..nout]);
+            match result {
+                ReadFieldResult::InputEmpty | ReadFieldResult::OutputFull => {}
+                ReadFieldResult::Field { record_end } => {
+                    let value = String::from_utf8(std::mem::take(&mut field))?;
+                    let quoted = quoted.take().unwrap_or(false);
+                    fields.push(if quoted || !value.is_empty() {
+                        Some(value)
+                    } else {
+                        None
+                    });
+                    if record_end {
+                        return Ok(Some(fields));
+                    }
+                }
+                ReadFieldResult::End => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Iterator for CsvReader {
+    type Item = Result<Vec<Option<String>>, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.record().transpose()
+    }
+}
+
+/// Read the entities in `path`, which must be a CSV file written by
+/// `graphman dump`
+pub(crate) fn csv_entities(path: &Path, fields: Fields) -> Result<EntitySource, anyhow::Error> {
+    let mut reader = CsvReader::new(path)?;
+    let header = match reader.next().transpose()? {
+        Some(header) => header
+            .into_iter()
+            .map(|name| {
+                let name = name.unwrap_or_default();
+                fields
+                    .get(&name)
+                    .cloned()
+                    .map(|field| (name.clone(), field))
+                    .ok_or_else(|| anyhow!("unknown field `{}` in {}", name, path.display()))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![],
+    };
+
+    let entities = reader.map(move |record| {
+        let record = record?;
+        if record.len() != header.len() {
+            bail!(
+                "expected {} fields but found {}",
+                header.len(),
+                record.len()
+            );
+        }
+        let mut entity = Entity::new();
+        for ((name, (column_type, is_list)), value) in header.iter().zip(record) {
+            let value = match value {
+                None => Value::Null,
+                Some(value) if *is_list => {
+                    parse_json(column_type, true, serde_json::from_str(&value)?)?
+                }
+                Some(value) => parse_scalar(column_type, &value)?,
+            };
+            entity.insert(name.clone(), value);
+        }
+        Ok(entity)
+    });
+    Ok(Box::new(entities))
+}
+
+/// Read the entities in `path`, which must be a JSONL file written by
+/// `graphman dump`
+pub(crate) fn jsonl_entities(path: &Path, fields: Fields) -> Result<EntitySource, anyhow::Error> {
+    let lines = BufReader::new(File::open(path)?).lines();
+    let entities = lines.map(move |line| {
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line?)?;
+        let mut entity = Entity::new();
+        for (name, json) in object {
+            let (column_type, is_list) = fields
+                .get(&name)
+                .ok_or_else(|| anyhow!("unknown field `{}`", name))?;
+            entity.insert(name, parse_json(column_type, *is_list, json)?);
+        }
+        Ok(entity)
+    });
+    Ok(Box::new(entities))
+}
+
+pub fn run(
+    store: Arc<SubgraphStore>,
+    directory: PathBuf,
+    name: String,
+    node: String,
+) -> Result<(), anyhow::Error> {
+    let name = SubgraphName::new(name.clone())
+        .map_err(|()| anyhow!("illegal subgraph name `{}`", name))?;
+    let node = NodeId::new(node.clone()).map_err(|()| anyhow!("illegal node id `{}`", node))?;
+
+    let metadata: Metadata = serde_json::from_reader(File::open(directory.join(Metadata::FILE))?)?;
+    // Dumps do not contain dynamic data sources; a deployment restored
+    // without them would silently miss all events for them
+    if metadata.dynamic_data_sources > 0 {
+        bail!(
+            "deployment {} had created {} dynamic data sources by block {}; \
+             deployments that use templates can not be restored",
+            metadata.deployment,
+            metadata.dynamic_data_sources,
+            metadata.block_number
+        );
+    }
+    let hash = DeploymentHash::new(metadata.deployment.clone())
+        .map_err(|s| anyhow!("illegal deployment hash `{}`", s))?;
+    let head = BlockPtr::new(
+        BlockHash::from_str(&metadata.block_hash)?,
+        metadata.block_number,
+    );
+
+    let raw_schema = fs::read_to_string(directory.join(Metadata::SCHEMA_FILE))?;
+    let schema = Schema::parse(&raw_schema, hash.clone())?;
+
+    // The deployment starts at the snapshot block and can't be queried for
+    // any blocks before it
+    let deployment = DeploymentCreate {
+        manifest: SubgraphManifestEntity {
+            spec_version: metadata.spec_version,
+            description: metadata.description,
+            repository: metadata.repository,
+            features: metadata.features,
+            schema: raw_schema,
+        },
+        earliest_block: Some(head.clone()),
+        graft_base: None,
+        graft_block: None,
+        debug_fork: None,
+    };
+
+    println!(
+        "Restoring deployment {} at block {} from {}",
+        hash,
+        head.number,
+        directory.display()
+    );
+    let start = Instant::now();
+
+    let (locator, count) = store.restore(
+        name,
+        &schema,
+        deployment,
+        node,
+        metadata.network,
+        head,
+        &mut |table| {
+            let entity_type = table.object.as_str();
+            let csv = directory.join(format!("{}.csv", entity_type));
+            let jsonl = directory.join(format!("{}.jsonl", entity_type));
+            let entities = if csv.exists() {
+                csv_entities(&csv, fields(table))?
+            } else if jsonl.exists() {
+                jsonl_entities(&jsonl, fields(table))?
+            } else {
+                bail!(
+                    "could not find {}.csv or {}.jsonl in {}",
+                    entity_type,
+                    entity_type,
+                    directory.display()
+                )
+            };
+            println!("  {}", entity_type);
+            Ok(entities)
+        },
+    )?;
+
+    println!(
+        "Restored {} entities into {} in {}s",
+        count,
+        locator,
+        start.elapsed().as_secs()
+    );
+    Ok(())
+}
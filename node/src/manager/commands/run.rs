@@ -142,6 +142,7 @@ pub async fn run(
     blockchain_map.insert(network_name.clone(), Arc::new(chain));
 
     let static_filters = ENV_VARS.experimental_static_filters;
+    let parallel_triggers = ENV_VARS.subgraph_parallel_triggers;
 
     let blockchain_map = Arc::new(blockchain_map);
     let subgraph_instance_manager = SubgraphInstanceManager::new(
@@ -151,6 +152,7 @@ pub async fn run(
         metrics_registry.clone(),
         link_resolver.cheap_clone(),
         static_filters,
+        parallel_triggers,
     );
 
     // Create IPFS-based subgraph provider
@@ -356,7 +358,7 @@ async fn create_ethereum_networks(
 
         for provider in &chain.providers {
             if let ProviderDetails::Web3(web3) = &provider.details {
-                let capabilities = web3.node_capabilities();
+                let mut capabilities = web3.node_capabilities();
 
                 let logger = logger.new(o!("provider" => provider.label.clone()));
                 info!(
@@ -376,20 +378,24 @@ async fn create_ethereum_networks(
 
                 let supports_eip_1898 = !web3.features.contains("no_eip1898");
 
+                let adapter = Arc::new(
+                    graph_chain_ethereum::EthereumAdapter::new(
+                        logger,
+                        provider.label.clone(),
+                        &web3.url,
+                        transport,
+                        eth_rpc_metrics.clone(),
+                        supports_eip_1898,
+                        web3.batch_size,
+                    )
+                    .await,
+                );
+                capabilities.block_receipts = adapter.supports_block_receipts();
+
                 parsed_networks.insert(
                     name.to_string(),
                     capabilities,
-                    Arc::new(
-                        graph_chain_ethereum::EthereumAdapter::new(
-                            logger,
-                            provider.label.clone(),
-                            &web3.url,
-                            transport,
-                            eth_rpc_metrics.clone(),
-                            supports_eip_1898,
-                        )
-                        .await,
-                    ),
+                    adapter,
                     web3.limit_for(&config.node),
                 );
             }
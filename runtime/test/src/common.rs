@@ -57,6 +57,7 @@ fn mock_host_exports(
         &data_source,
         network,
         Arc::new(templates),
+        Arc::new(vec![]),
         Arc::new(graph_core::LinkResolver::new(
             vec![IpfsClient::localhost()],
             Arc::new(EnvVars::default()),
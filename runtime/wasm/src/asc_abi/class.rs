@@ -408,6 +408,12 @@ impl From<EnumPayload> for i32 {
     }
 }
 
+impl From<EnumPayload> for i64 {
+    fn from(payload: EnumPayload) -> i64 {
+        payload.0 as i64
+    }
+}
+
 impl From<EnumPayload> for f64 {
     fn from(payload: EnumPayload) -> f64 {
         f64::from_bits(payload.0)
@@ -532,6 +538,8 @@ pub enum StoreValueKind {
     Null,
     Bytes,
     BigInt,
+    Int8,
+    Timestamp,
 }
 
 impl StoreValueKind {
@@ -547,6 +555,8 @@ impl StoreValueKind {
             Value::Null => StoreValueKind::Null,
             Value::Bytes(_) => StoreValueKind::Bytes,
             Value::BigInt(_) => StoreValueKind::BigInt,
+            Value::Int8(_) => StoreValueKind::Int8,
+            Value::Timestamp(_) => StoreValueKind::Timestamp,
         }
     }
 }
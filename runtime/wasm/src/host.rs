@@ -10,11 +10,12 @@ use graph::blockchain::{Blockchain, DataSource};
 use graph::blockchain::{HostFn, TriggerWithHandler};
 use graph::components::store::{EnsLookup, SubgraphFork};
 use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
+use graph::data_source::offchain;
 use graph::prelude::{
     RuntimeHost as RuntimeHostTrait, RuntimeHostBuilder as RuntimeHostBuilderTrait, *,
 };
 
-use crate::mapping::{MappingContext, MappingRequest};
+use crate::mapping::{MappingContext, MappingRequest, MappingTrigger};
 use crate::{host_exports::HostExports, module::ExperimentalFeatures};
 use graph::runtime::gas::Gas;
 
@@ -48,6 +49,7 @@ impl<C: Blockchain> RuntimeHostBuilder<C> {
     }
 }
 
+#[async_trait]
 impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C> {
     type Host = RuntimeHost<C>;
     type Req = MappingRequest<C>;
@@ -78,6 +80,7 @@ impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C> {
         subgraph_id: DeploymentHash,
         data_source: C::DataSource,
         templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
         mapping_request_sender: Sender<MappingRequest<C>>,
         metrics: Arc<HostMetrics>,
     ) -> Result<Self::Host, Error> {
@@ -88,16 +91,74 @@ impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C> {
             subgraph_id,
             data_source,
             templates,
+            offchain_templates,
             mapping_request_sender,
             metrics,
             self.ens_lookup.cheap_clone(),
         )
     }
+
+    fn build_offchain(
+        &self,
+        network_name: String,
+        subgraph_id: DeploymentHash,
+        data_source: offchain::DataSource,
+        templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
+        mapping_request_sender: Sender<MappingRequest<C>>,
+        metrics: Arc<HostMetrics>,
+    ) -> Result<Self::Host, Error> {
+        let host_exports = Arc::new(HostExports::new_offchain(
+            subgraph_id,
+            &data_source,
+            network_name,
+            templates,
+            offchain_templates,
+            self.link_resolver.clone(),
+            self.ens_lookup.cheap_clone(),
+        ));
+
+        // Chain specific host functions are not available to offchain
+        // data sources
+        Ok(RuntimeHost {
+            host_fns: Arc::new(vec![]),
+            data_source: HostDataSource::Offchain(data_source),
+            mapping_request_sender,
+            host_exports,
+            metrics,
+        })
+    }
+
+    fn prefetches(&self, data_source: &C::DataSource) -> bool {
+        self.runtime_adapter.prefetches(data_source)
+    }
+
+    async fn prefetch(
+        &self,
+        logger: &Logger,
+        triggers: &[(&C::DataSource, &TriggerWithHandler<C>)],
+    ) {
+        self.runtime_adapter.prefetch(logger, triggers).await
+    }
+}
+
+enum HostDataSource<C: Blockchain> {
+    Onchain(C::DataSource),
+    Offchain(offchain::DataSource),
+}
+
+impl<C: Blockchain> HostDataSource<C> {
+    fn name(&self) -> &str {
+        match self {
+            HostDataSource::Onchain(ds) => ds.name(),
+            HostDataSource::Offchain(ds) => &ds.name,
+        }
+    }
 }
 
 pub struct RuntimeHost<C: Blockchain> {
     host_fns: Arc<Vec<HostFn>>,
-    data_source: C::DataSource,
+    data_source: HostDataSource<C>,
     mapping_request_sender: Sender<MappingRequest<C>>,
     host_exports: Arc<HostExports<C>>,
     metrics: Arc<HostMetrics>,
@@ -114,6 +175,7 @@ where
         subgraph_id: DeploymentHash,
         data_source: C::DataSource,
         templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
         mapping_request_sender: Sender<MappingRequest<C>>,
         metrics: Arc<HostMetrics>,
         ens_lookup: Arc<dyn EnsLookup>,
@@ -125,6 +187,7 @@ where
             &data_source,
             network_name,
             templates,
+            offchain_templates,
             link_resolver,
             ens_lookup,
         ));
@@ -133,7 +196,7 @@ where
 
         Ok(RuntimeHost {
             host_fns,
-            data_source,
+            data_source: HostDataSource::Onchain(data_source),
             mapping_request_sender,
             host_exports,
             metrics,
@@ -146,14 +209,22 @@ where
         &self,
         logger: &Logger,
         state: BlockState<C>,
-        trigger: TriggerWithHandler<C>,
+        trigger: MappingTrigger<C>,
         block_ptr: BlockPtr,
         proof_of_indexing: SharedProofOfIndexing,
         debug_fork: &Option<Arc<dyn SubgraphFork>>,
     ) -> Result<BlockState<C>, MappingError> {
-        let handler = trigger.handler_name().to_string();
+        let (handler, extras) = match &trigger {
+            MappingTrigger::Onchain(trigger) => {
+                (trigger.handler_name().to_string(), trigger.logging_extras())
+            }
+            MappingTrigger::Offchain { handler, trigger } => {
+                let extras: Arc<dyn slog::SendSyncRefUnwindSafeKV> =
+                    Arc::new(o! { "file" => trigger.source.link.clone() });
+                (handler.clone(), extras)
+            }
+        };
 
-        let extras = trigger.logging_extras();
         trace!(
             logger, "Start processing trigger";
             &extras,
@@ -215,7 +286,10 @@ impl<C: Blockchain> RuntimeHostTrait<C> for RuntimeHost<C> {
         block: &Arc<C::Block>,
         logger: &Logger,
     ) -> Result<Option<TriggerWithHandler<C>>, Error> {
-        self.data_source.match_and_decode(trigger, block, logger)
+        match &self.data_source {
+            HostDataSource::Onchain(ds) => ds.match_and_decode(trigger, block, logger),
+            HostDataSource::Offchain(_) => Ok(None),
+        }
     }
 
     async fn process_mapping_trigger(
@@ -230,7 +304,7 @@ impl<C: Blockchain> RuntimeHostTrait<C> for RuntimeHost<C> {
         self.send_mapping_request(
             logger,
             state,
-            trigger,
+            MappingTrigger::Onchain(trigger),
             block_ptr,
             proof_of_indexing,
             debug_fork,
@@ -238,13 +312,69 @@ impl<C: Blockchain> RuntimeHostTrait<C> for RuntimeHost<C> {
         .await
     }
 
+    fn onchain_data_source(&self) -> Option<&C::DataSource> {
+        match &self.data_source {
+            HostDataSource::Onchain(ds) => Some(ds),
+            HostDataSource::Offchain(_) => None,
+        }
+    }
+
+    fn offchain_data_source(&self) -> Option<&offchain::DataSource> {
+        match &self.data_source {
+            HostDataSource::Onchain(_) => None,
+            HostDataSource::Offchain(ds) => Some(ds),
+        }
+    }
+
+    async fn process_offchain_trigger(
+        &self,
+        logger: &Logger,
+        block_ptr: BlockPtr,
+        trigger: offchain::TriggerData,
+        state: BlockState<C>,
+        debug_fork: &Option<Arc<dyn SubgraphFork>>,
+    ) -> Result<BlockState<C>, MappingError> {
+        let handler = match &self.data_source {
+            HostDataSource::Onchain(ds) => {
+                return Err(MappingError::Unknown(anyhow!(
+                    "data source `{}` is not an offchain data source",
+                    ds.name()
+                )))
+            }
+            HostDataSource::Offchain(ds) => ds.mapping.handler.clone(),
+        };
+
+        self.send_mapping_request(
+            logger,
+            state,
+            MappingTrigger::Offchain { handler, trigger },
+            block_ptr,
+            None,
+            debug_fork,
+        )
+        .await
+    }
+
     fn creation_block_number(&self) -> Option<BlockNumber> {
-        self.data_source.creation_block()
+        match &self.data_source {
+            HostDataSource::Onchain(ds) => ds.creation_block(),
+            HostDataSource::Offchain(ds) => ds.creation_block,
+        }
+    }
+    fn entities(&self) -> &[String] {
+        match &self.data_source {
+            HostDataSource::Onchain(ds) => ds.entities(),
+            HostDataSource::Offchain(ds) => &ds.mapping.entities,
+        }
     }
 }
 
 impl<C: Blockchain> PartialEq for RuntimeHost<C> {
     fn eq(&self, other: &Self) -> bool {
-        self.data_source.is_duplicate_of(&other.data_source)
+        match (&self.data_source, &other.data_source) {
+            (HostDataSource::Onchain(a), HostDataSource::Onchain(b)) => a.is_duplicate_of(b),
+            (HostDataSource::Offchain(a), HostDataSource::Offchain(b)) => a.is_duplicate_of(b),
+            _ => false,
+        }
     }
 }
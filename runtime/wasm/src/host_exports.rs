@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
@@ -14,6 +14,7 @@ use graph::components::store::EntityType;
 use graph::components::store::{EnsLookup, EntityKey};
 use graph::components::subgraph::{CausalityRegion, ProofOfIndexingEvent, SharedProofOfIndexing};
 use graph::data::store;
+use graph::data_source::offchain;
 use graph::ensure;
 use graph::prelude::ethabi::param_type::Reader;
 use graph::prelude::ethabi::{decode, encode, Token};
@@ -67,19 +68,62 @@ pub struct HostExports<C: Blockchain> {
     /// networks but will be expanded for ipfs and the availability chain.
     causality_region: String,
     templates: Arc<Vec<C::DataSourceTemplate>>,
+    offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
+    entity_access: EntityAccess,
     pub(crate) link_resolver: Arc<dyn LinkResolver>,
     ens_lookup: Arc<dyn EnsLookup>,
 }
 
+/// The entity types a data source can access. Offchain data sources are
+/// processed whenever their file becomes available, which is not the same
+/// block on every indexer. To keep indexing deterministic, the entities of
+/// offchain data sources and those of onchain data sources are kept apart.
+enum EntityAccess {
+    /// Onchain data sources can access all entity types except the ones of
+    /// offchain data sources
+    Onchain { excluded: HashSet<String> },
+    /// Offchain data sources can only access the entity types listed in
+    /// their mapping
+    Offchain { allowed: HashSet<String> },
+}
+
+impl EntityAccess {
+    fn check(&self, entity_type: &str) -> Result<(), DeterministicHostError> {
+        match self {
+            EntityAccess::Onchain { excluded } if excluded.contains(entity_type) => {
+                Err(DeterministicHostError::from(anyhow!(
+                    "entity type `{}` belongs to offchain data sources and can not be \
+                     accessed from onchain data sources",
+                    entity_type
+                )))
+            }
+            EntityAccess::Offchain { allowed } if !allowed.contains(entity_type) => {
+                Err(DeterministicHostError::from(anyhow!(
+                    "offchain data sources can only access the entity types in their \
+                     mapping, `{}` is not one of them",
+                    entity_type
+                )))
+            }
+            EntityAccess::Onchain { .. } | EntityAccess::Offchain { .. } => Ok(()),
+        }
+    }
+}
+
 impl<C: Blockchain> HostExports<C> {
     pub fn new(
         subgraph_id: DeploymentHash,
         data_source: &impl DataSource<C>,
         data_source_network: String,
         templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
         link_resolver: Arc<dyn LinkResolver>,
         ens_lookup: Arc<dyn EnsLookup>,
     ) -> Self {
+        let excluded = offchain_templates
+            .iter()
+            .flat_map(|template| template.mapping.entities.iter().cloned())
+            .collect();
+
         Self {
             subgraph_id,
             api_version: data_source.api_version(),
@@ -89,6 +133,35 @@ impl<C: Blockchain> HostExports<C> {
             causality_region: CausalityRegion::from_network(&data_source_network),
             data_source_network,
             templates,
+            offchain_templates,
+            entity_access: EntityAccess::Onchain { excluded },
+            link_resolver,
+            ens_lookup,
+        }
+    }
+
+    pub fn new_offchain(
+        subgraph_id: DeploymentHash,
+        data_source: &offchain::DataSource,
+        data_source_network: String,
+        templates: Arc<Vec<C::DataSourceTemplate>>,
+        offchain_templates: Arc<Vec<offchain::DataSourceTemplate>>,
+        link_resolver: Arc<dyn LinkResolver>,
+        ens_lookup: Arc<dyn EnsLookup>,
+    ) -> Self {
+        Self {
+            subgraph_id,
+            api_version: data_source.mapping.api_version.clone(),
+            data_source_name: data_source.name.clone(),
+            data_source_address: data_source.source.link.as_bytes().to_owned(),
+            data_source_context: data_source.context.cheap_clone(),
+            causality_region: data_source.kind.clone(),
+            data_source_network,
+            templates,
+            offchain_templates,
+            entity_access: EntityAccess::Offchain {
+                allowed: data_source.entities(),
+            },
             link_resolver,
             ens_lookup,
         }
@@ -136,7 +209,9 @@ impl<C: Blockchain> HostExports<C> {
         data: HashMap<String, Value>,
         stopwatch: &StopwatchMetrics,
         gas: &GasCounter,
-    ) -> Result<(), anyhow::Error> {
+    ) -> Result<(), HostExportError> {
+        self.entity_access.check(&entity_type)?;
+
         let poi_section = stopwatch.start_section("host_export_store_set__proof_of_indexing");
         write_poi_event(
             proof_of_indexing,
@@ -159,7 +234,10 @@ impl<C: Blockchain> HostExports<C> {
         gas.consume_host_fn(gas::STORE_SET.with_args(complexity::Linear, (&key, &data)))?;
 
         let entity = Entity::from(data);
-        state.entity_cache.set(key.clone(), entity)?;
+        state
+            .entity_cache
+            .set(key.clone(), entity)
+            .map_err(entity_cache_error)?;
 
         Ok(())
     }
@@ -173,6 +251,8 @@ impl<C: Blockchain> HostExports<C> {
         entity_id: String,
         gas: &GasCounter,
     ) -> Result<(), HostExportError> {
+        self.entity_access.check(&entity_type)?;
+
         write_poi_event(
             proof_of_indexing,
             &ProofOfIndexingEvent::RemoveEntity {
@@ -190,7 +270,7 @@ impl<C: Blockchain> HostExports<C> {
 
         gas.consume_host_fn(gas::STORE_REMOVE.with_args(complexity::Size, &key))?;
 
-        state.entity_cache.remove(key);
+        state.entity_cache.remove(key).map_err(entity_cache_error)?;
 
         Ok(())
     }
@@ -201,7 +281,9 @@ impl<C: Blockchain> HostExports<C> {
         entity_type: String,
         entity_id: String,
         gas: &GasCounter,
-    ) -> Result<Option<Entity>, anyhow::Error> {
+    ) -> Result<Option<Entity>, HostExportError> {
+        self.entity_access.check(&entity_type)?;
+
         let store_key = EntityKey {
             subgraph_id: self.subgraph_id.clone(),
             entity_type: EntityType::new(entity_type),
@@ -619,6 +701,31 @@ impl<C: Blockchain> HostExports<C> {
             "params" => format!("{}", params.join(","))
         );
 
+        // Offchain data sources are created from their own templates
+        if let Some(template) = self
+            .offchain_templates
+            .iter()
+            .find(|template| template.name == name)
+        {
+            let data_source =
+                offchain::DataSource::from_template(template, params, context, creation_block)
+                    .map_err(DeterministicHostError::from)?;
+            state.push_created_offchain_data_source(data_source);
+            return Ok(());
+        }
+
+        // Offchain data sources are processed at a different block on every
+        // indexer, so they can not create onchain data sources
+        if let EntityAccess::Offchain { .. } = self.entity_access {
+            return Err(DeterministicHostError::from(anyhow!(
+                "Failed to create data source from name `{}`: offchain data source `{}` \
+                 can only create offchain data sources",
+                name,
+                self.data_source_name
+            ))
+            .into());
+        }
+
         // Resolve the name into the right template
         let template = self
             .templates
@@ -634,6 +741,11 @@ impl<C: Blockchain> HostExports<C> {
                     self.templates
                         .iter()
                         .map(|template| template.name())
+                        .chain(
+                            self.offchain_templates
+                                .iter()
+                                .map(|template| template.name.as_str())
+                        )
                         .collect::<Vec<_>>()
                         .join(", ")
                 )
@@ -779,6 +891,16 @@ fn string_to_h160(string: &str) -> Result<H160, DeterministicHostError> {
         .map_err(DeterministicHostError::from)
 }
 
+/// The entity cache signals deterministic failures, like attempts to
+/// modify an immutable entity, by wrapping a `DeterministicHostError`; all
+/// other errors are treated as possibly transient.
+fn entity_cache_error(e: anyhow::Error) -> HostExportError {
+    match e.downcast::<DeterministicHostError>() {
+        Ok(e) => HostExportError::from(e),
+        Err(e) => HostExportError::Unknown(e),
+    }
+}
+
 fn bytes_to_string(logger: &Logger, bytes: Vec<u8>) -> String {
     let s = String::from_utf8_lossy(&bytes);
 
@@ -5,6 +5,7 @@ use futures03::channel::oneshot::Sender;
 use graph::blockchain::{Blockchain, HostFn, TriggerWithHandler};
 use graph::components::store::SubgraphFork;
 use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
+use graph::data_source::offchain;
 use graph::prelude::*;
 use graph::runtime::gas::Gas;
 use std::collections::BTreeMap;
@@ -77,7 +78,7 @@ pub fn spawn_module<C: Blockchain>(
 fn instantiate_module_and_handle_trigger<C: Blockchain>(
     valid_module: Arc<ValidModule>,
     ctx: MappingContext<C>,
-    trigger: TriggerWithHandler<C>,
+    trigger: MappingTrigger<C>,
     host_metrics: Arc<HostMetrics>,
     timeout: Option<Duration>,
     experimental_features: ExperimentalFeatures,
@@ -99,12 +100,27 @@ fn instantiate_module_and_handle_trigger<C: Blockchain>(
     if ENV_VARS.log_trigger_data {
         debug!(logger, "trigger data: {:?}", trigger);
     }
-    module.handle_trigger(trigger)
+    match trigger {
+        MappingTrigger::Onchain(trigger) => module.handle_trigger(trigger),
+        MappingTrigger::Offchain { handler, trigger } => {
+            module.handle_offchain_trigger(&handler, &trigger.data)
+        }
+    }
+}
+
+/// What a mapping request asks the module to handle
+#[derive(Debug)]
+pub enum MappingTrigger<C: Blockchain> {
+    Onchain(TriggerWithHandler<C>),
+    Offchain {
+        handler: String,
+        trigger: offchain::TriggerData,
+    },
 }
 
 pub struct MappingRequest<C: Blockchain> {
     pub(crate) ctx: MappingContext<C>,
-    pub(crate) trigger: TriggerWithHandler<C>,
+    pub(crate) trigger: MappingTrigger<C>,
     pub(crate) result_sender: Sender<Result<(BlockState<C>, Gas), MappingError>>,
 }
 
@@ -120,11 +136,16 @@ pub struct MappingContext<C: Blockchain> {
 
 impl<C: Blockchain> MappingContext<C> {
     pub fn derive_with_empty_block_state(&self) -> Self {
+        let mut state = BlockState::new(self.state.entity_cache.store.clone(), Default::default());
+        if let Some(scope) = self.state.entity_cache.scope() {
+            state.entity_cache.set_scope(scope.cheap_clone());
+        }
+
         MappingContext {
             logger: self.logger.cheap_clone(),
             host_exports: self.host_exports.cheap_clone(),
             block_ptr: self.block_ptr.cheap_clone(),
-            state: BlockState::new(self.state.entity_cache.store.clone(), Default::default()),
+            state,
             proof_of_indexing: self.proof_of_indexing.cheap_clone(),
             host_fns: self.host_fns.cheap_clone(),
             debug_fork: self.debug_fork.cheap_clone(),
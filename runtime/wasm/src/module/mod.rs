@@ -129,6 +129,18 @@ impl<C: Blockchain> WasmInstance<C> {
         self.invoke_handler(&handler_name, asc_trigger)
     }
 
+    /// Invoke the handler of an offchain data source with the content of
+    /// its file as a `Bytes` argument
+    pub(crate) fn handle_offchain_trigger(
+        mut self,
+        handler_name: &str,
+        data: &[u8],
+    ) -> Result<(BlockState<C>, Gas), MappingError> {
+        let gas = self.gas.clone();
+        let asc_data: AscPtr<Uint8Array> = asc_new(&mut self, data, &gas)?;
+        self.invoke_handler(handler_name, asc_data)
+    }
+
     pub fn take_ctx(&mut self) -> WasmInstanceContext<C> {
         self.instance_ctx.borrow_mut().take().unwrap()
     }
@@ -3,8 +3,12 @@ use hyper::body::Bytes;
 
 use graph::components::server::query::GraphQLServerError;
 use graph::prelude::*;
+use graph_graphql::prelude::PersistedQueries;
 
-pub fn parse_graphql_request(body: &Bytes) -> Result<Query, GraphQLServerError> {
+pub fn parse_graphql_request(
+    body: &Bytes,
+    persisted: &PersistedQueries,
+) -> Result<Query, GraphQLServerError> {
     // Parse request body as JSON
     let json: serde_json::Value = serde_json::from_slice(body)
         .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
@@ -14,22 +18,13 @@ pub fn parse_graphql_request(body: &Bytes) -> Result<Query, GraphQLServerError>
         GraphQLServerError::ClientError(String::from("Request data is not an object"))
     })?;
 
-    // Ensure the JSON data has a "query" field
-    let query_value = obj.get("query").ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from(
-            "The \"query\" field is missing in request data",
-        ))
-    })?;
-
-    // Ensure the "query" field is a string
-    let query_string = query_value.as_str().ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
-    })?;
-
-    // Parse the "query" field of the JSON body
-    let document = graphql_parser::parse_query(query_string)
-        .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
-        .into_static();
+    // Ensure the "query" field is a string, if present
+    let query_string = match obj.get("query") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(query_value) => Some(query_value.as_str().ok_or_else(|| {
+            GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
+        })?),
+    };
 
     // Parse the "variables" field of the JSON body, if present
     let variables = match obj.get("variables") {
@@ -42,7 +37,47 @@ pub fn parse_graphql_request(body: &Bytes) -> Result<Query, GraphQLServerError>
         )),
     }?;
 
-    Ok(Query::new(document, variables))
+    // Clients that use persisted queries send the SHA-256 hash of the query
+    // in `extensions.persistedQuery.sha256Hash`, and either leave out the
+    // query text to look up a query we already know, or send it along to
+    // register the query under that hash
+    let hash = obj
+        .get("extensions")
+        .and_then(|extensions| extensions.get("persistedQuery"))
+        .and_then(|persisted_query| persisted_query.get("sha256Hash"))
+        .map(|hash| {
+            hash.as_str().ok_or_else(|| {
+                GraphQLServerError::ClientError(String::from(
+                    "The \"sha256Hash\" field is not a string",
+                ))
+            })
+        })
+        .transpose()?;
+
+    match (query_string, hash) {
+        (Some(query_string), None) => {
+            // Parse the "query" field of the JSON body
+            let document = graphql_parser::parse_query(query_string)
+                .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
+                .into_static();
+            Ok(Query::new(document, variables))
+        }
+        (Some(query_string), Some(hash)) => {
+            let (document, allow_listed) = persisted.register(hash, query_string)?;
+            Ok(Query::new((*document).clone(), variables).persisted(hash, allow_listed))
+        }
+        (None, Some(hash)) => {
+            let (document, allow_listed) = persisted.get(hash).ok_or_else(|| {
+                GraphQLServerError::from(QueryError::from(
+                    QueryExecutionError::PersistedQueryNotFound,
+                ))
+            })?;
+            Ok(Query::new((*document).clone(), variables).persisted(hash, allow_listed))
+        }
+        (None, None) => Err(GraphQLServerError::ClientError(String::from(
+            "The \"query\" field is missing in request data",
+        ))),
+    }
 }
 
 #[cfg(test)]
@@ -56,6 +91,8 @@ mod tests {
         prelude::*,
     };
 
+    use graph_graphql::prelude::PersistedQueries;
+
     use super::parse_graphql_request;
 
     lazy_static! {
@@ -65,33 +102,46 @@ mod tests {
 
     #[test]
     fn rejects_invalid_json() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("!@#)%"));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("!@#)%"),
+            &PersistedQueries::default(),
+        );
         request.expect_err("Should reject invalid JSON");
     }
 
     #[test]
     fn rejects_json_without_query_field() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("{}"));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{}"),
+            &PersistedQueries::default(),
+        );
         request.expect_err("Should reject JSON without query field");
     }
 
     #[test]
     fn rejects_json_with_non_string_query_field() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("{\"query\": 5}"));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{\"query\": 5}"),
+            &PersistedQueries::default(),
+        );
         request.expect_err("Should reject JSON with a non-string query field");
     }
 
     #[test]
     fn rejects_broken_queries() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("{\"query\": \"foo\"}"));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{\"query\": \"foo\"}"),
+            &PersistedQueries::default(),
+        );
         request.expect_err("Should reject broken queries");
     }
 
     #[test]
     fn accepts_valid_queries() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "{\"query\": \"{ user { name } }\"}",
-        ));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{\"query\": \"{ user { name } }\"}"),
+            &PersistedQueries::default(),
+        );
         let query = request.expect("Should accept valid queries");
         assert_eq!(
             query.document,
@@ -103,13 +153,16 @@ mod tests {
 
     #[test]
     fn accepts_null_variables() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "\
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "\
                  {\
                  \"query\": \"{ user { name } }\", \
                  \"variables\": null \
                  }",
-        ));
+            ),
+            &PersistedQueries::default(),
+        );
         let query = request.expect("Should accept null variables");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }")
@@ -121,27 +174,33 @@ mod tests {
 
     #[test]
     fn rejects_non_map_variables() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "\
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "\
                  {\
                  \"query\": \"{ user { name } }\", \
                  \"variables\": 5 \
                  }",
-        ));
+            ),
+            &PersistedQueries::default(),
+        );
         request.expect_err("Should reject non-map variables");
     }
 
     #[test]
     fn parses_variables() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "\
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "\
                  {\
                  \"query\": \"{ user { name } }\", \
                  \"variables\": { \
                  \"string\": \"s\", \"map\": {\"k\": \"v\"}, \"int\": 5 \
                  } \
                  }",
-        ));
+            ),
+            &PersistedQueries::default(),
+        );
         let query = request.expect("Should accept valid queries");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }")
@@ -164,4 +223,75 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    fn persisted_request(hash: &str, query: Option<&str>) -> hyper::body::Bytes {
+        let mut request = serde_json::json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+        });
+        if let Some(query) = query {
+            request["query"] = serde_json::Value::from(query);
+        }
+        hyper::body::Bytes::from(request.to_string())
+    }
+
+    // sha256 of "{ user { name } }"
+    const USER_HASH: &str = "52978b222d1c8b138bf928ca07288c01cd4c553a1a8fe1322526b4ef6c6f6b48";
+
+    #[test]
+    fn rejects_unknown_persisted_queries() {
+        let persisted = PersistedQueries::default();
+        let request = parse_graphql_request(&persisted_request(USER_HASH, None), &persisted);
+        let err = request.expect_err("Should reject unknown persisted queries");
+        assert!(err.to_string().contains("PersistedQueryNotFound"));
+    }
+
+    #[test]
+    fn registers_persisted_queries() {
+        let persisted = PersistedQueries::default();
+        let query = parse_graphql_request(
+            &persisted_request(USER_HASH, Some("{ user { name } }")),
+            &persisted,
+        )
+        .expect("Should register persisted queries");
+        assert_eq!(
+            query.persisted_id.as_deref().map(String::as_str),
+            Some(USER_HASH)
+        );
+        assert!(!query.allow_listed);
+
+        let query = parse_graphql_request(&persisted_request(USER_HASH, None), &persisted)
+            .expect("Should find registered persisted queries");
+        let expected_query = graphql_parser::parse_query("{ user { name } }")
+            .unwrap()
+            .into_static();
+        assert_eq!(query.document, expected_query);
+        assert!(!query.allow_listed);
+    }
+
+    #[test]
+    fn rejects_persisted_queries_with_wrong_hash() {
+        let persisted = PersistedQueries::default();
+        let request = parse_graphql_request(
+            &persisted_request(USER_HASH, Some("{ user { id } }")),
+            &persisted,
+        );
+        request.expect_err("Should reject queries that do not match their hash");
+        assert!(persisted.get(USER_HASH).is_none());
+    }
+
+    #[test]
+    fn marks_allow_listed_queries() {
+        let persisted = PersistedQueries::new(HashMap::from_iter(vec![(
+            String::from("user-name"),
+            String::from("{ user { name } }"),
+        )]))
+        .unwrap();
+        let query = parse_graphql_request(&persisted_request("user-name", None), &persisted)
+            .expect("Should accept allow-listed queries");
+        assert_eq!(
+            query.persisted_id.as_deref().map(String::as_str),
+            Some("user-name")
+        );
+        assert!(query.allow_listed);
+    }
 }
@@ -5,6 +5,7 @@ use hyper::Server;
 
 use crate::service::{GraphQLService, GraphQLServiceMetrics};
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, *};
+use graph_graphql::prelude::PersistedQueries;
 use thiserror::Error;
 
 /// Errors that may occur when starting the server.
@@ -25,6 +26,7 @@ pub struct GraphQLServer<Q> {
     logger: Logger,
     metrics: Arc<GraphQLServiceMetrics>,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
     node_id: NodeId,
 }
 
@@ -34,6 +36,7 @@ impl<Q> GraphQLServer<Q> {
         logger_factory: &LoggerFactory,
         metrics_registry: Arc<dyn MetricsRegistry>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
         node_id: NodeId,
     ) -> Self {
         let logger = logger_factory.component_logger(
@@ -49,6 +52,7 @@ impl<Q> GraphQLServer<Q> {
             logger,
             metrics,
             graphql_runner,
+            persisted_queries,
             node_id,
         }
     }
@@ -78,6 +82,7 @@ where
         // incoming queries to the query sink.
         let logger_for_service = self.logger.clone();
         let graphql_runner = self.graphql_runner.clone();
+        let persisted_queries = self.persisted_queries.clone();
         let metrics = self.metrics.clone();
         let node_id = self.node_id.clone();
         let new_service = make_service_fn(move |_| {
@@ -85,6 +90,7 @@ where
                 logger_for_service.clone(),
                 metrics.clone(),
                 graphql_runner.clone(),
+                persisted_queries.clone(),
                 ws_port,
                 node_id.clone(),
             ))
@@ -5,9 +5,11 @@ use std::task::Context;
 use std::task::Poll;
 use std::time::Instant;
 
+use graph::data::graphql::effort;
 use graph::data::query::QueryResults;
 use graph::prelude::*;
 use graph::{components::server::query::GraphQLServerError, data::query::QueryTarget};
+use graph_graphql::prelude::PersistedQueries;
 use http::header;
 use http::header::{
     ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
@@ -15,9 +17,14 @@ use http::header::{
 };
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
+use subtle::ConstantTimeEq;
 
 use crate::request::parse_graphql_request;
 
+/// The header with which administrators ask for a trace of the execution
+/// of a query
+const EXPLAIN_HEADER: &str = "X-Graph-Explain";
+
 pub struct GraphQLServiceMetrics {
     query_execution_time: Box<HistogramVec>,
 }
@@ -66,6 +73,34 @@ impl GraphQLServiceMetrics {
     }
 }
 
+/// Identify the client that sent `request` by the header configured with
+/// `GRAPH_GRAPHQL_CLIENT_ID_HEADER`
+fn client_id(request: &Request<Body>) -> Option<String> {
+    let header = ENV_VARS.graphql.client_id_header.as_ref()?;
+    request
+        .headers()
+        .get(header.as_str())
+        .and_then(|value| value.to_str().ok())
+        .and_then(effort::client_id)
+}
+
+/// Whether `request` asks for a trace of the query execution and carries
+/// the token configured with `GRAPH_GRAPHQL_EXPLAIN_TOKEN`. The token is
+/// compared in constant time so that it can not be guessed from how long
+/// the comparison takes
+fn wants_trace(request: &Request<Body>) -> bool {
+    let token = match &ENV_VARS.graphql.explain_token {
+        Some(token) => token,
+        None => return false,
+    };
+    request
+        .headers()
+        .get(EXPLAIN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| bool::from(value.as_bytes().ct_eq(token.as_bytes())))
+        .unwrap_or(false)
+}
+
 pub type GraphQLServiceResult = Result<Response<Body>, GraphQLServerError>;
 /// An asynchronous response to a GraphQL request.
 pub type GraphQLServiceResponse =
@@ -77,6 +112,7 @@ pub struct GraphQLService<Q> {
     logger: Logger,
     metrics: Arc<GraphQLServiceMetrics>,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
     ws_port: u16,
     node_id: NodeId,
 }
@@ -87,6 +123,7 @@ impl<Q> Clone for GraphQLService<Q> {
             logger: self.logger.clone(),
             metrics: self.metrics.clone(),
             graphql_runner: self.graphql_runner.clone(),
+            persisted_queries: self.persisted_queries.clone(),
             ws_port: self.ws_port,
             node_id: self.node_id.clone(),
         }
@@ -102,6 +139,7 @@ where
         logger: Logger,
         metrics: Arc<GraphQLServiceMetrics>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
         ws_port: u16,
         node_id: NodeId,
     ) -> Self {
@@ -109,6 +147,7 @@ where
             logger,
             metrics,
             graphql_runner,
+            persisted_queries,
             ws_port,
             node_id,
         }
@@ -157,7 +196,7 @@ where
             GraphQLServerError::ClientError(format!("Invalid subgraph name {:?}", subgraph_name))
         })?;
 
-        self.handle_graphql_query(subgraph_name.into(), request.into_body())
+        self.handle_graphql_query(subgraph_name.into(), request)
             .await
     }
 
@@ -170,31 +209,52 @@ where
             .map_err(|id| GraphQLServerError::ClientError(format!("Invalid subgraph id `{}`", id)));
         match res {
             Err(_) => self.handle_not_found(),
-            Ok(id) => self
-                .handle_graphql_query(id.into(), request.into_body())
-                .boxed(),
+            Ok(id) => self.handle_graphql_query(id.into(), request).boxed(),
         }
     }
 
     async fn handle_graphql_query(
         self,
         target: QueryTarget,
-        request_body: Body,
+        request: Request<Body>,
     ) -> GraphQLServiceResult {
         let service = self.clone();
         let service_metrics = self.metrics.clone();
 
         let start = Instant::now();
-        let body = hyper::body::to_bytes(request_body)
+        let client = client_id(&request);
+        let trace = wants_trace(&request);
+        let body = hyper::body::to_bytes(request.into_body())
             .map_err(|_| GraphQLServerError::InternalError("Failed to read request body".into()))
             .await?;
-        let query = parse_graphql_request(&body);
+
+        // Enforce the limits for the client before doing any work on the
+        // query. The permit needs to be held until the query has finished
+        let permit = match client {
+            Some(client) => match self.graphql_runner.load_manager().admit_client(&client) {
+                Ok(permit) => Some(permit),
+                Err(e) => {
+                    let result: QueryResults = QueryResult::from(QueryError::from(e)).into();
+                    service_metrics.observe_query(start.elapsed(), &result);
+                    let mut response = result.as_http_response();
+                    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    return Ok(response);
+                }
+            },
+            None => None,
+        };
+
+        let query = parse_graphql_request(&body, &self.persisted_queries).map(|mut query| {
+            query.trace = trace;
+            query
+        });
 
         let result = match query {
             Ok(query) => service.graphql_runner.run_query(query, target).await,
             Err(GraphQLServerError::QueryError(e)) => QueryResult::from(e).into(),
             Err(e) => return Err(e),
         };
+        drop(permit);
 
         service_metrics.observe_query(start.elapsed(), &result);
 
@@ -369,6 +429,7 @@ mod tests {
         query::{QueryResults, QueryTarget},
     };
     use graph::prelude::*;
+    use graph_graphql::prelude::PersistedQueries;
     use graph_mock::MockMetricsRegistry;
 
     use crate::test_utils;
@@ -429,7 +490,14 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id);
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            Arc::new(PersistedQueries::default()),
+            8001,
+            node_id,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -461,7 +529,14 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id);
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            Arc::new(PersistedQueries::default()),
+            8001,
+            node_id,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -8,6 +8,7 @@ use graph::data::{
     value::Object,
 };
 use graph::prelude::*;
+use graph_graphql::prelude::PersistedQueries;
 
 use graph_server_http::test_utils;
 use graph_server_http::GraphQLServer as HyperGraphQLServer;
@@ -94,7 +95,7 @@ mod test {
                 let id = USERS.clone();
                 let query_runner = Arc::new(TestGraphQlRunner);
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+                let mut server = HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, Arc::new(PersistedQueries::default()), node_id);
                 let http_server = server
                     .serve(8007, 8008)
                     .expect("Failed to start GraphQL server");
@@ -136,8 +137,13 @@ mod test {
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                Arc::new(PersistedQueries::default()),
+                node_id,
+            );
             let http_server = server
                 .serve(8002, 8003)
                 .expect("Failed to start GraphQL server");
@@ -218,8 +224,13 @@ mod test {
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                Arc::new(PersistedQueries::default()),
+                node_id,
+            );
             let http_server = server
                 .serve(8003, 8004)
                 .expect("Failed to start GraphQL server");
@@ -265,8 +276,13 @@ mod test {
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                Arc::new(PersistedQueries::default()),
+                node_id,
+            );
             let http_server = server
                 .serve(8005, 8006)
                 .expect("Failed to start GraphQL server");
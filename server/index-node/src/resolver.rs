@@ -7,6 +7,7 @@ use web3::types::Address;
 use graph::blockchain::{Blockchain, BlockchainKind, BlockchainMap};
 use graph::components::store::{BlockStore, EntityType, Store};
 use graph::data::graphql::{object, IntoValue, ObjectOrInterface, ValueMap};
+use graph::data::query::Trace;
 use graph::data::subgraph::features::detect_features;
 use graph::data::subgraph::status;
 use graph::data::value::Object;
@@ -721,8 +722,8 @@ impl<S: Store> Resolver for IndexNodeResolver<S> {
         &self,
         _: &ExecutionContext<Self>,
         _: &a::SelectionSet,
-    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
-        Ok(None)
+    ) -> Result<(Option<r::Value>, Trace), Vec<QueryExecutionError>> {
+        Ok((None, Trace::None))
     }
 
     /// Resolves a scalar value for a given scalar type.
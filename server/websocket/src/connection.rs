@@ -1,21 +1,91 @@
 use futures::sync::mpsc;
 use futures03::stream::SplitStream;
 use graphql_parser::parse_query;
-use http::StatusCode;
+use http::{HeaderValue, StatusCode};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
 use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
 
+use graph::data::graphql::effort::ClientPermit;
+use graph::data::subscription::{diff, PatchOperation};
 use graph::{data::query::QueryTarget, prelude::*};
+use graph_graphql::prelude::PersistedQueries;
+
+/// How long clients using `graphql-transport-ws` have to send their
+/// `connection_init` message before we close the connection
+const CONNECTION_INIT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The subprotocols of GraphQL over WebSocket that we support
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    /// The legacy protocol of `subscriptions-transport-ws`, see
+    /// https://github.com/apollographql/subscriptions-transport-ws/blob/master/PROTOCOL.md
+    GraphQlWs,
+    /// The protocol of `graphql-ws`, see
+    /// https://github.com/enisdenjo/graphql-ws/blob/master/PROTOCOL.md
+    GraphQlTransportWs,
+}
+
+impl Protocol {
+    /// Pick the protocol from the subprotocols that the client offers in
+    /// its `Sec-WebSocket-Protocol` header. Clients that do not ask for
+    /// `graphql-transport-ws` get the legacy protocol
+    pub(crate) fn negotiate(offered: Option<&HeaderValue>) -> Self {
+        let transport_ws = offered
+            .and_then(|offered| offered.to_str().ok())
+            .map(|offered| {
+                offered
+                    .split(',')
+                    .any(|name| name.trim() == Protocol::GraphQlTransportWs.name())
+            })
+            .unwrap_or(false);
+        if transport_ws {
+            Protocol::GraphQlTransportWs
+        } else {
+            Protocol::GraphQlWs
+        }
+    }
+
+    /// The name of the protocol in the `Sec-WebSocket-Protocol` header
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Protocol::GraphQlWs => "graphql-ws",
+            Protocol::GraphQlTransportWs => "graphql-transport-ws",
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct StartPayload {
-    query: String,
+    /// Can be left out for persisted queries that the server already knows
+    query: Option<String>,
     variables: Option<serde_json::Value>,
     operation_name: Option<String>,
+    extensions: Option<StartExtensions>,
+}
+
+/// Extensions that clients can send along with a subscription
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct StartExtensions {
+    /// Send a JSON patch against the previous result instead of the full
+    /// result for every update after the first one
+    #[serde(default)]
+    deltas: bool,
+    /// The id of a persisted query, following the same automatic persisted
+    /// queries protocol as requests over HTTP
+    persisted_query: Option<PersistedQueryExtension>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedQueryExtension {
+    sha256_hash: String,
 }
 
 /// GraphQL/WebSocket message received from a client.
@@ -34,18 +104,78 @@ enum IncomingMessage {
     Stop {
         id: String,
     },
+    Subscribe {
+        id: String,
+        payload: StartPayload,
+    },
+    Complete {
+        id: String,
+    },
+    Ping {
+        payload: Option<serde_json::Value>,
+    },
+    Pong {
+        #[allow(dead_code)]
+        payload: Option<serde_json::Value>,
+    },
 }
 
 impl IncomingMessage {
-    pub fn from_ws_message(msg: WsMessage) -> Result<Self, WsError> {
+    pub fn from_ws_message(msg: WsMessage, protocol: Protocol) -> Result<Self, WsError> {
         let text = msg.into_text()?;
-        serde_json::from_str(text.as_str()).map_err(|e| {
-            WsError::Http(http::Response::new(Some(format!(
-                "Invalid GraphQL over WebSocket message: {}: {}",
-                text, e
-            ))))
-        })
+        serde_json::from_str(text.as_str())
+            .map_err(|e| e.to_string())
+            .and_then(|msg: IncomingMessage| match msg.is_allowed(protocol) {
+                true => Ok(msg),
+                false => Err(format!("not part of the {} protocol", protocol.name())),
+            })
+            .map_err(|e| {
+                WsError::Http(http::Response::new(Some(format!(
+                    "Invalid GraphQL over WebSocket message: {}: {}",
+                    text, e
+                ))))
+            })
     }
+
+    /// Whether clients may send this message when they speak `protocol`
+    fn is_allowed(&self, protocol: Protocol) -> bool {
+        use self::IncomingMessage::*;
+
+        match self {
+            ConnectionInit { .. } => true,
+            ConnectionTerminate | Start { .. } | Stop { .. } => protocol == Protocol::GraphQlWs,
+            Subscribe { .. } | Complete { .. } | Ping { .. } | Pong { .. } => {
+                protocol == Protocol::GraphQlTransportWs
+            }
+        }
+    }
+}
+
+/// The payload of a message that carries the result of an operation
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum DataPayload {
+    Result(Arc<QueryResult>),
+    Results(QueryResults),
+    /// Only the changes to the previous result
+    Patch {
+        patch: Vec<PatchOperation>,
+    },
+}
+
+/// The payload of an `error` message
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ErrorPayload {
+    /// The legacy protocol sends a plain error message
+    Message(String),
+    /// `graphql-transport-ws` sends a list of GraphQL errors
+    Errors(Vec<ErrorMessage>),
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorMessage {
+    message: String,
 }
 
 /// GraphQL/WebSocket message to be sent to the client.
@@ -55,27 +185,95 @@ enum OutgoingMessage {
     ConnectionAck,
     Error {
         id: String,
-        payload: String,
+        payload: ErrorPayload,
     },
     Data {
         id: String,
-        payload: Arc<QueryResult>,
+        payload: DataPayload,
+    },
+    Next {
+        id: String,
+        payload: DataPayload,
     },
     Complete {
         id: String,
     },
+    Pong {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        payload: Option<serde_json::Value>,
+    },
 }
 
 impl OutgoingMessage {
-    pub fn from_query_result(id: String, result: Arc<QueryResult>) -> Self {
-        OutgoingMessage::Data {
-            id,
-            payload: result,
+    fn data(protocol: Protocol, id: String, payload: DataPayload) -> Self {
+        match protocol {
+            Protocol::GraphQlWs => OutgoingMessage::Data { id, payload },
+            Protocol::GraphQlTransportWs => OutgoingMessage::Next { id, payload },
         }
     }
 
-    pub fn from_error_string(id: String, s: String) -> Self {
-        OutgoingMessage::Error { id, payload: s }
+    pub fn from_query_result(protocol: Protocol, id: String, result: Arc<QueryResult>) -> Self {
+        Self::data(protocol, id, DataPayload::Result(result))
+    }
+
+    pub fn from_query_results(protocol: Protocol, id: String, results: QueryResults) -> Self {
+        Self::data(protocol, id, DataPayload::Results(results))
+    }
+
+    /// Turn `result` into a message that only contains the changes to
+    /// `previous`, the last result that was sent. Results with errors and
+    /// the first result are sent in full. Returns `None` if nothing changed
+    pub fn from_query_result_delta(
+        protocol: Protocol,
+        id: String,
+        previous: &mut Option<serde_json::Value>,
+        result: Arc<QueryResult>,
+    ) -> Option<Self> {
+        let value = match result.has_errors() {
+            false => serde_json::to_value(&*result).ok(),
+            true => None,
+        };
+        let msg = match (previous.as_ref(), value.as_ref()) {
+            (Some(previous), Some(value)) => {
+                let patch = diff(previous, value);
+                if patch.is_empty() {
+                    return None;
+                }
+                Self::data(protocol, id, DataPayload::Patch { patch })
+            }
+            _ => Self::from_query_result(protocol, id, result),
+        };
+        *previous = value;
+        Some(msg)
+    }
+
+    pub fn from_error_string(protocol: Protocol, id: String, s: String) -> Self {
+        let payload = match protocol {
+            Protocol::GraphQlWs => ErrorPayload::Message(s),
+            Protocol::GraphQlTransportWs => ErrorPayload::Errors(vec![ErrorMessage { message: s }]),
+        };
+        OutgoingMessage::Error { id, payload }
+    }
+
+    /// Errors that kept an operation from running. The legacy protocol
+    /// sends them as a result that only has errors
+    pub fn from_errors(protocol: Protocol, id: String, errors: Vec<QueryExecutionError>) -> Self {
+        match protocol {
+            Protocol::GraphQlWs => {
+                Self::from_query_result(protocol, id, Arc::new(QueryResult::from(errors)))
+            }
+            Protocol::GraphQlTransportWs => OutgoingMessage::Error {
+                id,
+                payload: ErrorPayload::Errors(
+                    errors
+                        .into_iter()
+                        .map(|e| ErrorMessage {
+                            message: e.to_string(),
+                        })
+                        .collect(),
+                ),
+            },
+        }
     }
 }
 
@@ -100,10 +298,29 @@ fn send_message(
 /// Helper function to send error messages.
 fn send_error_string(
     sink: &mpsc::UnboundedSender<WsMessage>,
+    protocol: Protocol,
     operation_id: String,
     error: String,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
+    send_message(
+        sink,
+        OutgoingMessage::from_error_string(protocol, operation_id, error),
+    )
+}
+
+/// Helper function to close the connection with one of the close codes
+/// that `graphql-transport-ws` uses to tell clients that they violated
+/// the protocol
+fn send_close(
+    sink: &mpsc::UnboundedSender<WsMessage>,
+    code: u16,
+    reason: String,
+) -> Result<(), WsError> {
+    let frame = CloseFrame {
+        code: CloseCode::from(code),
+        reason: reason.into(),
+    };
+    sink.unbounded_send(WsMessage::Close(Some(frame)))
         .map_err(|_| {
             let mut response = http::Response::new(None);
             *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
@@ -111,35 +328,110 @@ fn send_error_string(
         })
 }
 
+/// Whether the operation that the client wants to run is a subscription
+fn is_subscription(document: &q::Document, operation_name: Option<&str>) -> bool {
+    document.definitions.iter().any(|def| match def {
+        q::Definition::Operation(q::OperationDefinition::Subscription(subscription)) => {
+            operation_name.is_none() || subscription.name.as_deref() == operation_name
+        }
+        _ => false,
+    })
+}
+
+/// Find the query document for `payload`, either by parsing its text or by
+/// looking it up among the persisted queries. Persisted queries also
+/// return their id and whether they are on the allow-list
+fn query_document(
+    payload: &StartPayload,
+    persisted: &PersistedQueries,
+) -> Result<(q::Document, Option<(String, bool)>), String> {
+    let hash = payload
+        .extensions
+        .as_ref()
+        .and_then(|extensions| extensions.persisted_query.as_ref())
+        .map(|persisted_query| persisted_query.sha256_hash.as_str());
+
+    match (payload.query.as_deref(), hash) {
+        (Some(query), None) => parse_query(query)
+            .map(|document| (document.into_static(), None))
+            .map_err(|e| format!("Invalid query: {}: {}", query, e)),
+        (Some(query), Some(hash)) => persisted
+            .register(hash, query)
+            .map(|(document, allow_listed)| {
+                ((*document).clone(), Some((hash.to_string(), allow_listed)))
+            })
+            .map_err(|e| e.to_string()),
+        (None, Some(hash)) => persisted
+            .get(hash)
+            .map(|(document, allow_listed)| {
+                ((*document).clone(), Some((hash.to_string(), allow_listed)))
+            })
+            .ok_or_else(|| QueryExecutionError::PersistedQueryNotFound.to_string()),
+        (None, None) => Err("The \"query\" field is missing in the payload".to_string()),
+    }
+}
+
+/// An operation that a client started
+struct Operation {
+    guard: CancelGuard,
+    /// Set once the operation finished by itself, for example because it
+    /// was a query and its result has been sent
+    done: Arc<AtomicBool>,
+}
+
+impl Operation {
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+}
+
 /// Responsible for recording operation ids and stopping them.
 /// On drop, cancels all operations.
 struct Operations {
-    operations: HashMap<String, CancelGuard>,
+    protocol: Protocol,
+    operations: HashMap<String, Operation>,
     msg_sink: mpsc::UnboundedSender<WsMessage>,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(protocol: Protocol, msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
         Self {
+            protocol,
             operations: HashMap::new(),
             msg_sink,
         }
     }
 
     fn contains(&self, id: &str) -> bool {
-        self.operations.contains_key(id)
+        self.operations
+            .get(id)
+            .map_or(false, |operation| !operation.is_done())
+    }
+
+    /// The number of operations that are still running
+    fn len(&self) -> usize {
+        self.operations
+            .values()
+            .filter(|operation| !operation.is_done())
+            .count()
     }
 
-    fn insert(&mut self, id: String, guard: CancelGuard) {
-        self.operations.insert(id, guard);
+    fn insert(&mut self, id: String, guard: CancelGuard, done: Arc<AtomicBool>) {
+        // Forget about operations that finished so that clients can reuse
+        // their ids
+        self.operations.retain(|_, operation| !operation.is_done());
+        self.operations.insert(id, Operation { guard, done });
     }
 
     fn stop(&mut self, operation_id: String) -> Result<(), WsError> {
         // Remove the operation with this ID from the known operations.
-        match self.operations.remove(&operation_id) {
-            Some(stopper) => {
+        match (self.operations.remove(&operation_id), self.protocol) {
+            // The `complete` for an operation that finished by itself has
+            // already been sent
+            (Some(operation), Protocol::GraphQlWs) if operation.is_done() => Ok(()),
+            (Some(operation), Protocol::GraphQlWs) => {
                 // Cancel the subscription result stream.
-                stopper.cancel();
+                operation.guard.cancel();
 
                 // Send a GQL_COMPLETE to indicate the operation is been completed.
                 send_message(
@@ -149,11 +441,21 @@ impl Operations {
                     },
                 )
             }
-            None => send_error_string(
+            (None, Protocol::GraphQlWs) => send_error_string(
                 &self.msg_sink,
+                self.protocol,
                 operation_id.clone(),
                 format!("Unknown operation ID: {}", operation_id),
             ),
+            // With `graphql-transport-ws`, the client does not expect an
+            // answer when it completes an operation, and operations that
+            // already finished may be completed by the client, too
+            (operation, Protocol::GraphQlTransportWs) => {
+                if let Some(operation) = operation {
+                    operation.guard.cancel();
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -175,6 +477,9 @@ pub struct GraphQlConnection<Q, S> {
     graphql_runner: Arc<Q>,
     stream: WebSocketStream<S>,
     deployment: DeploymentHash,
+    client: Option<String>,
+    protocol: Protocol,
+    persisted_queries: Arc<PersistedQueries>,
 }
 
 impl<Q, S> GraphQlConnection<Q, S>
@@ -186,8 +491,11 @@ where
     pub(crate) fn new(
         logger: &Logger,
         deployment: DeploymentHash,
+        client: Option<String>,
+        protocol: Protocol,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
@@ -195,6 +503,9 @@ where
             graphql_runner,
             stream,
             deployment,
+            client,
+            protocol,
+            persisted_queries,
         }
     }
 
@@ -204,28 +515,92 @@ where
         logger: Logger,
         connection_id: String,
         deployment: DeploymentHash,
+        client: Option<String>,
+        protocol: Protocol,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
     ) -> Result<(), WsError> {
-        let mut operations = Operations::new(msg_sink.clone());
+        let mut operations = Operations::new(protocol, msg_sink.clone());
+
+        // Whether the client sent a `connection_init` message
+        let mut initialized = false;
+
+        // Whether we asked the client to close the connection; we ignore
+        // anything it sends until it does
+        let mut closing = false;
+
+        let init_deadline = tokio::time::Instant::now() + CONNECTION_INIT_TIMEOUT;
 
         // Process incoming messages as long as the WebSocket is open
-        while let Some(ws_msg) = ws_stream.try_next().await? {
+        loop {
             use self::IncomingMessage::*;
-            use self::OutgoingMessage::*;
+
+            // `graphql-transport-ws` clients have to initialize the
+            // connection within a limited time
+            let ws_msg = if protocol == Protocol::GraphQlTransportWs && !initialized && !closing {
+                match tokio::time::timeout_at(init_deadline, ws_stream.try_next()).await {
+                    Ok(ws_msg) => ws_msg?,
+                    Err(_) => {
+                        closing = true;
+                        send_close(
+                            &msg_sink,
+                            4408,
+                            "Connection initialisation timeout".to_string(),
+                        )?;
+                        continue;
+                    }
+                }
+            } else {
+                ws_stream.try_next().await?
+            };
+            let ws_msg = match ws_msg {
+                Some(ws_msg) => ws_msg,
+                None => break,
+            };
+
+            // WebSocket control frames are answered by tungstenite
+            if closing || ws_msg.is_close() || ws_msg.is_ping() || ws_msg.is_pong() {
+                continue;
+            }
 
             debug!(logger, "Received message";
                    "connection" => &connection_id,
                    "msg" => format!("{}", ws_msg).as_str());
 
-            let msg = IncomingMessage::from_ws_message(ws_msg.clone())?;
+            let msg = match IncomingMessage::from_ws_message(ws_msg.clone(), protocol) {
+                Ok(msg) => msg,
+                Err(e) if protocol == Protocol::GraphQlTransportWs => {
+                    debug!(logger, "Invalid GraphQL/WebSocket message";
+                           "connection" => &connection_id,
+                           "error" => e.to_string());
+                    closing = true;
+                    send_close(&msg_sink, 4400, "Invalid message received".to_string())?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
 
             debug!(logger, "GraphQL/WebSocket message";
                    "connection" => &connection_id,
                    "msg" => format!("{:?}", msg).as_str());
 
             match msg {
-                // Always accept connection init requests
-                ConnectionInit { payload: _ } => send_message(&msg_sink, ConnectionAck),
+                // The legacy protocol always accepts connection init requests,
+                // `graphql-transport-ws` only accepts one
+                ConnectionInit { payload: _ }
+                    if initialized && protocol == Protocol::GraphQlTransportWs =>
+                {
+                    closing = true;
+                    send_close(
+                        &msg_sink,
+                        4429,
+                        "Too many initialisation requests".to_string(),
+                    )
+                }
+                ConnectionInit { payload: _ } => {
+                    initialized = true;
+                    send_message(&msg_sink, OutgoingMessage::ConnectionAck)
+                }
 
                 // When receiving a connection termination request
                 ConnectionTerminate => {
@@ -236,153 +611,281 @@ where
                     Err(WsError::ConnectionClosed)
                 }
 
+                Ping { payload } => send_message(&msg_sink, OutgoingMessage::Pong { payload }),
+                Pong { payload: _ } => Ok(()),
+
                 // When receiving a stop request
-                Stop { id } => operations.stop(id),
+                Stop { id } | Complete { id } => operations.stop(id),
+
+                // `graphql-transport-ws` requires clients to wait for the
+                // `connection_ack` before they subscribe, and does not allow
+                // reusing the id of an operation that is still running
+                Subscribe { .. } if !initialized => {
+                    closing = true;
+                    send_close(&msg_sink, 4401, "Unauthorized".to_string())
+                }
+                Subscribe { id, .. } if operations.contains(&id) => {
+                    closing = true;
+                    send_close(
+                        &msg_sink,
+                        4409,
+                        format!("Subscriber for {} already exists", id),
+                    )
+                }
 
                 // When receiving a start request
-                Start { id, payload } => {
-                    // Respond with a GQL_ERROR if we already have an operation with this ID
-                    if operations.contains(&id) {
+                Start { id, payload } | Subscribe { id, payload } => Self::start_operation(
+                    &mut operations,
+                    &msg_sink,
+                    &logger,
+                    &connection_id,
+                    &deployment,
+                    &client,
+                    protocol,
+                    &graphql_runner,
+                    &persisted_queries,
+                    id,
+                    payload,
+                ),
+            }?
+        }
+        Ok(())
+    }
+
+    /// Start a subscription, or run a query, that a client sent over the
+    /// connection. Errors in the operation are sent to the client and do
+    /// not close the connection
+    fn start_operation(
+        operations: &mut Operations,
+        msg_sink: &mpsc::UnboundedSender<WsMessage>,
+        logger: &Logger,
+        connection_id: &str,
+        deployment: &DeploymentHash,
+        client: &Option<String>,
+        protocol: Protocol,
+        graphql_runner: &Arc<Q>,
+        persisted_queries: &PersistedQueries,
+        id: String,
+        payload: StartPayload,
+    ) -> Result<(), WsError> {
+        // Respond with a GQL_ERROR if we already have an operation with this ID
+        if operations.contains(&id) {
+            return send_error_string(
+                msg_sink,
+                protocol,
+                id.clone(),
+                format!("Operation with ID already started: {}", id),
+            );
+        }
+
+        if let Some(max_ops) = ENV_VARS.graphql.max_operations_per_connection {
+            if operations.len() >= max_ops {
+                return send_error_string(
+                    msg_sink,
+                    protocol,
+                    id,
+                    format!("Reached the limit of {} operations per connection", max_ops),
+                );
+            }
+        }
+
+        let permit: Option<ClientPermit> = match client {
+            Some(client) => match graphql_runner.load_manager().admit_client(client) {
+                Ok(permit) => Some(permit),
+                Err(e) => return send_error_string(msg_sink, protocol, id, e.to_string()),
+            },
+            None => None,
+        };
+
+        // Parse the GraphQL query document, or look up the persisted
+        // query; respond with a GQL_ERROR if the query is invalid
+        let (document, persisted) = match query_document(&payload, persisted_queries) {
+            Ok(found) => found,
+            Err(e) => return send_error_string(msg_sink, protocol, id, e),
+        };
+
+        // Parse the query variables, if present
+        let variables = match payload.variables {
+            None | Some(serde_json::Value::Null) => None,
+            Some(variables @ serde_json::Value::Object(_)) => {
+                match serde_json::from_value(variables.clone()) {
+                    Ok(variables) => Some(variables),
+                    Err(e) => {
                         return send_error_string(
-                            &msg_sink,
-                            id.clone(),
-                            format!("Operation with ID already started: {}", id),
+                            msg_sink,
+                            protocol,
+                            id,
+                            format!("Invalid variables provided: {}", e),
                         );
                     }
+                }
+            }
+            _ => {
+                return send_error_string(
+                    msg_sink,
+                    protocol,
+                    id,
+                    format!("Invalid variables provided (must be an object)"),
+                );
+            }
+        };
 
-                    if let Some(max_ops) = ENV_VARS.graphql.max_operations_per_connection {
-                        if operations.operations.len() >= max_ops {
-                            return send_error_string(
-                                &msg_sink,
-                                id,
-                                format!(
-                                    "Reached the limit of {} operations per connection",
-                                    max_ops
-                                ),
-                            );
-                        }
-                    }
+        let is_subscription = is_subscription(&document, payload.operation_name.as_deref());
+        let query = match persisted {
+            Some((persisted_id, allow_listed)) => {
+                Query::new(document, variables).persisted(persisted_id, allow_listed)
+            }
+            None => Query::new(document, variables),
+        };
 
-                    // Parse the GraphQL query document; respond with a GQL_ERROR if
-                    // the query is invalid
-                    let query = match parse_query(&payload.query) {
-                        Ok(query) => query.into_static(),
-                        Err(e) => {
-                            return send_error_string(
-                                &msg_sink,
-                                id,
-                                format!("Invalid query: {}: {}", payload.query, e),
-                            );
-                        }
-                    };
-
-                    // Parse the query variables, if present
-                    let variables = match payload.variables {
-                        None | Some(serde_json::Value::Null) => None,
-                        Some(variables @ serde_json::Value::Object(_)) => {
-                            match serde_json::from_value(variables.clone()) {
-                                Ok(variables) => Some(variables),
-                                Err(e) => {
-                                    return send_error_string(
-                                        &msg_sink,
-                                        id,
-                                        format!("Invalid variables provided: {}", e),
-                                    );
-                                }
-                            }
-                        }
-                        _ => {
-                            return send_error_string(
-                                &msg_sink,
-                                id,
-                                format!("Invalid variables provided (must be an object)"),
-                            );
-                        }
-                    };
+        let target = QueryTarget::Deployment(deployment.clone());
+        let done = Arc::new(AtomicBool::new(false));
 
-                    // Construct a subscription
-                    let target = QueryTarget::Deployment(deployment.clone());
-                    let subscription = Subscription {
-                        // Subscriptions currently do not benefit from the generational cache
-                        // anyways, so don't bother passing a network.
-                        query: Query::new(query, variables),
-                    };
+        debug!(logger, "Start operation";
+               "connection" => connection_id,
+               "id" => &id);
 
-                    debug!(logger, "Start operation";
-                           "connection" => &connection_id,
-                           "id" => &id);
-
-                    // Execute the GraphQL subscription
-                    let error_sink = msg_sink.clone();
-                    let result_sink = msg_sink.clone();
-                    let result_id = id.clone();
-                    let err_id = id.clone();
-                    let err_connection_id = connection_id.clone();
-                    let err_logger = logger.clone();
-                    let run_subscription = graphql_runner
-                        .cheap_clone()
-                        .run_subscription(subscription, target)
-                        .compat()
-                        .map_err(move |e| {
-                            debug!(err_logger, "Subscription error";
-                                               "connection" => &err_connection_id,
-                                               "id" => &err_id,
-                                               "error" => format!("{:?}", e));
-
-                            // Send errors back to the client as GQL_DATA
-                            match e {
-                                SubscriptionError::GraphQLError(e) => {
-                                    // Don't bug clients with transient `TooExpensive` errors,
-                                    // simply skip updating them
-                                    if !e
-                                        .iter()
-                                        .any(|err| matches!(err, QueryExecutionError::TooExpensive))
-                                    {
-                                        let result = Arc::new(QueryResult::from(e));
-                                        let msg = OutgoingMessage::from_query_result(
-                                            err_id.clone(),
-                                            result,
-                                        );
-
-                                        // An error means the client closed the websocket, ignore
-                                        // and let it be handled in the websocket loop above.
-                                        let _ = error_sink.unbounded_send(msg.into());
-                                    }
-                                }
-                            };
-                        })
-                        .and_then(move |result_stream| {
-                            // Send results back to the client as GQL_DATA
-                            result_stream
-                                .map(move |result| {
-                                    OutgoingMessage::from_query_result(result_id.clone(), result)
-                                })
-                                .map(WsMessage::from)
-                                .map(Ok)
-                                .compat()
-                                .forward(result_sink.sink_map_err(|_| ()))
-                                .map(|_| ())
-                        });
-
-                    // Setup cancelation.
-                    let guard = CancelGuard::new();
-                    let logger = logger.clone();
-                    let cancel_id = id.clone();
-                    let connection_id = connection_id.clone();
-                    let run_subscription =
-                        run_subscription.compat().cancelable(&guard, move || {
-                            debug!(logger, "Stopped operation";
-                                       "connection" => &connection_id,
-                                       "id" => &cancel_id);
-                            Ok(())
-                        });
-                    operations.insert(id, guard);
-
-                    graph::spawn_allow_panic(run_subscription);
-                    Ok(())
-                }
-            }?
+        // Setup cancelation.
+        let guard = CancelGuard::new();
+        let cancel_logger = logger.clone();
+        let cancel_id = id.clone();
+        let cancel_connection_id = connection_id.to_string();
+        let on_cancel = move || {
+            debug!(cancel_logger, "Stopped operation";
+                   "connection" => &cancel_connection_id,
+                   "id" => &cancel_id);
+        };
+
+        if !is_subscription {
+            // Queries count towards the client's limits until they are done,
+            // just like queries over HTTP
+            let result_sink = msg_sink.clone();
+            let result_id = id.clone();
+            let query_done = done.cheap_clone();
+            let run_query = graphql_runner
+                .cheap_clone()
+                .run_query(query, target)
+                .map(move |results| {
+                    drop(permit);
+
+                    // Mark the query as done before the client can see its
+                    // `complete` so that a `stop` the client sends in
+                    // response does not send another one
+                    query_done.store(true, Ordering::SeqCst);
+
+                    // An error means the client closed the websocket, ignore
+                    // and let it be handled in the websocket loop above.
+                    let msg =
+                        OutgoingMessage::from_query_results(protocol, result_id.clone(), results);
+                    let _ = result_sink.unbounded_send(msg.into());
+                    let msg = OutgoingMessage::Complete { id: result_id };
+                    let _ = result_sink.unbounded_send(msg.into());
+                })
+                .cancelable(&guard, on_cancel);
+            operations.insert(id, guard, done);
+
+            graph::spawn_allow_panic(run_query);
+            return Ok(());
         }
+
+        // Subscriptions count towards the client's request rate,
+        // but since they are long-lived, not towards its
+        // concurrency or effort, and we let go of the permit
+        // right away
+        drop(permit);
+
+        // Construct a subscription
+        let subscription = Subscription {
+            // Subscriptions currently do not benefit from the generational cache
+            // anyways, so don't bother passing a network.
+            query,
+        };
+
+        let deltas = payload.extensions.unwrap_or_default().deltas;
+        let mut previous = None;
+
+        // Execute the GraphQL subscription
+        let error_sink = msg_sink.clone();
+        let result_sink = msg_sink.clone();
+        let complete_sink = msg_sink.clone();
+        let result_id = id.clone();
+        let err_id = id.clone();
+        let complete_id = id.clone();
+        let err_connection_id = connection_id.to_string();
+        let err_logger = logger.clone();
+        let subscription_done = done.cheap_clone();
+        let complete_done = done.cheap_clone();
+        let run_subscription = graphql_runner
+            .cheap_clone()
+            .run_subscription(subscription, target)
+            .compat()
+            .map_err(move |e| {
+                debug!(err_logger, "Subscription error";
+                                   "connection" => &err_connection_id,
+                                   "id" => &err_id,
+                                   "error" => format!("{:?}", e));
+
+                // Send errors back to the client as GQL_DATA
+                match e {
+                    SubscriptionError::GraphQLError(e) => {
+                        // Don't bug clients with transient `TooExpensive` errors,
+                        // simply skip updating them
+                        if !e
+                            .iter()
+                            .any(|err| matches!(err, QueryExecutionError::TooExpensive))
+                        {
+                            let msg = OutgoingMessage::from_errors(protocol, err_id.clone(), e);
+
+                            // An error means the client closed the websocket, ignore
+                            // and let it be handled in the websocket loop above.
+                            let _ = error_sink.unbounded_send(msg.into());
+                        }
+                    }
+                };
+            })
+            .and_then(move |result_stream| {
+                // Send results back to the client as GQL_DATA
+                result_stream
+                    .filter_map(move |result| {
+                        let msg = if deltas {
+                            OutgoingMessage::from_query_result_delta(
+                                protocol,
+                                result_id.clone(),
+                                &mut previous,
+                                result,
+                            )
+                        } else {
+                            Some(OutgoingMessage::from_query_result(
+                                protocol,
+                                result_id.clone(),
+                                result,
+                            ))
+                        };
+                        futures03::future::ready(msg)
+                    })
+                    .map(WsMessage::from)
+                    .map(Ok)
+                    .compat()
+                    .forward(result_sink.sink_map_err(|_| ()))
+                    .map(move |_| {
+                        // The subscription ended without the client stopping it
+                        complete_done.store(true, Ordering::SeqCst);
+                        let msg = OutgoingMessage::Complete { id: complete_id };
+                        let _ = complete_sink.unbounded_send(msg.into());
+                    })
+            })
+            .then(move |result| {
+                subscription_done.store(true, Ordering::SeqCst);
+                result
+            });
+
+        let run_subscription = run_subscription.compat().cancelable(&guard, move || {
+            on_cancel();
+            Ok(())
+        });
+        operations.insert(id, guard, done);
+
+        graph::spawn_allow_panic(run_subscription);
         Ok(())
     }
 }
@@ -397,7 +900,9 @@ where
     type Error = ();
 
     fn into_future(self) -> Self::Future {
-        debug!(self.logger, "GraphQL over WebSocket connection opened"; "id" => &self.id);
+        debug!(self.logger, "GraphQL over WebSocket connection opened";
+               "id" => &self.id,
+               "protocol" => self.protocol.name());
 
         // Obtain sink/stream pair to send and receive WebSocket messages
         let (ws_sink, ws_stream) = self.stream.split();
@@ -412,7 +917,10 @@ where
             self.logger.clone(),
             self.id.clone(),
             self.deployment.clone(),
+            self.client.clone(),
+            self.protocol,
             self.graphql_runner.clone(),
+            self.persisted_queries.clone(),
         );
 
         // Send outgoing messages asynchronously
@@ -435,3 +943,350 @@ where
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures03::{SinkExt, StreamExt};
+    use graph::data::graphql::effort::LoadManager;
+    use graph::data::value::Object;
+    use graph::prelude::tokio;
+    use tokio::io::DuplexStream;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    use super::*;
+
+    /// Answers every query with `{ name: "Jordi" }`, and every subscription
+    /// with that result once. With `only_persisted`, operations that are not
+    /// on the allow-list are rejected like the real runner does for
+    /// deployments that only accept persisted queries
+    #[derive(Default)]
+    struct TestGraphQlRunner {
+        only_persisted: bool,
+    }
+
+    impl TestGraphQlRunner {
+        fn check_allow_listed(&self, query: &Query) -> Result<(), QueryExecutionError> {
+            if self.only_persisted && !query.allow_listed {
+                return Err(QueryExecutionError::PersistedQueryRequired(
+                    "test".to_string(),
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    fn test_result() -> Object {
+        Object::from_iter(vec![(
+            String::from("name"),
+            r::Value::String(String::from("Jordi")),
+        )])
+    }
+
+    #[async_trait]
+    impl GraphQlRunner for TestGraphQlRunner {
+        async fn run_query(self: Arc<Self>, query: Query, _target: QueryTarget) -> QueryResults {
+            match self.check_allow_listed(&query) {
+                Ok(()) => QueryResults::from(test_result()),
+                Err(e) => QueryResults::from(e),
+            }
+        }
+
+        async fn run_query_with_complexity(
+            self: Arc<Self>,
+            _query: Query,
+            _target: QueryTarget,
+            _complexity: Option<u64>,
+            _max_depth: Option<u8>,
+            _max_first: Option<u32>,
+            _max_skip: Option<u32>,
+        ) -> QueryResults {
+            unimplemented!();
+        }
+
+        async fn run_subscription(
+            self: Arc<Self>,
+            subscription: Subscription,
+            _target: QueryTarget,
+        ) -> Result<SubscriptionResult, SubscriptionError> {
+            self.check_allow_listed(&subscription.query)?;
+            let result = Arc::new(QueryResult::new(test_result()));
+            Ok(futures03::stream::iter(vec![result]).boxed())
+        }
+
+        fn load_manager(&self) -> Arc<LoadManager> {
+            unimplemented!()
+        }
+    }
+
+    type Client = WebSocketStream<DuplexStream>;
+
+    /// Open a connection that speaks `protocol` and return the client end
+    async fn connect(protocol: Protocol) -> Client {
+        connect_with(
+            protocol,
+            TestGraphQlRunner::default(),
+            PersistedQueries::default(),
+        )
+        .await
+    }
+
+    async fn connect_with(
+        protocol: Protocol,
+        runner: TestGraphQlRunner,
+        persisted_queries: PersistedQueries,
+    ) -> Client {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+        let connection = GraphQlConnection::new(
+            &Logger::root(slog::Discard, o!()),
+            DeploymentHash::new("test").unwrap(),
+            None,
+            protocol,
+            server,
+            Arc::new(runner),
+            Arc::new(persisted_queries),
+        );
+        graph::spawn(connection.into_future().compat());
+        client
+    }
+
+    async fn send(client: &mut Client, msg: serde_json::Value) {
+        client.send(WsMessage::text(msg.to_string())).await.unwrap();
+    }
+
+    async fn receive(client: &mut Client) -> serde_json::Value {
+        match client.next().await.unwrap().unwrap() {
+            WsMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    /// Run `query` as operation `id` and check that the client receives
+    /// the test result and then `complete`
+    async fn run_operation(client: &mut Client, protocol: Protocol, id: &str, query: &str) {
+        let payload = serde_json::json!({ "query": query });
+        run_payload(client, protocol, id, payload).await
+    }
+
+    /// Like `run_operation`, but with the full `payload` of the start message
+    async fn run_payload(
+        client: &mut Client,
+        protocol: Protocol,
+        id: &str,
+        payload: serde_json::Value,
+    ) {
+        let (start, data) = match protocol {
+            Protocol::GraphQlWs => ("start", "data"),
+            Protocol::GraphQlTransportWs => ("subscribe", "next"),
+        };
+        send(
+            client,
+            serde_json::json!({"type": start, "id": id, "payload": payload}),
+        )
+        .await;
+
+        let msg = receive(client).await;
+        assert_eq!(serde_json::json!(data), msg["type"]);
+        assert_eq!(serde_json::json!(id), msg["id"]);
+        assert_eq!(serde_json::json!({"name": "Jordi"}), msg["payload"]["data"]);
+        assert_eq!(
+            serde_json::json!({"type": "complete", "id": id}),
+            receive(client).await
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_operations() {
+        for protocol in [Protocol::GraphQlWs, Protocol::GraphQlTransportWs] {
+            let mut client = connect(protocol).await;
+
+            send(&mut client, serde_json::json!({"type": "connection_init"})).await;
+            assert_eq!(
+                serde_json::json!({"type": "connection_ack"}),
+                receive(&mut client).await
+            );
+
+            run_operation(&mut client, protocol, "1", "{ name }").await;
+            run_operation(&mut client, protocol, "2", "subscription { name }").await;
+        }
+    }
+
+    #[tokio::test]
+    async fn completes_operations_once() {
+        let mut client = connect(Protocol::GraphQlWs).await;
+        send(&mut client, serde_json::json!({"type": "connection_init"})).await;
+        receive(&mut client).await;
+
+        // Stopping an operation that is done does not send another
+        // `complete`, so the next message is the answer to whatever the
+        // client sends next
+        let stop = |id: &str| serde_json::json!({"type": "stop", "id": id});
+        run_operation(&mut client, Protocol::GraphQlWs, "1", "{ name }").await;
+        send(&mut client, stop("1")).await;
+        run_operation(
+            &mut client,
+            Protocol::GraphQlWs,
+            "2",
+            "subscription { name }",
+        )
+        .await;
+        send(&mut client, stop("2")).await;
+        send(&mut client, stop("3")).await;
+        let msg = receive(&mut client).await;
+        assert_eq!(serde_json::json!("error"), msg["type"]);
+        assert_eq!(serde_json::json!("3"), msg["id"]);
+    }
+
+    #[tokio::test]
+    async fn checks_persisted_queries() {
+        let persisted_queries = PersistedQueries::new(HashMap::from_iter(vec![(
+            "user-name".to_string(),
+            "subscription { name }".to_string(),
+        )]))
+        .unwrap();
+        let runner = TestGraphQlRunner {
+            only_persisted: true,
+        };
+        let protocol = Protocol::GraphQlTransportWs;
+        let mut client = connect_with(protocol, runner, persisted_queries).await;
+        send(&mut client, serde_json::json!({"type": "connection_init"})).await;
+        receive(&mut client).await;
+
+        // Subscriptions on the allow-list can be run by their id
+        let persisted = serde_json::json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": "user-name" } }
+        });
+        run_payload(&mut client, protocol, "1", persisted).await;
+
+        // Sending the text of the same subscription is rejected, and so are
+        // unknown persisted queries
+        let unknown = serde_json::json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": "unknown" } }
+        });
+        for (id, payload) in [
+            ("2", serde_json::json!({ "query": "subscription { name }" })),
+            ("3", unknown),
+        ] {
+            send(
+                &mut client,
+                serde_json::json!({"type": "subscribe", "id": id, "payload": payload}),
+            )
+            .await;
+            let msg = receive(&mut client).await;
+            assert_eq!(serde_json::json!("error"), msg["type"]);
+            assert_eq!(serde_json::json!(id), msg["id"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn times_out_connection_init() {
+        tokio::time::pause();
+
+        let mut client = connect(Protocol::GraphQlTransportWs).await;
+        match client.next().await.unwrap().unwrap() {
+            WsMessage::Close(Some(frame)) => assert_eq!(CloseCode::from(4408), frame.code),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn negotiates_protocol() {
+        fn negotiate(offered: Option<&'static str>) -> Protocol {
+            let offered = offered.map(HeaderValue::from_static);
+            Protocol::negotiate(offered.as_ref())
+        }
+
+        assert_eq!(Protocol::GraphQlWs, negotiate(None));
+        assert_eq!(Protocol::GraphQlWs, negotiate(Some("graphql-ws")));
+        assert_eq!(
+            Protocol::GraphQlTransportWs,
+            negotiate(Some("graphql-transport-ws"))
+        );
+        assert_eq!(
+            Protocol::GraphQlTransportWs,
+            negotiate(Some("graphql-ws, graphql-transport-ws"))
+        );
+    }
+
+    #[test]
+    fn checks_messages_against_protocol() {
+        fn parse(text: &str, protocol: Protocol) -> bool {
+            IncomingMessage::from_ws_message(WsMessage::text(text), protocol).is_ok()
+        }
+
+        let subscribe = r#"{"type": "subscribe", "id": "1", "payload": {"query": "{ a }"}}"#;
+        let start = r#"{"type": "start", "id": "1", "payload": {"query": "{ a }"}}"#;
+        let ping = r#"{"type": "ping"}"#;
+
+        assert!(parse(subscribe, Protocol::GraphQlTransportWs));
+        assert!(!parse(subscribe, Protocol::GraphQlWs));
+        assert!(parse(start, Protocol::GraphQlWs));
+        assert!(!parse(start, Protocol::GraphQlTransportWs));
+        assert!(parse(ping, Protocol::GraphQlTransportWs));
+        assert!(!parse(ping, Protocol::GraphQlWs));
+    }
+
+    #[test]
+    fn sends_errors_for_protocol() {
+        let msg = |protocol| {
+            let msg = OutgoingMessage::from_error_string(protocol, "1".to_string(), "oops".into());
+            serde_json::to_value(&msg).unwrap()
+        };
+
+        assert_eq!(
+            serde_json::json!({"type": "error", "id": "1", "payload": "oops"}),
+            msg(Protocol::GraphQlWs)
+        );
+        assert_eq!(
+            serde_json::json!({"type": "error", "id": "1", "payload": [{"message": "oops"}]}),
+            msg(Protocol::GraphQlTransportWs)
+        );
+    }
+
+    #[test]
+    fn sends_deltas() {
+        let result = |count: i64| {
+            let data = Object::from_iter(vec![("count".to_string(), r::Value::Int(count))]);
+            Arc::new(QueryResult::new(data))
+        };
+        let error = || Arc::new(QueryResult::from(QueryExecutionError::Timeout));
+        let mut previous = None;
+        let mut msg = |protocol, result| {
+            OutgoingMessage::from_query_result_delta(
+                protocol,
+                "1".to_string(),
+                &mut previous,
+                result,
+            )
+            .map(|msg| serde_json::to_value(&msg).unwrap())
+        };
+
+        // The first result is sent in full, unchanged results are not sent,
+        // and changes are sent as a patch
+        assert_eq!(
+            Some(serde_json::json!({"type": "data", "id": "1", "payload": {"data": {"count": 1}}})),
+            msg(Protocol::GraphQlWs, result(1))
+        );
+        assert_eq!(None, msg(Protocol::GraphQlWs, result(1)));
+        assert_eq!(
+            Some(serde_json::json!({
+                "type": "data",
+                "id": "1",
+                "payload": {"patch": [{"op": "replace", "path": "/data/count", "value": 2}]}
+            })),
+            msg(Protocol::GraphQlWs, result(2))
+        );
+
+        // Results with errors are sent in full, and so is the result after
+        // them
+        let sent = msg(Protocol::GraphQlTransportWs, error()).unwrap();
+        assert_eq!("next", sent["type"]);
+        assert!(sent["payload"]["errors"].is_array());
+        assert_eq!(
+            Some(serde_json::json!({"type": "next", "id": "1", "payload": {"data": {"count": 2}}})),
+            msg(Protocol::GraphQlTransportWs, result(2))
+        );
+    }
+}
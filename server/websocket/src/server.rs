@@ -1,8 +1,8 @@
 use graph::{
-    data::query::QueryTarget,
+    data::{graphql::effort, query::QueryTarget},
     prelude::{SubscriptionServer as SubscriptionServerTrait, *},
 };
-use http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
+use http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, SEC_WEBSOCKET_PROTOCOL};
 use http::{HeaderValue, Response, StatusCode};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Mutex;
@@ -10,13 +10,16 @@ use tokio::net::TcpListener;
 use tokio_tungstenite::accept_hdr_async;
 use tokio_tungstenite::tungstenite::handshake::server::Request;
 
-use crate::connection::GraphQlConnection;
+use graph_graphql::prelude::PersistedQueries;
+
+use crate::connection::{GraphQlConnection, Protocol};
 
 /// A GraphQL subscription server based on Hyper / Websockets.
 pub struct SubscriptionServer<Q, S> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    persisted_queries: Arc<PersistedQueries>,
 }
 
 impl<Q, S> SubscriptionServer<Q, S>
@@ -24,11 +27,17 @@ where
     Q: GraphQlRunner,
     S: QueryStoreManager,
 {
-    pub fn new(logger: &Logger, graphql_runner: Arc<Q>, store: Arc<S>) -> Self {
+    pub fn new(
+        logger: &Logger,
+        graphql_runner: Arc<Q>,
+        store: Arc<S>,
+        persisted_queries: Arc<PersistedQueries>,
+    ) -> Self {
         SubscriptionServer {
             logger: logger.new(o!("component" => "SubscriptionServer")),
             graphql_runner,
             store,
+            persisted_queries,
         }
     }
 
@@ -114,11 +123,20 @@ where
             let logger2 = self.logger.clone();
             let graphql_runner = self.graphql_runner.clone();
             let store = self.store.clone();
+            let persisted_queries = self.persisted_queries.clone();
 
             // Subgraph that the request is resolved to (if any)
             let subgraph_id = Arc::new(Mutex::new(None));
             let accept_subgraph_id = subgraph_id.clone();
 
+            // Client that opened the connection (if it identified itself)
+            let client = Arc::new(Mutex::new(None));
+            let accept_client = client.clone();
+
+            // Subprotocol that we agreed on with the client
+            let protocol = Arc::new(Mutex::new(Protocol::GraphQlWs));
+            let accept_protocol = protocol.clone();
+
             accept_hdr_async(stream, move |request: &Request, mut response: Response<()>| {
                 // Try to obtain the subgraph ID or name from the URL path.
                 // Return a 404 if the URL path contains no name/ID segment.
@@ -172,9 +190,18 @@ where
                     }
 
                 *accept_subgraph_id.lock().unwrap() = Some(state.id);
+                *accept_client.lock().unwrap() = ENV_VARS
+                    .graphql
+                    .client_id_header
+                    .as_ref()
+                    .and_then(|header| request.headers().get(header.as_str()))
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(effort::client_id);
+                let protocol = Protocol::negotiate(request.headers().get(SEC_WEBSOCKET_PROTOCOL));
+                *accept_protocol.lock().unwrap() = protocol;
                 response.headers_mut().insert(
-                    "Sec-WebSocket-Protocol",
-                    HeaderValue::from_static("graphql-ws"),
+                    SEC_WEBSOCKET_PROTOCOL,
+                    HeaderValue::from_static(protocol.name()),
                 );
                 Ok(response)
             })
@@ -183,13 +210,18 @@ where
                     Ok(ws_stream) => {
                         // Obtain the subgraph ID or name that we resolved the request to
                         let subgraph_id = subgraph_id.lock().unwrap().clone().unwrap();
+                        let client = client.lock().unwrap().take();
+                        let protocol = *protocol.lock().unwrap();
 
                         // Spawn a GraphQL over WebSocket connection
                         let service = GraphQlConnection::new(
                             &logger2,
                             subgraph_id,
+                            client,
+                            protocol,
                             ws_stream,
                             graphql_runner.clone(),
+                            persisted_queries.clone(),
                         );
 
                         graph::spawn_allow_panic(service.into_future().compat());
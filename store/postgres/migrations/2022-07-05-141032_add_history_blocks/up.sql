@@ -0,0 +1,5 @@
+-- The number of blocks of history that should be kept for a deployment.
+-- Entity versions that are only visible before the latest block minus
+-- `history_blocks` can be removed with `graphman prune`
+alter table subgraphs.subgraph_manifest
+      add column history_blocks int4 not null default 2147483647;
@@ -0,0 +1,5 @@
+-- The block at which the handler of an offchain data source ran. Offchain
+-- data sources that have been processed are not fetched again when the
+-- subgraph restarts
+alter table subgraphs.dynamic_ethereum_contract_data_source
+      add column done_at int4;
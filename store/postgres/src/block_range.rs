@@ -180,11 +180,11 @@ impl<'a> BlockRangeColumn<'a> {
                     // we generally only see BLOCK_NUMBER_MAX here for metadata
                     // queries where block ranges don't matter anyway
                     out.push_sql(" and coalesce(upper(");
-                    out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                    self.name(out);
                     out.push_sql("), 2147483647) > ");
                     out.push_bind_param::<Integer, _>(block)?;
                     out.push_sql(" and lower(");
-                    out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                    self.name(out);
                     out.push_sql(") <= ");
                     out.push_bind_param::<Integer, _>(block)
                 } else {
@@ -284,12 +284,12 @@ impl<'a> BlockRangeColumn<'a> {
         match self {
             BlockRangeColumn::Mutable { block, .. } => {
                 out.push_sql("lower(");
-                out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                self.name(out);
                 out.push_sql(") >= ");
                 out.push_bind_param::<Integer, _>(block)
             }
             BlockRangeColumn::Immutable { block, .. } => {
-                out.push_identifier(BLOCK_COLUMN)?;
+                self.name(out);
                 out.push_sql(" >= ");
                 out.push_bind_param::<Integer, _>(block)
             }
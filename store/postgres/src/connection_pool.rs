@@ -29,7 +29,9 @@ use std::sync::Arc;
 use std::time::Duration;
 use std::{collections::HashMap, sync::RwLock};
 
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
 use postgres::config::{Config, Host};
+use postgres_openssl::MakeTlsConnector;
 
 use crate::primary::{self, NAMESPACE_PUBLIC};
 use crate::{advisory_lock, catalog};
@@ -460,6 +462,13 @@ impl ConnectionPool {
         ForeignServer::new(pool.shard.clone(), &pool.postgres_url).map_err(|e| e.into())
     }
 
+    /// Open a connection with the `postgres` crate instead of Diesel. This
+    /// is only needed for operations that Diesel does not support, like
+    /// `COPY FROM STDIN`
+    pub(crate) fn postgres_client(&self) -> Result<postgres::Client, StoreError> {
+        self.get_ready()?.postgres_client()
+    }
+
     /// Check that we can connect to the database
     pub fn check(&self) -> bool {
         true
@@ -949,6 +958,15 @@ impl PoolInner {
         ForeignServer::new(self.shard.clone(), &self.postgres_url).map_err(|e| e.into())
     }
 
+    fn postgres_client(&self) -> Result<postgres::Client, StoreError> {
+        let mut builder =
+            SslConnector::builder(SslMethod::tls()).map_err(|e| StoreError::Unknown(e.into()))?;
+        builder.set_verify(SslVerifyMode::NONE);
+        let connector = MakeTlsConnector::new(builder.build());
+        postgres::Client::connect(&self.postgres_url, connector)
+            .map_err(|e| StoreError::Unknown(e.into()))
+    }
+
     /// Check that we can connect to the database
     pub fn check(&self) -> bool {
         self.pool
@@ -113,6 +113,9 @@ table! {
         /// Parent of the smallest start block from the manifest
         start_block_number -> Nullable<Integer>,
         start_block_hash -> Nullable<Binary>,
+        /// How many blocks of history to keep; older entity versions can
+        /// be pruned
+        history_blocks -> Integer,
     }
 }
 
@@ -585,6 +588,53 @@ pub fn state(conn: &PgConnection, id: DeploymentHash) -> Result<DeploymentState,
     }
 }
 
+/// Return the number of blocks of history that should be kept for the
+/// deployment
+pub fn history_blocks(conn: &PgConnection, site: &Site) -> Result<BlockNumber, StoreError> {
+    use subgraph_manifest as sm;
+
+    sm::table
+        .select(sm::history_blocks)
+        .filter(sm::id.eq(site.id))
+        .first::<BlockNumber>(conn)
+        .map_err(StoreError::from)
+}
+
+pub fn set_history_blocks(
+    conn: &PgConnection,
+    site: &Site,
+    history_blocks: BlockNumber,
+) -> Result<(), StoreError> {
+    use subgraph_manifest as sm;
+
+    update(sm::table.filter(sm::id.eq(site.id)))
+        .set(sm::history_blocks.eq(history_blocks))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(StoreError::from)
+}
+
+/// Move the earliest block for which the deployment can answer queries
+/// forward to `earliest_block`. The earliest block never moves backwards,
+/// and setting it to an earlier block than the current one does nothing
+pub fn set_earliest_block(
+    conn: &PgConnection,
+    site: &Site,
+    earliest_block: BlockNumber,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(
+        d::table
+            .filter(d::id.eq(site.id))
+            .filter(d::earliest_block_number.lt(earliest_block)),
+    )
+    .set(d::earliest_block_number.eq(earliest_block))
+    .execute(conn)
+    .map(|_| ())
+    .map_err(StoreError::from)
+}
+
 /// Mark the deployment `id` as synced
 pub fn set_synced(conn: &PgConnection, id: &DeploymentHash) -> Result<(), StoreError> {
     use subgraph_deployment as d;
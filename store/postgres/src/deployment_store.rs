@@ -5,6 +5,7 @@ use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use graph::components::store::{EntityType, StoredDynamicDataSource};
+use graph::data::query::Trace;
 use graph::data::subgraph::status;
 use graph::prelude::{
     tokio, CancelHandle, CancelToken, CancelableError, EntityOperation, PoolWaitStats,
@@ -26,10 +27,11 @@ use graph::components::subgraph::ProofOfIndexingFinisher;
 use graph::constraint_violation;
 use graph::data::subgraph::schema::{DeploymentCreate, SubgraphError, POI_OBJECT};
 use graph::prelude::{
-    anyhow, debug, info, o, warn, web3, ApiSchema, AttributeNames, BlockNumber, BlockPtr,
-    CheapClone, DeploymentHash, DeploymentState, Entity, EntityKey, EntityModification,
-    EntityQuery, Error, Logger, QueryExecutionError, Schema, StopwatchMetrics, StoreError,
-    StoreEvent, UnfailOutcome, Value, ENV_VARS,
+    anyhow, debug, error, info, o, warn, web3, ApiSchema, AttributeNames, BlockNumber, BlockPtr,
+    CheapClone, DeploymentHash, DeploymentState, Entity, EntityAggregateQuery, EntityFilter,
+    EntityKey, EntityModification, EntityOrder, EntityQuery, EntityRange, Error, FulltextOutput,
+    Logger, QueryExecutionError, Schema, StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome,
+    Value, ENV_VARS,
 };
 use graph_graphql::prelude::api_schema;
 use web3::types::Address;
@@ -41,6 +43,7 @@ use crate::detail::ErrorDetail;
 use crate::dynds::DataSourcesTable;
 use crate::relational::{Layout, LayoutCache, SqlName, Table};
 use crate::relational_queries::FromEntityData;
+use crate::restore::{self, EntitySource};
 use crate::{connection_pool::ConnectionPool, detail};
 use crate::{dynds, primary::Site};
 
@@ -73,6 +76,16 @@ pub(crate) struct SubgraphInfo {
     pub(crate) repository: Option<String>,
 }
 
+/// The changes a subgraph made while processing one block
+pub(crate) struct BlockWrite {
+    /// The block at which we are writing the changes
+    pub(crate) block_ptr: BlockPtr,
+    pub(crate) firehose_cursor: Option<String>,
+    pub(crate) mods: Vec<EntityModification>,
+    pub(crate) data_sources: Vec<StoredDynamicDataSource>,
+    pub(crate) deterministic_errors: Vec<SubgraphError>,
+}
+
 pub struct StoreInner {
     logger: Logger,
 
@@ -227,7 +240,7 @@ impl DeploymentStore {
         conn: &PgConnection,
         site: Arc<Site>,
         query: EntityQuery,
-    ) -> Result<Vec<T>, QueryExecutionError> {
+    ) -> Result<(Vec<T>, Trace), QueryExecutionError> {
         let layout = self.layout(conn, site)?;
 
         let logger = query.logger.unwrap_or_else(|| self.logger.clone());
@@ -238,8 +251,29 @@ impl DeploymentStore {
             query.filter,
             query.order,
             query.range,
+            query.fulltext,
             query.block,
             query.query_id,
+            query.trace,
+        )
+    }
+
+    pub(crate) fn execute_aggregate(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityAggregateQuery,
+    ) -> Result<Vec<Value>, QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+
+        let logger = query.logger.unwrap_or_else(|| self.logger.clone());
+        layout.aggregate(
+            &logger,
+            conn,
+            &query.entity_type,
+            query.filter.as_ref(),
+            &query.aggregates,
+            query.block,
         )
     }
 
@@ -658,6 +692,181 @@ impl DeploymentStore {
         .await
     }
 
+    /// Remove entity versions that are not needed to answer queries for
+    /// the last `history_blocks` blocks of the deployment and move the
+    /// earliest block that can be queried forward accordingly. If
+    /// `history_blocks` is given, it also becomes the deployment's new
+    /// setting; otherwise, the stored setting is used. Returns the
+    /// earliest block after pruning and the number of removed versions
+    pub(crate) fn prune(
+        &self,
+        site: Arc<Site>,
+        history_blocks: Option<BlockNumber>,
+    ) -> Result<(BlockNumber, usize), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| -> Result<_, StoreError> {
+            let history_blocks = match history_blocks {
+                Some(history_blocks) => {
+                    if history_blocks <= 0 {
+                        return Err(constraint_violation!(
+                            "the number of history blocks must be positive but is {}",
+                            history_blocks
+                        ));
+                    }
+                    deployment::set_history_blocks(&conn, &site, history_blocks)?;
+                    history_blocks
+                }
+                None => deployment::history_blocks(&conn, &site)?,
+            };
+
+            let state = deployment::state(&conn, site.deployment.clone())?;
+            let earliest_block = state.latest_block.number.saturating_sub(history_blocks);
+            if earliest_block <= state.earliest_block_number {
+                return Ok((state.earliest_block_number, 0));
+            }
+
+            deployment::set_earliest_block(&conn, &site, earliest_block)?;
+            let layout = self.layout(&conn, site.cheap_clone())?;
+            let removed = layout.prune(&conn, earliest_block)?;
+            Ok((earliest_block, removed))
+        })
+    }
+
+    /// The number of dynamic data sources that the deployment created up to
+    /// and including `block`
+    pub(crate) fn dynamic_data_source_count(
+        &self,
+        site: Arc<Site>,
+        block: BlockNumber,
+    ) -> Result<usize, StoreError> {
+        let conn = self.get_conn()?;
+        dynds::load(&conn, &site, block).map(|data_sources| data_sources.len())
+    }
+
+    /// Pass all entities of the deployment as of `block` to `sink`, one
+    /// entity type after the other and in batches ordered by `id`. If
+    /// `block` is `None`, use the latest block of the deployment. Return
+    /// the block at which the entities were read
+    pub(crate) fn dump(
+        &self,
+        site: Arc<Site>,
+        block: Option<BlockNumber>,
+        batch_size: u32,
+        sink: &mut dyn FnMut(&Table, Vec<Entity>) -> Result<(), anyhow::Error>,
+    ) -> Result<BlockNumber, StoreError> {
+        let conn = self.get_conn()?;
+        let state = deployment::state(&conn, site.deployment.clone())?;
+        let block = block.unwrap_or(state.latest_block.number);
+        if block > state.latest_block.number || block < state.earliest_block_number {
+            return Err(StoreError::QueryExecutionError(format!(
+                "block {} is not available for deployment {}, only blocks from {} to {} are",
+                block, site.deployment, state.earliest_block_number, state.latest_block.number
+            )));
+        }
+
+        let layout = self.layout(&conn, site.cheap_clone())?;
+        let mut tables: Vec<_> = layout.tables.values().collect();
+        tables.sort_by(|a, b| a.object.as_str().cmp(b.object.as_str()));
+
+        for table in tables {
+            let mut last_id: Option<Value> = None;
+            loop {
+                let filter = last_id
+                    .take()
+                    .map(|id| EntityFilter::GreaterThan("id".to_string(), id));
+                let (entities, _): (Vec<Entity>, _) = layout.query(
+                    &self.logger,
+                    &conn,
+                    EntityCollection::All(vec![(table.object.clone(), AttributeNames::All)]),
+                    filter,
+                    EntityOrder::Default,
+                    EntityRange::first(batch_size),
+                    FulltextOutput::default(),
+                    block,
+                    None,
+                    false,
+                )?;
+                let done = entities.len() < batch_size as usize;
+                last_id = entities.last().and_then(|entity| entity.get("id").cloned());
+                sink(table, entities)?;
+                if done || last_id.is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(block)
+    }
+
+    /// Create the deployment `site` and load the entities that `source`
+    /// produces for each of its tables. The entities become visible at
+    /// `head`, and the deployment head is set to `head` so that indexing
+    /// continues from there. Return the number of entities that were loaded
+    pub(crate) fn restore(
+        &self,
+        schema: &Schema,
+        deployment: DeploymentCreate,
+        site: Arc<Site>,
+        head: &BlockPtr,
+        source: &mut dyn FnMut(&Table) -> Result<EntitySource, anyhow::Error>,
+    ) -> Result<usize, StoreError> {
+        if deployment::exists(&*self.get_conn()?, &site)? {
+            return Err(StoreError::Unknown(anyhow!(
+                "can not restore into deployment {} since it already exists",
+                site.deployment
+            )));
+        }
+        self.create_deployment(schema, deployment, site.cheap_clone(), None, false)?;
+
+        // The entities are loaded with `COPY` on a separate connection, so
+        // that creating the deployment and loading the entities can not
+        // happen in one transaction. Remove the deployment again if loading
+        // fails so that a failed restore can simply be retried
+        self.restore_entities(site.cheap_clone(), head, source)
+            .map_err(|e| {
+                if let Err(drop_err) = self.drop_deployment(&site) {
+                    error!(self.logger, "Failed to remove deployment after failed restore";
+                           "deployment" => site.deployment.as_str(),
+                           "error" => drop_err.to_string());
+                }
+                e
+            })
+    }
+
+    /// Load the entities that `source` produces into the tables of the
+    /// freshly created deployment `site` and move its head to `head`
+    fn restore_entities(
+        &self,
+        site: Arc<Site>,
+        head: &BlockPtr,
+        source: &mut dyn FnMut(&Table) -> Result<EntitySource, anyhow::Error>,
+    ) -> Result<usize, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site.cheap_clone())?;
+        let mut tables: Vec<_> = layout.tables.values().collect();
+        tables.sort_by(|a, b| a.object.as_str().cmp(b.object.as_str()));
+
+        let mut client = self.pool.postgres_client()?;
+        let mut count = 0;
+        for table in tables {
+            let start = Instant::now();
+            let entities = source(table).map_err(StoreError::Unknown)?;
+            let loaded = restore::load_table(&mut client, table, head.number, entities)?;
+            restore::fill_fulltext(&conn, table)?;
+            self.analyze_with_conn(site.cheap_clone(), table.object.as_str(), &conn)?;
+            info!(self.logger, "Restored table";
+                  "entity_type" => table.object.as_str(),
+                  "entities" => loaded,
+                  "time_ms" => start.elapsed().as_millis());
+            count += loaded;
+        }
+
+        conn.transaction(|| -> Result<_, StoreError> {
+            deployment::set_entity_count(&conn, &site, &layout.count_query)?;
+            deployment::forward_block_ptr(&conn, &site.deployment, head)
+        })?;
+        Ok(count)
+    }
+
     /// Runs the SQL `ANALYZE` command in a table.
     pub(crate) fn analyze(&self, site: Arc<Site>, entity_name: &str) -> Result<(), StoreError> {
         let conn = self.get_conn()?;
@@ -951,21 +1160,22 @@ impl DeploymentStore {
     ) -> Result<Vec<Entity>, QueryExecutionError> {
         let conn = self.get_conn()?;
         self.execute_query(&conn, site, query)
+            .map(|(entities, _)| entities)
     }
 
+    /// Write the changes for `blocks` in one transaction. The blocks must
+    /// be consecutive blocks in increasing order, and the subgraph head is
+    /// moved to the last of them
     pub(crate) fn transact_block_operations(
         &self,
         site: Arc<Site>,
-        block_ptr_to: &BlockPtr,
-        firehose_cursor: Option<&str>,
-        mods: &[EntityModification],
+        blocks: &[BlockWrite],
         stopwatch: &StopwatchMetrics,
-        data_sources: &[StoredDynamicDataSource],
-        deterministic_errors: &[SubgraphError],
     ) -> Result<StoreEvent, StoreError> {
         // All operations should apply only to data or metadata for this subgraph
-        if mods
+        if blocks
             .iter()
+            .flat_map(|block| &block.mods)
             .map(|modification| modification.entity_key())
             .any(|key| key.subgraph_id != site.deployment)
         {
@@ -975,6 +1185,11 @@ impl DeploymentStore {
             );
         }
 
+        let last = match blocks.last() {
+            Some(last) => last,
+            None => return Ok(StoreEvent::new(vec![])),
+        };
+
         let conn = {
             let _section = stopwatch.start_section("transact_blocks_get_conn");
             self.get_conn()?
@@ -985,36 +1200,41 @@ impl DeploymentStore {
             // wait with sending it until we have done all our other work
             // so that we do not hold a lock on the notification queue
             // for longer than we have to
-            let event: StoreEvent = mods.iter().collect();
+            let event: StoreEvent = blocks.iter().flat_map(|block| &block.mods).collect();
 
-            // Make the changes
+            // Make the changes. Blocks have to be applied in order so that
+            // the block ranges of entity versions written by an earlier
+            // block get closed by changes to the same entity in a later one
             let layout = self.layout(&conn, site.clone())?;
-            let section = stopwatch.start_section("apply_entity_modifications");
-            let count = self.apply_entity_modifications(
-                &conn,
-                layout.as_ref(),
-                mods,
-                block_ptr_to,
-                stopwatch,
-            )?;
-            section.end();
-
-            dynds::insert(&conn, &site, data_sources, block_ptr_to)?;
-
-            if !deterministic_errors.is_empty() {
-                deployment::insert_subgraph_errors(
+            let mut count = 0;
+            for block in blocks {
+                let section = stopwatch.start_section("apply_entity_modifications");
+                count += self.apply_entity_modifications(
                     &conn,
-                    &site.deployment,
-                    deterministic_errors,
-                    block_ptr_to.block_number(),
+                    layout.as_ref(),
+                    &block.mods,
+                    &block.block_ptr,
+                    stopwatch,
                 )?;
+                section.end();
+
+                dynds::insert(&conn, &site, &block.data_sources, &block.block_ptr)?;
+
+                if !block.deterministic_errors.is_empty() {
+                    deployment::insert_subgraph_errors(
+                        &conn,
+                        &site.deployment,
+                        &block.deterministic_errors,
+                        block.block_ptr.block_number(),
+                    )?;
+                }
             }
 
             deployment::transact_block(
                 &conn,
                 &site,
-                block_ptr_to,
-                firehose_cursor,
+                &last.block_ptr,
+                last.firehose_cursor.as_deref(),
                 layout.count_query.as_str(),
                 count,
             )?;
@@ -7,7 +7,7 @@ use diesel::{
     sql_query,
     sql_types::{Integer, Text},
 };
-use diesel::{insert_into, pg::PgConnection};
+use diesel::{insert_into, pg::PgConnection, update};
 
 use graph::{
     components::store::StoredDynamicDataSource,
@@ -33,6 +33,7 @@ table! {
         ethereum_block_number -> Numeric,
         deployment -> Text,
         context -> Nullable<Text>,
+        done_at -> Nullable<Integer>,
     }
 }
 
@@ -54,27 +55,34 @@ pub(super) fn load(
             decds::context,
             decds::address,
             decds::ethereum_block_number,
+            decds::done_at,
         ))
         .filter(decds::ethereum_block_number.le(sql(&format!("{}::numeric", block))))
         .order_by((decds::ethereum_block_number, decds::vid))
-        .load::<(i64, String, Option<String>, Vec<u8>, BigDecimal)>(conn)?;
+        .load::<(
+            i64,
+            String,
+            Option<String>,
+            Vec<u8>,
+            BigDecimal,
+            Option<BlockNumber>,
+        )>(conn)?;
 
     let mut data_sources: Vec<StoredDynamicDataSource> = Vec::new();
-    for (vid, name, context, address, creation_block) in dds.into_iter() {
-        if address.len() != 20 {
-            return Err(constraint_violation!(
-                "Data source address `0x{:?}` for dynamic data source {} should be 20 bytes long but is {} bytes long",
-                address, vid,
-            address.len()
-        ));
-        }
-
+    // The `address` column holds the parameter of the data source. For
+    // Ethereum data sources, that is the contract address, for offchain data
+    // sources the link to their file. Data sources check it when they are
+    // loaded from the `StoredDynamicDataSource`
+    for (_vid, name, context, address, creation_block, done_at) in dds.into_iter() {
         let creation_block = creation_block.to_i32();
         let data_source = StoredDynamicDataSource {
             name,
             param: Some(address.into()),
             context: context.map(|ctx| serde_json::from_str(&ctx)).transpose()?,
             creation_block,
+            // A data source that was processed after `block` has not been
+            // processed yet as of `block`
+            done_at: done_at.filter(|done_at| *done_at <= block),
         };
 
         if data_sources.last().and_then(|d| d.creation_block) > data_source.creation_block {
@@ -101,7 +109,12 @@ pub(super) fn insert(
         return Ok(0);
     }
 
-    let dds: Vec<_> = data_sources
+    // Entries with `done_at` record that the handler of an offchain data
+    // source that was created in an earlier block has run
+    let (done, created): (Vec<_>, Vec<_>) =
+        data_sources.iter().partition(|ds| ds.done_at.is_some());
+
+    let dds: Vec<_> = created
         .into_iter()
         .map(|ds| {
             let StoredDynamicDataSource {
@@ -109,6 +122,7 @@ pub(super) fn insert(
                 param,
                 context,
                 creation_block: _,
+                done_at: _,
             } = ds;
             let address = match param {
                 Some(param) => param,
@@ -134,10 +148,30 @@ pub(super) fn insert(
         })
         .collect::<Result<_, _>>()?;
 
-    insert_into(decds::table)
-        .values(dds)
-        .execute(conn)
-        .map_err(|e| e.into())
+    let mut count = match dds.is_empty() {
+        true => 0,
+        false => insert_into(decds::table).values(dds).execute(conn)?,
+    };
+
+    for ds in done {
+        let address = ds.param.as_ref().ok_or_else(|| {
+            constraint_violation!(
+                "dynamic data sources must have an address, but `{}` has none",
+                ds.name
+            )
+        })?;
+        count += update(
+            decds::table
+                .filter(decds::deployment.eq(deployment.as_str()))
+                .filter(decds::name.eq(ds.name.as_str()))
+                .filter(decds::address.eq(&**address))
+                .filter(decds::done_at.is_null()),
+        )
+        .set(decds::done_at.eq(ds.done_at))
+        .execute(conn)?;
+    }
+
+    Ok(count)
 }
 
 /// Copy the dynamic data sources for `src` to `dst`. All data sources that
@@ -170,10 +204,11 @@ pub(super) fn copy(
         "\
       insert into subgraphs.dynamic_ethereum_contract_data_source(name,
              address, abi, start_block, ethereum_block_hash,
-             ethereum_block_number, deployment, context)
+             ethereum_block_number, deployment, context, done_at)
       select e.name, e.address, e.abi, e.start_block,
              e.ethereum_block_hash, e.ethereum_block_number, $2 as deployment,
-             e.context
+             e.context,
+             case when e.done_at <= $3 then e.done_at end
         from {src_nsp}.dynamic_ethereum_contract_data_source e
        where e.deployment = $1
          and e.ethereum_block_number <= $3",
@@ -196,6 +231,12 @@ pub(super) fn revert(
 
     let dds = decds::table.filter(decds::deployment.eq(id.as_str()));
     delete(dds.filter(decds::ethereum_block_number.ge(sql(&block.to_string())))).execute(conn)?;
+    // Offchain data sources that were processed in the reverted blocks need
+    // to be processed again
+    let dds = decds::table.filter(decds::deployment.eq(id.as_str()));
+    update(dds.filter(decds::done_at.ge(block)))
+        .set(decds::done_at.eq(None::<BlockNumber>))
+        .execute(conn)?;
     Ok(())
 }
 
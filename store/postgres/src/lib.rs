@@ -32,6 +32,7 @@ mod primary;
 pub mod query_store;
 mod relational;
 mod relational_queries;
+mod restore;
 mod sql_value;
 mod store;
 mod store_events;
@@ -78,5 +79,6 @@ pub mod command_support {
         };
     }
     pub use crate::primary::Namespace;
-    pub use crate::relational::{Catalog, Column, ColumnType, Layout, SqlName};
+    pub use crate::relational::{Catalog, Column, ColumnType, Layout, SqlName, Table};
+    pub use crate::restore::EntitySource;
 }
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 
+use graph::data::query::Trace;
 use graph::data::value::Word;
 
 use crate::deployment_store::{DeploymentStore, ReplicaId};
@@ -36,7 +37,7 @@ impl QueryStoreTrait for QueryStore {
     fn find_query_values(
         &self,
         query: EntityQuery,
-    ) -> Result<Vec<BTreeMap<Word, r::Value>>, QueryExecutionError> {
+    ) -> Result<(Vec<BTreeMap<Word, r::Value>>, Trace), QueryExecutionError> {
         assert_eq!(&self.site.deployment, &query.subgraph_id);
         let conn = self
             .store
@@ -45,6 +46,16 @@ impl QueryStoreTrait for QueryStore {
         self.store.execute_query(&conn, self.site.clone(), query)
     }
 
+    fn aggregate(&self, query: EntityAggregateQuery) -> Result<Vec<Value>, QueryExecutionError> {
+        assert_eq!(&self.site.deployment, &query.subgraph_id);
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        self.store
+            .execute_aggregate(&conn, self.site.clone(), query)
+    }
+
     /// Return true if the deployment with the given id is fully synced,
     /// and return false otherwise. Errors from the store are passed back up
     async fn is_deployment_synced(&self) -> Result<bool, Error> {
@@ -35,19 +35,24 @@ use crate::relational_queries::{FindChangesQuery, FindPossibleDeletionsQuery};
 use crate::{
     primary::{Namespace, Site},
     relational_queries::{
-        ClampRangeQuery, ConflictingEntityQuery, EntityData, EntityDeletion, FilterCollection,
-        FilterQuery, FindManyQuery, FindQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
+        AggregateData, AggregateQuery, ClampRangeQuery, ConflictingEntityQuery, EntityData,
+        EntityDeletion, ExplainLine, ExplainQuery, FilterCollection, FilterQuery, FindManyQuery,
+        FindQuery, InsertQuery, PruneQuery, RevertClampQuery, RevertRemoveQuery,
     },
 };
 use graph::components::store::EntityType;
 use graph::data::graphql::ext::{DirectiveFinder, DocumentExt, ObjectTypeExt};
-use graph::data::schema::{FulltextConfig, FulltextDefinition, Schema, SCHEMA_TYPE_NAME};
+use graph::data::query::Trace;
+use graph::data::schema::{
+    FulltextConfig, FulltextDefinition, FulltextWeight, Schema, SCHEMA_TYPE_NAME,
+};
 use graph::data::store::BYTES_SCALAR;
 use graph::data::subgraph::schema::{POI_OBJECT, POI_TABLE};
 use graph::prelude::{
-    anyhow, info, BlockNumber, DeploymentHash, Entity, EntityChange, EntityCollection,
-    EntityFilter, EntityKey, EntityOperation, EntityOrder, EntityRange, Logger,
-    QueryExecutionError, StoreError, StoreEvent, ValueType, BLOCK_NUMBER_MAX,
+    anyhow, info, BlockNumber, DeploymentHash, Entity, EntityAggregate, EntityChange,
+    EntityCollection, EntityFilter, EntityKey, EntityOperation, EntityOrder, EntityRange,
+    FulltextOutput, Logger, QueryExecutionError, StoreError, StoreEvent, Value, ValueType,
+    BLOCK_NUMBER_MAX,
 };
 
 use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
@@ -624,9 +629,11 @@ impl Layout {
         filter: Option<EntityFilter>,
         order: EntityOrder,
         range: EntityRange,
+        fulltext: FulltextOutput,
         block: BlockNumber,
         query_id: Option<String>,
-    ) -> Result<Vec<T>, QueryExecutionError> {
+        trace: bool,
+    ) -> Result<(Vec<T>, Trace), QueryExecutionError> {
         fn log_query_timing(
             logger: &Logger,
             query: &FilterQuery,
@@ -663,6 +670,8 @@ impl Layout {
             filter.as_ref(),
             order,
             range,
+            fulltext,
+            self,
             block,
             query_id,
         )?;
@@ -702,17 +711,84 @@ impl Layout {
                     )),
                 }
             })?;
-        log_query_timing(logger, &query_clone, start.elapsed(), values.len());
+        let elapsed = start.elapsed();
+        log_query_timing(logger, &query_clone, elapsed, values.len());
+
+        let trace = if trace {
+            let explain = conn
+                .transaction(|| {
+                    if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                        conn.batch_execute(timeout_sql)?;
+                    }
+                    ExplainQuery::new(&query_clone).load::<ExplainLine>(conn)
+                })
+                .map(|lines| lines.into_iter().map(|line| line.line).collect())
+                .unwrap_or_else(|e| vec![format!("EXPLAIN failed: {}", e)]);
+            // See the comment about `debug_query` failing above
+            let mut sql = String::new();
+            if write!(sql, "{}", debug_query(&query_clone)).is_err() {
+                sql = query_clone.to_string();
+            }
+            Trace::query(sql, explain, elapsed, values.len())
+        } else {
+            Trace::None
+        };
 
         let parent_type = filter_collection.parent_type()?.map(ColumnType::from);
-        values
+        let entities = values
             .into_iter()
             .map(|entity_data| {
                 entity_data
                     .deserialize_with_layout(self, parent_type.as_ref())
                     .map_err(|e| e.into())
             })
-            .collect()
+            .collect::<Result<_, _>>()?;
+        Ok((entities, trace))
+    }
+
+    /// Compute `aggregates` over all entities of type `entity_type` that
+    /// are visible at `block` and match `filter`. The result contains one
+    /// value for each aggregate, in the same order as `aggregates`
+    pub fn aggregate(
+        &self,
+        logger: &Logger,
+        conn: &PgConnection,
+        entity_type: &EntityType,
+        filter: Option<&EntityFilter>,
+        aggregates: &[EntityAggregate],
+        block: BlockNumber,
+    ) -> Result<Vec<Value>, QueryExecutionError> {
+        let table = self.table_for_entity(entity_type)?;
+        let query = AggregateQuery::new(self, table, filter, aggregates, block)?;
+        let query_clone = query.clone();
+
+        let start = Instant::now();
+        let data = conn
+            .transaction(|| {
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                query.get_result::<AggregateData>(conn)
+            })
+            .map_err(|e| {
+                let mut query_text = String::new();
+                match write!(query_text, "{}", debug_query(&query_clone)) {
+                    Ok(()) => (),
+                    Err(_) => {
+                        write!(query_text, "{query_clone}").ok();
+                    }
+                };
+                QueryExecutionError::ResolveEntitiesError(format!("{e}, query = {query_text}"))
+            })?;
+        if ENV_VARS.log_sql_timing() {
+            info!(
+                logger,
+                "Query timing (SQL)";
+                "query" => debug_query(&query_clone).to_string().replace("\n", "\t"),
+                "time_ms" => start.elapsed().as_millis()
+            );
+        }
+        query_clone.values(data).map_err(|e| e.into())
     }
 
     pub fn update<'a>(
@@ -843,6 +919,22 @@ impl Layout {
         Ok((StoreEvent::new(changes), count))
     }
 
+    /// Remove all entity versions that are not visible at `earliest_block`
+    /// or any later block. Versions of immutable entities are visible
+    /// forever and are therefore never removed. Returns the number of
+    /// versions that were removed
+    pub fn prune(
+        &self,
+        conn: &PgConnection,
+        earliest_block: BlockNumber,
+    ) -> Result<usize, StoreError> {
+        let mut count = 0;
+        for table in self.tables.values().filter(|table| !table.immutable) {
+            count += PruneQuery::new(table, earliest_block)?.execute(conn)?;
+        }
+        Ok(count)
+    }
+
     /// Revert the metadata (dynamic data sources and related entities) for
     /// the given `subgraph`.
     ///
@@ -928,7 +1020,9 @@ pub enum ColumnType {
     BigInt,
     Bytes,
     Int,
+    Int8,
     String,
+    Timestamp,
     TSVector(FulltextConfig),
     Enum(EnumType),
 }
@@ -985,7 +1079,9 @@ impl ColumnType {
             ValueType::BigInt => Ok(ColumnType::BigInt),
             ValueType::Bytes => Ok(ColumnType::Bytes),
             ValueType::Int => Ok(ColumnType::Int),
+            ValueType::Int8 => Ok(ColumnType::Int8),
             ValueType::String => Ok(ColumnType::String),
+            ValueType::Timestamp => Ok(ColumnType::Timestamp),
         }
     }
 
@@ -996,7 +1092,9 @@ impl ColumnType {
             ColumnType::BigInt => "numeric",
             ColumnType::Bytes => "bytea",
             ColumnType::Int => "integer",
+            ColumnType::Int8 => "int8",
             ColumnType::String => "text",
+            ColumnType::Timestamp => "timestamptz",
             ColumnType::TSVector(_) => "tsvector",
             ColumnType::Enum(enum_type) => enum_type.name.as_str(),
         }
@@ -1022,7 +1120,9 @@ pub struct Column {
     pub field: String,
     pub field_type: q::Type,
     pub column_type: ColumnType,
-    pub fulltext_fields: Option<HashSet<String>>,
+    /// For fulltext columns, the fields that the search includes and
+    /// their weights
+    pub fulltext_fields: Option<BTreeMap<String, FulltextWeight>>,
     is_reference: bool,
     /// Whether to use a prefix of the column for comparisons and index
     /// creation, or column values in their entirety
@@ -9,18 +9,26 @@ use diesel::pg::{Pg, PgConnection};
 use diesel::query_builder::{AstPass, QueryFragment, QueryId};
 use diesel::query_dsl::{LoadQuery, RunQueryDsl};
 use diesel::result::{Error as DieselError, QueryResult};
-use diesel::sql_types::{Array, BigInt, Binary, Bool, Integer, Jsonb, Text};
-use diesel::Connection;
+use diesel::row::NamedRow;
+use diesel::sql_types::{Array, BigInt, Binary, Bool, Integer, Jsonb, Nullable, Text, Timestamptz};
+use diesel::{deserialize, Connection};
 
 use graph::data::value::Word;
 use graph::prelude::{
-    anyhow, r, serde_json, Attribute, BlockNumber, ChildMultiplicity, Entity, EntityCollection,
-    EntityFilter, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow, ParentLink,
-    QueryExecutionError, StoreError, Value, ENV_VARS,
+    anyhow, r, serde_json, Attribute, BlockNumber, ChildMultiplicity, Entity, EntityAggregate,
+    EntityCollection, EntityFilter, EntityKey, EntityLink, EntityOrder, EntityOrderByChild,
+    EntityRange, EntityWindow, FulltextOutput, ParentLink, QueryExecutionError, SortPosition,
+    StoreError, Value, ENV_VARS,
 };
 use graph::{
     components::store::{AttributeNames, EntityType},
-    data::{schema::FulltextAlgorithm, store::scalar},
+    data::{
+        schema::{
+            FulltextAlgorithm, FulltextLanguage, FulltextWeight, FULLTEXT_RANK_FIELD,
+            FULLTEXT_SNIPPET_FIELD,
+        },
+        store::scalar,
+    },
 };
 use itertools::Itertools;
 use std::borrow::Cow;
@@ -280,10 +288,16 @@ pub trait FromColumnValue: Sized + std::fmt::Debug {
 
     fn from_i32(i: i32) -> Self;
 
+    fn from_i64(i: i64) -> Self;
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self;
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self;
 
     fn from_big_int(i: serde_json::Number) -> Result<Self, StoreError>;
 
+    fn from_f64(f: f64) -> Self;
+
     // The string returned by the DB, without the leading '\x'
     fn from_bytes(i: &str) -> Result<Self, StoreError>;
 
@@ -309,6 +323,13 @@ pub trait FromColumnValue: Sized + std::fmt::Debug {
                     number
                 ))),
             },
+            (j::Number(number), ColumnType::Int8) => match number.as_i64() {
+                Some(i) => Ok(Self::from_i64(i)),
+                None => Err(StoreError::Unknown(anyhow!(
+                    "failed to convert {} to Int8",
+                    number
+                ))),
+            },
             (j::Number(number), ColumnType::BigDecimal) => {
                 let s = number.to_string();
                 scalar::BigDecimal::from_str(s.as_str())
@@ -331,6 +352,11 @@ pub trait FromColumnValue: Sized + std::fmt::Debug {
                 Ok(Self::from_string(s))
             }
             (j::String(s), ColumnType::Bytes) => Self::from_bytes(s.trim_start_matches("\\x")),
+            (j::String(s), ColumnType::Timestamp) => scalar::Timestamp::from_str(&s)
+                .map(Self::from_timestamp)
+                .map_err(|e| {
+                    StoreError::Unknown(anyhow!("failed to convert {} to Timestamp: {}", s, e))
+                }),
             (j::String(s), column_type) => Err(StoreError::Unknown(anyhow!(
                 "can not convert string {} to {:?}",
                 s,
@@ -370,6 +396,14 @@ impl FromColumnValue for r::Value {
         r::Value::Int(i.into())
     }
 
+    fn from_i64(i: i64) -> Self {
+        r::Value::String(i.to_string())
+    }
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self {
+        r::Value::String(ts.to_string())
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         r::Value::String(d.to_string())
     }
@@ -378,6 +412,10 @@ impl FromColumnValue for r::Value {
         Ok(r::Value::String(i.to_string()))
     }
 
+    fn from_f64(f: f64) -> Self {
+        r::Value::Float(f)
+    }
+
     fn from_bytes(b: &str) -> Result<Self, StoreError> {
         // In some cases, we pass strings as parent_id's through the
         // database; those are already prefixed with '0x' and we need to
@@ -415,6 +453,14 @@ impl FromColumnValue for graph::prelude::Value {
         graph::prelude::Value::Int(i)
     }
 
+    fn from_i64(i: i64) -> Self {
+        graph::prelude::Value::Int8(i)
+    }
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self {
+        graph::prelude::Value::Timestamp(ts)
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         graph::prelude::Value::BigDecimal(d)
     }
@@ -425,6 +471,10 @@ impl FromColumnValue for graph::prelude::Value {
             .map_err(|e| StoreError::Unknown(anyhow!("failed to convert {} to BigInt: {}", i, e)))
     }
 
+    fn from_f64(f: f64) -> Self {
+        graph::prelude::Value::BigDecimal(scalar::BigDecimal::from(f))
+    }
+
     fn from_bytes(b: &str) -> Result<Self, StoreError> {
         scalar::Bytes::from_str(b)
             .map(graph::prelude::Value::Bytes)
@@ -516,6 +566,21 @@ impl EntityData {
                                 out.insert_entity_data("g$parent_id".to_owned(), value);
                             }
                         }
+                    } else if key == "g$rank" {
+                        // The rank and snippet of a fulltext search
+                        if let Some(rank) = json.as_f64() {
+                            out.insert_entity_data(
+                                FULLTEXT_RANK_FIELD.to_owned(),
+                                T::Value::from_f64(rank),
+                            );
+                        }
+                    } else if key == "g$snippet" {
+                        if let j::String(snippet) = json {
+                            out.insert_entity_data(
+                                FULLTEXT_SNIPPET_FIELD.to_owned(),
+                                T::Value::from_string(snippet),
+                            );
+                        }
                     } else if let Some(column) = table.column(&SqlName::verbatim(key)) {
                         let value = T::Value::from_column_value(&column.column_type, json)?;
                         if !value.is_null() {
@@ -566,6 +631,8 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                 ),
             },
             Value::Int(i) => out.push_bind_param::<Integer, _>(i),
+            Value::Int8(i) => out.push_bind_param::<BigInt, _>(i),
+            Value::Timestamp(ts) => out.push_bind_param::<Timestamptz, _>(ts.as_datetime()),
             Value::BigDecimal(d) => {
                 out.push_bind_param::<Text, _>(&d.to_string())?;
                 out.push_sql("::numeric");
@@ -584,6 +651,10 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                     ColumnType::Boolean => out.push_bind_param::<Array<Bool>, _>(&sql_values),
                     ColumnType::Bytes => out.push_bind_param::<Array<Binary>, _>(&sql_values),
                     ColumnType::Int => out.push_bind_param::<Array<Integer>, _>(&sql_values),
+                    ColumnType::Int8 => out.push_bind_param::<Array<BigInt>, _>(&sql_values),
+                    ColumnType::Timestamp => {
+                        out.push_bind_param::<Array<Timestamptz>, _>(&sql_values)
+                    }
                     ColumnType::String => out.push_bind_param::<Array<Text>, _>(&sql_values),
                     ColumnType::Enum(enum_type) => {
                         out.push_bind_param::<Array<Text>, _>(&sql_values)?;
@@ -592,27 +663,10 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                         out.push_sql("[]");
                         Ok(())
                     }
-                    // TSVector will only be in a Value::List() for inserts so "to_tsvector" can always be used here
-                    ColumnType::TSVector(config) => {
-                        if sql_values.is_empty() {
-                            out.push_sql("''::tsvector");
-                        } else {
-                            out.push_sql("(");
-                            for (i, value) in sql_values.iter().enumerate() {
-                                if i > 0 {
-                                    out.push_sql(") || ");
-                                }
-                                out.push_sql("to_tsvector(");
-                                out.push_bind_param::<Text, _>(
-                                    &config.language.as_str().to_string(),
-                                )?;
-                                out.push_sql("::regconfig, ");
-                                out.push_bind_param::<Text, _>(&value)?;
-                            }
-                            out.push_sql("))");
-                        }
-
-                        Ok(())
+                    // Values for TSVector columns are only inserted, and
+                    // that uses `FulltextValue`
+                    ColumnType::TSVector(_) => {
+                        unreachable!("fulltext values are inserted with `FulltextValue`")
                     }
                 }
             }
@@ -630,6 +684,58 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
     }
 }
 
+/// The `tsvector` for the fulltext column `column` of `entity`, built from
+/// the fields that the column includes and their weights. Generates
+///   (setweight(to_tsvector($language::regconfig, $value), 'A') || ...)
+struct FulltextValue<'a> {
+    language: &'a FulltextLanguage,
+    values: Vec<(&'a str, FulltextWeight)>,
+}
+
+impl<'a> FulltextValue<'a> {
+    fn new(column: &'a Column, entity: &'a Entity) -> Self {
+        let language = match &column.column_type {
+            ColumnType::TSVector(config) => &config.language,
+            _ => unreachable!("only fulltext columns have fulltext values"),
+        };
+        let values = column
+            .fulltext_fields
+            .iter()
+            .flatten()
+            .filter_map(|(field, weight)| match entity.get(field) {
+                Some(Value::String(value)) => Some((value.as_str(), *weight)),
+                _ => None,
+            })
+            .collect();
+        FulltextValue { language, values }
+    }
+}
+
+impl<'a> QueryFragment<Pg> for FulltextValue<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        if self.values.is_empty() {
+            out.push_sql("''::tsvector");
+            return Ok(());
+        }
+
+        out.push_sql("(");
+        for (i, (value, weight)) in self.values.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(" || ");
+            }
+            out.push_sql("setweight(to_tsvector(");
+            out.push_bind_param::<Text, _>(&self.language.as_str().to_string())?;
+            out.push_sql("::regconfig, ");
+            out.push_bind_param::<Text, _>(value)?;
+            out.push_sql("), '");
+            out.push_sql(weight.as_str());
+            out.push_sql("')");
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 enum Comparison {
     Less,
@@ -893,7 +999,7 @@ impl<'a> QueryFilter<'a> {
         layout: &'a Layout,
         block: BlockNumber,
     ) -> Result<Self, StoreError> {
-        Self::valid_attributes(filter, table, layout, false)?;
+        Self::valid_attributes(filter, table, layout)?;
 
         Ok(QueryFilter {
             filter,
@@ -908,43 +1014,39 @@ impl<'a> QueryFilter<'a> {
         filter: &'a EntityFilter,
         table: &'a Table,
         layout: &'a Layout,
-        child_filter_ancestor: bool,
     ) -> Result<(), StoreError> {
         use EntityFilter::*;
         match filter {
             And(filters) | Or(filters) => {
                 for filter in filters {
-                    Self::valid_attributes(filter, table, layout, child_filter_ancestor)?;
+                    Self::valid_attributes(filter, table, layout)?;
                 }
             }
             Child(child) => {
-                if child_filter_ancestor {
-                    return Err(StoreError::QueryExecutionError(
-                        "Child filters can not be nested".to_string(),
-                    ));
-                }
-
+                let child_table = layout.table_for_entity(&child.entity_type)?;
+                // Make sure that the attribute name is valid for the given table
                 if child.derived {
-                    let derived_table = layout.table_for_entity(&child.entity_type)?;
-                    // Make sure that the attribute name is valid for the given table
-                    derived_table.column_for_field(child.attr.as_str())?;
-
-                    Self::valid_attributes(&child.filter, derived_table, layout, true)?;
+                    child_table.column_for_field(child.attr.as_str())?;
                 } else {
-                    // Make sure that the attribute name is valid for the given table
                     table.column_for_field(child.attr.as_str())?;
-
-                    Self::valid_attributes(
-                        &child.filter,
-                        layout.table_for_entity(&child.entity_type)?,
-                        layout,
-                        true,
-                    )?;
                 }
+
+                Self::valid_attributes(&child.filter, child_table, layout)?;
             }
             // This is a special case since we want to allow passing "block" column filter, but we dont
             // want to fail/error when this is passed here, since this column is not really an entity column.
             ChangeBlockGte(..) => {}
+            After(pos) => {
+                if let Some((attr, _)) = &pos.key {
+                    let column = table.column_for_field(attr)?;
+                    if column.is_fulltext() {
+                        return Err(StoreError::QueryExecutionError(format!(
+                            "cursors can not be used when sorting by the fulltext field `{}`",
+                            attr
+                        )));
+                    }
+                }
+            }
             Contains(attr, _)
             | ContainsNoCase(attr, _)
             | NotContains(attr, _)
@@ -994,12 +1096,19 @@ impl<'a> QueryFilter<'a> {
             .table_for_entity(entity_type)
             .expect("Table for child entity not found");
 
-        let child_prefix = "i.";
-        let parent_prefix = "c.";
+        // Child filters can be nested, and every level needs its own alias:
+        // the child of `c` is `i`, the child of `i` is `ii` and so on
+        let parent_prefix = self.table_prefix;
+        let child_alias = match parent_prefix {
+            "c." => "i".to_string(),
+            _ => format!("{}i", parent_prefix.trim_end_matches('.')),
+        };
+        let child_prefix = format!("{}.", child_alias);
 
         out.push_sql("exists (select 1 from ");
         out.push_sql(child_table.qualified_name.as_str());
-        out.push_sql(" as i");
+        out.push_sql(" as ");
+        out.push_sql(&child_alias);
 
         out.push_sql(" where ");
 
@@ -1018,7 +1127,7 @@ impl<'a> QueryFilter<'a> {
                 out.push_sql(parent_prefix);
                 out.push_identifier(parent_column.name.as_str())?;
                 out.push_sql(" = any(");
-                out.push_sql(child_prefix);
+                out.push_sql(&child_prefix);
                 out.push_identifier(child_column.name.as_str())?;
                 out.push_sql(")");
             } else {
@@ -1026,7 +1135,7 @@ impl<'a> QueryFilter<'a> {
                 out.push_sql(parent_prefix);
                 out.push_identifier(parent_column.name.as_str())?;
                 out.push_sql(" = ");
-                out.push_sql(child_prefix);
+                out.push_sql(&child_prefix);
                 out.push_identifier(child_column.name.as_str())?;
             }
         } else {
@@ -1041,7 +1150,7 @@ impl<'a> QueryFilter<'a> {
 
             if parent_column.is_list() {
                 // Type C: i.id = any(c.child_ids)
-                out.push_sql(child_prefix);
+                out.push_sql(&child_prefix);
                 out.push_identifier(child_column.name.as_str())?;
                 out.push_sql(" = any(");
                 out.push_sql(parent_prefix);
@@ -1049,7 +1158,7 @@ impl<'a> QueryFilter<'a> {
                 out.push_sql(")");
             } else {
                 // Type D: i.id = c.child_id
-                out.push_sql(child_prefix);
+                out.push_sql(&child_prefix);
                 out.push_identifier(child_column.name.as_str())?;
                 out.push_sql(" = ");
                 out.push_sql(parent_prefix);
@@ -1060,7 +1169,7 @@ impl<'a> QueryFilter<'a> {
         out.push_sql(" and ");
 
         // Match by block
-        BlockRangeColumn::new(&child_table, child_prefix, self.block).contains(&mut out)?;
+        BlockRangeColumn::new(&child_table, &child_prefix, self.block).contains(&mut out)?;
 
         out.push_sql(" and ");
 
@@ -1070,7 +1179,7 @@ impl<'a> QueryFilter<'a> {
             table: child_table,
             layout: self.layout,
             block: self.block,
-            table_prefix: child_prefix,
+            table_prefix: &child_prefix,
         };
 
         query_filter.walk_ast(out.reborrow())?;
@@ -1163,6 +1272,8 @@ impl<'a> QueryFilter<'a> {
             Value::Null
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::Bool(_)
             | Value::BigInt(_) => {
                 let filter = match negated {
@@ -1191,6 +1302,7 @@ impl<'a> QueryFilter<'a> {
         if matches!(value, Value::Null) {
             // Deal with nulls first since they always need special
             // treatment
+            out.push_sql(&self.table_prefix);
             out.push_identifier(column.name.as_str())?;
             match op {
                 Comparison::Equal => out.push_sql(" is null"),
@@ -1233,6 +1345,8 @@ impl<'a> QueryFilter<'a> {
                 | Value::Bytes(_)
                 | Value::BigDecimal(_)
                 | Value::Int(_)
+                | Value::Int8(_)
+                | Value::Timestamp(_)
                 | Value::String(_) => QueryValue(value, &column.column_type).walk_ast(out)?,
                 Value::Bool(_) | Value::List(_) | Value::Null => {
                     return Err(UnsupportedFilter {
@@ -1342,7 +1456,97 @@ impl<'a> QueryFilter<'a> {
         block_number_gte: &BlockNumber,
         mut out: AstPass<Pg>,
     ) -> QueryResult<()> {
-        BlockRangeColumn::new(self.table, "c.", *block_number_gte).changed_since(&mut out)
+        BlockRangeColumn::new(self.table, self.table_prefix, *block_number_gte)
+            .changed_since(&mut out)
+    }
+
+    /// Select the entities that come after `pos` in the order that
+    /// `SortKey` produces. This has to mirror `SortKey::sort_expr` exactly,
+    /// in particular where nulls are sorted and whether `id` is sorted in
+    /// the same direction as the sort key
+    fn after(&self, pos: &SortPosition, mut out: AstPass<Pg>) -> QueryResult<()> {
+        let key = match &pos.key {
+            Some((attr, value)) if !self.column(attr).is_primary_key() => {
+                Some((self.column(attr), value))
+            }
+            _ => None,
+        };
+        let old_order = ENV_VARS.store.reversible_order_by_off;
+        let key_op = if pos.descending { " < " } else { " > " };
+        // With the old order, `id` is always sorted ascending as the
+        // tie-breaker for the sort key
+        let id_op = if pos.descending && (key.is_none() || !old_order) {
+            " < "
+        } else {
+            " > "
+        };
+        let nulls_last = !pos.descending || old_order;
+        let primary_key = self.table.primary_key();
+
+        let (column, value) = match key {
+            Some(key) => key,
+            None => return self.compare_id(id_op, &pos.id, &mut out),
+        };
+
+        out.push_sql("(");
+        if value == &Value::Null {
+            // The entity is among the ones with a null sort key
+            out.push_sql("(");
+            out.push_sql(&self.table_prefix);
+            out.push_identifier(column.name.as_str())?;
+            out.push_sql(" is null and ");
+            self.compare_id(id_op, &pos.id, &mut out)?;
+            out.push_sql(")");
+            if !nulls_last {
+                out.push_sql(" or ");
+                out.push_sql(&self.table_prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(" is not null");
+            }
+        } else {
+            if key_op == id_op {
+                out.push_sql("(");
+                out.push_sql(&self.table_prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(", ");
+                out.push_sql(&self.table_prefix);
+                out.push_identifier(PRIMARY_KEY_COLUMN)?;
+                out.push_sql(")");
+                out.push_sql(key_op);
+                out.push_sql("(");
+                QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
+                out.push_sql(", ");
+                QueryValue(&pos.id, &primary_key.column_type).walk_ast(out.reborrow())?;
+                out.push_sql(")");
+            } else {
+                out.push_sql(&self.table_prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(key_op);
+                QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
+                out.push_sql(" or ");
+                out.push_sql(&self.table_prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(" = ");
+                QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
+                out.push_sql(" and ");
+                self.compare_id(id_op, &pos.id, &mut out)?;
+            }
+            if nulls_last {
+                out.push_sql(" or ");
+                out.push_sql(&self.table_prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(" is null");
+            }
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+
+    fn compare_id(&self, op: &str, id: &Value, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        out.push_sql(&self.table_prefix);
+        out.push_identifier(PRIMARY_KEY_COLUMN)?;
+        out.push_sql(op);
+        QueryValue(id, &self.table.primary_key().column_type).walk_ast(out.reborrow())
     }
 
     fn starts_or_ends_with(
@@ -1372,6 +1576,8 @@ impl<'a> QueryFilter<'a> {
             | Value::Bytes(_)
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::List(_)
             | Value::Null => {
                 return Err(UnsupportedFilter {
@@ -1434,6 +1640,7 @@ impl<'a> QueryFragment<Pg> for QueryFilter<'a> {
                 self.starts_or_ends_with(attr, value, " not ilike ", false, out)?
             }
             ChangeBlockGte(block_number) => self.filter_block_gte(block_number, out)?,
+            After(pos) => self.after(pos, out)?,
             Child(child) => self.child(
                 &child.attr,
                 &child.entity_type,
@@ -1655,7 +1862,7 @@ impl<'a> InsertQuery<'a> {
             for column in table.columns.iter() {
                 if let Some(fields) = column.fulltext_fields.as_ref() {
                     let fulltext_field_values = fields
-                        .iter()
+                        .keys()
                         .filter_map(|field| entity.get(field))
                         .cloned()
                         .collect::<Vec<Value>>();
@@ -1736,7 +1943,9 @@ impl<'a> QueryFragment<Pg> for InsertQuery<'a> {
             for column in &self.unique_columns {
                 // If the column name is not within this entity's fields, we will issue the
                 // null value in its place
-                if let Some(value) = entity.get(&column.field) {
+                if column.is_fulltext() && entity.contains_key(&column.field) {
+                    FulltextValue::new(column, entity).walk_ast(out.reborrow())?;
+                } else if let Some(value) = entity.get(&column.field) {
                     QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
                 } else {
                     out.push_sql("null");
@@ -1946,7 +2155,7 @@ impl<'a> ParentLimit<'a> {
     fn restrict(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
         if let ParentLimit::Ranked(sort_key, range) = self {
             out.push_sql(" ");
-            sort_key.order_by(out)?;
+            sort_key.order_by(false, out)?;
             range.walk_ast(out.reborrow())?;
         }
         Ok(())
@@ -2469,6 +2678,16 @@ impl<'a> FilterCollection<'a> {
         }
     }
 
+    /// Whether the query only uses one table, i.e., does not need to
+    /// combine the entities from several tables
+    fn single_table(&self) -> bool {
+        match self {
+            FilterCollection::All(entities) => entities.len() == 1,
+            FilterCollection::SingleWindow(_) => true,
+            FilterCollection::MultiWindow(..) => false,
+        }
+    }
+
     fn first_table(&self) -> Option<&Table> {
         match self {
             FilterCollection::All(entities) => entities.first().map(|pair| pair.0),
@@ -2530,6 +2749,15 @@ pub enum SortKey<'a> {
         value: Option<&'a str>,
         direction: &'static str,
     },
+    /// Order by `sort_column` of the entity in `child_table` whose id is
+    /// stored in `join_column`
+    ChildKey {
+        join_column: &'a Column,
+        child_table: &'a Table,
+        sort_column: &'a Column,
+        block: BlockNumber,
+        direction: &'static str,
+    },
 }
 
 /// String representation that is useful for debugging when `walk_ast` fails
@@ -2555,6 +2783,21 @@ impl<'a> fmt::Display for SortKey<'a> {
                 PRIMARY_KEY_COLUMN,
                 direction
             ),
+            ChildKey {
+                join_column,
+                child_table: _,
+                sort_column,
+                block: _,
+                direction,
+            } => write!(
+                f,
+                "{}.{} {}, {} {}",
+                join_column.name.as_str(),
+                sort_column.name.as_str(),
+                direction,
+                PRIMARY_KEY_COLUMN,
+                direction
+            ),
         }
     }
 }
@@ -2564,6 +2807,7 @@ impl<'a> SortKey<'a> {
         order: EntityOrder,
         collection: &'a FilterCollection,
         filter: Option<&'a EntityFilter>,
+        layout: &'a Layout,
         block: BlockNumber,
     ) -> Result<Self, QueryExecutionError> {
         const ASC: &str = "asc";
@@ -2605,6 +2849,41 @@ impl<'a> SortKey<'a> {
             }
         }
 
+        fn with_child_key<'a>(
+            collection: &'a FilterCollection,
+            table: &'a Table,
+            layout: &'a Layout,
+            child: EntityOrderByChild,
+            direction: &'static str,
+            block: BlockNumber,
+        ) -> Result<SortKey<'a>, QueryExecutionError> {
+            // The sort expression refers to the join column of the one
+            // table `c` that we query, which we do not have when we query
+            // several tables at once
+            if !collection.single_table() {
+                return Err(QueryExecutionError::NotSupported(format!(
+                    "sorting by `{}__{}` is not supported for interfaces",
+                    child.join_attribute, child.sort_by_attribute
+                )));
+            }
+            let join_column = table.column_for_field(&child.join_attribute)?;
+            let child_table = layout.table_for_entity(&child.entity_type)?;
+            let sort_column = child_table.column_for_field(&child.sort_by_attribute)?;
+            if sort_column.is_fulltext() {
+                return Err(QueryExecutionError::OrderByNotSupportedError(
+                    child.entity_type.to_string(),
+                    child.sort_by_attribute,
+                ));
+            }
+            Ok(SortKey::ChildKey {
+                join_column,
+                child_table: child_table.as_ref(),
+                sort_column,
+                block,
+                direction,
+            })
+        }
+
         // If there is more than one table, we are querying an interface,
         // and the order is on an attribute in that interface so that all
         // tables have a column for that. It is therefore enough to just
@@ -2622,6 +2901,12 @@ impl<'a> SortKey<'a> {
         match order {
             EntityOrder::Ascending(attr, _) => with_key(table, attr, filter, ASC, br_column),
             EntityOrder::Descending(attr, _) => with_key(table, attr, filter, DESC, br_column),
+            EntityOrder::ChildAscending(child) => {
+                with_child_key(collection, table, layout, child, ASC, block)
+            }
+            EntityOrder::ChildDescending(child) => {
+                with_child_key(collection, table, layout, child, DESC, block)
+            }
             EntityOrder::Default => Ok(SortKey::IdAsc(br_column)),
             EntityOrder::Unordered => Ok(SortKey::None),
         }
@@ -2640,22 +2925,41 @@ impl<'a> SortKey<'a> {
             }
             SortKey::Key {
                 column,
-                value: _,
+                value,
                 direction: _,
             } => {
                 if column.is_primary_key() {
                     return Err(constraint_violation!("SortKey::Key never uses 'id'"));
                 }
-                out.push_sql(", c.");
-                out.push_identifier(column.name.as_str())?;
+                match (&column.column_type, value) {
+                    (ColumnType::TSVector(_), Some(text)) => {
+                        // Select the rank under a name so that queries
+                        // that combine several tables with `union all`
+                        // can order by it
+                        out.push_sql(", ");
+                        SortKey::fulltext_rank(column, text, "c.", out)?;
+                        out.push_sql(" as g$rank");
+                    }
+                    _ => {
+                        out.push_sql(", c.");
+                        out.push_identifier(column.name.as_str())?;
+                    }
+                }
                 Ok(())
             }
+            SortKey::ChildKey { .. } => Err(constraint_violation!(
+                "SortKey::ChildKey can only be used when querying a single table"
+            )),
         }
     }
 
     /// Generate
     ///   order by [name direction], id
-    fn order_by(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+    ///
+    /// If `selected` is true, the sort key was selected with `select` in
+    /// a query that combines several tables with `union all`, and the
+    /// order can only refer to the selected columns
+    fn order_by(&self, selected: bool, out: &mut AstPass<Pg>) -> QueryResult<()> {
         match self {
             SortKey::None => Ok(()),
             SortKey::IdAsc(br_column) => {
@@ -2684,14 +2988,20 @@ impl<'a> SortKey<'a> {
                 direction,
             } => {
                 out.push_sql("order by ");
-                SortKey::sort_expr(column, value, direction, out)
+                SortKey::sort_expr(column, value, direction, selected, out)
+            }
+            SortKey::ChildKey { .. } => {
+                out.push_sql("order by ");
+                self.child_sort_expr(out)
             }
         }
     }
 
     /// Generate
     ///   order by g$parent_id, [name direction], id
-    fn order_by_parent(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+    ///
+    /// See `order_by` for the meaning of `selected`
+    fn order_by_parent(&self, selected: bool, out: &mut AstPass<Pg>) -> QueryResult<()> {
         match self {
             SortKey::None => Ok(()),
             SortKey::IdAsc(_) => {
@@ -2710,7 +3020,11 @@ impl<'a> SortKey<'a> {
                 direction,
             } => {
                 out.push_sql("order by g$parent_id, ");
-                SortKey::sort_expr(column, value, direction, out)
+                SortKey::sort_expr(column, value, direction, selected, out)
+            }
+            SortKey::ChildKey { .. } => {
+                out.push_sql("order by g$parent_id, ");
+                self.child_sort_expr(out)
             }
         }
     }
@@ -2721,6 +3035,7 @@ impl<'a> SortKey<'a> {
         column: &Column,
         value: &Option<&str>,
         direction: &str,
+        selected: bool,
         out: &mut AstPass<Pg>,
     ) -> QueryResult<()> {
         if column.is_primary_key() {
@@ -2731,24 +3046,137 @@ impl<'a> SortKey<'a> {
         }
 
         match &column.column_type {
-            ColumnType::TSVector(config) => {
-                let algorithm = match config.algorithm {
-                    FulltextAlgorithm::Rank => "ts_rank(",
-                    FulltextAlgorithm::ProximityRank => "ts_rank_cd(",
-                };
-                out.push_sql(algorithm);
+            ColumnType::TSVector(_) if selected => {
+                out.push_sql("g$rank");
+            }
+            ColumnType::TSVector(_) => {
+                SortKey::fulltext_rank(column, value.unwrap(), "", out)?;
+            }
+            _ => {
                 let name = column.name.as_str();
                 out.push_identifier(name)?;
-                out.push_sql(", to_tsquery(");
+            }
+        }
+        SortKey::sort_direction(direction, out)
+    }
 
-                out.push_bind_param::<Text, _>(&value.unwrap())?;
-                out.push_sql("))");
+    /// The fulltext column and the search text if this sort key orders
+    /// by the rank of a fulltext search
+    fn fulltext(&self) -> Option<(&'a Column, &'a str)> {
+        match self {
+            SortKey::Key {
+                column,
+                value: Some(text),
+                direction: _,
+            } if column.is_fulltext() => Some((*column, *text)),
+            _ => None,
+        }
+    }
+
+    /// Generate
+    ///   ts_rank({prefix}{column}, to_tsquery($text))
+    fn fulltext_rank(
+        column: &Column,
+        text: &str,
+        prefix: &str,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let algorithm = match &column.column_type {
+            ColumnType::TSVector(config) => match config.algorithm {
+                FulltextAlgorithm::Rank => "ts_rank(",
+                FulltextAlgorithm::ProximityRank => "ts_rank_cd(",
+            },
+            _ => {
+                return Err(constraint_violation!(
+                    "fulltext_rank called with column `{}` that is not a fulltext column",
+                    column.name.as_str()
+                ))
             }
+        };
+        out.push_sql(algorithm);
+        out.push_sql(prefix);
+        out.push_identifier(column.name.as_str())?;
+        out.push_sql(", to_tsquery(");
+        out.push_bind_param::<Text, _>(&text)?;
+        out.push_sql("))");
+        Ok(())
+    }
+
+    /// Generate
+    ///   ts_headline($language::regconfig,
+    ///               concat_ws(' ', {prefix}{field}, ..), to_tsquery($text))
+    /// for the fields of `table` that the fulltext column `column` includes
+    fn fulltext_snippet(
+        table: &Table,
+        column: &Column,
+        text: &str,
+        prefix: &str,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let (language, fields) = match (&column.column_type, &column.fulltext_fields) {
+            (ColumnType::TSVector(config), Some(fields)) => (&config.language, fields),
             _ => {
-                let name = column.name.as_str();
-                out.push_identifier(name)?;
+                return Err(constraint_violation!(
+                    "fulltext_snippet called with column `{}` that is not a fulltext column",
+                    column.name.as_str()
+                ))
             }
+        };
+        out.push_sql("ts_headline(");
+        out.push_bind_param::<Text, _>(&language.as_str().to_string())?;
+        out.push_sql("::regconfig, concat_ws(' '");
+        for field in fields.keys() {
+            let source = table
+                .column_for_field(field)
+                .map_err(|e| constraint_violation!("{}", e))?;
+            out.push_sql(", ");
+            out.push_sql(prefix);
+            out.push_identifier(source.name.as_str())?;
         }
+        out.push_sql("), to_tsquery(");
+        out.push_bind_param::<Text, _>(&text)?;
+        out.push_sql("))");
+        Ok(())
+    }
+
+    /// Generate
+    ///   (select cc.{sort_column} from {child_table} cc
+    ///     where cc.id = c.{join_column} and {block range}) direction, id
+    ///
+    /// The subquery joins the entity with its child at the query block
+    fn child_sort_expr(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        let (join_column, child_table, sort_column, block, direction) = match self {
+            SortKey::ChildKey {
+                join_column,
+                child_table,
+                sort_column,
+                block,
+                direction,
+            } => (join_column, child_table, sort_column, *block, direction),
+            _ => {
+                return Err(constraint_violation!(
+                    "child_sort_expr called with a sort key that is not a SortKey::ChildKey"
+                ))
+            }
+        };
+
+        out.push_sql("(select cc.");
+        out.push_identifier(sort_column.name.as_str())?;
+        out.push_sql(" from ");
+        out.push_sql(child_table.qualified_name.as_str());
+        out.push_sql(" cc where cc.");
+        out.push_identifier(child_table.primary_key().name.as_str())?;
+        out.push_sql(" = c.");
+        out.push_identifier(join_column.name.as_str())?;
+        out.push_sql(" and ");
+        BlockRangeColumn::new(child_table, "cc.", block).contains(out)?;
+        out.push_sql(")");
+        SortKey::sort_direction(direction, out)
+    }
+
+    /// Generate
+    ///   direction, id [direction]
+    fn sort_direction(direction: &str, out: &mut AstPass<Pg>) -> QueryResult<()> {
         if ENV_VARS.store.reversible_order_by_off {
             // Old behavior
             out.push_sql(" ");
@@ -2812,6 +3240,7 @@ pub struct FilterQuery<'a> {
     collection: &'a FilterCollection<'a>,
     sort_key: SortKey<'a>,
     range: FilterRange,
+    fulltext: FulltextOutput,
     block: BlockNumber,
     query_id: Option<String>,
 }
@@ -2837,20 +3266,82 @@ impl<'a> FilterQuery<'a> {
         filter: Option<&'a EntityFilter>,
         order: EntityOrder,
         range: EntityRange,
+        fulltext: FulltextOutput,
+        layout: &'a Layout,
         block: BlockNumber,
         query_id: Option<String>,
     ) -> Result<Self, QueryExecutionError> {
-        let sort_key = SortKey::new(order, collection, filter, block)?;
+        let sort_key = SortKey::new(order, collection, filter, layout, block)?;
 
         Ok(FilterQuery {
             collection,
             sort_key,
             range: FilterRange(range),
+            fulltext,
             block,
             query_id,
         })
     }
 
+    /// The fulltext column of `table` and the search text when the rank
+    /// or snippet of a fulltext search should be returned. When we query
+    /// several tables, they all have a fulltext column with the same name
+    fn fulltext_column<'b>(&self, table: &'b Table) -> QueryResult<Option<(&'b Column, &'a str)>> {
+        if self.fulltext == FulltextOutput::default() {
+            return Ok(None);
+        }
+        match self.sort_key.fulltext() {
+            Some((column, text)) => table
+                .column_for_field(&column.field)
+                .map(|column| Some((column, text)))
+                .map_err(|e| constraint_violation!("{}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Generate
+    ///   , {rank} as g$rank, {snippet} as g$snippet
+    /// for selecting the rank and snippet of a fulltext search together
+    /// with the columns of `table`
+    fn select_fulltext(&self, table: &Table, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        if let Some((column, text)) = self.fulltext_column(table)? {
+            if self.fulltext.rank {
+                out.push_sql(", ");
+                SortKey::fulltext_rank(column, text, "c.", out)?;
+                out.push_sql(" as g$rank");
+            }
+            if self.fulltext.snippet {
+                out.push_sql(", ");
+                SortKey::fulltext_snippet(table, column, text, "c.", out)?;
+                out.push_sql(" as g$snippet");
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate
+    ///   || jsonb_build_object('g$rank', {rank}, 'g$snippet', {snippet})
+    /// for adding the rank and snippet of a fulltext search to the JSONB
+    /// data for the entities in `table`
+    fn build_fulltext_object(&self, table: &Table, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        if let Some((column, text)) = self.fulltext_column(table)? {
+            out.push_sql(" || jsonb_build_object(");
+            if self.fulltext.rank {
+                out.push_sql("'g$rank', ");
+                SortKey::fulltext_rank(column, text, "c.", out)?;
+            }
+            if self.fulltext.snippet {
+                if self.fulltext.rank {
+                    out.push_sql(", ");
+                }
+                out.push_sql("'g$snippet', ");
+                SortKey::fulltext_snippet(table, column, text, "c.", out)?;
+            }
+            out.push_sql(")");
+        }
+        Ok(())
+    }
+
     /// Generate
     ///     from schema.table c
     ///    where block_range @> $block
@@ -2890,7 +3381,7 @@ impl<'a> FilterQuery<'a> {
     ///
     ///   select '..' as entity, to_jsonb(e.*) as data
     ///     from
-    ///       (select {column names}
+    ///       (select {column names} [, {fulltext rank and snippet}]
     ///          from table c
     ///         where block_range @> $block
     ///           and filter
@@ -2905,9 +3396,10 @@ impl<'a> FilterQuery<'a> {
         Self::select_entity_and_data(table, &mut out);
         out.push_sql(" from (select ");
         write_column_names(column_names, table, &mut out)?;
+        self.select_fulltext(table, &mut out)?;
         self.filtered_rows(table, filter, out.reborrow())?;
         out.push_sql("\n ");
-        self.sort_key.order_by(&mut out)?;
+        self.sort_key.order_by(false, &mut out)?;
         self.range.walk_ast(out.reborrow())?;
         out.push_sql(") c");
         Ok(())
@@ -2935,7 +3427,7 @@ impl<'a> FilterQuery<'a> {
         )?;
         out.push_sql(") c");
         out.push_sql("\n ");
-        self.sort_key.order_by_parent(&mut out)
+        self.sort_key.order_by_parent(false, &mut out)
     }
 
     /// No windowing, but multiple entity types
@@ -2962,12 +3454,17 @@ impl<'a> FilterQuery<'a> {
         //    order by {sort_key}
         //    limit n offset m)
         //
-        // select m.entity, to_jsonb({column names}) as data, c.id, c.{sort_key}
+        // select m.entity, to_jsonb({column names}) [|| {fulltext rank and snippet}] as data,
+        //        c.id, c.{sort_key}
         //   from {table} c, matches m
         //  where c.vid = m.vid and m.entity = '...'
         //  union all
         //  ...
         //  order by c.{sort_key}
+        //
+        // When we sort by the rank of a fulltext search, {sort_key} selects
+        // the rank as `g$rank` and we order by that since the order of a
+        // `union all` can only refer to its output columns
 
         // Step 1: build matches CTE
         out.push_sql("with matches as (");
@@ -2986,7 +3483,7 @@ impl<'a> FilterQuery<'a> {
             self.filtered_rows(table, filter, out.reborrow())?;
         }
         out.push_sql("\n ");
-        self.sort_key.order_by(&mut out)?;
+        self.sort_key.order_by(true, &mut out)?;
         self.range.walk_ast(out.reborrow())?;
 
         out.push_sql(")\n");
@@ -2998,6 +3495,7 @@ impl<'a> FilterQuery<'a> {
             }
             out.push_sql("select m.entity, ");
             jsonb_build_object(column_names, "c", table, &mut out)?;
+            self.build_fulltext_object(table, &mut out)?;
             out.push_sql(" as data, c.id");
             self.sort_key.select(&mut out)?;
             out.push_sql("\n  from ");
@@ -3008,7 +3506,7 @@ impl<'a> FilterQuery<'a> {
             out.push_bind_param::<Text, _>(&table.object.as_str())?;
         }
         out.push_sql("\n ");
-        self.sort_key.order_by(&mut out)?;
+        self.sort_key.order_by(true, &mut out)?;
         Ok(())
     }
 
@@ -3059,7 +3557,7 @@ impl<'a> FilterQuery<'a> {
             window.children_uniform(&self.sort_key, self.block, out.reborrow())?;
         }
         out.push_sql("\n");
-        self.sort_key.order_by(&mut out)?;
+        self.sort_key.order_by(true, &mut out)?;
         self.range.walk_ast(out.reborrow())?;
         out.push_sql(") c)\n");
 
@@ -3097,7 +3595,7 @@ impl<'a> FilterQuery<'a> {
             out.push_sql("'");
         }
         out.push_sql("\n ");
-        self.sort_key.order_by_parent(&mut out)
+        self.sort_key.order_by_parent(true, &mut out)
     }
 }
 
@@ -3154,6 +3652,55 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FilterQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FilterQuery<'a> {}
 
+/// Run `EXPLAIN (ANALYZE, BUFFERS)` for a query. Note that this runs the
+/// query, and therefore takes at least as long as the query itself
+#[derive(Debug)]
+pub struct ExplainQuery<'a, Q> {
+    query: &'a Q,
+}
+
+impl<'a, Q: QueryFragment<Pg>> ExplainQuery<'a, Q> {
+    pub fn new(query: &'a Q) -> Self {
+        ExplainQuery { query }
+    }
+}
+
+impl<'a, Q: QueryFragment<Pg>> QueryFragment<Pg> for ExplainQuery<'a, Q> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("explain (analyze, buffers) ");
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+impl<'a, Q> QueryId for ExplainQuery<'a, Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// One line of the output of `EXPLAIN`
+pub struct ExplainLine {
+    pub line: String,
+}
+
+impl deserialize::QueryableByName<Pg> for ExplainLine {
+    fn build<R: NamedRow<Pg>>(row: &R) -> deserialize::Result<Self> {
+        // The name of the column has a space in it, which the derive
+        // macro can not handle
+        let line = row.get::<Text, _>("QUERY PLAN")?;
+        Ok(ExplainLine { line })
+    }
+}
+
+impl<'a, Q: QueryFragment<Pg>> LoadQuery<PgConnection, ExplainLine> for ExplainQuery<'a, Q> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<ExplainLine>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Q, Conn> RunQueryDsl<Conn> for ExplainQuery<'a, Q> {}
+
 /// Reduce the upper bound of the current entry's block range to `block` as
 /// long as that does not result in an empty block range
 #[derive(Debug)]
@@ -3377,6 +3924,260 @@ impl<'a> LoadQuery<PgConnection, ReturnedEntityData> for RevertClampQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for RevertClampQuery<'a> {}
 
+/// A query that removes all versions that are not visible at
+/// `earliest_block` or any later block, i.e., whose block range ends at or
+/// before `earliest_block`
+#[derive(Debug, Clone)]
+pub struct PruneQuery<'a> {
+    table: &'a Table,
+    earliest_block: BlockNumber,
+}
+
+impl<'a> PruneQuery<'a> {
+    pub(crate) fn new(table: &'a Table, earliest_block: BlockNumber) -> Result<Self, StoreError> {
+        if table.immutable {
+            Err(graph::constraint_violation!(
+                "can not prune immutable table `{}`",
+                table.qualified_name
+            ))
+        } else {
+            Ok(Self {
+                table,
+                earliest_block,
+            })
+        }
+    }
+}
+
+impl<'a> QueryFragment<Pg> for PruneQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Construct a query
+        //   delete from table
+        //    where upper(block_range) <= $earliest_block
+        //
+        // Current versions have an unbounded upper end, and therefore
+        // `upper(block_range)` is null for them, so that they are never
+        // removed
+        out.push_sql("delete from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql("\n where upper(");
+        out.push_identifier(BLOCK_RANGE_COLUMN)?;
+        out.push_sql(") <= ");
+        out.push_bind_param::<Integer, _>(&self.earliest_block)?;
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for PruneQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for PruneQuery<'a> {}
+
+/// A query that computes aggregates over all entities in `table` that are
+/// visible at `block` and match `filter`. Postgres returns a single row
+/// with the value of each aggregate, in the order in which they were
+/// requested, as text
+#[derive(Debug, Clone)]
+pub struct AggregateQuery<'a> {
+    table: &'a Table,
+    filter: Option<QueryFilter<'a>>,
+    aggregates: Vec<(&'a EntityAggregate, Option<&'a Column>)>,
+    block: BlockNumber,
+}
+
+impl<'a> AggregateQuery<'a> {
+    pub fn new(
+        layout: &'a Layout,
+        table: &'a Table,
+        filter: Option<&'a EntityFilter>,
+        aggregates: &'a [EntityAggregate],
+        block: BlockNumber,
+    ) -> Result<Self, StoreError> {
+        let filter = filter
+            .map(|filter| QueryFilter::new(filter, table, layout, block))
+            .transpose()?;
+        let aggregates = aggregates
+            .iter()
+            .map(|aggregate| {
+                let column = match aggregate.attribute() {
+                    Some(attr) => {
+                        let column = table.column_for_field(attr)?;
+                        let numeric = matches!(
+                            column.column_type,
+                            ColumnType::Int
+                                | ColumnType::Int8
+                                | ColumnType::BigInt
+                                | ColumnType::BigDecimal
+                        );
+                        if column.is_list() || !numeric {
+                            return Err(StoreError::QueryExecutionError(format!(
+                                "can not aggregate attribute `{}` of `{}` since it is not numeric",
+                                attr, table.object
+                            )));
+                        }
+                        Some(column)
+                    }
+                    None => None,
+                };
+                Ok((aggregate, column))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(AggregateQuery {
+            table,
+            filter,
+            aggregates,
+            block,
+        })
+    }
+
+    /// Turn the raw `data` returned by running this query into values.
+    /// Sums and extrema of `Int` attributes are returned as `BigInt` since
+    /// they may not fit into an `Int`, and averages are always returned as
+    /// `BigDecimal`
+    pub fn values(&self, data: AggregateData) -> Result<Vec<Value>, StoreError> {
+        fn parse<T: FromStr>(text: &str) -> Result<T, StoreError>
+        where
+            T::Err: Display,
+        {
+            T::from_str(text).map_err(|e| {
+                graph::constraint_violation!(
+                    "invalid aggregate value `{}` from database: {}",
+                    text,
+                    e
+                )
+            })
+        }
+
+        if data.aggregates.len() != self.aggregates.len() {
+            return Err(graph::constraint_violation!(
+                "expected {} aggregate values but got {}",
+                self.aggregates.len(),
+                data.aggregates.len()
+            ));
+        }
+
+        self.aggregates
+            .iter()
+            .zip(data.aggregates.into_iter())
+            .map(|((aggregate, column), text)| {
+                let text = match text {
+                    Some(text) => text,
+                    // Aggregates other than `count` over an empty set of
+                    // entities are `null`
+                    None => return Ok(Value::Null),
+                };
+                let value = match (aggregate, column.map(|column| &column.column_type)) {
+                    (EntityAggregate::Count, _) => {
+                        let count = parse::<i64>(&text)?;
+                        let count = i32::try_from(count).map_err(|_| {
+                            StoreError::QueryExecutionError(format!(
+                                "the number of `{}` entities, {}, is too large to be returned",
+                                self.table.object, count
+                            ))
+                        })?;
+                        Value::Int(count)
+                    }
+                    (EntityAggregate::Avg(_), _) | (_, Some(ColumnType::BigDecimal)) => {
+                        Value::BigDecimal(parse::<scalar::BigDecimal>(&text)?)
+                    }
+                    (_, _) => Value::BigInt(parse::<scalar::BigInt>(&text)?),
+                };
+                Ok(value)
+            })
+            .collect()
+    }
+}
+
+impl<'a> QueryFragment<Pg> for AggregateQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Construct a query
+        //   select array[count(*)::text, sum(c.attr)::text, ..] as aggregates
+        //     from table c
+        //    where block_range @> $block
+        //      and filter
+        out.push_sql("select array[");
+        for (i, (aggregate, column)) in self.aggregates.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            let func = match aggregate {
+                EntityAggregate::Count => "count",
+                EntityAggregate::Sum(_) => "sum",
+                EntityAggregate::Min(_) => "min",
+                EntityAggregate::Max(_) => "max",
+                EntityAggregate::Avg(_) => "avg",
+            };
+            out.push_sql(func);
+            out.push_sql("(");
+            match column {
+                Some(column) => {
+                    out.push_sql("c.");
+                    out.push_identifier(column.name.as_str())?;
+                }
+                None => out.push_sql("*"),
+            }
+            out.push_sql(")::text");
+        }
+        out.push_sql("]::text[] as aggregates\n  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" c\n where ");
+        BlockRangeColumn::new(self.table, "c.", self.block).contains(&mut out)?;
+        if let Some(filter) = &self.filter {
+            out.push_sql(" and ");
+            filter.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for AggregateQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, AggregateData> for AggregateQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<AggregateData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for AggregateQuery<'a> {}
+
+/// The raw result of an `AggregateQuery`
+#[derive(QueryableByName)]
+pub struct AggregateData {
+    #[sql_type = "Array<Nullable<Text>>"]
+    aggregates: Vec<Option<String>>,
+}
+
+/// String representation that is useful for debugging when `walk_ast` fails
+impl<'a> fmt::Display for AggregateQuery<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let aggregates = self
+            .aggregates
+            .iter()
+            .map(|(aggregate, _)| format!("{:?}", aggregate))
+            .join(", ");
+        write!(
+            f,
+            "{} of {} at {}",
+            aggregates, self.table.object, self.block
+        )?;
+        if let Some(filter) = &self.filter {
+            write!(f, " where {}", filter)?;
+        }
+        Ok(())
+    }
+}
+
 #[test]
 fn block_number_max_is_i32_max() {
     // The code in RevertClampQuery::walk_ast embeds i32::MAX
@@ -0,0 +1,171 @@
+//! This module is responsible for loading entities into the tables of a
+//! freshly created deployment. It is used to seed a deployment from a dump
+//! of another deployment, for example one produced with `graphman dump`.
+//!
+//! The entities are streamed into each table with `COPY FROM STDIN` which
+//! is much faster than inserting them with the normal write path. All
+//! entity versions are made visible starting at the block of the snapshot,
+//! which means that the restored deployment has no history before that
+//! block.
+use std::io::Write;
+
+use diesel::{connection::SimpleConnection, PgConnection};
+use graph::prelude::{anyhow, BlockNumber, Entity, StoreError, Value};
+use postgres::Client;
+
+use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
+use crate::relational::{ColumnType, Table};
+
+/// The entities that should be loaded into one table
+pub type EntitySource = Box<dyn Iterator<Item = Result<Entity, anyhow::Error>>>;
+
+/// Load all `entities` into `table` with `COPY` and mark them as valid
+/// from `block` on. Return the number of entities that were loaded
+pub(crate) fn load_table(
+    client: &mut Client,
+    table: &Table,
+    block: BlockNumber,
+    entities: EntitySource,
+) -> Result<usize, StoreError> {
+    // Fulltext columns can't be written with `COPY`; they are filled in
+    // by `fill_fulltext` afterwards
+    let columns: Vec<_> = table
+        .columns
+        .iter()
+        .filter(|column| !column.is_fulltext())
+        .collect();
+    let (block_column, block_value) = if table.immutable {
+        (BLOCK_COLUMN, block.to_string())
+    } else {
+        (BLOCK_RANGE_COLUMN, format!("\"[{},)\"", block))
+    };
+    let names: Vec<_> = columns
+        .iter()
+        .map(|column| column.name.quoted())
+        .chain(std::iter::once(block_column.to_string()))
+        .collect();
+    let query = format!(
+        "copy {}({}) from stdin with (format csv)",
+        table.qualified_name,
+        names.join(", ")
+    );
+
+    let mut writer = client.copy_in(query.as_str()).map_err(unknown)?;
+    let mut count = 0;
+    let mut row = String::new();
+    for entity in entities {
+        let entity = entity.map_err(StoreError::Unknown)?;
+        row.clear();
+        for column in &columns {
+            match entity.get(&column.field) {
+                None | Some(Value::Null) => { /* an unquoted empty field is null */ }
+                Some(value) => csv_quote(&mut row, &pg_text(value)),
+            }
+            row.push(',');
+        }
+        row.push_str(&block_value);
+        row.push('\n');
+        writer.write_all(row.as_bytes()).map_err(unknown)?;
+        count += 1;
+    }
+    writer.finish().map_err(unknown)?;
+    Ok(count)
+}
+
+/// Compute the fulltext search columns of `table` from the fields they
+/// include. This must be called after all entities have been loaded
+pub(crate) fn fill_fulltext(conn: &PgConnection, table: &Table) -> Result<(), StoreError> {
+    for column in &table.columns {
+        let (config, fields) = match (&column.column_type, &column.fulltext_fields) {
+            (ColumnType::TSVector(config), Some(fields)) => (config, fields),
+            _ => continue,
+        };
+        let vectors = fields
+            .iter()
+            .map(|(field, weight)| {
+                table.column_for_field(field).map(|source| {
+                    format!(
+                        "setweight(to_tsvector('{}'::regconfig, coalesce({}, '')), '{}')",
+                        config.language.as_str(),
+                        source.name.quoted(),
+                        weight.as_str()
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if vectors.is_empty() {
+            continue;
+        }
+        let query = format!(
+            "update {} set {} = {}",
+            table.qualified_name,
+            column.name.quoted(),
+            vectors.join(" || ")
+        );
+        conn.batch_execute(&query)?;
+    }
+    Ok(())
+}
+
+/// Format `value` the way Postgres expects it as text input
+fn pg_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Bytes(b) => format!("\\x{}", hex::encode(b.as_slice())),
+        Value::Timestamp(ts) => ts.as_datetime().to_rfc3339(),
+        Value::List(values) => {
+            let elements: Vec<_> = values
+                .iter()
+                .map(|value| match value {
+                    Value::Null => "NULL".to_string(),
+                    value => format!(
+                        "\"{}\"",
+                        pg_text(value).replace('\\', "\\\\").replace('"', "\\\"")
+                    ),
+                })
+                .collect();
+            format!("{{{}}}", elements.join(","))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Append `s` to `out` as a quoted CSV field. We always quote so that
+/// empty strings can be distinguished from nulls
+fn csv_quote(out: &mut String, s: &str) {
+    out.push('"');
+    out.push_str(&s.replace('"', "\"\""));
+    out.push('"');
+}
+
+fn unknown<E: Into<anyhow::Error>>(e: E) -> StoreError {
+    StoreError::Unknown(e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::data::store::scalar::Bytes;
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn pg_text_formats_values() {
+        let bytes = Bytes::from_str("0x01ff").unwrap();
+        assert_eq!("\\x01ff", pg_text(&Value::Bytes(bytes.clone())));
+        assert_eq!("12", pg_text(&Value::Int(12)));
+        assert_eq!("a,\"b\"", pg_text(&Value::String("a,\"b\"".to_string())));
+
+        let list = Value::List(vec![
+            Value::String("a\"b".to_string()),
+            Value::Null,
+            Value::Bytes(bytes),
+        ]);
+        assert_eq!("{\"a\\\"b\",NULL,\"\\\\x01ff\"}", pg_text(&list));
+
+        let mut row = String::new();
+        csv_quote(&mut row, "");
+        csv_quote(&mut row, "say \"hi\"");
+        assert_eq!("\"\"\"say \"\"hi\"\"\"", row);
+    }
+}
@@ -1,6 +1,6 @@
 use diesel::pg::Pg;
 use diesel::serialize::{self, Output, ToSql};
-use diesel::sql_types::{Binary, Bool, Integer, Text};
+use diesel::sql_types::{BigInt, Binary, Bool, Integer, Text, Timestamptz};
 use graph::prelude::anyhow::anyhow;
 use std::io::Write;
 use std::str::FromStr;
@@ -42,6 +42,32 @@ impl ToSql<Integer, Pg> for SqlValue {
     }
 }
 
+impl ToSql<BigInt, Pg> for SqlValue {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match &self.0 {
+            Value::Int8(i) => <i64 as ToSql<BigInt, Pg>>::to_sql(i, out),
+            v => Err(anyhow!(
+                "Failed to convert non-int8 attribute value to int8 in SQL: {}",
+                v
+            )
+            .into()),
+        }
+    }
+}
+
+impl ToSql<Timestamptz, Pg> for SqlValue {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match &self.0 {
+            Value::Timestamp(ts) => <_ as ToSql<Timestamptz, Pg>>::to_sql(ts.as_datetime(), out),
+            v => Err(anyhow!(
+                "Failed to convert non-timestamp attribute value to timestamptz in SQL: {}",
+                v
+            )
+            .into()),
+        }
+    }
+}
+
 impl ToSql<Text, Pg> for SqlValue {
     fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
         match &self.0 {
@@ -23,9 +23,10 @@ use graph::{
     prelude::StoreEvent,
     prelude::{
         anyhow, futures03::future::join_all, lazy_static, o, web3::types::Address, ApiSchema,
-        BlockHash, BlockNumber, BlockPtr, ChainStore, DeploymentHash, EntityOperation, Logger,
-        MetricsRegistry, NodeId, PartialBlockPtr, Schema, StoreError, SubgraphName,
-        SubgraphStore as SubgraphStoreTrait, SubgraphVersionSwitchingMode,
+        BlockHash, BlockNumber, BlockPtr, ChainStore, DeploymentHash, Entity, EntityOperation,
+        Logger, MetricsRegistry, NodeId, PartialBlockPtr, Schema, StoreError,
+        SubgraphDeploymentEntity, SubgraphName, SubgraphStore as SubgraphStoreTrait,
+        SubgraphVersionSwitchingMode,
     },
     url::Url,
     util::timed_cache::TimedCache,
@@ -37,7 +38,8 @@ use crate::{
     deployment::SubgraphHealth,
     primary,
     primary::{DeploymentId, Mirror as PrimaryMirror, Site},
-    relational::Layout,
+    relational::{Layout, Table},
+    restore::EntitySource,
     writable::WritableStore,
     NotificationSender,
 };
@@ -1007,6 +1009,123 @@ impl SubgraphStoreInner {
         store.analyze(site, entity_name)
     }
 
+    /// Prune historical entity versions of `deployment` so that only the
+    /// last `history_blocks` blocks can be queried. See
+    /// `DeploymentStore::prune` for details
+    pub fn prune(
+        &self,
+        deployment: &DeploymentLocator,
+        history_blocks: Option<BlockNumber>,
+    ) -> Result<(BlockNumber, usize), StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.prune(site, history_blocks)
+    }
+
+    /// Pass all entities of `deployment` as of `block` to `sink`. See
+    /// `DeploymentStore::dump` for details
+    pub fn dump(
+        &self,
+        deployment: &DeploymentLocator,
+        block: Option<BlockNumber>,
+        batch_size: u32,
+        sink: &mut dyn FnMut(&Table, Vec<Entity>) -> Result<(), anyhow::Error>,
+    ) -> Result<BlockNumber, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.dump(site, block, batch_size, sink)
+    }
+
+    /// The number of dynamic data sources that `deployment` created up to
+    /// and including `block`
+    pub fn dynamic_data_source_count(
+        &self,
+        deployment: &DeploymentLocator,
+        block: BlockNumber,
+    ) -> Result<usize, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.dynamic_data_source_count(site, block)
+    }
+
+    /// Create a new deployment and seed it with the entities that `source`
+    /// produces for each table, for example from a dump made with
+    /// `graphman dump`. The deployment starts out at `head` and is only
+    /// assigned to `node_id` once all entities have been loaded. Return the
+    /// new deployment and the number of entities that were loaded
+    pub fn restore(
+        &self,
+        name: SubgraphName,
+        schema: &Schema,
+        deployment: DeploymentCreate,
+        node_id: NodeId,
+        network_name: String,
+        head: BlockPtr,
+        source: &mut dyn FnMut(&Table) -> Result<EntitySource, anyhow::Error>,
+    ) -> Result<(DeploymentLocator, usize), StoreError> {
+        if self.mirror.find_active_site(&schema.id)?.is_some() {
+            return Err(StoreError::Unknown(anyhow!(
+                "deployment {} already exists; remove it before restoring it",
+                schema.id
+            )));
+        }
+
+        let (shard, node_id) = self.place(&name, &network_name, node_id)?;
+        let site = Arc::new(
+            self.primary_conn()?
+                .allocate_site(shard, &schema.id, network_name)?,
+        );
+
+        let deployment_store = self
+            .stores
+            .get(&site.shard)
+            .ok_or_else(|| StoreError::UnknownShard(site.shard.to_string()))?;
+        let count = deployment_store
+            .restore(schema, deployment, site.clone(), &head, source)
+            .map_err(|e| {
+                // The shard does not leave a partially restored deployment
+                // behind; remove the site we just allocated, too, so that
+                // the restore can be retried
+                match self.primary_conn().and_then(|pconn| pconn.drop_site(&site)) {
+                    Ok(()) => e,
+                    Err(drop_err) => StoreError::Unknown(anyhow!(
+                        "{}; removing the site for {} also failed: {}",
+                        e,
+                        site.deployment,
+                        drop_err
+                    )),
+                }
+            })?;
+
+        let exists_and_synced = |id: &DeploymentHash| {
+            let (store, _) = self.store(id)?;
+            store.deployment_exists_and_synced(id)
+        };
+
+        // Only now that all data is in place do we assign the deployment
+        // so that no index node starts indexing it prematurely
+        let pconn = self.primary_conn()?;
+        pconn.transaction(|| -> Result<_, StoreError> {
+            let changes = pconn.create_subgraph_version(
+                name,
+                &site,
+                node_id,
+                SubgraphVersionSwitchingMode::Instant,
+                exists_and_synced,
+            )?;
+            let event = StoreEvent::new(changes);
+            pconn.send_store_event(&self.sender, &event)?;
+            Ok(())
+        })?;
+        Ok((site.as_ref().into(), count))
+    }
+
+    /// Load the metadata of `deployment`
+    pub fn load_deployment(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<SubgraphDeploymentEntity, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?.load_deployment(site.as_ref())
+    }
+
     pub async fn create_manual_index(
         &self,
         deployment: &DeploymentLocator,
@@ -1,6 +1,6 @@
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
-use std::time::Duration;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 use std::{collections::BTreeMap, sync::Arc};
 
 use graph::data::subgraph::schema;
@@ -23,7 +23,7 @@ use graph::{
 };
 use store::StoredDynamicDataSource;
 
-use crate::deployment_store::DeploymentStore;
+use crate::deployment_store::{BlockWrite, DeploymentStore};
 use crate::{primary, primary::Site, relational::Layout, SubgraphStore};
 
 graph::prelude::lazy_static! {
@@ -248,31 +248,24 @@ impl SyncStore {
 
     fn transact_block_operations(
         &self,
-        block_ptr_to: &BlockPtr,
-        firehose_cursor: Option<&str>,
-        mods: &[EntityModification],
+        blocks: &[BlockWrite],
         stopwatch: &StopwatchMetrics,
-        data_sources: &[StoredDynamicDataSource],
-        deterministic_errors: &[SubgraphError],
     ) -> Result<(), StoreError> {
-        fn same_subgraph(mods: &[EntityModification], id: &DeploymentHash) -> bool {
-            mods.iter().all(|md| &md.entity_key().subgraph_id == id)
+        fn same_subgraph(blocks: &[BlockWrite], id: &DeploymentHash) -> bool {
+            blocks
+                .iter()
+                .flat_map(|block| &block.mods)
+                .all(|md| &md.entity_key().subgraph_id == id)
         }
 
         assert!(
-            same_subgraph(mods, &self.site.deployment),
+            same_subgraph(blocks, &self.site.deployment),
             "can only transact operations within one shard"
         );
         self.retry("transact_block_operations", move || {
-            let event = self.writable.transact_block_operations(
-                self.site.clone(),
-                block_ptr_to,
-                firehose_cursor,
-                mods,
-                stopwatch,
-                data_sources,
-                deterministic_errors,
-            )?;
+            let event =
+                self.writable
+                    .transact_block_operations(self.site.clone(), blocks, stopwatch)?;
 
             let _section = stopwatch.start_section("send_store_event");
             self.try_send_store_event(event)?;
@@ -384,8 +377,8 @@ impl BlockTracker {
 
     fn update(&mut self, req: &Request) {
         match req {
-            Request::Write { block_ptr, .. } => {
-                self.block = self.block.min(block_ptr.number - 1);
+            Request::Write { first_block, .. } => {
+                self.block = self.block.min(first_block - 1);
             }
             Request::RevertTo { block_ptr, .. } => {
                 // `block_ptr` is the block pointer we are reverting _to_,
@@ -410,18 +403,54 @@ impl BlockTracker {
     }
 }
 
+/// The writes for consecutive blocks that will be committed to the store
+/// in one transaction
+struct Batch {
+    /// The blocks in the batch in increasing order of block number
+    blocks: Vec<BlockWrite>,
+    /// The total number of entity modifications in `blocks`
+    entity_count: usize,
+    /// When the batch was started
+    started: Instant,
+}
+
+impl Batch {
+    fn new(block: BlockWrite) -> Self {
+        Self {
+            entity_count: block.mods.len(),
+            blocks: vec![block],
+            started: Instant::now(),
+        }
+    }
+
+    /// Return `true` if `block` can be added to this batch without making
+    /// it exceed the limits on batch size and duration
+    fn accepts(&self, block: &BlockWrite) -> bool {
+        let size = ENV_VARS.store.write_batch_size;
+        size > 0
+            && self.entity_count + block.mods.len() <= size
+            && self.started.elapsed() < ENV_VARS.store.write_batch_duration
+    }
+
+    fn append(&mut self, block: BlockWrite) {
+        self.entity_count += block.mods.len();
+        self.blocks.push(block);
+    }
+}
+
 /// A write request received from the `WritableStore` frontend that gets
 /// queued
 enum Request {
     Write {
         store: Arc<SyncStore>,
         stopwatch: StopwatchMetrics,
-        /// The block at which we are writing the changes
-        block_ptr: BlockPtr,
-        firehose_cursor: Option<String>,
-        mods: Vec<EntityModification>,
-        data_sources: Vec<StoredDynamicDataSource>,
-        deterministic_errors: Vec<SubgraphError>,
+        /// The number of the first block in the batch
+        first_block: BlockNumber,
+        /// Set once no more blocks can be added to the batch, either
+        /// because the background writer has started writing it, or
+        /// because a revert was queued after it
+        closed: AtomicBool,
+        batch: RwLock<Batch>,
     },
     RevertTo {
         store: Arc<SyncStore>,
@@ -437,19 +466,9 @@ impl Request {
             Request::Write {
                 store,
                 stopwatch,
-                block_ptr: block_ptr_to,
-                firehose_cursor,
-                mods,
-                data_sources,
-                deterministic_errors,
-            } => store.transact_block_operations(
-                block_ptr_to,
-                firehose_cursor.as_deref(),
-                mods,
-                stopwatch,
-                data_sources,
-                deterministic_errors,
-            ),
+                batch,
+                ..
+            } => store.transact_block_operations(&batch.read().unwrap().blocks, stopwatch),
             Request::RevertTo {
                 store,
                 block_ptr,
@@ -468,7 +487,9 @@ struct Queue {
     /// and popped off the front for processing. When the queue only
     /// contains `Write` requests block numbers in the requests are
     /// increasing going front-to-back. When `Revert` requests are queued,
-    /// that is not true anymore
+    /// that is not true anymore. Writes for a new block are added to the
+    /// `Write` request at the back of the queue if that is still open and
+    /// has room for them
     queue: BoundedQueue<Arc<Request>>,
 
     /// The write task puts errors from `transact_block_operations` here so
@@ -537,6 +558,13 @@ impl Queue {
                     let _section = queue.stopwatch.start_section("queue_wait");
                     queue.queue.peek().await
                 };
+                if let Request::Write { closed, .. } = req.as_ref() {
+                    // Stop accepting blocks into the batch before writing
+                    // it. `push_write` checks this flag again while holding
+                    // the batch's write lock so it can not add a block to
+                    // a batch that is being written
+                    closed.store(true, Ordering::SeqCst);
+                }
                 let res = {
                     let _section = queue.stopwatch.start_section("queue_execute");
                     graph::spawn_blocking_allow_panic(move || req.execute()).await
@@ -597,6 +625,58 @@ impl Queue {
         Ok(())
     }
 
+    /// Add the changes for `block` to the queue, either by adding them to
+    /// the batch at the back of the queue, or by queueing a new batch
+    async fn push_write(&self, block: BlockWrite) -> Result<(), StoreError> {
+        self.check_err()?;
+
+        // Only the subgraph runner pushes to the queue, and it does that
+        // from one task. The back of the queue can therefore not change
+        // under us other than by the writer closing the batch
+        let block = match self.queue.back().as_deref() {
+            Some(Request::Write { closed, batch, .. }) if !closed.load(Ordering::SeqCst) => {
+                let mut batch = batch.write().unwrap();
+                if !closed.load(Ordering::SeqCst) && batch.accepts(&block) {
+                    batch.append(block);
+                    return Ok(());
+                }
+                block
+            }
+            _ => block,
+        };
+
+        let req = Request::Write {
+            store: self.store.cheap_clone(),
+            stopwatch: self.stopwatch.cheap_clone(),
+            first_block: block.block_ptr.number,
+            closed: AtomicBool::new(false),
+            batch: RwLock::new(Batch::new(block)),
+        };
+        self.push(req).await
+    }
+
+    /// Queue a revert to `block_ptr`. The batch at the back of the queue
+    /// gets closed so that writes after the revert go into a new batch.
+    /// Blocks in that batch that the revert undoes are still written, and
+    /// then reverted, since the revert requires the subgraph head to be
+    /// past the block it reverts to
+    async fn push_revert(
+        &self,
+        block_ptr: BlockPtr,
+        firehose_cursor: Option<String>,
+    ) -> Result<(), StoreError> {
+        if let Some(Request::Write { closed, .. }) = self.queue.back().as_deref() {
+            closed.store(true, Ordering::SeqCst);
+        }
+
+        let req = Request::RevertTo {
+            store: self.store.cheap_clone(),
+            block_ptr,
+            firehose_cursor,
+        };
+        self.push(req).await
+    }
+
     /// Wait for the background writer to finish processing queued entries
     async fn flush(&self) -> Result<(), StoreError> {
         self.queue.wait_empty().await;
@@ -640,11 +720,17 @@ impl Queue {
         let op = self.queue.find_map(|req| {
             tracker.update(req.as_ref());
             match req.as_ref() {
-                Request::Write {
-                    block_ptr, mods, ..
-                } => {
-                    if tracker.visible(block_ptr) {
-                        mods.iter()
+                Request::Write { batch, .. } => batch
+                    .read()
+                    .unwrap()
+                    .blocks
+                    .iter()
+                    .rev()
+                    .filter(|block| tracker.visible(&block.block_ptr))
+                    .find_map(|block| {
+                        block
+                            .mods
+                            .iter()
                             .find(|emod| emod.entity_key() == key)
                             .map(|emod| match emod {
                                 EntityModification::Insert { data, .. }
@@ -653,10 +739,7 @@ impl Queue {
                                 }
                                 EntityModification::Remove { .. } => Op::Remove,
                             })
-                    } else {
-                        None
-                    }
-                }
+                    }),
                 Request::RevertTo { .. } => None,
             }
         });
@@ -682,11 +765,15 @@ impl Queue {
             |mut map: BTreeMap<EntityType, Vec<Entity>>, req| {
                 tracker.update(req.as_ref());
                 match req.as_ref() {
-                    Request::Write {
-                        block_ptr, mods, ..
-                    } => {
-                        if tracker.visible(block_ptr) {
-                            for emod in mods {
+                    Request::Write { batch, .. } => {
+                        let batch = batch.read().unwrap();
+                        let blocks = batch
+                            .blocks
+                            .iter()
+                            .rev()
+                            .filter(|block| tracker.visible(&block.block_ptr));
+                        for block in blocks {
+                            for emod in &block.mods {
                                 let key = emod.entity_key();
                                 if let Some(ids) = ids_for_type.get_mut(&key.entity_type) {
                                     if let Some(idx) =
@@ -749,13 +836,12 @@ impl Queue {
         let mut queue_dds = self.queue.fold(Vec::new(), |mut dds, req| {
             tracker.update(req.as_ref());
             match req.as_ref() {
-                Request::Write {
-                    block_ptr,
-                    data_sources,
-                    ..
-                } => {
-                    if tracker.visible(block_ptr) {
-                        dds.extend(data_sources.clone());
+                Request::Write { batch, .. } => {
+                    let batch = batch.read().unwrap();
+                    for block in batch.blocks.iter().rev() {
+                        if tracker.visible(&block.block_ptr) {
+                            dds.extend(block.data_sources.clone());
+                        }
                     }
                 }
                 Request::RevertTo { .. } => { /* nothing to do */ }
@@ -768,12 +854,26 @@ impl Queue {
         // queue has been written)
         queue_dds.sort_by_key(|dds| dds.creation_block);
 
+        // Entries with `done_at` in the queue record that an offchain data
+        // source has been processed and need to be applied to the entry
+        // for that data source
+        let (done, mut queue_dds): (Vec<_>, Vec<_>) =
+            queue_dds.into_iter().partition(|ds| ds.done_at.is_some());
+
         let mut dds = self
             .store
             .load_dynamic_data_sources(tracker.query_block())
             .await?;
         dds.append(&mut queue_dds);
 
+        for marker in done {
+            if let Some(ds) = dds.iter_mut().find(|ds| {
+                ds.done_at.is_none() && ds.name == marker.name && ds.param == marker.param
+            }) {
+                ds.done_at = marker.done_at;
+            }
+        }
+
         Ok(dds)
     }
 }
@@ -808,27 +908,16 @@ impl Writer {
         data_sources: Vec<StoredDynamicDataSource>,
         deterministic_errors: Vec<SubgraphError>,
     ) -> Result<(), StoreError> {
+        let block = BlockWrite {
+            block_ptr: block_ptr_to,
+            firehose_cursor,
+            mods,
+            data_sources,
+            deterministic_errors,
+        };
         match self {
-            Writer::Sync(store) => store.transact_block_operations(
-                &block_ptr_to,
-                firehose_cursor.as_deref(),
-                &mods,
-                &stopwatch,
-                &data_sources,
-                &deterministic_errors,
-            ),
-            Writer::Async(queue) => {
-                let req = Request::Write {
-                    store: queue.store.cheap_clone(),
-                    stopwatch: queue.stopwatch.cheap_clone(),
-                    block_ptr: block_ptr_to,
-                    firehose_cursor,
-                    mods,
-                    data_sources,
-                    deterministic_errors,
-                };
-                queue.push(req).await
-            }
+            Writer::Sync(store) => store.transact_block_operations(&[block], stopwatch),
+            Writer::Async(queue) => queue.push_write(block).await,
         }
     }
 
@@ -841,12 +930,7 @@ impl Writer {
             Writer::Sync(store) => store.revert_block_operations(block_ptr_to, firehose_cursor),
             Writer::Async(queue) => {
                 let firehose_cursor = firehose_cursor.map(|c| c.to_string());
-                let req = Request::RevertTo {
-                    store: queue.store.cheap_clone(),
-                    block_ptr: block_ptr_to,
-                    firehose_cursor,
-                };
-                queue.push(req).await
+                queue.push_revert(block_ptr_to, firehose_cursor).await
             }
         }
     }
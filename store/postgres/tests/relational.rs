@@ -1,13 +1,14 @@
 //! Test mapping of GraphQL schema to a relational schema
 use diesel::connection::SimpleConnection as _;
 use diesel::pg::PgConnection;
+use graph::data::query::Trace;
 use graph::data::store::scalar;
 use graph::entity;
 use graph::prelude::BlockNumber;
 use graph::prelude::{
-    o, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityCollection, EntityFilter,
-    EntityKey, EntityOrder, EntityQuery, EntityRange, Logger, Schema, StopwatchMetrics, Value,
-    ValueType, BLOCK_NUMBER_MAX,
+    o, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityAggregate, EntityCollection,
+    EntityFilter, EntityKey, EntityOrder, EntityQuery, EntityRange, FulltextOutput, Logger, Schema,
+    SortPosition, StopwatchMetrics, Value, ValueType, BLOCK_NUMBER_MAX,
 };
 use graph_mock::MockMetricsRegistry;
 use graph_store_postgres::layout_for_tests::set_account_like;
@@ -61,6 +62,14 @@ const THINGS_GQL: &str = r#"
                 ]
             }
         ]
+    ) @fulltext(
+        name: "petSearch"
+        language: en
+        algorithm: rank
+        include: [
+            { entity: "Cat", fields: [{ name: "name" }] },
+            { entity: "Dog", fields: [{ name: "name" }] }
+        ]
     )
 
     type Thing @entity {
@@ -82,6 +91,8 @@ const THINGS_GQL: &str = r#"
         byteArray: [Bytes!],
         bigInt: BigInt,
         bigIntArray: [BigInt!]!
+        int8: Int8,
+        timestamp: Timestamp,
         color: Color,
     }
 
@@ -166,6 +177,8 @@ lazy_static! {
     static ref BYTES_VALUE3: H256 = H256::from(hex!(
         "977c084229c72a0fa377cae304eda9099b6a2cb5d83b25cdf0f0969b69874255"
     ));
+    static ref TIMESTAMP_VALUE: scalar::Timestamp =
+        scalar::Timestamp::from_microseconds_since_epoch(1_654_084_800_123_456).unwrap();
     static ref SCALAR_ENTITY: Entity = {
         let decimal = (*LARGE_DECIMAL).clone();
         let big_int = (*LARGE_INT).clone();
@@ -181,6 +194,8 @@ lazy_static! {
             byteArray: vec![*BYTES_VALUE, *BYTES_VALUE2, *BYTES_VALUE3],
             bigInt: big_int.clone(),
             bigIntArray: vec![big_int.clone(), (big_int + 1.into()).clone()],
+            int8: std::i64::MAX,
+            timestamp: *TIMESTAMP_VALUE,
             color: "yellow",
             __typename: "Scalar",
         }
@@ -711,6 +726,71 @@ fn serialize_bigdecimal() {
     });
 }
 
+#[test]
+fn filter_int8_and_timestamp() {
+    run_test(|conn, layout| {
+        insert_entity(&conn, &layout, "Scalar", vec![SCALAR_ENTITY.clone()]);
+
+        let query = |filter: EntityFilter| {
+            let collection = EntityCollection::All(vec![(SCALAR.to_owned(), AttributeNames::All)]);
+            layout
+                .query::<Entity>(
+                    &*LOGGER,
+                    &conn,
+                    collection,
+                    Some(filter),
+                    EntityOrder::Default,
+                    EntityRange {
+                        first: None,
+                        skip: 0,
+                    },
+                    FulltextOutput::default(),
+                    BLOCK_NUMBER_MAX,
+                    None,
+                    false,
+                )
+                .expect("Scalar query failed")
+                .0
+                .len()
+        };
+
+        let later = scalar::Timestamp::from_microseconds_since_epoch(
+            TIMESTAMP_VALUE.as_microseconds_since_epoch() + 1,
+        )
+        .unwrap();
+
+        assert_eq!(
+            1,
+            query(EntityFilter::Equal("int8".into(), Value::Int8(i64::MAX)))
+        );
+        assert_eq!(
+            0,
+            query(EntityFilter::LessThan("int8".into(), Value::Int8(0)))
+        );
+        assert_eq!(
+            1,
+            query(EntityFilter::Equal(
+                "timestamp".into(),
+                Value::Timestamp(*TIMESTAMP_VALUE)
+            ))
+        );
+        assert_eq!(
+            1,
+            query(EntityFilter::LessThan(
+                "timestamp".into(),
+                Value::Timestamp(later)
+            ))
+        );
+        assert_eq!(
+            0,
+            query(EntityFilter::GreaterThan(
+                "timestamp".into(),
+                Value::Timestamp(later)
+            ))
+        );
+    });
+}
+
 fn count_scalar_entities(conn: &PgConnection, layout: &Layout) -> usize {
     let filter = EntityFilter::Or(vec![
         EntityFilter::Equal("bool".into(), true.into()),
@@ -728,13 +808,53 @@ fn count_scalar_entities(conn: &PgConnection, layout: &Layout) -> usize {
                 first: None,
                 skip: 0,
             },
+            FulltextOutput::default(),
             BLOCK_NUMBER_MAX,
             None,
+            false,
         )
         .expect("Count query failed")
+        .0
         .len()
 }
 
+#[test]
+fn query_trace() {
+    run_test(|conn, layout| {
+        insert_entity(&conn, &layout, "Scalar", vec![SCALAR_ENTITY.clone()]);
+
+        let collection = EntityCollection::All(vec![(SCALAR.to_owned(), AttributeNames::All)]);
+        let (entities, trace) = layout
+            .query::<Entity>(
+                &*LOGGER,
+                &conn,
+                collection,
+                None,
+                EntityOrder::Default,
+                EntityRange::first(10),
+                FulltextOutput::default(),
+                BLOCK_NUMBER_MAX,
+                None,
+                true,
+            )
+            .expect("Traced query failed");
+        assert_eq!(1, entities.len());
+        match trace {
+            Trace::Query {
+                sql,
+                explain,
+                entity_count,
+                ..
+            } => {
+                assert!(sql.contains("scalar"));
+                assert!(explain.iter().any(|line| line.contains("actual time")));
+                assert_eq!(1, entity_count);
+            }
+            _ => panic!("expected a trace for the query but got {:?}", trace),
+        }
+    });
+}
+
 #[test]
 fn delete() {
     run_test(|conn, layout| {
@@ -962,10 +1082,13 @@ fn revert_block() {
                     )),
                     EntityOrder::Ascending("order".to_string(), ValueType::Int),
                     EntityRange::first(100),
+                    FulltextOutput::default(),
                     BLOCK_NUMBER_MAX,
                     None,
+                    false,
                 )
-                .expect("loading all marties works");
+                .expect("loading all marties works")
+                .0;
 
             let mut skipped = 0;
             for block in 0..=max_block {
@@ -1004,6 +1127,102 @@ fn revert_block() {
     });
 }
 
+#[test]
+fn prune() {
+    run_test(|conn, layout| {
+        let id = "fred";
+        let fred_at = |block| {
+            layout
+                .find(conn, &EntityType::from("Cat"), id, block)
+                .unwrap()
+                .map(|fred| fred.get("name").unwrap().as_str().unwrap().to_owned())
+        };
+
+        insert_entity_at(
+            conn,
+            layout,
+            "Cat",
+            vec![entity! { id: id, name: "zero" }],
+            0,
+        );
+        for (name, block) in [("one", 1), ("two", 2), ("three", 3)] {
+            update_entity_at(
+                conn,
+                layout,
+                "Cat",
+                vec![entity! { id: id, name: name }],
+                block,
+            );
+        }
+
+        // Only the versions for blocks 0 and 1 are not visible at block 2
+        assert_eq!(2, layout.prune(conn, 2).unwrap());
+        assert_eq!(None, fred_at(1));
+        assert_eq!(Some("two".to_owned()), fred_at(2));
+        assert_eq!(Some("three".to_owned()), fred_at(3));
+
+        // Pruning is idempotent
+        assert_eq!(0, layout.prune(conn, 2).unwrap());
+
+        // The current version is never removed
+        assert_eq!(1, layout.prune(conn, BLOCK_NUMBER_MAX).unwrap());
+        assert_eq!(Some("three".to_owned()), fred_at(BLOCK_NUMBER_MAX));
+    });
+}
+
+#[test]
+fn aggregate() {
+    run_test(|conn, layout| {
+        use EntityAggregate::*;
+
+        insert_users(conn, layout);
+
+        let user = EntityType::from("User");
+        let aggregate = |filter: Option<EntityFilter>, aggregates: Vec<EntityAggregate>| {
+            layout.aggregate(
+                &*LOGGER,
+                conn,
+                &user,
+                filter.as_ref(),
+                &aggregates,
+                BLOCK_NUMBER_MAX,
+            )
+        };
+        let age = || "age".to_owned();
+
+        let values = aggregate(
+            None,
+            vec![Count, Sum(age()), Min(age()), Max(age()), Avg(age())],
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Value::Int(3),
+                Value::BigInt(BigInt::from(138)),
+                Value::BigInt(BigInt::from(28)),
+                Value::BigInt(BigInt::from(67)),
+                Value::BigDecimal(BigDecimal::from(46)),
+            ],
+            values
+        );
+
+        let filter = EntityFilter::GreaterThan("age".to_owned(), Value::Int(30));
+        let values = aggregate(Some(filter), vec![Count, Sum(age())]).unwrap();
+        assert_eq!(
+            vec![Value::Int(2), Value::BigInt(BigInt::from(110))],
+            values
+        );
+
+        // Aggregates other than the count of an empty set are null
+        let filter = EntityFilter::GreaterThan("age".to_owned(), Value::Int(100));
+        let values = aggregate(Some(filter), vec![Count, Max(age())]).unwrap();
+        assert_eq!(vec![Value::Int(0), Value::Null], values);
+
+        // Only numeric attributes can be aggregated
+        assert!(aggregate(None, vec![Sum("name".to_owned())]).is_err());
+    });
+}
+
 struct QueryChecker<'a> {
     conn: &'a PgConnection,
     layout: &'a Layout,
@@ -1043,10 +1262,13 @@ impl<'a> QueryChecker<'a> {
                 query.filter,
                 query.order,
                 query.range,
+                query.fulltext,
                 BLOCK_NUMBER_MAX,
                 None,
+                false,
             )
-            .expect("layout.query failed to execute query");
+            .expect("layout.query failed to execute query")
+            .0;
 
         let mut entity_ids: Vec<_> = entities
             .into_iter()
@@ -1122,6 +1344,77 @@ fn check_fulltext_search_syntax_error() {
     });
 }
 
+#[test]
+fn check_fulltext_rank_and_snippet() {
+    run_test(move |conn, layout| {
+        insert_users(conn, layout);
+
+        let columns = AttributeNames::Select(vec!["name".to_string()].into_iter().collect());
+        let (entities, _) = layout
+            .query::<Entity>(
+                &*LOGGER,
+                conn,
+                EntityCollection::All(vec![(EntityType::from("User"), columns)]),
+                Some(EntityFilter::Equal("userSearch".into(), "Shaq:*".into())),
+                EntityOrder::Descending("userSearch".into(), ValueType::String),
+                EntityRange::first(10),
+                FulltextOutput {
+                    rank: true,
+                    snippet: true,
+                },
+                BLOCK_NUMBER_MAX,
+                None,
+                false,
+            )
+            .expect("fulltext query succeeds");
+        assert_eq!(1, entities.len());
+        let entity = &entities[0];
+        assert!(matches!(entity.get("_rank"), Some(Value::BigDecimal(_))));
+        match entity.get("_snippet") {
+            Some(Value::String(snippet)) => assert!(snippet.contains("<b>Shaqueeena</b>")),
+            snippet => panic!("unexpected snippet {:?}", snippet),
+        }
+    });
+}
+
+#[test]
+fn check_fulltext_interface_rank() {
+    run_test(move |conn, layout| {
+        insert_pets(conn, layout);
+
+        let columns = AttributeNames::Select(vec!["name".to_string()].into_iter().collect());
+        let (entities, _) = layout
+            .query::<Entity>(
+                &*LOGGER,
+                conn,
+                EntityCollection::All(vec![
+                    (EntityType::from("Cat"), columns.clone()),
+                    (EntityType::from("Dog"), columns),
+                ]),
+                Some(EntityFilter::Equal(
+                    "petSearch".into(),
+                    "Pluto | Garfield".into(),
+                )),
+                EntityOrder::Descending("petSearch".into(), ValueType::String),
+                EntityRange::first(10),
+                FulltextOutput {
+                    rank: true,
+                    snippet: false,
+                },
+                BLOCK_NUMBER_MAX,
+                None,
+                false,
+            )
+            .expect("fulltext query across an interface succeeds");
+        let mut ids: Vec<_> = entities.iter().map(|entity| entity.id().unwrap()).collect();
+        ids.sort();
+        assert_eq!(vec!["garfield", "pluto"], ids);
+        for entity in &entities {
+            assert!(matches!(entity.get("_rank"), Some(Value::BigDecimal(_))));
+        }
+    });
+}
+
 #[test]
 fn check_block_finds() {
     run_test(move |conn, layout| {
@@ -1161,6 +1454,54 @@ fn check_block_finds() {
     });
 }
 
+#[test]
+fn check_after_cursor() {
+    fn after(key: Option<(&str, Value)>, id: &str, descending: bool) -> EntityFilter {
+        EntityFilter::After(SortPosition {
+            key: key.map(|(attr, value)| (attr.to_owned(), value)),
+            id: Value::from(id),
+            descending,
+        })
+    }
+
+    run_test(move |conn, layout| {
+        QueryChecker::new(conn, layout)
+            .check(vec!["2", "3"], user_query().filter(after(None, "1", false)))
+            .check(
+                vec!["1", "3"],
+                user_query()
+                    .filter(after(Some(("name", "Cindini".into())), "2", false))
+                    .asc("name"),
+            )
+            .check(
+                vec!["2"],
+                user_query()
+                    .filter(after(Some(("name", "Jono".into())), "1", true))
+                    .desc("name"),
+            )
+            // Entities with a null sort key come last when sorting
+            // ascending and first when sorting descending
+            .check(
+                vec!["3"],
+                user_query()
+                    .filter(after(Some(("favorite_color", "yellow".into())), "1", false))
+                    .asc("favorite_color"),
+            )
+            .check(
+                vec![],
+                user_query()
+                    .filter(after(Some(("favorite_color", Value::Null)), "3", false))
+                    .asc("favorite_color"),
+            )
+            .check(
+                vec!["1", "2"],
+                user_query()
+                    .filter(after(Some(("favorite_color", Value::Null)), "3", true))
+                    .desc("favorite_color"),
+            );
+    });
+}
+
 #[test]
 fn check_find() {
     run_test(move |conn, layout| {
@@ -1700,10 +2041,13 @@ impl<'a> FilterChecker<'a> {
                 query.filter,
                 query.order,
                 query.range,
+                query.fulltext,
                 BLOCK_NUMBER_MAX,
                 None,
+                false,
             )
-            .expect("layout.query failed to execute query");
+            .expect("layout.query failed to execute query")
+            .0;
 
         let entity_ids: Vec<_> = entities
             .into_iter()
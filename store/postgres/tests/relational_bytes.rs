@@ -11,8 +11,9 @@ use std::{collections::BTreeMap, sync::Arc};
 
 use graph::prelude::{
     o, slog, web3::types::H256, AttributeNames, ChildMultiplicity, DeploymentHash, Entity,
-    EntityCollection, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow, Logger,
-    ParentLink, Schema, StopwatchMetrics, Value, WindowAttribute, BLOCK_NUMBER_MAX,
+    EntityCollection, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow,
+    FulltextOutput, Logger, ParentLink, Schema, StopwatchMetrics, Value, WindowAttribute,
+    BLOCK_NUMBER_MAX,
 };
 use graph::{
     components::store::EntityType,
@@ -409,10 +410,13 @@ fn query() {
                 None,
                 EntityOrder::Default,
                 EntityRange::first(10),
+                FulltextOutput::default(),
                 BLOCK_NUMBER_MAX,
                 None,
+                false,
             )
             .expect("the query succeeds")
+            .0
             .into_iter()
             .map(|e| e.id().expect("entities have an id"))
             .collect::<Vec<_>>()
@@ -9,7 +9,7 @@ use std::{collections::HashSet, sync::Mutex};
 use std::{marker::PhantomData, str::FromStr};
 use test_store::*;
 
-use graph::components::store::{DeploymentLocator, WritableStore};
+use graph::components::store::{DeploymentLocator, StoredDynamicDataSource, WritableStore};
 use graph::data::subgraph::*;
 use graph::prelude::*;
 use graph::{
@@ -1272,6 +1272,64 @@ fn revert_block_with_dynamic_data_source_operations() {
     })
 }
 
+#[test]
+fn offchain_data_source_done_at() {
+    run_test(|store, writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+
+        let data_source = StoredDynamicDataSource {
+            name: "File".to_string(),
+            param: Some(scalar::Bytes::from("QmFile".as_bytes())),
+            context: None,
+            creation_block: Some(TEST_BLOCK_3_PTR.number),
+            done_at: None,
+        };
+        let done_at = |dds: Vec<StoredDynamicDataSource>| {
+            assert_eq!(1, dds.len());
+            dds[0].done_at
+        };
+
+        transact_entities_and_dynamic_data_sources(
+            &subgraph_store,
+            deployment.clone(),
+            TEST_BLOCK_3_PTR.clone(),
+            vec![data_source.clone()],
+            vec![],
+        )
+        .await
+        .unwrap();
+        let loaded_dds = writable.load_dynamic_data_sources().await.unwrap();
+        assert_eq!(None, done_at(loaded_dds));
+
+        // The data source is processed in a later block
+        transact_entities_and_dynamic_data_sources(
+            &subgraph_store,
+            deployment.clone(),
+            TEST_BLOCK_4_PTR.clone(),
+            vec![StoredDynamicDataSource {
+                done_at: Some(TEST_BLOCK_4_PTR.number),
+                ..data_source
+            }],
+            vec![],
+        )
+        .await
+        .unwrap();
+        let loaded_dds = writable.load_dynamic_data_sources().await.unwrap();
+        assert_eq!(Some(TEST_BLOCK_4_PTR.number), done_at(loaded_dds));
+
+        // After a restart, the data source is still marked as done
+        writable.flush().await.unwrap();
+        let loaded_dds = writable.load_dynamic_data_sources().await.unwrap();
+        assert_eq!(Some(TEST_BLOCK_4_PTR.number), done_at(loaded_dds));
+
+        // Reverting the block in which it was processed keeps the data
+        // source, but it needs to be processed again
+        revert_block(&store, &deployment, &*TEST_BLOCK_3_PTR).await;
+        let loaded_dds = writable.load_dynamic_data_sources().await.unwrap();
+        assert_eq!(None, done_at(loaded_dds));
+    })
+}
+
 #[test]
 fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
     run_test(|store, _, _| async move {
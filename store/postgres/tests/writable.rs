@@ -123,6 +123,25 @@ async fn insert_count(store: &Arc<DieselSubgraphStore>, deployment: &DeploymentL
         .unwrap();
 }
 
+/// The counts of all versions of the counter that are visible at `block`
+fn counts_at(
+    store: &Arc<DieselSubgraphStore>,
+    deployment: &DeploymentLocator,
+    block: BlockNumber,
+) -> Vec<i32> {
+    let query = EntityQuery::new(
+        deployment.hash.clone(),
+        block,
+        EntityCollection::All(vec![(EntityType::from(COUNTER), AttributeNames::All)]),
+    );
+    store
+        .find(query)
+        .unwrap()
+        .iter()
+        .map(|counter| counter.get("count").unwrap().as_int().unwrap())
+        .collect()
+}
+
 async fn pause_writer(deployment: &DeploymentLocator) {
     flush(&deployment).await.unwrap();
     writable::allow_steps(0).await;
@@ -165,3 +184,52 @@ fn tracker() {
         assert_eq!(2, read_count());
     })
 }
+
+#[test]
+fn batch() {
+    run_test(|store, writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+
+        let read_count = || {
+            let counter = writable.get(&count_key(&deployment, "1")).unwrap().unwrap();
+            counter.get("count").unwrap().as_int().unwrap()
+        };
+        insert_count(&subgraph_store, &deployment, 1).await;
+        pause_writer(&deployment).await;
+        // The writer might already be waiting for the next request; let it
+        // have that so that it is paused before the requests we care about
+        insert_count(&subgraph_store, &deployment, 2).await;
+        flush(&deployment).await.unwrap();
+
+        // Writes for consecutive blocks are combined into one request
+        for count in 3..6 {
+            insert_count(&subgraph_store, &deployment, count).await;
+            assert_eq!(count as i32, read_count());
+        }
+
+        // A revert closes the batch, and later writes start a new one
+        writable
+            .revert_block_operations(block_pointer(2), None)
+            .await
+            .unwrap();
+        assert_eq!(2, read_count());
+        insert_count(&subgraph_store, &deployment, 3).await;
+        assert_eq!(3, read_count());
+
+        // Writing the first batch, the revert, and the second batch takes
+        // three steps
+        resume_writer(&deployment, 3).await;
+        assert_eq!(3, read_count());
+
+        // A batch that updates the counter in several blocks leaves exactly
+        // one version visible at each block
+        for count in 4..7 {
+            insert_count(&subgraph_store, &deployment, count).await;
+        }
+        resume_writer(&deployment, 1).await;
+        assert_eq!(6, read_count());
+        for block in 1..7 {
+            assert_eq!(vec![block], counts_at(&subgraph_store, &deployment, block));
+        }
+    })
+}
@@ -0,0 +1,34 @@
+import { ethereum } from "@graphprotocol/graph-ts";
+import { Bar, Foo } from "../generated/schema";
+
+export function handleBlockFoo(block: ethereum.Block): void {
+  let foo = Foo.load(block.number.toString());
+  if (foo == null) {
+    foo = new Foo(block.number.toString());
+    foo.count = 0;
+  }
+  foo.count += 1;
+  foo.save();
+}
+
+function loadBar(block: ethereum.Block): Bar {
+  let bar = Bar.load(block.number.toString());
+  if (bar == null) {
+    bar = new Bar(block.number.toString());
+    bar.count = 0;
+    bar.calls = 0;
+  }
+  return bar as Bar;
+}
+
+export function handleBlockBar(block: ethereum.Block): void {
+  let bar = loadBar(block);
+  bar.count += 1;
+  bar.save();
+}
+
+export function handleCallBar(block: ethereum.Block): void {
+  let bar = loadBar(block);
+  bar.calls += 1;
+  bar.save();
+}
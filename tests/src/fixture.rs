@@ -29,7 +29,7 @@ use graph_core::{
 use graph_mock::MockMetricsRegistry;
 use graph_node::manager::PanicSubscriptionManager;
 use graph_node::{config::Config, store_builder::StoreBuilder};
-use graph_store_postgres::SubgraphStore;
+use graph_store_postgres::{Store, SubgraphStore};
 use slog::Logger;
 use std::env::VarError;
 use std::pin::Pin;
@@ -86,6 +86,7 @@ pub struct TestContext {
         >,
     >,
     pub store: Arc<SubgraphStore>,
+    pub network_store: Arc<Store>,
     pub deployment_locator: DeploymentLocator,
 }
 
@@ -94,6 +95,25 @@ pub async fn setup(
     hash: &DeploymentHash,
     store_config_path: &str,
     chain: Vec<BlockWithTriggers<Chain>>,
+) -> TestContext {
+    setup_with_parallel_triggers(
+        subgraph_name,
+        hash,
+        store_config_path,
+        chain,
+        ENV_VARS.subgraph_parallel_triggers,
+    )
+    .await
+}
+
+/// Like `setup`, but with parallel trigger processing turned on or off
+/// regardless of `GRAPH_SUBGRAPH_PARALLEL_TRIGGERS`
+pub async fn setup_with_parallel_triggers(
+    subgraph_name: SubgraphName,
+    hash: &DeploymentHash,
+    store_config_path: &str,
+    chain: Vec<BlockWithTriggers<Chain>>,
+    parallel_triggers: bool,
 ) -> TestContext {
     let logger = graph::log::logger(true);
     let logger_factory = LoggerFactory::new(logger.clone(), None);
@@ -185,6 +205,7 @@ pub async fn setup(
         mock_registry.clone(),
         link_resolver.cheap_clone(),
         static_filters,
+        parallel_triggers,
     );
 
     // Create IPFS-based subgraph provider
@@ -226,6 +247,7 @@ pub async fn setup(
         logger_factory,
         provider: subgraph_provider,
         store: subgraph_store,
+        network_store,
         deployment_locator,
     }
 }
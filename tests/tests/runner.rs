@@ -1,5 +1,6 @@
 use graph_chain_ethereum::chain::BlockFinality;
 use graph_tests::fixture::{self, test_ptr};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -7,9 +8,11 @@ use anyhow::anyhow;
 use ethereum::trigger::{EthereumBlockTriggerType, EthereumTrigger};
 use graph::blockchain::block_stream::BlockWithTriggers;
 use graph::blockchain::{Block, BlockPtr};
-use graph::prelude::ethabi::ethereum_types::{H256, U64};
+use graph::components::store::StatusStore as _;
+use graph::prelude::ethabi::ethereum_types::{Address, H256, U64};
 use graph::prelude::{
-    LightEthereumBlock, SubgraphAssignmentProvider, SubgraphName, SubgraphStore as _,
+    DeploymentHash, LightEthereumBlock, SubgraphAssignmentProvider, SubgraphName,
+    SubgraphStore as _,
 };
 use graph_chain_ethereum::{self as ethereum, Chain};
 use slog::{debug, info};
@@ -116,3 +119,126 @@ async fn data_source_revert() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn parallel_triggers() -> anyhow::Result<()> {
+    let subgraph_name = SubgraphName::new("parallel-triggers")
+        .expect("Subgraph name must contain only a-z, A-Z, 0-9, '-' and '_'");
+
+    let hash = {
+        let test_dir = format!("./integration-tests/{}", subgraph_name);
+        fixture::build_subgraph(&test_dir).await
+    };
+
+    // Every block has a trigger for both data sources and a call trigger
+    // for `Bar`, so the data sources process their triggers in parallel
+    let bar = Address::from_str("2E645469f354BB4F5c8a05B3b30A929361cf77eC").unwrap();
+    let chain: Vec<BlockWithTriggers<Chain>> = (0..4)
+        .map(|number| {
+            let ptr = test_ptr(number);
+            let trigger_data = match number {
+                0 => vec![],
+                _ => vec![
+                    EthereumTrigger::Block(ptr.clone(), EthereumBlockTriggerType::Every),
+                    EthereumTrigger::Block(ptr.clone(), EthereumBlockTriggerType::WithCallTo(bar)),
+                ],
+            };
+            BlockWithTriggers {
+                block: BlockFinality::Final(Arc::new(LightEthereumBlock {
+                    hash: Some(H256::from_low_u64_be(number as u64)),
+                    number: Some(U64::from(number as u64)),
+                    parent_hash: match number {
+                        0 => H256::zero(),
+                        _ => H256::from_low_u64_be(number as u64 - 1),
+                    },
+                    ..Default::default()
+                })),
+                trigger_data,
+            }
+        })
+        .collect();
+
+    let sequential = run_parallel_triggers(&subgraph_name, &hash, chain.clone(), false).await?;
+    let parallel = run_parallel_triggers(&subgraph_name, &hash, chain, true).await?;
+
+    assert!(sequential.iter().all(|(changes, _)| !changes.is_empty()));
+    assert!(sequential.iter().all(|(_, poi)| poi.is_some()));
+    assert_eq!(sequential, parallel);
+
+    Ok(())
+}
+
+/// Index the `parallel-triggers` subgraph up to the end of `chain` and
+/// return the entity changes and the proof of indexing of each block
+/// after the first one
+async fn run_parallel_triggers(
+    subgraph_name: &SubgraphName,
+    hash: &DeploymentHash,
+    chain: Vec<BlockWithTriggers<Chain>>,
+    parallel_triggers: bool,
+) -> anyhow::Result<Vec<(Vec<String>, Option<[u8; 32]>)>> {
+    let stop_block = chain.last().unwrap().block.ptr();
+    let block_ptrs: Vec<_> = chain.iter().skip(1).map(|block| block.ptr()).collect();
+
+    let ctx = fixture::setup_with_parallel_triggers(
+        subgraph_name.clone(),
+        hash,
+        "./integration-tests/config.simple.toml",
+        chain,
+        parallel_triggers,
+    )
+    .await;
+
+    let provider = ctx.provider.clone();
+    let store = ctx.store.clone();
+
+    let logger = ctx.logger_factory.subgraph_logger(&ctx.deployment_locator);
+
+    SubgraphAssignmentProvider::start(provider.as_ref(), ctx.deployment_locator.clone(), None)
+        .await
+        .expect("unable to start subgraph");
+
+    loop {
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+
+        let block_ptr = match store.least_block_ptr(hash).await {
+            Ok(Some(ptr)) => ptr,
+            res => {
+                info!(&logger, "{:?}", res);
+                continue;
+            }
+        };
+
+        if block_ptr == stop_block {
+            break;
+        }
+
+        if !store.is_healthy(hash).await.unwrap() {
+            return Err(anyhow!("subgraph failed unexpectedly"));
+        }
+    }
+
+    SubgraphAssignmentProvider::stop(provider.as_ref(), ctx.deployment_locator.clone())
+        .await
+        .expect("unable to stop subgraph");
+
+    let mut blocks = Vec::new();
+    for block_ptr in block_ptrs {
+        // The order of the changes depends on how they were written
+        let mut changes: Vec<_> = store
+            .entity_changes_in_block(hash, block_ptr.number)?
+            .into_iter()
+            .map(|change| format!("{:?}", change))
+            .collect();
+        changes.sort();
+        let poi = ctx
+            .network_store
+            .get_proof_of_indexing(hash, &None, block_ptr)
+            .await?;
+        blocks.push((changes, poi));
+    }
+
+    fixture::cleanup(&ctx.store, subgraph_name, hash);
+
+    Ok(blocks)
+}